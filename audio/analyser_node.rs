@@ -1,23 +1,272 @@
-use block::{Block, Chunk, FRAMES_PER_BLOCK_USIZE};
+use block::{Block, Chunk};
+use fft::Fft;
+use log::warn;
 use node::AudioNodeEngine;
 use node::BlockInfo;
 use node::{AudioNodeType, ChannelInfo, ChannelInterpretation};
+use num_complex::Complex32;
 use std::cmp;
 use std::f32::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use triple_buffer::{self, Reader, Writer};
+
+pub struct AnalyserNodeOptions {
+    pub fft_size: usize,
+    pub smoothing_time_constant: f64,
+    pub min_decibels: f64,
+    pub max_decibels: f64,
+    /// Where the node publishes its analysis, see `Analyser::channel`
+    pub publisher: Option<AnalyserPublisher>,
+}
+
+impl Default for AnalyserNodeOptions {
+    fn default() -> Self {
+        AnalyserNodeOptions {
+            fft_size: 2048,
+            smoothing_time_constant: 0.8,
+            min_decibels: -100.,
+            max_decibels: -30.,
+            publisher: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum AnalyserNodeMessage {
+    SetFftSize(usize),
+    SetSmoothingTimeConstant(f64),
+    SetMinDecibels(f64),
+    SetMaxDecibels(f64),
+}
+
+/// How long a frequency read waits for the node to publish before
+/// assuming that its context isn't rendering
+const PUBLISH_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// The analysis published by the render thread
+///
+/// The buffers are allocated for MAX_FFT_SIZE up front, so that the render
+/// thread never resizes them.
+#[derive(Clone)]
+struct AnalysisSnapshot {
+    min_decibels: f64,
+    max_decibels: f64,
+    /// The number of render quanta published before this one
+    quantum: u64,
+    fft_size: usize,
+    /// The last fft_size sample-frames, in the first fft_size elements
+    time_domain: Vec<f32>,
+    /// The frequency data request this snapshot answers
+    frequency_request: usize,
+    frequency_bin_count: usize,
+    /// The smoothed spectrum in decibels, in the first
+    /// frequency_bin_count elements
+    frequency: Vec<f32>,
+}
+
+impl AnalysisSnapshot {
+    fn time_domain(&self) -> &[f32] {
+        &self.time_domain[..self.fft_size]
+    }
+
+    fn frequency(&self) -> &[f32] {
+        &self.frequency[..self.frequency_bin_count]
+    }
+}
+
+/// The render thread end of an `Analyser`
+pub struct AnalyserPublisher {
+    writer: Writer<AnalysisSnapshot>,
+    quantum: u64,
+    /// The number of frequency data requests so far
+    requests: Arc<AtomicUsize>,
+    /// The last request the published frequency data answers
+    answered: usize,
+}
+
+/// Reads the analysis of an AnalyserNode from any thread
+///
+/// The node shares its analysis without locking. It publishes its time
+/// domain data at the end of every render quantum, but only computes the
+/// FFT when frequency data is read: a frequency read asks for a new
+/// analysis and waits for the node to publish it at the end of the next
+/// render quantum, or returns the last one if the context isn't rendering.
+pub struct Analyser {
+    reader: Reader<AnalysisSnapshot>,
+    requests: Arc<AtomicUsize>,
+}
+
+impl Analyser {
+    /// Create an analyser, and the publisher to pass to its node in
+    /// `AnalyserNodeOptions::publisher`
+    pub fn channel() -> (Analyser, AnalyserPublisher) {
+        let defaults = AnalyserNodeOptions::default();
+        let (writer, reader) = triple_buffer::triple_buffer(AnalysisSnapshot {
+            min_decibels: defaults.min_decibels,
+            max_decibels: defaults.max_decibels,
+            quantum: 0,
+            fft_size: 0,
+            time_domain: vec![0.; MAX_FFT_SIZE],
+            frequency_request: 0,
+            frequency_bin_count: 0,
+            frequency: vec![0.; MAX_FFT_SIZE / 2],
+        });
+        let requests = Arc::new(AtomicUsize::new(0));
+        (
+            Analyser {
+                reader,
+                requests: requests.clone(),
+            },
+            AnalyserPublisher {
+                writer,
+                quantum: 0,
+                requests,
+                answered: 0,
+            },
+        )
+    }
+
+    /// Ask for a new frequency analysis, and wait until it is published
+    fn frequency_snapshot(&mut self) -> &AnalysisSnapshot {
+        let request = self.requests.fetch_add(1, Ordering::AcqRel) + 1;
+        let mut quantum = self.reader.read().quantum;
+        let mut progress = Instant::now();
+        loop {
+            let snapshot = self.reader.read();
+            if snapshot.frequency_request >= request {
+                break;
+            }
+            if snapshot.quantum != quantum {
+                quantum = snapshot.quantum;
+                progress = Instant::now();
+            } else if progress.elapsed() > PUBLISH_TIMEOUT {
+                warn!("Analyser isn't rendering, returning its last frequency data");
+                break;
+            }
+            thread::sleep(Duration::from_micros(250));
+        }
+        self.reader.read()
+    }
+
+    /// The size of the FFT of the last published analysis
+    pub fn fft_size(&mut self) -> usize {
+        self.reader.read().fft_size
+    }
+
+    /// https://webaudio.github.io/web-audio-api/#dom-analysernode-getfloatfrequencydata
+    pub fn get_float_frequency_data(&mut self, dest: &mut [f32]) {
+        let frequency = self.frequency_snapshot().frequency();
+        let len = cmp::min(dest.len(), frequency.len());
+        dest[..len].copy_from_slice(&frequency[..len]);
+    }
+
+    /// https://webaudio.github.io/web-audio-api/#dom-analysernode-getbytefrequencydata
+    pub fn get_byte_frequency_data(&mut self, dest: &mut [u8]) {
+        let snapshot = self.frequency_snapshot();
+        for (byte, &db) in dest.iter_mut().zip(snapshot.frequency()) {
+            *byte = byte_frequency(db, snapshot.min_decibels, snapshot.max_decibels);
+        }
+    }
+
+    /// https://webaudio.github.io/web-audio-api/#dom-analysernode-getfloattimedomaindata
+    pub fn get_float_time_domain_data(&mut self, dest: &mut [f32]) {
+        let time_domain = self.reader.read().time_domain();
+        let len = cmp::min(dest.len(), time_domain.len());
+        dest[..len].copy_from_slice(&time_domain[..len]);
+    }
+
+    /// https://webaudio.github.io/web-audio-api/#dom-analysernode-getbytetimedomaindata
+    pub fn get_byte_time_domain_data(&mut self, dest: &mut [u8]) {
+        for (byte, &sample) in dest.iter_mut().zip(self.reader.read().time_domain()) {
+            *byte = byte_time_domain(sample);
+        }
+    }
+}
 
 #[derive(AudioNodeCommon)]
 pub(crate) struct AnalyserNode {
     channel_info: ChannelInfo,
-    callback: Box<dyn FnMut(Block) + Send>,
+    engine: AnalysisEngine,
+    publisher: Option<AnalyserPublisher>,
 }
 
 impl AnalyserNode {
-    pub fn new(callback: Box<dyn FnMut(Block) + Send>, channel_info: ChannelInfo) -> Self {
+    pub fn new(options: AnalyserNodeOptions, channel_info: ChannelInfo) -> Self {
         Self {
-            callback,
             channel_info,
+            engine: AnalysisEngine::new(
+                options.fft_size,
+                options.smoothing_time_constant,
+                options.min_decibels,
+                options.max_decibels,
+            ),
+            publisher: options.publisher,
         }
     }
+
+    fn handle_analyser_message(&mut self, message: AnalyserNodeMessage, _sample_rate: f32) {
+        let engine = &mut self.engine;
+        match message {
+            AnalyserNodeMessage::SetFftSize(size) => {
+                if !(32..=MAX_FFT_SIZE).contains(&size) || !size.is_power_of_two() {
+                    warn!("Ignoring invalid FFT size {}", size);
+                } else {
+                    engine.set_fft_size(size);
+                }
+            }
+            AnalyserNodeMessage::SetSmoothingTimeConstant(constant) => {
+                if (0. ..=1.).contains(&constant) {
+                    engine.set_smoothing_constant(constant);
+                } else {
+                    warn!("Ignoring invalid smoothing time constant {}", constant);
+                }
+            }
+            AnalyserNodeMessage::SetMinDecibels(min) => {
+                if min < engine.get_max_decibels() {
+                    engine.set_min_decibels(min);
+                } else {
+                    warn!("Ignoring min decibels {} above max decibels", min);
+                }
+            }
+            AnalyserNodeMessage::SetMaxDecibels(max) => {
+                if max > engine.get_min_decibels() {
+                    engine.set_max_decibels(max);
+                } else {
+                    warn!("Ignoring max decibels {} below min decibels", max);
+                }
+            }
+        }
+    }
+
+    /// Share the current time domain data, and a new frequency analysis
+    /// if one was asked for since the last time
+    fn publish(&mut self) {
+        let publisher = match self.publisher {
+            Some(ref mut publisher) => publisher,
+            None => return,
+        };
+        let requested = publisher.requests.load(Ordering::Acquire);
+        let engine = &mut self.engine;
+        let snapshot = publisher.writer.back_mut();
+        snapshot.min_decibels = engine.get_min_decibels();
+        snapshot.max_decibels = engine.get_max_decibels();
+        snapshot.quantum = publisher.quantum;
+        snapshot.fft_size = engine.get_fft_size();
+        engine.fill_time_domain_data(&mut snapshot.time_domain[..snapshot.fft_size]);
+        // The back buffer holds an older analysis, so the last one is
+        // copied again when there is no new request
+        if requested != publisher.answered {
+            engine.compute_fft();
+            publisher.answered = requested;
+        }
+        snapshot.frequency_request = publisher.answered;
+        snapshot.frequency_bin_count = engine.fill_last_frequency_data(&mut snapshot.frequency);
+        publisher.writer.publish();
+        publisher.quantum += 1;
+    }
 }
 
 impl AudioNodeEngine for AnalyserNode {
@@ -30,21 +279,21 @@ impl AudioNodeEngine for AnalyserNode {
 
         let mut push = inputs.blocks[0].clone();
         push.mix(1, ChannelInterpretation::Speakers);
-
-        (self.callback)(push);
+        self.engine.push(push);
+        self.publish();
 
         // analyser node doesn't modify the inputs
         inputs
     }
+
+    make_message_handler!(AnalyserNode: handle_analyser_message);
 }
 
 /// From https://webaudio.github.io/web-audio-api/#dom-analysernode-fftsize
 pub const MAX_FFT_SIZE: usize = 32768;
-pub const MAX_BLOCK_COUNT: usize = MAX_FFT_SIZE / FRAMES_PER_BLOCK_USIZE;
 
-/// The actual analysis is done on the DOM side. We provide
-/// the actual base functionality in this struct, so the DOM
-/// just has to do basic shimming
+/// The analysis of an AnalyserNode, which runs on the render thread
+/// and publishes its results through an `Analyser`
 pub struct AnalysisEngine {
     /// The number of past sample-frames to consider in the FFT
     fft_size: usize,
@@ -54,8 +303,8 @@ pub struct AnalysisEngine {
     /// This is a ring buffer containing the last MAX_FFT_SIZE
     /// sample-frames
     data: Box<[f32; MAX_FFT_SIZE]>,
-    /// The index of the current block
-    current_block: usize,
+    /// The index of the next sample-frame to write in `data`
+    write_index: usize,
     /// Have we computed the FFT already?
     fft_computed: bool,
     /// Cached blackman window data
@@ -67,6 +316,9 @@ pub struct AnalysisEngine {
     /// The windowed time domain data
     /// Used during FFT computation
     windowed: Vec<f32>,
+    fft: Fft,
+    /// The output of the FFT, before smoothing
+    frequency_bins: Vec<Complex32>,
 }
 
 impl AnalysisEngine {
@@ -87,12 +339,14 @@ impl AnalysisEngine {
             min_decibels,
             max_decibels,
             data: Box::new([0.; MAX_FFT_SIZE]),
-            current_block: MAX_BLOCK_COUNT - 1,
+            write_index: 0,
             fft_computed: false,
             blackman_windows: Vec::with_capacity(fft_size),
             computed_fft_data: Vec::with_capacity(fft_size / 2),
             smoothed_fft_data: Vec::with_capacity(fft_size / 2),
             windowed: Vec::with_capacity(fft_size),
+            fft: Fft::new(fft_size),
+            frequency_bins: vec![Complex32::new(0., 0.); fft_size / 2 + 1],
         }
     }
 
@@ -102,6 +356,11 @@ impl AnalysisEngine {
         debug_assert!(fft_size & fft_size - 1 == 0);
         self.fft_size = fft_size;
         self.fft_computed = false;
+        if self.fft.size() != fft_size {
+            self.fft = Fft::new(fft_size);
+            self.frequency_bins
+                .resize(fft_size / 2 + 1, Complex32::new(0., 0.));
+        }
     }
 
     pub fn get_fft_size(&self) -> usize {
@@ -136,29 +395,11 @@ impl AnalysisEngine {
         self.max_decibels
     }
 
-    fn advance(&mut self) {
-        self.current_block += 1;
-        if self.current_block >= MAX_BLOCK_COUNT {
-            self.current_block = 0;
-        }
-    }
-
-    /// Get the data of the current block
-    fn curent_block_mut(&mut self) -> &mut [f32] {
-        let index = FRAMES_PER_BLOCK_USIZE * self.current_block;
-        &mut self.data[index..(index + FRAMES_PER_BLOCK_USIZE)]
-    }
-
     /// Given an index from 0 to fft_size, convert it into an index into
     /// the backing array
     fn convert_index(&self, index: usize) -> usize {
         let offset = self.fft_size - index;
-        let last_element = (1 + self.current_block) * FRAMES_PER_BLOCK_USIZE - 1;
-        if offset > last_element {
-            MAX_FFT_SIZE - offset + last_element
-        } else {
-            last_element - offset
-        }
+        (self.write_index + MAX_FFT_SIZE - offset) % MAX_FFT_SIZE
     }
 
     /// Given an index into the backing array, increment it
@@ -169,12 +410,20 @@ impl AnalysisEngine {
         }
     }
 
-    pub fn push(&mut self, mut block: Block) {
+    /// Push a render quantum, render quantum sizes being powers of two,
+    /// they always fit in the backing array without wrapping around
+    pub fn push(&mut self, block: Block) {
         debug_assert!(block.chan_count() == 1);
-        self.advance();
-        if !block.is_silence() {
-            self.curent_block_mut().copy_from_slice(block.data_mut());
+        let frames = block.frames();
+        let index = self.write_index;
+        if block.is_silence() {
+            for sample in &mut self.data[index..index + frames] {
+                *sample = 0.;
+            }
+        } else {
+            self.data[index..index + frames].copy_from_slice(block.data_chan(0));
         }
+        self.write_index = (index + frames) % MAX_FFT_SIZE;
         self.fft_computed = false;
     }
 
@@ -215,17 +464,10 @@ impl AnalysisEngine {
         self.computed_fft_data.resize(self.fft_size / 2, 0.);
         self.smoothed_fft_data.resize(self.fft_size / 2, 0.);
 
+        self.fft
+            .real_forward(&self.windowed, &mut self.frequency_bins);
         for k in 0..(self.fft_size / 2) {
-            let mut sum_real = 0.;
-            let mut sum_imaginary = 0.;
-            let factor = -2. * PI * k as f32 / self.fft_size as f32;
-            for n in 0..(self.fft_size) {
-                sum_real += self.windowed[n] * (factor * n as f32).cos();
-                sum_imaginary += self.windowed[n] * (factor * n as f32).sin();
-            }
-            let sum_real = sum_real / self.fft_size as f32;
-            let sum_imaginary = sum_imaginary / self.fft_size as f32;
-            let magnitude = (sum_real * sum_real + sum_imaginary * sum_imaginary).sqrt();
+            let magnitude = self.frequency_bins[k].norm() / self.fft_size as f32;
             self.smoothed_fft_data[k] = (self.smoothing_constant * self.smoothed_fft_data[k] as f64
                 + (1. - self.smoothing_constant) * magnitude as f64)
                 as f32;
@@ -246,8 +488,7 @@ impl AnalysisEngine {
         let mut data_idx = self.convert_index(0);
         let end = cmp::min(self.fft_size, dest.len());
         for n in 0..end {
-            let result = 128. * (1. + self.data[data_idx]);
-            dest[n] = clamp_255(result);
+            dest[n] = byte_time_domain(self.data[data_idx]);
             self.advance_index(&mut data_idx)
         }
     }
@@ -258,17 +499,38 @@ impl AnalysisEngine {
         dest[0..len].copy_from_slice(&mut self.computed_fft_data[0..len]);
     }
 
+    /// Copy the last computed frequency data without computing the FFT
+    /// again, returning the number of frequency bins copied
+    pub fn fill_last_frequency_data(&self, dest: &mut [f32]) -> usize {
+        let len = cmp::min(dest.len(), self.computed_fft_data.len());
+        dest[..len].copy_from_slice(&self.computed_fft_data[..len]);
+        len
+    }
+
     pub fn fill_byte_frequency_data(&mut self, dest: &mut [u8]) {
         self.compute_fft();
         let len = cmp::min(dest.len(), self.computed_fft_data.len());
-        let ratio = 255. / (self.max_decibels - self.min_decibels);
         for freq in 0..len {
-            let result = ratio * (self.computed_fft_data[freq] as f64 - self.min_decibels);
-            dest[freq] = clamp_255(result as f32);
+            dest[freq] = byte_frequency(
+                self.computed_fft_data[freq],
+                self.min_decibels,
+                self.max_decibels,
+            );
         }
     }
 }
 
+/// https://webaudio.github.io/web-audio-api/#dom-analysernode-getbytetimedomaindata
+fn byte_time_domain(sample: f32) -> u8 {
+    clamp_255(128. * (1. + sample))
+}
+
+/// https://webaudio.github.io/web-audio-api/#dom-analysernode-getbytefrequencydata
+fn byte_frequency(db: f32, min_decibels: f64, max_decibels: f64) -> u8 {
+    let ratio = 255. / (max_decibels - min_decibels);
+    clamp_255((ratio * (db as f64 - min_decibels)) as f32)
+}
+
 fn clamp_255(val: f32) -> u8 {
     if val > 255. {
         255
@@ -278,3 +540,76 @@ fn clamp_255(val: f32) -> u8 {
         val as u8
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::frames_per_block_usize;
+    use std::sync::atomic::AtomicBool;
+
+    const SIZE: usize = 2048;
+    const BIN: usize = 64;
+
+    fn sine(frame: usize) -> f32 {
+        (2. * PI * (BIN * frame) as f32 / SIZE as f32).sin()
+    }
+
+    fn push_block(node: &mut AnalyserNode, frame: &mut usize) {
+        let frames = frames_per_block_usize();
+        let block = Block::for_vec((*frame..*frame + frames).map(sine).collect());
+        *frame += frames;
+        node.engine.push(block);
+        node.publish();
+    }
+
+    #[test]
+    fn publishes_every_quantum() {
+        let (mut analyser, publisher) = Analyser::channel();
+        let options = AnalyserNodeOptions {
+            fft_size: SIZE,
+            smoothing_time_constant: 0.,
+            publisher: Some(publisher),
+            ..Default::default()
+        };
+        let mut node = AnalyserNode::new(options, ChannelInfo::default());
+        let mut frame = 0;
+
+        // The time domain data of the last quantum is read without waiting
+        for _ in 0..SIZE / frames_per_block_usize() + 1 {
+            push_block(&mut node, &mut frame);
+        }
+        let mut time_domain = vec![0.; SIZE];
+        analyser.get_float_time_domain_data(&mut time_domain);
+        assert_eq!(analyser.fft_size(), SIZE);
+        let start = frame - SIZE;
+        for (i, &sample) in time_domain.iter().enumerate() {
+            assert!((sample - sine(start + i)).abs() < 1e-6);
+        }
+
+        // Frequency reads wait for the next quantum to be rendered
+        let done = Arc::new(AtomicBool::new(false));
+        let rendering = {
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(Ordering::Acquire) {
+                    push_block(&mut node, &mut frame);
+                    thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+        let mut frequency = vec![0.; SIZE / 2];
+        analyser.get_float_frequency_data(&mut frequency);
+        let peak = (0..frequency.len())
+            .max_by(|&a, &b| frequency[a].partial_cmp(&frequency[b]).unwrap())
+            .unwrap();
+        assert_eq!(peak, BIN);
+        done.store(true, Ordering::Release);
+        rendering.join().unwrap();
+
+        // Without rendering, the last analysis is returned
+        let mut bytes = vec![0; SIZE / 2];
+        analyser.get_byte_frequency_data(&mut bytes);
+        assert_eq!(bytes[BIN], 255);
+        assert_eq!(bytes[SIZE / 2 - 1], 0);
+    }
+}
@@ -1,11 +1,13 @@
 use block::Chunk;
 use block::Tick;
 use node::AudioNodeEngine;
-use node::BlockInfo;
-use node::{AudioNodeMessage, AudioNodeType, ChannelInfo};
+use node::{AudioNodeMessage, AudioNodeType, ChannelInfo, FrequencyResponse};
+use node::{BlockInfo, QUIESCENT_LEVEL};
+use num_complex::Complex64;
 use param::{Param, ParamType};
 use smallvec::SmallVec;
 use std::f64::consts::{PI, SQRT_2};
+use std::sync::mpsc::Sender;
 
 #[derive(Copy, Clone, Debug)]
 pub struct BiquadFilterNodeOptions {
@@ -40,9 +42,12 @@ impl Default for BiquadFilterNodeOptions {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum BiquadFilterNodeMessage {
     SetFilterType(FilterType),
+    /// The response of the filter at these frequencies, in Hz, with the
+    /// current values of the params
+    GetFrequencyResponse(Vec<f32>, Sender<FrequencyResponse>),
 }
 
 /// The last two input and output values, per-channel
@@ -67,6 +72,12 @@ impl BiquadState {
         self.y2 = self.y1;
         self.y1 = y;
     }
+
+    fn is_quiescent(&self) -> bool {
+        [self.x1, self.x2, self.y1, self.y2]
+            .iter()
+            .all(|v| v.abs() < QUIESCENT_LEVEL)
+    }
 }
 
 /// https://webaudio.github.io/web-audio-api/#biquadfilternode
@@ -78,26 +89,8 @@ pub(crate) struct BiquadFilterNode {
     detune: Param,
     q: Param,
     gain: Param,
-    /// The computed filter parameter b0
-    /// This is actually b0 / a0, we pre-divide
-    /// for efficiency
-    b0: f64,
-    /// The computed filter parameter b1
-    /// This is actually b1 / a0, we pre-divide
-    /// for efficiency
-    b1: f64,
-    /// The computed filter parameter b2
-    /// This is actually b2 / a0, we pre-divide
-    /// for efficiency
-    b2: f64,
-    /// The computed filter parameter a1
-    /// This is actually a1 / a0, we pre-divide
-    /// for efficiency
-    a1: f64,
-    /// The computed filter parameter a2
-    /// This is actually a2 / a0, we pre-divide
-    /// for efficiency
-    a2: f64,
+    /// The coefficients for the current values of the params
+    coefficients: BiquadCoefficients,
     /// Stored filter state, this contains the last two
     /// frames of input and output values for every
     /// channel
@@ -110,18 +103,15 @@ impl BiquadFilterNode {
         channel_info: ChannelInfo,
         sample_rate: f32,
     ) -> Self {
+        let node = AudioNodeType::BiquadFilterNode;
         let mut ret = Self {
             channel_info,
             filter: options.filter,
-            frequency: Param::new(options.frequency),
-            gain: Param::new(options.gain),
-            q: Param::new(options.q),
-            detune: Param::new(options.detune),
-            b0: 0.,
-            b1: 0.,
-            b2: 0.,
-            a1: 0.,
-            a2: 0.,
+            frequency: Param::for_node(node, ParamType::Frequency, options.frequency, sample_rate),
+            gain: Param::for_node(node, ParamType::Gain, options.gain, sample_rate),
+            q: Param::for_node(node, ParamType::Q, options.q, sample_rate),
+            detune: Param::for_node(node, ParamType::Detune, options.detune, sample_rate),
+            coefficients: BiquadCoefficients::constant(0.),
             state: SmallVec::new(),
         };
         ret.update_coefficients(sample_rate);
@@ -140,23 +130,48 @@ impl BiquadFilterNode {
         changed
     }
 
-    /// Set to the constant z-transform y[n] = b0 * x[n]
-    fn constant_z_transform(&mut self, b0: f64) {
-        self.b0 = b0;
-        self.b1 = 0.;
-        self.b2 = 0.;
-        self.a1 = 0.;
-        self.a2 = 0.;
+    /// Update the coefficients for the current values of the params
+    fn update_coefficients(&mut self, fs: f32) {
+        self.coefficients = BiquadCoefficients::new(
+            self.filter,
+            self.frequency.value(),
+            self.detune.value(),
+            self.q.value(),
+            self.gain.value(),
+            fs,
+        );
+    }
+}
+
+/// The coefficients of the filter's transfer function
+///
+/// These are actually divided by a0, we pre-divide for efficiency.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct BiquadCoefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl BiquadCoefficients {
+    /// The constant z-transform y[n] = b0 * x[n]
+    fn constant(b0: f64) -> Self {
+        BiquadCoefficients {
+            b0,
+            ..Default::default()
+        }
     }
 
-    /// Update the coefficients a1, a2, b0, b1, b2, given the sample_rate
+    /// The coefficients for the values of the params, given the sample_rate
     ///
     /// See https://webaudio.github.io/web-audio-api/#filters-characteristics
-    fn update_coefficients(&mut self, fs: f32) {
-        let g: f64 = self.gain.value().into();
-        let q: f64 = self.q.value().into();
-        let freq: f64 = self.frequency.value().into();
-        let f0: f64 = freq * (2.0_f64).powf(self.detune.value() as f64 / 1200.);
+    fn new(filter: FilterType, frequency: f32, detune: f32, q: f32, gain: f32, fs: f32) -> Self {
+        let g: f64 = gain.into();
+        let q: f64 = q.into();
+        let freq: f64 = frequency.into();
+        let f0: f64 = freq * (2.0_f64).powf(detune as f64 / 1200.);
         let fs: f64 = fs.into();
         // clamp to nominal range
         // https://webaudio.github.io/web-audio-api/#biquadfilternode
@@ -173,77 +188,61 @@ impl BiquadFilterNode {
 
         // the boundary values sometimes need limits to
         // be taken
-        match self.filter {
+        match filter {
             FilterType::LowPass => {
                 if normalized == 1. {
-                    self.constant_z_transform(1.);
-                    return;
+                    return BiquadCoefficients::constant(1.);
                 } else if normalized == 0. {
-                    self.constant_z_transform(0.);
-                    return;
+                    return BiquadCoefficients::constant(0.);
                 }
             }
             FilterType::HighPass => {
                 if normalized == 1. {
-                    self.constant_z_transform(0.);
-                    return;
+                    return BiquadCoefficients::constant(0.);
                 } else if normalized == 0. {
-                    self.constant_z_transform(1.);
-                    return;
+                    return BiquadCoefficients::constant(1.);
                 }
             }
             FilterType::LowShelf => {
                 if normalized == 1. {
-                    self.constant_z_transform(a * a);
-                    return;
+                    return BiquadCoefficients::constant(a * a);
                 } else if normalized == 0. {
-                    self.constant_z_transform(1.);
-                    return;
+                    return BiquadCoefficients::constant(1.);
                 }
             }
             FilterType::HighShelf => {
                 if normalized == 1. {
-                    self.constant_z_transform(1.);
-                    return;
+                    return BiquadCoefficients::constant(1.);
                 } else if normalized == 0. {
-                    self.constant_z_transform(a * a);
-                    return;
+                    return BiquadCoefficients::constant(a * a);
                 }
             }
             FilterType::Peaking => {
                 if normalized == 0. || normalized == 1. {
-                    self.constant_z_transform(1.);
-                    return;
+                    return BiquadCoefficients::constant(1.);
                 } else if q <= 0. {
-                    self.constant_z_transform(a * a);
-                    return;
+                    return BiquadCoefficients::constant(a * a);
                 }
             }
             FilterType::AllPass => {
                 if normalized == 0. || normalized == 1. {
-                    self.constant_z_transform(1.);
-                    return;
+                    return BiquadCoefficients::constant(1.);
                 } else if q <= 0. {
-                    self.constant_z_transform(-1.);
-                    return;
+                    return BiquadCoefficients::constant(-1.);
                 }
             }
             FilterType::Notch => {
                 if normalized == 0. || normalized == 1. {
-                    self.constant_z_transform(1.);
-                    return;
+                    return BiquadCoefficients::constant(1.);
                 } else if q <= 0. {
-                    self.constant_z_transform(0.);
-                    return;
+                    return BiquadCoefficients::constant(0.);
                 }
             }
             FilterType::BandPass => {
                 if normalized == 0. || normalized == 1. {
-                    self.constant_z_transform(0.);
-                    return;
+                    return BiquadCoefficients::constant(0.);
                 } else if q <= 0. {
-                    self.constant_z_transform(1.);
-                    return;
+                    return BiquadCoefficients::constant(1.);
                 }
             }
         }
@@ -257,88 +256,133 @@ impl BiquadFilterNode {
 
         // we predivide by a0
         let a0;
+        let mut c = BiquadCoefficients::default();
 
-        match self.filter {
+        match filter {
             FilterType::LowPass => {
-                self.b0 = (1. - cos_omega) / 2.;
-                self.b1 = 1. - cos_omega;
-                self.b2 = self.b1 / 2.;
+                c.b0 = (1. - cos_omega) / 2.;
+                c.b1 = 1. - cos_omega;
+                c.b2 = c.b1 / 2.;
                 a0 = 1. + alpha_q_db;
-                self.a1 = -2. * cos_omega;
-                self.a2 = 1. - alpha_q_db;
+                c.a1 = -2. * cos_omega;
+                c.a2 = 1. - alpha_q_db;
             }
             FilterType::HighPass => {
-                self.b0 = (1. + cos_omega) / 2.;
-                self.b1 = -(1. + cos_omega);
-                self.b2 = -self.b1 / 2.;
+                c.b0 = (1. + cos_omega) / 2.;
+                c.b1 = -(1. + cos_omega);
+                c.b2 = -c.b1 / 2.;
                 a0 = 1. + alpha_q_db;
-                self.a1 = -2. * cos_omega;
-                self.a2 = 1. - alpha_q_db;
+                c.a1 = -2. * cos_omega;
+                c.a2 = 1. - alpha_q_db;
             }
             FilterType::BandPass => {
-                self.b0 = alpha_q;
-                self.b1 = 0.;
-                self.b2 = -alpha_q;
+                c.b0 = alpha_q;
+                c.b1 = 0.;
+                c.b2 = -alpha_q;
                 a0 = 1. + alpha_q;
-                self.a1 = -2. * cos_omega;
-                self.a2 = 1. - alpha_q;
+                c.a1 = -2. * cos_omega;
+                c.a2 = 1. - alpha_q;
             }
             FilterType::Notch => {
-                self.b0 = 1.;
-                self.b1 = -2. * cos_omega;
-                self.b2 = 1.;
+                c.b0 = 1.;
+                c.b1 = -2. * cos_omega;
+                c.b2 = 1.;
                 a0 = 1. + alpha_q;
-                self.a1 = -2. * cos_omega;
-                self.a2 = 1. - alpha_q;
+                c.a1 = -2. * cos_omega;
+                c.a2 = 1. - alpha_q;
             }
             FilterType::AllPass => {
-                self.b0 = 1. - alpha_q;
-                self.b1 = -2. * cos_omega;
-                self.b2 = 1. + alpha_q;
+                c.b0 = 1. - alpha_q;
+                c.b1 = -2. * cos_omega;
+                c.b2 = 1. + alpha_q;
                 a0 = 1. + alpha_q;
-                self.a1 = -2. * cos_omega;
-                self.a2 = 1. - alpha_q;
+                c.a1 = -2. * cos_omega;
+                c.a2 = 1. - alpha_q;
             }
             FilterType::Peaking => {
-                self.b0 = 1. + alpha_q * a;
-                self.b1 = -2. * cos_omega;
-                self.b2 = 1. - alpha_q * a;
+                c.b0 = 1. + alpha_q * a;
+                c.b1 = -2. * cos_omega;
+                c.b2 = 1. - alpha_q * a;
                 a0 = 1. + alpha_q / a;
-                self.a1 = -2. * cos_omega;
-                self.a2 = 1. - alpha_q / a;
+                c.a1 = -2. * cos_omega;
+                c.a2 = 1. - alpha_q / a;
             }
             FilterType::LowShelf => {
                 let alpha_rt_a = 2. * alpha_s * a.sqrt();
-                self.b0 = a * ((a + 1.) - (a - 1.) * cos_omega + alpha_rt_a);
-                self.b1 = 2. * a * ((a - 1.) - (a + 1.) * cos_omega);
-                self.b2 = a * ((a + 1.) - (a - 1.) * cos_omega - alpha_rt_a);
+                c.b0 = a * ((a + 1.) - (a - 1.) * cos_omega + alpha_rt_a);
+                c.b1 = 2. * a * ((a - 1.) - (a + 1.) * cos_omega);
+                c.b2 = a * ((a + 1.) - (a - 1.) * cos_omega - alpha_rt_a);
                 a0 = (a + 1.) + (a - 1.) * cos_omega + alpha_rt_a;
-                self.a1 = -2. * ((a - 1.) + (a + 1.) * cos_omega);
-                self.a2 = (a + 1.) + (a - 1.) * cos_omega - alpha_rt_a;
+                c.a1 = -2. * ((a - 1.) + (a + 1.) * cos_omega);
+                c.a2 = (a + 1.) + (a - 1.) * cos_omega - alpha_rt_a;
             }
             FilterType::HighShelf => {
                 let alpha_rt_a = 2. * alpha_s * a.sqrt();
-                self.b0 = a * ((a + 1.) + (a - 1.) * cos_omega + alpha_rt_a);
-                self.b1 = -2. * a * ((a - 1.) + (a + 1.) * cos_omega);
-                self.b2 = a * ((a + 1.) + (a - 1.) * cos_omega - alpha_rt_a);
+                c.b0 = a * ((a + 1.) + (a - 1.) * cos_omega + alpha_rt_a);
+                c.b1 = -2. * a * ((a - 1.) + (a + 1.) * cos_omega);
+                c.b2 = a * ((a + 1.) + (a - 1.) * cos_omega - alpha_rt_a);
                 a0 = (a + 1.) - (a - 1.) * cos_omega + alpha_rt_a;
-                self.a1 = 2. * ((a - 1.) - (a + 1.) * cos_omega);
-                self.a2 = (a + 1.) - (a - 1.) * cos_omega - alpha_rt_a;
+                c.a1 = 2. * ((a - 1.) - (a + 1.) * cos_omega);
+                c.a2 = (a + 1.) - (a - 1.) * cos_omega - alpha_rt_a;
+            }
+        }
+        c.b0 = c.b0 / a0;
+        c.b1 = c.b1 / a0;
+        c.b2 = c.b2 / a0;
+        c.a1 = c.a1 / a0;
+        c.a2 = c.a2 / a0;
+        c
+    }
+
+    /// The response of the filter at each of `frequency_hz`
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-biquadfilternode-getfrequencyresponse
+    fn frequency_response(&self, frequency_hz: &[f32], sample_rate: f32) -> FrequencyResponse {
+        let nyquist = sample_rate / 2.;
+        let mut response = FrequencyResponse::with_capacity(frequency_hz.len());
+        for &f in frequency_hz {
+            if f < 0. || f > nyquist || f.is_nan() {
+                response.push(None);
+                continue;
             }
+            // z^-1 on the unit circle
+            let omega = -2. * PI * f as f64 / sample_rate as f64;
+            let z = Complex64::new(omega.cos(), omega.sin());
+            let numerator = self.b0 + z * (self.b1 + z * self.b2);
+            let denominator = 1. + z * (self.a1 + z * self.a2);
+            response.push(Some(numerator / denominator));
         }
-        self.b0 = self.b0 / a0;
-        self.b1 = self.b1 / a0;
-        self.b2 = self.b2 / a0;
-        self.a1 = self.a1 / a0;
-        self.a2 = self.a2 / a0;
+        response
     }
 }
 
+/// The response of a BiquadFilterNode with these options at each of
+/// `frequency_hz`, for a context running at `sample_rate`
+pub fn frequency_response(
+    options: &BiquadFilterNodeOptions,
+    sample_rate: f32,
+    frequency_hz: &[f32],
+) -> FrequencyResponse {
+    BiquadCoefficients::new(
+        options.filter,
+        options.frequency,
+        options.detune,
+        options.q,
+        options.gain,
+        sample_rate,
+    )
+    .frequency_response(frequency_hz, sample_rate)
+}
+
 impl AudioNodeEngine for BiquadFilterNode {
     fn node_type(&self) -> AudioNodeType {
         AudioNodeType::BiquadFilterNode
     }
 
+    fn is_actively_processing(&self) -> bool {
+        !self.state.iter().all(BiquadState::is_quiescent)
+    }
+
     fn process(&mut self, mut inputs: Chunk, info: &BlockInfo) -> Chunk {
         debug_assert!(inputs.len() == 1);
         self.state
@@ -371,9 +415,10 @@ impl AudioNodeEngine for BiquadFilterNode {
                 frame.mutate_with(|sample, chan| {
                     let state = &mut self.state[chan as usize];
                     let x0 = *sample as f64;
-                    let y0 = self.b0 * x0 + self.b1 * state.x1 + self.b2 * state.x2
-                        - self.a1 * state.y1
-                        - self.a2 * state.y2;
+                    let c = &self.coefficients;
+                    let y0 = c.b0 * x0 + c.b1 * state.x1 + c.b2 * state.x2
+                        - c.a1 * state.y1
+                        - c.a2 * state.y2;
                     *sample = y0 as f32;
                     state.update(x0, y0);
                 });
@@ -388,13 +433,22 @@ impl AudioNodeEngine for BiquadFilterNode {
         inputs
     }
 
-    fn get_param(&mut self, id: ParamType) -> &mut Param {
+    fn param_types(&self) -> Vec<ParamType> {
+        vec![
+            ParamType::Frequency,
+            ParamType::Detune,
+            ParamType::Q,
+            ParamType::Gain,
+        ]
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
         match id {
-            ParamType::Frequency => &mut self.frequency,
-            ParamType::Detune => &mut self.detune,
-            ParamType::Q => &mut self.q,
-            ParamType::Gain => &mut self.gain,
-            _ => panic!("Unknown param {:?} for BiquadFilterNode", id),
+            ParamType::Frequency => Some(&mut self.frequency),
+            ParamType::Detune => Some(&mut self.detune),
+            ParamType::Q => Some(&mut self.q),
+            ParamType::Gain => Some(&mut self.gain),
+            _ => None,
         }
     }
 
@@ -405,8 +459,68 @@ impl AudioNodeEngine for BiquadFilterNode {
                     self.filter = f;
                     self.update_coefficients(sample_rate);
                 }
+                BiquadFilterNodeMessage::GetFrequencyResponse(frequency_hz, tx) => {
+                    let response = self
+                        .coefficients
+                        .frequency_response(&frequency_hz, sample_rate);
+                    let _ = tx.send(response);
+                }
             },
             _ => (),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iir_filter_node::{self, IIRFilterNodeOptions};
+    use std::sync::Arc;
+
+    const RATE: f32 = 48000.;
+
+    #[test]
+    fn lowpass_response() {
+        let options = BiquadFilterNodeOptions {
+            frequency: 1000.,
+            ..Default::default()
+        };
+        let response = frequency_response(&options, RATE, &[0., RATE / 2., -1., RATE]);
+        assert!((response.magnitude[0] - 1.).abs() < 1e-6);
+        assert!(response.magnitude[1] < 1e-6);
+        assert!(response.magnitude[2].is_nan() && response.phase[2].is_nan());
+        assert!(response.magnitude[3].is_nan() && response.phase[3].is_nan());
+    }
+
+    #[test]
+    fn same_response_as_iir_filter() {
+        let options = BiquadFilterNodeOptions {
+            filter: FilterType::Peaking,
+            frequency: 2000.,
+            q: 3.,
+            gain: 6.,
+            ..Default::default()
+        };
+        let c = BiquadCoefficients::new(
+            options.filter,
+            options.frequency,
+            options.detune,
+            options.q,
+            options.gain,
+            RATE,
+        );
+        let iir = IIRFilterNodeOptions {
+            feedforward: Arc::new(vec![c.b0, c.b1, c.b2]),
+            feedback: Arc::new(vec![1., c.a1, c.a2]),
+        };
+        let frequencies: Vec<f32> = (0..24).map(|i| i as f32 * 1000.).collect();
+        let biquad = frequency_response(&options, RATE, &frequencies);
+        let iir = iir_filter_node::frequency_response(&iir, RATE, &frequencies);
+        for i in 0..frequencies.len() {
+            assert!((biquad.magnitude[i] - iir.magnitude[i]).abs() < 1e-5);
+            assert!((biquad.phase[i] - iir.phase[i]).abs() < 1e-5);
+        }
+        // 6dB of gain at the center frequency
+        assert!((biquad.magnitude[2] - 10f32.powf(6. / 20.)).abs() < 1e-4);
+    }
+}
@@ -2,15 +2,78 @@ use byte_slice_cast::*;
 use euclid::default::Vector3D;
 use graph::{PortIndex, PortKind};
 use node::ChannelInterpretation;
+use simd;
 use smallvec::SmallVec;
+use std::cell::Cell;
 use std::f32::consts::SQRT_2;
 use std::mem;
 use std::ops::*;
 
 // defined by spec
 // https://webaudio.github.io/web-audio-api/#render-quantum
-pub const FRAMES_PER_BLOCK: Tick = Tick(128);
-pub const FRAMES_PER_BLOCK_USIZE: usize = FRAMES_PER_BLOCK.0 as usize;
+pub const DEFAULT_FRAMES_PER_BLOCK: Tick = Tick(128);
+pub const DEFAULT_FRAMES_PER_BLOCK_USIZE: usize = DEFAULT_FRAMES_PER_BLOCK.0 as usize;
+
+/// The range of supported render quantum sizes. Sizes are powers of two,
+/// so that nodes working in the frequency domain can transform a whole
+/// number of blocks.
+pub const MIN_FRAMES_PER_BLOCK: u32 = 32;
+pub const MAX_FRAMES_PER_BLOCK: u32 = 4096;
+
+thread_local! {
+    static FRAMES_PER_BLOCK: Cell<u64> = Cell::new(DEFAULT_FRAMES_PER_BLOCK.0);
+}
+
+/// The render quantum size of the context rendering on this thread,
+/// or of the context whose nodes are being created on it
+///
+/// Silent blocks, which have no data, are this long.
+#[inline]
+pub fn frames_per_block() -> Tick {
+    Tick(FRAMES_PER_BLOCK.with(|frames| frames.get()))
+}
+
+#[inline]
+pub fn frames_per_block_usize() -> usize {
+    frames_per_block().0 as usize
+}
+
+/// The render quantum size used for a render size hint, which is the
+/// hint rounded up to a power of two in the supported range
+///
+/// https://webaudio.github.io/web-audio-api/#dom-audiocontextoptions-rendersizehint
+pub fn render_quantum_size(render_size_hint: Option<u32>) -> Tick {
+    match render_size_hint {
+        Some(frames) => {
+            let frames = frames
+                .max(MIN_FRAMES_PER_BLOCK)
+                .min(MAX_FRAMES_PER_BLOCK)
+                .next_power_of_two();
+            Tick(frames as u64)
+        }
+        None => DEFAULT_FRAMES_PER_BLOCK,
+    }
+}
+
+/// Set the render quantum size of this thread, for render threads
+pub(crate) fn set_frames_per_block(frames: Tick) {
+    FRAMES_PER_BLOCK.with(|f| f.set(frames.0));
+}
+
+/// Run `f` with the render quantum size of this thread set to `frames`,
+/// for creating the nodes of a context on the control thread
+pub(crate) fn with_frames_per_block<R, F: FnOnce() -> R>(frames: Tick, f: F) -> R {
+    struct Restore(Tick);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            set_frames_per_block(self.0);
+        }
+    }
+
+    let _restore = Restore(frames_per_block());
+    set_frames_per_block(frames);
+    f()
+}
 
 /// A tick, i.e. the time taken for a single frame
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
@@ -57,7 +120,7 @@ impl Chunk {
     }
 }
 
-/// We render audio in blocks of size FRAMES_PER_BLOCK
+/// We render audio in blocks of one render quantum, see `frames_per_block`
 ///
 /// A single block may contain multiple channels
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -71,8 +134,8 @@ pub struct Block {
     /// If this vector is empty, it is a shorthand for "silence"
     /// It is possible to obtain an explicitly silent buffer via .explicit_silence()
     ///
-    /// This must be of length channels * FRAMES_PER_BLOCK, unless `repeat` is true,
-    /// in which case it will be of length FRAMES_PER_BLOCK
+    /// This must be of length channels * frames, unless `repeat` is true,
+    /// in which case it will be of length frames
     buffer: Vec<f32>,
 }
 
@@ -102,7 +165,7 @@ impl Block {
         Block {
             channels,
             repeat: false,
-            buffer: vec![0.; FRAMES_PER_BLOCK_USIZE * channels as usize],
+            buffer: vec![0.; frames_per_block_usize() * channels as usize],
         }
     }
 
@@ -112,9 +175,31 @@ impl Block {
     }
 
     pub fn for_vec(buffer: Vec<f32>) -> Self {
-        assert!(buffer.len() % FRAMES_PER_BLOCK_USIZE == 0);
+        Self::for_vec_with_frames(buffer, frames_per_block_usize())
+    }
+
+    /// A block of planar `buffer`, with channels of `frames` frames, for
+    /// threads that don't know the render quantum size of the context
+    pub fn for_vec_with_frames(buffer: Vec<f32>, frames: usize) -> Self {
+        assert!(buffer.len() % frames == 0);
         Block {
-            channels: (buffer.len() / FRAMES_PER_BLOCK_USIZE) as u8,
+            channels: (buffer.len() / frames) as u8,
+            repeat: false,
+            buffer,
+        }
+    }
+
+    /// A block holding `channels`, which can be of any length, as long
+    /// as they all have the same one
+    pub fn for_channel_data(channels: Vec<Vec<f32>>) -> Self {
+        let frames = channels.first().map_or(0, |chan| chan.len());
+        assert!(channels.iter().all(|chan| chan.len() == frames));
+        let mut buffer = Vec::with_capacity(frames * channels.len());
+        for chan in &channels {
+            buffer.extend_from_slice(chan);
+        }
+        Block {
+            channels: channels.len() as u8,
             repeat: false,
             buffer,
         }
@@ -135,9 +220,7 @@ impl Block {
                 other.explicit_repeat();
             }
             debug_assert_eq!(self.buffer.len(), other.buffer.len());
-            for (a, b) in self.buffer.iter_mut().zip(other.buffer.iter()) {
-                *a += b
-            }
+            simd::add(&mut self.buffer, &other.buffer);
             self
         }
     }
@@ -145,7 +228,7 @@ impl Block {
     /// If this is in "silence" mode without a buffer, allocate a silent buffer
     pub fn explicit_silence(&mut self) {
         if self.buffer.is_empty() {
-            self.buffer.resize(FRAMES_PER_BLOCK_USIZE, 0.);
+            self.buffer.resize(frames_per_block_usize(), 0.);
             self.repeat = true;
         }
     }
@@ -158,9 +241,8 @@ impl Block {
 
     pub fn explicit_repeat(&mut self) {
         if self.repeat {
-            debug_assert!(self.buffer.len() == FRAMES_PER_BLOCK_USIZE);
             if self.channels > 1 {
-                let mut new = Vec::with_capacity(FRAMES_PER_BLOCK_USIZE * self.channels as usize);
+                let mut new = Vec::with_capacity(self.buffer.len() * self.channels as usize);
                 for _ in 0..self.channels {
                     new.extend(&self.buffer)
                 }
@@ -170,14 +252,30 @@ impl Block {
             self.repeat = false;
         } else if self.is_silence() {
             self.buffer
-                .resize(FRAMES_PER_BLOCK_USIZE * self.channels as usize, 0.);
+                .resize(frames_per_block_usize() * self.channels as usize, 0.);
+        }
+    }
+
+    /// The number of frames of each channel
+    ///
+    /// Blocks with data know their length, so that they can be used on
+    /// other threads, silent blocks are one render quantum long.
+    #[inline]
+    pub fn frames(&self) -> usize {
+        if self.buffer.is_empty() {
+            frames_per_block_usize()
+        } else if self.repeat {
+            self.buffer.len()
+        } else {
+            self.buffer.len() / self.channels as usize
         }
     }
 
     pub fn data_chan_mut(&mut self, chan: u8) -> &mut [f32] {
         self.explicit_repeat();
-        let start = chan as usize * FRAMES_PER_BLOCK_USIZE;
-        &mut self.buffer[start..start + FRAMES_PER_BLOCK_USIZE]
+        let frames = self.frames();
+        let start = chan as usize * frames;
+        &mut self.buffer[start..start + frames]
     }
 
     #[inline]
@@ -186,12 +284,13 @@ impl Block {
             !self.is_silence(),
             "data_chan doesn't work with silent buffers"
         );
+        let frames = self.frames();
         let offset = if self.repeat {
             0
         } else {
-            chan as usize * FRAMES_PER_BLOCK_USIZE
+            chan as usize * frames
         };
-        &self.buffer[offset..offset + FRAMES_PER_BLOCK_USIZE]
+        &self.buffer[offset..offset + frames]
     }
 
     pub fn take(&mut self) -> Block {
@@ -227,7 +326,7 @@ impl Block {
     pub fn push_chan(&mut self, data: &[f32]) {
         assert!(!self.repeat);
         assert!(!self.is_silence() || self.channels == 0);
-        assert!(data.len() == FRAMES_PER_BLOCK_USIZE);
+        assert!(data.len() == self.frames());
         self.buffer.extend(data);
         self.channels += 1;
     }
@@ -247,6 +346,7 @@ impl Block {
             self.channels = channels;
             return;
         }
+        let frames = self.frames();
 
         if interpretation == ChannelInterpretation::Discrete {
             // discrete downmixes by truncation, upmixes by adding
@@ -287,9 +387,9 @@ impl Block {
                     self.resize_silence(4);
                 }
                 (1, 6) => {
-                    let mut v = Vec::with_capacity(channels as usize * FRAMES_PER_BLOCK_USIZE);
+                    let mut v = Vec::with_capacity(channels as usize * frames);
                     // output.{L, R} = 0
-                    v.resize(2 * FRAMES_PER_BLOCK_USIZE, 0.);
+                    v.resize(2 * frames, 0.);
                     // output.C = input
                     v.extend(&self.buffer);
                     self.buffer = v;
@@ -312,13 +412,13 @@ impl Block {
                     // a `repeat` quad block should be rare
                     self.explicit_repeat();
 
-                    let mut v = Vec::with_capacity(6 * FRAMES_PER_BLOCK_USIZE);
+                    let mut v = Vec::with_capacity(6 * frames);
                     // output.{L, R} = input.{L, R}
-                    v.extend(&self.buffer[0..2 * FRAMES_PER_BLOCK_USIZE]);
+                    v.extend(&self.buffer[0..2 * frames]);
                     // output.{C, LFE} = 0
-                    v.resize(4 * FRAMES_PER_BLOCK_USIZE, 0.);
+                    v.resize(4 * frames, 0.);
                     // output.{SL, R} = input.{SL, SR}
-                    v.extend(&self.buffer[2 * FRAMES_PER_BLOCK_USIZE..]);
+                    v.extend(&self.buffer[2 * frames..]);
                     self.buffer = v;
                     self.channels = channels;
                 }
@@ -328,49 +428,34 @@ impl Block {
 
                 // mono
                 (2, 1) => {
-                    let mut v = Vec::with_capacity(FRAMES_PER_BLOCK_USIZE);
-                    for frame in 0..FRAMES_PER_BLOCK_USIZE {
-                        // output = 0.5 * (input.L + input.R);
-                        let o =
-                            0.5 * (self.data_chan_frame(frame, 0) + self.data_chan_frame(frame, 1));
-                        v.push(o);
-                    }
+                    let mut v = vec![0.; frames];
+                    // output = 0.5 * (input.L + input.R);
+                    simd::sum2_scaled(&mut v, self.data_chan(0), self.data_chan(1), 0.5);
                     self.buffer = v;
                     self.channels = 1;
                     self.repeat = false;
                 }
                 (4, 1) => {
-                    let mut v = Vec::with_capacity(FRAMES_PER_BLOCK_USIZE);
-                    for frame in 0..FRAMES_PER_BLOCK_USIZE {
-                        // output = 0.5 * (input.L + input.R + input.SL + input.SR);
-                        let o = 0.25
-                            * (self.data_chan_frame(frame, 0)
-                                + self.data_chan_frame(frame, 1)
-                                + self.data_chan_frame(frame, 2)
-                                + self.data_chan_frame(frame, 3));
-                        v.push(o);
-                    }
+                    let mut v = vec![0.; frames];
+                    // output = 0.5 * (input.L + input.R + input.SL + input.SR);
+                    let input = [
+                        self.data_chan(0),
+                        self.data_chan(1),
+                        self.data_chan(2),
+                        self.data_chan(3),
+                    ];
+                    simd::sum4_scaled(&mut v, input, 0.25);
                     self.buffer = v;
                     self.channels = 1;
                     self.repeat = false;
                 }
                 (6, 1) => {
-                    let mut v = Vec::with_capacity(FRAMES_PER_BLOCK_USIZE);
-                    for frame in 0..FRAMES_PER_BLOCK_USIZE {
-                        // output = sqrt(0.5) * (input.L + input.R) + input.C + 0.5 * (input.SL + input.SR)
-                        let o =
-                            // sqrt(0.5) * (input.L + input.R)
-                            SQRT_2 * (self.data_chan_frame(frame, 0) +
-                                      self.data_chan_frame(frame, 1)) +
-                            // input.C
-                            self.data_chan_frame(frame, 2) +
-                            // (ignore LFE)
-                            // + 0 * self.buffer[frame + 3 * FRAMES_PER_BLOCK_USIZE]
-                            // 0.5 * (input.SL + input.SR)
-                            0.5 * (self.data_chan_frame(frame, 4) +
-                                   self.data_chan_frame(frame, 5));
-                        v.push(o);
-                    }
+                    let mut v = vec![0.; frames];
+                    // output = sqrt(0.5) * (input.L + input.R) + input.C + 0.5 * (input.SL + input.SR)
+                    simd::sum2_scaled(&mut v, self.data_chan(0), self.data_chan(1), SQRT_2);
+                    simd::add(&mut v, self.data_chan(2));
+                    // (ignore LFE)
+                    simd::add_sum2_scaled(&mut v, self.data_chan(4), self.data_chan(5), 0.5);
                     self.buffer = v;
                     self.channels = 1;
                     self.repeat = false;
@@ -378,32 +463,28 @@ impl Block {
 
                 // stereo
                 (4, 2) => {
-                    let mut v = Vec::with_capacity(2 * FRAMES_PER_BLOCK_USIZE);
-                    v.resize(2 * FRAMES_PER_BLOCK_USIZE, 0.);
-                    for frame in 0..FRAMES_PER_BLOCK_USIZE {
+                    let mut v = vec![0.; 2 * frames];
+                    {
+                        let (l, r) = v.split_at_mut(frames);
                         // output.L = 0.5 * (input.L + input.SL)
-                        v[frame] =
-                            0.5 * (self.data_chan_frame(frame, 0) + self.data_chan_frame(frame, 2));
+                        simd::sum2_scaled(l, self.data_chan(0), self.data_chan(2), 0.5);
                         // output.R = 0.5 * (input.R + input.SR)
-                        v[frame + FRAMES_PER_BLOCK_USIZE] =
-                            0.5 * (self.data_chan_frame(frame, 1) + self.data_chan_frame(frame, 3));
+                        simd::sum2_scaled(r, self.data_chan(1), self.data_chan(3), 0.5);
                     }
                     self.buffer = v;
                     self.channels = 2;
                     self.repeat = false;
                 }
                 (6, 2) => {
-                    let mut v = Vec::with_capacity(2 * FRAMES_PER_BLOCK_USIZE);
-                    v.resize(2 * FRAMES_PER_BLOCK_USIZE, 0.);
-                    for frame in 0..FRAMES_PER_BLOCK_USIZE {
+                    let mut v = Vec::with_capacity(2 * frames);
+                    v.extend(self.data_chan(0));
+                    v.extend(self.data_chan(1));
+                    {
+                        let (l, r) = v.split_at_mut(frames);
                         // output.L = L + sqrt(0.5) * (input.C + input.SL)
-                        v[frame] = self.data_chan_frame(frame, 0)
-                            + SQRT_2
-                                * (self.data_chan_frame(frame, 2) + self.data_chan_frame(frame, 4));
+                        simd::add_sum2_scaled(l, self.data_chan(2), self.data_chan(4), SQRT_2);
                         // output.R = R + sqrt(0.5) * (input.C + input.SR)
-                        v[frame + FRAMES_PER_BLOCK_USIZE] = self.data_chan_frame(frame, 1)
-                            + SQRT_2
-                                * (self.data_chan_frame(frame, 2) + self.data_chan_frame(frame, 5));
+                        simd::add_sum2_scaled(r, self.data_chan(2), self.data_chan(5), SQRT_2);
                     }
                     self.buffer = v;
                     self.channels = 2;
@@ -412,20 +493,19 @@ impl Block {
 
                 // quad
                 (6, 4) => {
-                    let mut v = Vec::with_capacity(6 * FRAMES_PER_BLOCK_USIZE);
-                    v.resize(6 * FRAMES_PER_BLOCK_USIZE, 0.);
-                    for frame in 0..FRAMES_PER_BLOCK_USIZE {
+                    let mut v = Vec::with_capacity(4 * frames);
+                    v.extend(self.data_chan(0));
+                    v.extend(self.data_chan(1));
+                    {
+                        let (l, r) = v.split_at_mut(frames);
                         // output.L = L + sqrt(0.5) * input.C
-                        v[frame] = self.data_chan_frame(frame, 0)
-                            + SQRT_2 * self.data_chan_frame(frame, 2);
+                        simd::add_scaled(l, self.data_chan(2), SQRT_2);
                         // output.R = R + sqrt(0.5) * input.C
-                        v[frame + FRAMES_PER_BLOCK_USIZE] = self.data_chan_frame(frame, 1)
-                            + SQRT_2 * self.data_chan_frame(frame, 2);
-                        // output.SL = input.SL
-                        v[frame + 2 * FRAMES_PER_BLOCK_USIZE] = self.data_chan_frame(frame, 4);
-                        // output.SR = input.SR
-                        v[frame + 3 * FRAMES_PER_BLOCK_USIZE] = self.data_chan_frame(frame, 5);
+                        simd::add_scaled(r, self.data_chan(2), SQRT_2);
                     }
+                    // output.{SL, SR} = input.{SL, SR}
+                    v.extend(self.data_chan(4));
+                    v.extend(self.data_chan(5));
                     self.buffer = v;
                     self.channels = 4;
                     self.repeat = false;
@@ -443,9 +523,9 @@ impl Block {
 
     /// Resize to add or remove channels, fill extra channels with silence
     pub fn resize_silence(&mut self, channels: u8) {
+        let frames = self.frames();
         self.explicit_repeat();
-        self.buffer
-            .resize(FRAMES_PER_BLOCK_USIZE * channels as usize, 0.);
+        self.buffer.resize(frames * channels as usize, 0.);
         self.channels = channels;
     }
 
@@ -461,15 +541,9 @@ impl Block {
 
     pub fn interleave(&mut self) -> Vec<f32> {
         self.explicit_repeat();
-        let mut vec = Vec::with_capacity(self.buffer.len());
-        // FIXME this isn't too efficient
-        vec.resize(self.buffer.len(), 0.);
-        for frame in 0..FRAMES_PER_BLOCK_USIZE {
-            let channels = self.channels as usize;
-            for chan in 0..channels {
-                vec[frame * channels + chan] = self.buffer[chan * FRAMES_PER_BLOCK_USIZE + frame]
-            }
-        }
+        let mut vec = vec![0.; self.buffer.len()];
+        let frames = self.frames();
+        simd::interleave(&mut vec, &self.buffer, self.channels as usize, frames);
         vec
     }
 
@@ -504,6 +578,7 @@ impl Block {
 /// An iterator over frames in a block
 pub struct FrameIterator<'a> {
     frame: Tick,
+    frames: Tick,
     block: &'a mut Block,
 }
 
@@ -512,6 +587,7 @@ impl<'a> FrameIterator<'a> {
     pub fn new(block: &'a mut Block) -> Self {
         FrameIterator {
             frame: Tick(0),
+            frames: Tick(block.frames() as u64),
             block,
         }
     }
@@ -524,10 +600,11 @@ impl<'a> FrameIterator<'a> {
     #[inline]
     pub fn next<'b>(&'b mut self) -> Option<FrameRef<'b>> {
         let curr = self.frame;
-        if curr < FRAMES_PER_BLOCK {
+        if curr < self.frames {
             self.frame.advance();
             Some(FrameRef {
                 frame: curr,
+                frames: self.frames.0 as usize,
                 block: &mut self.block,
             })
         } else {
@@ -539,6 +616,7 @@ impl<'a> FrameIterator<'a> {
 /// A reference to a frame
 pub struct FrameRef<'a> {
     frame: Tick,
+    frames: usize,
     block: &'a mut Block,
 }
 
@@ -571,8 +649,7 @@ impl<'a> FrameRef<'a> {
         } else {
             for chan in 0..self.block.channels {
                 f(
-                    &mut self.block.buffer
-                        [chan as usize * FRAMES_PER_BLOCK_USIZE + self.frame.0 as usize],
+                    &mut self.block.buffer[chan as usize * self.frames + self.frame.0 as usize],
                     chan,
                 )
             }
@@ -645,8 +722,14 @@ impl Div<f64> for Tick {
 }
 
 impl Tick {
-    pub const FRAMES_PER_BLOCK: Tick = FRAMES_PER_BLOCK;
     const EPSILON: f64 = 1e-7;
+
+    /// The render quantum size, see `frames_per_block`
+    #[inline]
+    pub fn frames_per_block() -> Tick {
+        frames_per_block()
+    }
+
     pub fn from_time(time: f64, rate: f32) -> Tick {
         Tick((time * rate as f64 - Tick::EPSILON).ceil() as u64)
     }
@@ -655,3 +738,65 @@ impl Tick {
         self.0 += 1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(channels: usize) -> Block {
+        let frames = frames_per_block_usize();
+        Block::for_vec(
+            (0..channels * frames)
+                .map(|i| ((i * 7919) % 1000) as f32 / 500. - 1.)
+                .collect(),
+        )
+    }
+
+    /// The speaker down-mix matrix, frame by frame
+    fn downmix_frame(input: &[f32], channels: u8) -> Vec<f32> {
+        match (input.len(), channels) {
+            (2, 1) => vec![0.5 * (input[0] + input[1])],
+            (4, 1) => vec![0.25 * (input[0] + input[1] + input[2] + input[3])],
+            (6, 1) => {
+                vec![SQRT_2 * (input[0] + input[1]) + input[2] + 0.5 * (input[4] + input[5])]
+            }
+            (4, 2) => vec![0.5 * (input[0] + input[2]), 0.5 * (input[1] + input[3])],
+            (6, 2) => vec![
+                input[0] + SQRT_2 * (input[2] + input[4]),
+                input[1] + SQRT_2 * (input[2] + input[5]),
+            ],
+            (6, 4) => vec![
+                input[0] + SQRT_2 * input[2],
+                input[1] + SQRT_2 * input[2],
+                input[4],
+                input[5],
+            ],
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn speaker_downmix_matches_matrix() {
+        let frames = frames_per_block_usize();
+        for &(from, to) in &[(2, 1), (4, 1), (6, 1), (4, 2), (6, 2), (6, 4)] {
+            let mut block = input(from);
+            let expected: Vec<Vec<f32>> = (0..frames)
+                .map(|frame| {
+                    let input: Vec<f32> = (0..from as u8)
+                        .map(|chan| block.data_chan_frame(frame, chan))
+                        .collect();
+                    downmix_frame(&input, to)
+                })
+                .collect();
+            block.mix(to, ChannelInterpretation::Speakers);
+            assert_eq!(block.chan_count(), to);
+            assert_eq!(block.frames(), frames);
+            for (frame, expected) in expected.iter().enumerate() {
+                for chan in 0..to {
+                    let actual = block.data_chan_frame(frame, chan);
+                    assert_eq!(actual.to_bits(), expected[chan as usize].to_bits());
+                }
+            }
+        }
+    }
+}
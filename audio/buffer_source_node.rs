@@ -1,7 +1,56 @@
-use block::{Block, Chunk, Tick, FRAMES_PER_BLOCK};
+use block::{frames_per_block, Block, Chunk, Tick};
+use context::AudioContextError;
 use node::{AudioNodeEngine, AudioScheduledSourceNodeMessage, BlockInfo, OnEndedCallback};
-use node::{AudioNodeType, ChannelInfo, ShouldPlay};
+use node::{AudioNodeType, ChannelInfo, ChannelInterpretation, ShouldPlay};
 use param::{Param, ParamType};
+use render_thread::Garbage;
+use resampler::{self, ResampleQuality};
+use std::cmp;
+use std::f64::consts::PI;
+use std::ops::Range;
+
+/// The number of zero crossings on each side of the sinc kernel
+const SINC_ZERO_CROSSINGS: f64 = 8.;
+/// How wide the kernel can get to filter out aliasing, in sample-frames
+const SINC_MAX_HALF_WIDTH: f64 = 64.;
+/// The number of table entries per zero crossing of the sinc, and per
+/// 1 / SINC_RESOLUTION of the window's half width
+const SINC_RESOLUTION: usize = 512;
+
+lazy_static! {
+    /// sinc(πx) for x from 0 to SINC_ZERO_CROSSINGS
+    static ref SINC_TABLE: Vec<f64> = table(SINC_ZERO_CROSSINGS as usize, |x| {
+        let x = PI * x;
+        if x == 0. {
+            1.
+        } else {
+            x.sin() / x
+        }
+    });
+    /// The Blackman window, from its center at 0 to its end at 1
+    static ref WINDOW_TABLE: Vec<f64> = table(1, |x| {
+        let x = PI * x;
+        0.42 + 0.5 * x.cos() + 0.08 * (2. * x).cos()
+    });
+}
+
+/// `f` sampled SINC_RESOLUTION times per unit from 0 to `len`, with a
+/// trailing zero so that lookups past the end can interpolate
+fn table<F: Fn(f64) -> f64>(len: usize, f: F) -> Vec<f64> {
+    (0..len * SINC_RESOLUTION + 1)
+        .map(|i| f(i as f64 / SINC_RESOLUTION as f64))
+        .chain(Some(0.))
+        .collect()
+}
+
+/// The linearly interpolated value of `table` at `x`, which must be
+/// positive and at most the table's length
+fn lookup(table: &[f64], x: f64) -> f64 {
+    let pos = x * SINC_RESOLUTION as f64;
+    let index = pos as usize;
+    let t = pos - index as f64;
+    (1. - t) * table[index] + t * table[index + 1]
+}
 
 /// Control messages directed to AudioBufferSourceNodes.
 #[derive(Debug, Clone)]
@@ -16,6 +65,29 @@ pub enum AudioBufferSourceNodeMessage {
     SetLoopStart(f64),
     /// Set start parameters (when, offset, duration).
     SetStartParams(f64, Option<f64>, Option<f64>),
+    /// Set the interpolation mode.
+    SetInterpolation(InterpolationMode),
+}
+
+/// How to compute the samples between the sample-frames of the buffer,
+/// when the playback rate isn't 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InterpolationMode {
+    /// Linear interpolation of the two nearest sample-frames. Cheap, but
+    /// aliases at high playback rates.
+    Linear,
+    /// Cubic Hermite (Catmull-Rom) interpolation of the four nearest
+    /// sample-frames.
+    Cubic,
+    /// Blackman windowed sinc interpolation, which also filters out what
+    /// would alias when playing faster than the buffer's rate.
+    Sinc,
+}
+
+impl Default for InterpolationMode {
+    fn default() -> Self {
+        InterpolationMode::Linear
+    }
 }
 
 /// This specifies options for constructing an AudioBufferSourceNode.
@@ -33,6 +105,8 @@ pub struct AudioBufferSourceNodeOptions {
     pub loop_start: Option<f64>,
     /// The initial value for the playback_rate AudioParam.
     pub playback_rate: f32,
+    /// How to interpolate the buffer.
+    pub interpolation: InterpolationMode,
 }
 
 impl Default for AudioBufferSourceNodeOptions {
@@ -44,6 +118,7 @@ impl Default for AudioBufferSourceNodeOptions {
             loop_end: None,
             loop_start: None,
             playback_rate: 1.,
+            interpolation: Default::default(),
         }
     }
 }
@@ -67,6 +142,8 @@ pub(crate) struct AudioBufferSourceNode {
     detune: Param,
     /// Whether we need to compute offsets from scratch.
     initialized_pos: bool,
+    /// How to interpolate the buffer.
+    interpolation: InterpolationMode,
     /// Indicates if the region of audio data designated by loopStart and loopEnd
     /// should be played continuously in a loop.
     loop_enabled: bool,
@@ -93,20 +170,38 @@ pub(crate) struct AudioBufferSourceNode {
     stop_at: Option<Tick>,
     /// The ended event callback.
     pub onended_callback: Option<OnEndedCallback>,
+    /// Whether the source has ended, it won't play again.
+    ended: bool,
+    /// The buffer the last message replaced
+    garbage: Option<Garbage>,
 }
 
 impl AudioBufferSourceNode {
-    pub fn new(options: AudioBufferSourceNodeOptions, channel_info: ChannelInfo) -> Self {
+    pub fn new(
+        options: AudioBufferSourceNodeOptions,
+        channel_info: ChannelInfo,
+        sample_rate: f32,
+    ) -> Self {
+        let node = AudioNodeType::AudioBufferSourceNode;
+        // The interpolation mode can change while rendering
+        lazy_static::initialize(&SINC_TABLE);
+        lazy_static::initialize(&WINDOW_TABLE);
         Self {
             channel_info,
             buffer: options.buffer,
             buffer_pos: 0.,
-            detune: Param::new_krate(options.detune),
+            detune: Param::for_node(node, ParamType::Detune, options.detune, sample_rate),
             initialized_pos: false,
+            interpolation: options.interpolation,
             loop_enabled: options.loop_enabled,
             loop_end: options.loop_end,
             loop_start: options.loop_start,
-            playback_rate: Param::new_krate(options.playback_rate),
+            playback_rate: Param::for_node(
+                node,
+                ParamType::PlaybackRate,
+                options.playback_rate,
+                sample_rate,
+            ),
             buffer_duration: std::f64::INFINITY,
             start_at: None,
             start_offset: None,
@@ -114,13 +209,16 @@ impl AudioBufferSourceNode {
             start_when: 0.,
             stop_at: None,
             onended_callback: None,
+            ended: false,
+            garbage: None,
         }
     }
 
     pub fn handle_message(&mut self, message: AudioBufferSourceNodeMessage, _: f32) {
         match message {
             AudioBufferSourceNodeMessage::SetBuffer(buffer) => {
-                self.buffer = buffer;
+                let previous = ::std::mem::replace(&mut self.buffer, buffer);
+                self.garbage = previous.map(Garbage::Buffer);
             }
             // XXX(collares): To fully support dynamically updating loop bounds,
             // Must truncate self.buffer_pos if it is now outside the loop.
@@ -136,6 +234,9 @@ impl AudioBufferSourceNode {
                 self.start_offset = offset;
                 self.start_duration = duration;
             }
+            AudioBufferSourceNodeMessage::SetInterpolation(interpolation) => {
+                self.interpolation = interpolation
+            }
         }
     }
 }
@@ -145,6 +246,10 @@ impl AudioNodeEngine for AudioBufferSourceNode {
         AudioNodeType::AudioBufferSourceNode
     }
 
+    fn is_actively_processing(&self) -> bool {
+        self.start_at.is_some() && !self.ended
+    }
+
     fn input_count(&self) -> u32 {
         0
     }
@@ -242,7 +347,8 @@ impl AudioNodeEngine for AudioBufferSourceNode {
         // We will output at most this many frames (fewer if we run out of data).
         let frames_to_output = stop_at - start_at;
 
-        if self.loop_enabled && buffer_offset_per_tick.abs() < actual_loop_end - actual_loop_start {
+        if self.loop_enabled && buffer_offset_per_tick.abs() >= actual_loop_end - actual_loop_start
+        {
             // Refuse to output data in this extreme edge case.
             //
             // XXX(collares): There are two ways we could handle it:
@@ -255,14 +361,14 @@ impl AudioNodeEngine for AudioBufferSourceNode {
             return inputs;
         }
 
-        // Fast path for the case where we can just copy FRAMES_PER_BLOCK
-        // frames straight from the buffer.
-        if frames_to_output == FRAMES_PER_BLOCK.0 as usize
+        // Fast path for the case where we can just copy a whole render quantum
+        // straight from the buffer.
+        if frames_to_output == frames_per_block().0 as usize
             && forward
             && buffer_offset_per_tick == 1.
             && self.buffer_pos.trunc() == self.buffer_pos
-            && self.buffer_pos + (FRAMES_PER_BLOCK.0 as f64) <= actual_loop_end
-            && FRAMES_PER_BLOCK.0 as f64 <= self.buffer_duration
+            && self.buffer_pos + (frames_per_block().0 as f64) <= actual_loop_end
+            && frames_per_block().0 as f64 <= self.buffer_duration
         {
             let mut block = Block::empty();
             let pos = self.buffer_pos as usize;
@@ -272,8 +378,8 @@ impl AudioNodeEngine for AudioBufferSourceNode {
             }
 
             inputs.blocks.push(block);
-            self.buffer_pos += FRAMES_PER_BLOCK.0 as f64;
-            self.buffer_duration -= FRAMES_PER_BLOCK.0 as f64;
+            self.buffer_pos += frames_per_block().0 as f64;
+            self.buffer_duration -= frames_per_block().0 as f64;
         } else {
             // Slow path, with interpolation.
             let mut block = Block::default();
@@ -282,6 +388,18 @@ impl AudioNodeEngine for AudioBufferSourceNode {
 
             debug_assert!(buffer.chans() > 0);
 
+            // Interpolating across the loop's boundaries reads from its
+            // other end, so that it joins seamlessly.
+            let loop_frames = if self.loop_enabled {
+                Some((
+                    actual_loop_start.round() as usize,
+                    actual_loop_end.round() as usize,
+                ))
+            } else {
+                None
+            };
+            let step = buffer_offset_per_tick.abs();
+
             for chan in 0..buffer.chans() {
                 let data = block.data_chan_mut(chan);
                 let (_, data) = data.split_at_mut(start_at);
@@ -305,7 +423,14 @@ impl AudioNodeEngine for AudioBufferSourceNode {
                         break;
                     }
 
-                    *sample = buffer.interpolate(chan, pos);
+                    *sample = match loop_frames {
+                        Some(region) if pos >= actual_loop_start && pos < actual_loop_end => buffer
+                            .interpolate_with(self.interpolation, chan, pos, step, Some(region)),
+                        _ if self.interpolation == InterpolationMode::Linear => {
+                            buffer.interpolate(chan, pos)
+                        }
+                        _ => buffer.interpolate_with(self.interpolation, chan, pos, step, None),
+                    };
                     pos += buffer_offset_per_tick;
                     duration -= buffer_offset_per_tick.abs();
                 }
@@ -329,11 +454,15 @@ impl AudioNodeEngine for AudioBufferSourceNode {
         inputs
     }
 
-    fn get_param(&mut self, id: ParamType) -> &mut Param {
+    fn param_types(&self) -> Vec<ParamType> {
+        vec![ParamType::PlaybackRate, ParamType::Detune]
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
         match id {
-            ParamType::PlaybackRate => &mut self.playback_rate,
-            ParamType::Detune => &mut self.detune,
-            _ => panic!("Unknown param {:?} for AudioBufferSourceNode", id),
+            ParamType::PlaybackRate => Some(&mut self.playback_rate),
+            ParamType::Detune => Some(&mut self.detune),
+            _ => None,
         }
     }
 
@@ -341,6 +470,25 @@ impl AudioNodeEngine for AudioBufferSourceNode {
         AudioBufferSourceNode: handle_message,
         AudioScheduledSourceNode: handle_source_node_message
     );
+
+    fn take_garbage(&mut self) -> Option<Garbage> {
+        self.garbage.take()
+    }
+}
+
+/// Errors of the AudioBuffer editing methods
+#[derive(Clone, Debug, PartialEq)]
+pub enum AudioBufferError {
+    /// The buffer has no such channel
+    UnknownChannel(u8),
+    /// The range of sample-frames isn't inside the buffer
+    InvalidRange(Range<usize>),
+    /// The buffers don't have the same number of channels
+    ChannelCountMismatch(u8, u8),
+    /// The buffers don't have the same sample rate
+    SampleRateMismatch(f32, f32),
+    /// A buffer needs at least one channel
+    NoChannels,
 }
 
 #[derive(Debug, Clone)]
@@ -385,10 +533,27 @@ impl AudioBuffer {
         self.buffers.len() as u8
     }
 
+    /// This buffer at `sample_rate`, for playback in a context running
+    /// at a different rate than the one it was recorded at
+    pub fn resample(
+        &self,
+        sample_rate: f32,
+        quality: ResampleQuality,
+    ) -> Result<AudioBuffer, AudioContextError> {
+        let buffers = self
+            .buffers
+            .iter()
+            .map(|b| resampler::resample(b, self.sample_rate, sample_rate, quality))
+            .collect::<Result<_, _>>()?;
+        Ok(AudioBuffer {
+            buffers,
+            sample_rate,
+        })
+    }
+
     // XXX(collares): There are better fast interpolation algorithms.
-    // Firefox uses (via Speex's resampler) the algorithm described in
-    // https://ccrma.stanford.edu/~jos/resample/resample.pdf
-    // There are Rust bindings: https://github.com/rust-av/speexdsp-rs
+    // Buffers are resampled with Speex when they don't match the context's
+    // rate, but a playback rate other than 1 still uses this.
     pub fn interpolate(&self, chan: u8, pos: f64) -> f32 {
         debug_assert!(pos >= 0. && pos < self.len() as f64);
 
@@ -412,7 +577,302 @@ impl AudioBuffer {
         }
     }
 
+    /// The value at `pos`, with `mode`
+    ///
+    /// `step` is how far apart consecutive positions are, to filter out
+    /// what would alias. When `pos` is inside `loop_frames`, the samples
+    /// past one of its ends are read from the other. Samples outside of
+    /// the buffer are silent.
+    pub fn interpolate_with(
+        &self,
+        mode: InterpolationMode,
+        chan: u8,
+        pos: f64,
+        step: f64,
+        loop_frames: Option<(usize, usize)>,
+    ) -> f32 {
+        let index = pos.floor() as isize;
+        let t = pos - pos.floor();
+        let sample = |offset: isize| self.sample_at(chan, index + offset, loop_frames) as f64;
+        let value = match mode {
+            InterpolationMode::Linear => (1. - t) * sample(0) + t * sample(1),
+            InterpolationMode::Cubic => {
+                let (xm1, x0, x1, x2) = (sample(-1), sample(0), sample(1), sample(2));
+                let c1 = 0.5 * (x1 - xm1);
+                let c2 = xm1 - 2.5 * x0 + 2. * x1 - 0.5 * x2;
+                let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+                ((c3 * t + c2) * t + c1) * t + x0
+            }
+            InterpolationMode::Sinc => {
+                // Lower the cutoff below the new Nyquist frequency when
+                // skipping samples, widening the kernel to match.
+                let cutoff = if step > 1. { 1. / step } else { 1. };
+                let half_width = (SINC_ZERO_CROSSINGS / cutoff).min(SINC_MAX_HALF_WIDTH);
+                let taps = half_width.ceil() as isize;
+                let (sinc, window) = (&*SINC_TABLE, &*WINDOW_TABLE);
+                let (mut sum, mut weights) = (0., 0.);
+                for offset in (1 - taps)..(taps + 1) {
+                    let distance = (offset as f64 - t).abs();
+                    if distance >= half_width {
+                        continue;
+                    }
+                    let weight =
+                        lookup(sinc, distance * cutoff) * lookup(window, distance / half_width);
+                    sum += weight * sample(offset);
+                    weights += weight;
+                }
+                sum / weights
+            }
+        };
+        value as f32
+    }
+
+    fn sample_at(&self, chan: u8, index: isize, loop_frames: Option<(usize, usize)>) -> f32 {
+        let index = match loop_frames {
+            Some((start, end)) if end > start => {
+                let (start, len) = (start as isize, (end - start) as isize);
+                start + (index - start).rem_euclid(len)
+            }
+            _ => index,
+        };
+        if index < 0 {
+            return 0.;
+        }
+        self.buffers[chan as usize]
+            .get(index as usize)
+            .cloned()
+            .unwrap_or(0.)
+    }
+
     pub fn data_chan_mut(&mut self, chan: u8) -> &mut [f32] {
         &mut self.buffers[chan as usize]
     }
+
+    /// The duration of the buffer, in seconds
+    pub fn duration(&self) -> f64 {
+        self.len() as f64 / self.sample_rate as f64
+    }
+
+    /// The sample-frames of a channel, if the buffer has it
+    pub fn channel(&self, chan: u8) -> Option<&[f32]> {
+        self.buffers.get(chan as usize).map(|buf| &buf[..])
+    }
+
+    /// The sample-frames of a channel, if the buffer has it
+    pub fn channel_mut(&mut self, chan: u8) -> Option<&mut [f32]> {
+        self.buffers.get_mut(chan as usize).map(|buf| &mut buf[..])
+    }
+
+    /// Copy the sample-frames of `chan` from `offset` on to `dest`,
+    /// returning how many were copied
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-audiobuffer-copyfromchannel
+    pub fn copy_from_channel(
+        &self,
+        dest: &mut [f32],
+        chan: u8,
+        offset: usize,
+    ) -> Result<usize, AudioBufferError> {
+        let source = self
+            .channel(chan)
+            .ok_or(AudioBufferError::UnknownChannel(chan))?;
+        let source = source.get(offset..).unwrap_or(&[]);
+        let len = cmp::min(source.len(), dest.len());
+        dest[..len].copy_from_slice(&source[..len]);
+        Ok(len)
+    }
+
+    /// Copy `source` to the sample-frames of `chan` from `offset` on,
+    /// returning how many were copied
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-audiobuffer-copytochannel
+    pub fn copy_to_channel(
+        &mut self,
+        source: &[f32],
+        chan: u8,
+        offset: usize,
+    ) -> Result<usize, AudioBufferError> {
+        let dest = self
+            .channel_mut(chan)
+            .ok_or(AudioBufferError::UnknownChannel(chan))?;
+        let dest = dest.get_mut(offset..).unwrap_or(&mut []);
+        let len = cmp::min(source.len(), dest.len());
+        dest[..len].copy_from_slice(&source[..len]);
+        Ok(len)
+    }
+
+    /// A new buffer holding the sample-frames in `range`
+    pub fn slice(&self, range: Range<usize>) -> Result<AudioBuffer, AudioBufferError> {
+        if range.start > range.end || range.end > self.len() {
+            return Err(AudioBufferError::InvalidRange(range));
+        }
+        let buffers = self
+            .buffers
+            .iter()
+            .map(|buf| buf[range.clone()].to_vec())
+            .collect();
+        Ok(AudioBuffer::from_buffers(buffers, self.sample_rate))
+    }
+
+    /// Add the sample-frames of `other` at the end of this buffer
+    pub fn append(&mut self, other: &AudioBuffer) -> Result<(), AudioBufferError> {
+        if other.chans() != self.chans() {
+            return Err(AudioBufferError::ChannelCountMismatch(
+                self.chans(),
+                other.chans(),
+            ));
+        }
+        if other.sample_rate != self.sample_rate {
+            return Err(AudioBufferError::SampleRateMismatch(
+                self.sample_rate,
+                other.sample_rate,
+            ));
+        }
+        for (buf, other) in self.buffers.iter_mut().zip(&other.buffers) {
+            buf.extend_from_slice(other);
+        }
+        Ok(())
+    }
+
+    /// Reverse the order of the sample-frames
+    pub fn reverse(&mut self) {
+        for buf in &mut self.buffers {
+            buf.reverse();
+        }
+    }
+
+    /// Scale all channels so that the highest absolute sample is `peak`,
+    /// silent buffers are left as they are
+    pub fn normalize(&mut self, peak: f32) {
+        let max = self
+            .buffers
+            .iter()
+            .flat_map(|buf| buf.iter())
+            .fold(0f32, |max, sample| max.max(sample.abs()));
+        if max == 0. {
+            return;
+        }
+        let gain = peak / max;
+        for sample in self.buffers.iter_mut().flat_map(|buf| buf.iter_mut()) {
+            *sample *= gain;
+        }
+    }
+
+    /// Up or down mix to `channels`, following the same rules as the
+    /// inputs of audio nodes
+    ///
+    /// https://webaudio.github.io/web-audio-api/#channel-up-mixing-and-down-mixing
+    pub fn mix(
+        &mut self,
+        channels: u8,
+        interpretation: ChannelInterpretation,
+    ) -> Result<(), AudioBufferError> {
+        if channels == 0 {
+            return Err(AudioBufferError::NoChannels);
+        }
+        let len = self.len();
+        if len == 0 {
+            self.buffers.resize(channels as usize, Vec::new());
+            return Ok(());
+        }
+        let buffers = std::mem::take(&mut self.buffers);
+        let mut block = Block::for_channel_data(buffers);
+        block.mix(channels, interpretation);
+        self.buffers = (0..channels)
+            .map(|chan| block.data_chan(chan).to_vec())
+            .collect();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_with_offsets() {
+        let mut buffer = AudioBuffer::new(2, 4, 8000.);
+        assert_eq!(buffer.duration(), 0.0005);
+        assert_eq!(buffer.copy_to_channel(&[1., 2., 3.], 1, 2), Ok(2));
+        assert_eq!(buffer.channel(1), Some(&[0., 0., 1., 2.][..]));
+        assert_eq!(buffer.copy_to_channel(&[1.], 0, 5), Ok(0));
+        assert_eq!(
+            buffer.copy_to_channel(&[1.], 2, 0),
+            Err(AudioBufferError::UnknownChannel(2))
+        );
+
+        let mut dest = [9.; 3];
+        assert_eq!(buffer.copy_from_channel(&mut dest, 1, 1), Ok(3));
+        assert_eq!(dest, [0., 1., 2.]);
+        assert_eq!(buffer.copy_from_channel(&mut dest, 1, 3), Ok(1));
+        assert_eq!(dest, [2., 1., 2.]);
+        assert!(buffer.channel(2).is_none());
+    }
+
+    #[test]
+    fn edit_and_mix() {
+        let mut buffer = AudioBuffer::from_buffers(vec![vec![1., 2.], vec![3., 4.]], 8000.);
+        let tail = buffer.slice(1..2).unwrap();
+        buffer.append(&tail).unwrap();
+        assert_eq!(buffer.buffers, vec![vec![1., 2., 2.], vec![3., 4., 4.]]);
+        assert_eq!(
+            buffer.slice(2..4).unwrap_err(),
+            AudioBufferError::InvalidRange(2..4)
+        );
+        let mono = AudioBuffer::from_buffer(vec![0.], 8000.);
+        assert_eq!(
+            buffer.append(&mono),
+            Err(AudioBufferError::ChannelCountMismatch(2, 1))
+        );
+
+        buffer.reverse();
+        buffer.normalize(0.5);
+        assert_eq!(
+            buffer.buffers,
+            vec![vec![0.25, 0.25, 0.125], vec![0.5, 0.5, 0.375]]
+        );
+
+        // Speakers downmix stereo to mono by averaging
+        buffer.mix(1, ChannelInterpretation::Speakers).unwrap();
+        assert_eq!(buffer.buffers, vec![vec![0.375, 0.375, 0.25]]);
+        buffer.mix(2, ChannelInterpretation::Speakers).unwrap();
+        assert_eq!(buffer.chans(), 2);
+        assert_eq!(buffer.channel(1), buffer.channel(0));
+        buffer.mix(4, ChannelInterpretation::Discrete).unwrap();
+        assert_eq!(buffer.channel(3), Some(&[0., 0., 0.][..]));
+        assert_eq!(
+            buffer.mix(0, ChannelInterpretation::Discrete),
+            Err(AudioBufferError::NoChannels)
+        );
+    }
+
+    #[test]
+    fn loops_join_seamlessly() {
+        // One period of a sine, looped, should sound like several of them
+        const PERIOD: usize = 64;
+        let period = |periods: usize| {
+            let data = (0..PERIOD * periods)
+                .map(|i| (2. * PI * i as f64 / PERIOD as f64).sin() as f32)
+                .collect();
+            AudioBuffer::from_buffers(vec![data], 8000.)
+        };
+        let (looped, repeated) = (period(1), period(4));
+        for &mode in &[InterpolationMode::Cubic, InterpolationMode::Sinc] {
+            for &step in &[1., 3.] {
+                for &pos in &[0.25, 0.5, 62.7, 63.5, 63.9] {
+                    let value = looped.interpolate_with(mode, 0, pos, step, Some((0, PERIOD)));
+                    let expected =
+                        repeated.interpolate_with(mode, 0, pos + 2. * PERIOD as f64, step, None);
+                    assert!(
+                        (value - expected).abs() < 1e-5,
+                        "{:?} at {}: {} != {}",
+                        mode,
+                        pos,
+                        value,
+                        expected
+                    );
+                }
+            }
+        }
+    }
 }
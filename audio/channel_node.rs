@@ -1,5 +1,6 @@
-use block::FRAMES_PER_BLOCK_USIZE;
+use block::frames_per_block_usize;
 use block::{Block, Chunk};
+use log::warn;
 use node::AudioNodeType;
 use node::BlockInfo;
 use node::{AudioNodeEngine, ChannelCountMode, ChannelInfo, ChannelInterpretation};
@@ -38,7 +39,7 @@ impl AudioNodeEngine for ChannelMergerNode {
 
         for (i, channel) in block
             .data_mut()
-            .chunks_mut(FRAMES_PER_BLOCK_USIZE)
+            .chunks_mut(frames_per_block_usize())
             .enumerate()
         {
             channel.copy_from_slice(inputs.blocks[i].data_mut())
@@ -54,11 +55,11 @@ impl AudioNodeEngine for ChannelMergerNode {
     }
 
     fn set_channel_count_mode(&mut self, _: ChannelCountMode) {
-        panic!("channel merger nodes cannot have their mode changed");
+        warn!("channel merger nodes cannot have their mode changed");
     }
 
     fn set_channel_count(&mut self, _: u8) {
-        panic!("channel merger nodes cannot have their channel count changed");
+        warn!("channel merger nodes cannot have their channel count changed");
     }
 }
 
@@ -103,14 +104,14 @@ impl AudioNodeEngine for ChannelSplitterNode {
     }
 
     fn set_channel_count_mode(&mut self, _: ChannelCountMode) {
-        panic!("channel splitter nodes cannot have their mode changed");
+        warn!("channel splitter nodes cannot have their mode changed");
     }
 
     fn set_channel_interpretation(&mut self, _: ChannelInterpretation) {
-        panic!("channel splitter nodes cannot have their channel interpretation changed");
+        warn!("channel splitter nodes cannot have their channel interpretation changed");
     }
 
     fn set_channel_count(&mut self, _: u8) {
-        panic!("channel splitter nodes cannot have their channel count changed");
+        warn!("channel splitter nodes cannot have their channel count changed");
     }
 }
@@ -23,16 +23,27 @@ pub(crate) struct ConstantSourceNode {
     start_at: Option<Tick>,
     stop_at: Option<Tick>,
     onended_callback: Option<OnEndedCallback>,
+    ended: bool,
 }
 
 impl ConstantSourceNode {
-    pub fn new(options: ConstantSourceNodeOptions, channel_info: ChannelInfo) -> Self {
+    pub fn new(
+        options: ConstantSourceNodeOptions,
+        channel_info: ChannelInfo,
+        sample_rate: f32,
+    ) -> Self {
         Self {
             channel_info,
-            offset: Param::new(options.offset.into()),
+            offset: Param::for_node(
+                AudioNodeType::ConstantSourceNode,
+                ParamType::Offset,
+                options.offset,
+                sample_rate,
+            ),
             start_at: None,
             stop_at: None,
             onended_callback: None,
+            ended: false,
         }
     }
 
@@ -46,6 +57,10 @@ impl AudioNodeEngine for ConstantSourceNode {
         AudioNodeType::ConstantSourceNode
     }
 
+    fn is_actively_processing(&self) -> bool {
+        self.start_at.is_some() && !self.ended
+    }
+
     fn process(&mut self, mut inputs: Chunk, info: &BlockInfo) -> Chunk {
         debug_assert!(inputs.len() == 0);
 
@@ -82,10 +97,14 @@ impl AudioNodeEngine for ConstantSourceNode {
         0
     }
 
-    fn get_param(&mut self, id: ParamType) -> &mut Param {
+    fn param_types(&self) -> Vec<ParamType> {
+        vec![ParamType::Offset]
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
         match id {
-            ParamType::Offset => &mut self.offset,
-            _ => panic!("Unknown param {:?} for the offset", id),
+            ParamType::Offset => Some(&mut self.offset),
+            _ => None,
         }
     }
     make_message_handler!(AudioScheduledSourceNode: handle_source_node_message);
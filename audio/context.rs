@@ -1,14 +1,49 @@
+use analyser_node::AnalyserNode;
+use biquad_filter_node::BiquadFilterNode;
+use block::{render_quantum_size, with_frames_per_block, Tick};
+use boxfnonce::SendBoxFnOnce;
+use buffer_source_node::{AudioBuffer, AudioBufferSourceNode, AudioBufferSourceNodeMessage};
+use channel_node::{ChannelMergerNode, ChannelSplitterNode};
+use constant_source_node::ConstantSourceNode;
+use convolver_node::{ConvolverKernel, ConvolverNode, ConvolverNodeMessage};
 use decoder::{AudioDecoder, AudioDecoderCallbacks, AudioDecoderOptions};
-use graph::{AudioGraph, InputPort, NodeId, OutputPort, PortId};
-use node::{AudioNodeInit, AudioNodeMessage, ChannelInfo};
-use render_thread::AudioRenderThread;
-use render_thread::AudioRenderThreadMsg;
+use delay_node::DelayNode;
+use dynamics_compressor_node::DynamicsCompressorNode;
+use gain_node::GainNode;
+use graph::{AudioGraph, InputPort, NodeId, NodeIdAllocator, OutputPort, PortId, PortIndex};
+use graph_snapshot::GraphSnapshot;
+use hrtf::HrtfPanner;
+use iir_filter_node::IIRFilterNode;
+use log::warn;
+use media_element_source_node::MediaElementSourceNode;
+use media_stream_destination_node::MediaStreamDestinationNode;
+use media_stream_source_node::MediaStreamSourceNode;
+use meter_node::MeterNode;
+use node::{AudioNodeEngine, AudioNodeInit, AudioNodeMessage, AudioNodeType};
+use node::{ChannelCountMode, ChannelInfo};
+use oscillator_node::{OscillatorNode, OscillatorNodeMessage, OscillatorType};
+use panner_node::{PannerNode, PannerNodeMessage, PanningModel};
+use param::UserAutomationEvent;
+use param::{AutomationSnapshot, ParamDescriptor, ParamError, ParamEvent, ParamRate, ParamType};
+use render_capacity::RenderCapacityReport;
+use render_thread::{AudioRenderThread, AudioRenderThreadMsg, Garbage, NewNode, QUEUE_CAPACITY};
+use resampler::{self, ResampleQuality};
+use servo_media_streams::MediaStreamId;
 use servo_media_traits::{BackendMsg, ClientContextId, MediaInstance};
-use std::cell::Cell;
+use sink::{AudioSink, AudioSinkError, AudioSinkOutput};
+use spsc::{self, Consumer, Producer};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::{self, Sender};
 use std::sync::{Arc, Mutex};
-use std::thread::Builder;
-use AudioBackend;
+use std::thread::{Builder, Thread};
+use stereo_panner::StereoPannerNode;
+use wav_writer::RenderWriter;
+use wave_shaper_node::WaveShaperNode;
+use worklet_node::AudioWorkletNode;
+use {AudioBackend, AudioStreamReader};
 
 /// Describes the state of the audio context on the control thread.
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -25,6 +60,78 @@ pub enum ProcessingState {
 
 pub type StateChangeResult = Result<(), ()>;
 
+/// Why an operation on an audio context failed
+#[derive(Debug, PartialEq)]
+pub enum AudioContextError {
+    /// The context was closed, or its render thread is gone.
+    Closed,
+    /// This type of node can't be created.
+    UnsupportedNode,
+    /// There is no node with this id, or it was dropped.
+    UnknownNode(NodeId),
+    /// The node has no input or output with this index.
+    UnknownPort(NodeId, u32),
+    /// The node has no such param.
+    UnknownParam(NodeId, ParamType),
+    /// The param doesn't accept this automation.
+    Param(ParamError),
+    /// The audio sink of a MediaStreamDestinationNode couldn't be created.
+    Sink(AudioSinkError),
+    /// The options or the message aren't supported by the node, the
+    /// spec's NotSupportedError.
+    NotSupported(&'static str),
+    /// The node can't do this in its current state, or ever, the spec's
+    /// InvalidStateError.
+    InvalidState(&'static str),
+    /// A list in the options doesn't have the length it should, the
+    /// spec's IndexSizeError.
+    IndexSize(&'static str),
+}
+
+impl From<ParamError> for AudioContextError {
+    fn from(error: ParamError) -> Self {
+        AudioContextError::Param(error)
+    }
+}
+
+impl From<AudioSinkError> for AudioContextError {
+    fn from(error: AudioSinkError) -> Self {
+        AudioContextError::Sink(error)
+    }
+}
+
+/// What the control thread knows of a node, to check the operations
+/// on it before they reach the render thread
+struct NodeInfo {
+    node_type: AudioNodeType,
+    inputs: u32,
+    outputs: u32,
+    params: Vec<(ParamType, ParamDescriptor)>,
+}
+
+impl NodeInfo {
+    fn new(engine: &mut dyn AudioNodeEngine) -> Self {
+        let params = engine
+            .param_types()
+            .into_iter()
+            .filter_map(|param| Some((param, engine.get_param(param)?.descriptor())))
+            .collect();
+        NodeInfo {
+            node_type: engine.node_type(),
+            inputs: engine.input_count(),
+            outputs: engine.output_count(),
+            params,
+        }
+    }
+
+    fn param(&self, param: ParamType) -> Option<ParamDescriptor> {
+        self.params
+            .iter()
+            .find(|&&(p, _)| p == param)
+            .map(|&(_, descriptor)| descriptor)
+    }
+}
+
 /// Identify the type of playback, which affects tradeoffs between audio output
 /// and power consumption.
 #[derive(Copy, Clone)]
@@ -39,12 +146,22 @@ pub enum LatencyCategory {
 }
 
 /// User-specified options for a real time audio context.
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct RealTimeAudioContextOptions {
     /// Number of samples that will play in one second, measured in Hz.
     pub sample_rate: f32,
     /// Type of playback.
     pub latency_hint: LatencyCategory,
+    /// The id of the audio output device to play to, as enumerated by
+    /// the backend's `MediaDeviceMonitor`, or `None` for the default one.
+    /// If the device cannot be used, the default device is used instead.
+    pub sink_id: Option<String>,
+    /// The preferred render quantum size, in sample-frames, or `None`
+    /// for the default of 128. See `block::render_quantum_size`.
+    pub render_size_hint: Option<u32>,
+    /// How to resample decoded audio and buffers that don't match
+    /// `sample_rate`.
+    pub resample_quality: ResampleQuality,
 }
 
 impl Default for RealTimeAudioContextOptions {
@@ -52,6 +169,9 @@ impl Default for RealTimeAudioContextOptions {
         Self {
             sample_rate: 44100.,
             latency_hint: LatencyCategory::Interactive,
+            sink_id: None,
+            render_size_hint: None,
+            resample_quality: Default::default(),
         }
     }
 }
@@ -65,6 +185,12 @@ pub struct OfflineAudioContextOptions {
     pub length: usize,
     /// Number of samples that will be rendered in one second, measured in Hz.
     pub sample_rate: f32,
+    /// The preferred render quantum size, in sample-frames, or `None`
+    /// for the default of 128. See `block::render_quantum_size`.
+    pub render_size_hint: Option<u32>,
+    /// How to resample decoded audio and buffers that don't match
+    /// `sample_rate`.
+    pub resample_quality: ResampleQuality,
 }
 
 impl Default for OfflineAudioContextOptions {
@@ -73,6 +199,8 @@ impl Default for OfflineAudioContextOptions {
             channels: 1,
             length: 0,
             sample_rate: 44100.,
+            render_size_hint: None,
+            resample_quality: Default::default(),
         }
     }
 }
@@ -90,7 +218,7 @@ impl From<OfflineAudioContextOptions> for AudioContextOptions {
 }
 
 /// User-specified options for a real time or offline audio context.
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum AudioContextOptions {
     RealTimeAudioContext(RealTimeAudioContextOptions),
     OfflineAudioContext(OfflineAudioContextOptions),
@@ -103,6 +231,8 @@ impl Default for AudioContextOptions {
     }
 }
 
+type MakeSinkOutput = dyn Fn(Option<&str>) -> Result<AudioSinkOutput, AudioSinkError> + Send;
+
 /// Representation of an audio context on the control thread.
 pub struct AudioContext {
     /// Media instance ID.
@@ -111,17 +241,39 @@ pub struct AudioContext {
     client_context_id: ClientContextId,
     /// Owner backend communication channel.
     backend_chan: Arc<Mutex<Sender<BackendMsg>>>,
-    /// Rendering thread communication channel.
-    sender: Sender<AudioRenderThreadMsg>,
+    /// Rendering thread communication queue.
+    sender: Producer<AudioRenderThreadMsg>,
+    /// The rendering thread, to wake it up when queueing messages.
+    render_thread: Thread,
+    /// Values the rendering thread is done with, to be deallocated here.
+    garbage: Consumer<Garbage>,
+    /// Ids for the nodes created on this thread.
+    node_ids: RefCell<NodeIdAllocator>,
+    /// The nodes that can be used, until they are dropped.
+    nodes: RefCell<HashMap<NodeId, NodeInfo>>,
     /// State of the audio context on the control thread.
     state: Cell<ProcessingState>,
+    /// The last time the render thread reported, in seconds.
+    last_time: Cell<f64>,
+    /// How many times an offline context suspended itself, as counted
+    /// by the render thread when it calls the `suspend_at` callbacks.
+    self_suspensions: Arc<AtomicUsize>,
+    /// How many of these `state` already accounts for.
+    seen_suspensions: Cell<usize>,
     /// Number of samples that will be played in one second.
     sample_rate: f32,
+    /// The length of a render quantum, in sample-frames.
+    frames_per_block: Tick,
+    /// How to resample audio that doesn't match `sample_rate`.
+    resample_quality: ResampleQuality,
     /// The identifier of an AudioDestinationNode with a single input
     /// representing the final destination for all audio.
     dest_node: NodeId,
     listener: NodeId,
     make_decoder: Arc<(dyn Fn() -> Box<dyn AudioDecoder> + Sync + Send)>,
+    make_sink: Box<dyn Fn() -> Result<Box<dyn AudioSink>, AudioSinkError> + Send>,
+    make_sink_output: Box<MakeSinkOutput>,
+    make_stream_reader: Box<dyn Fn(MediaStreamId, f32) -> Box<dyn AudioStreamReader + Send> + Send>,
 }
 
 impl AudioContext {
@@ -132,42 +284,136 @@ impl AudioContext {
         backend_chan: Arc<Mutex<Sender<BackendMsg>>>,
         options: AudioContextOptions,
     ) -> Self {
-        let (sample_rate, channels) = match options {
-            AudioContextOptions::RealTimeAudioContext(ref options) => (options.sample_rate, 2),
-            AudioContextOptions::DummyAudioContext(ref options) => (options.sample_rate, 2),
-            AudioContextOptions::OfflineAudioContext(ref options) => {
-                (options.sample_rate, options.channels)
-            }
+        let (sample_rate, channels, render_size_hint, resample_quality) = match options {
+            AudioContextOptions::RealTimeAudioContext(ref options)
+            | AudioContextOptions::DummyAudioContext(ref options) => (
+                options.sample_rate,
+                2,
+                options.render_size_hint,
+                options.resample_quality,
+            ),
+            AudioContextOptions::OfflineAudioContext(ref options) => (
+                options.sample_rate,
+                options.channels,
+                options.render_size_hint,
+                options.resample_quality,
+            ),
         };
+        let frames_per_block = render_quantum_size(render_size_hint);
 
-        let (sender, receiver) = mpsc::channel();
-        let sender_ = sender.clone();
+        let (sender, receiver) = spsc::channel(QUEUE_CAPACITY);
+        let (garbage_sender, garbage) = spsc::channel(QUEUE_CAPACITY);
         let graph = AudioGraph::new(channels);
         let dest_node = graph.dest_id();
         let listener = graph.listener_id();
-        Builder::new()
+        let node_ids = NodeIdAllocator::new(&graph);
+        let mut nodes = HashMap::new();
+        for &id in &[dest_node, listener] {
+            let mut engine = graph.node_mut(id).expect("Graph nodes exist");
+            nodes.insert(id, NodeInfo::new(&mut *engine));
+        }
+        // Finding the output device can block, so it isn't done on
+        // the render thread
+        let output = match options {
+            AudioContextOptions::RealTimeAudioContext(ref options) => {
+                options.sink_id.as_ref().and_then(|sink_id| {
+                    B::make_sink_output(Some(sink_id))
+                        .map_err(|_| {
+                            warn!("Could not use audio output {}, using the default", sink_id)
+                        })
+                        .ok()
+                })
+            }
+            _ => None,
+        };
+        let render_thread = Builder::new()
             .name("AudioRenderThread".to_owned())
             .spawn(move || {
-                AudioRenderThread::start::<B>(receiver, sender_, sample_rate, graph, options);
+                AudioRenderThread::start::<B>(
+                    receiver,
+                    garbage_sender,
+                    sample_rate,
+                    frames_per_block,
+                    graph,
+                    options,
+                    output,
+                );
             })
-            .unwrap();
+            .unwrap()
+            .thread()
+            .clone();
         Self {
             id,
             client_context_id: *client_context_id,
             backend_chan,
             sender,
+            render_thread,
+            garbage,
+            node_ids: RefCell::new(node_ids),
+            nodes: RefCell::new(nodes),
             state: Cell::new(ProcessingState::Suspended),
+            last_time: Cell::new(0.),
+            self_suspensions: Arc::new(AtomicUsize::new(0)),
+            seen_suspensions: Cell::new(0),
             sample_rate,
+            frames_per_block,
+            resample_quality,
             dest_node,
             listener,
             make_decoder: Arc::new(|| B::make_decoder()),
+            make_sink: Box::new(|| B::make_sink().map(|s| Box::new(s) as Box<dyn AudioSink>)),
+            make_sink_output: Box::new(|sink_id| B::make_sink_output(sink_id)),
+            make_stream_reader: Box::new(|id, sample_rate| B::make_streamreader(id, sample_rate)),
+        }
+    }
+
+    /// Queue a message for the render thread and wake it up
+    ///
+    /// This never blocks on the render thread, unless the queue is full,
+    /// in which case this waits for the render thread to catch up. This
+    /// fails if the render thread is gone.
+    fn send(&self, msg: AudioRenderThreadMsg) -> Result<(), ()> {
+        self.collect_garbage();
+        let render_thread = &self.render_thread;
+        self.sender
+            .push_wait(msg, || render_thread.unpark())
+            .map_err(|_| ())?;
+        self.render_thread.unpark();
+        Ok(())
+    }
+
+    /// Deallocate what the render thread is done with, and reclaim
+    /// the ids of the nodes it removed
+    fn collect_garbage(&self) {
+        while let Some(garbage) = self.garbage.pop() {
+            match garbage {
+                Garbage::Node(id, engine) => {
+                    drop(engine);
+                    self.node_ids.borrow_mut().free(id);
+                }
+                garbage => drop(garbage),
+            }
         }
     }
 
     pub fn state(&self) -> ProcessingState {
+        self.sync_state();
         self.state.get()
     }
 
+    /// Account for the times an offline context suspended itself
+    /// since the last state change
+    fn sync_state(&self) {
+        let suspensions = self.self_suspensions.load(Ordering::SeqCst);
+        if suspensions == self.seen_suspensions.get() {
+            return;
+        }
+        self.seen_suspensions.set(suspensions);
+        if self.state.get() == ProcessingState::Running {
+            self.state.set(ProcessingState::Suspended);
+        }
+    }
+
     pub fn dest_node(&self) -> NodeId {
         self.dest_node
     }
@@ -176,18 +422,160 @@ impl AudioContext {
         self.listener
     }
 
+    /// The length of a render quantum, in sample-frames
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-baseaudiocontext-renderquantumsize
+    pub fn render_quantum_size(&self) -> usize {
+        self.frames_per_block.0 as usize
+    }
+
+    /// The time rendering is at, or was at when the render thread
+    /// stopped
     pub fn current_time(&self) -> f64 {
         let (tx, rx) = mpsc::channel();
-        let _ = self.sender.send(AudioRenderThreadMsg::GetCurrentTime(tx));
-        rx.recv().unwrap()
+        let _ = self.send(AudioRenderThreadMsg::GetCurrentTime(tx));
+        if let Ok(time) = rx.recv() {
+            self.last_time.set(time);
+        }
+        self.last_time.get()
     }
 
-    pub fn create_node(&self, node_type: AudioNodeInit, ch: ChannelInfo) -> NodeId {
+    /// Describe the render side graph, with the current values of
+    /// the params. This is meant for debugging, it can be serialized
+    /// with `GraphSnapshot::to_dot` and `GraphSnapshot::to_json`.
+    /// The snapshot is empty once the render thread is gone.
+    pub fn graph_snapshot(&self) -> GraphSnapshot {
         let (tx, rx) = mpsc::channel();
-        let _ = self
-            .sender
-            .send(AudioRenderThreadMsg::CreateNode(node_type, tx, ch));
-        rx.recv().unwrap()
+        let _ = self.send(AudioRenderThreadMsg::GetGraphSnapshot(tx));
+        rx.recv().unwrap_or_default()
+    }
+
+    /// Queue a message for the render thread, unless the context is closed
+    fn send_checked(&self, msg: AudioRenderThreadMsg) -> Result<(), AudioContextError> {
+        if self.state.get() == ProcessingState::Closed {
+            return Err(AudioContextError::Closed);
+        }
+        self.send(msg).map_err(|()| AudioContextError::Closed)
+    }
+
+    /// What is known of `node`, if it can be used
+    fn node_info<T, F>(&self, node: NodeId, f: F) -> Result<T, AudioContextError>
+    where
+        F: FnOnce(&NodeInfo) -> Result<T, AudioContextError>,
+    {
+        match self.nodes.borrow().get(&node) {
+            Some(info) => f(info),
+            None => Err(AudioContextError::UnknownNode(node)),
+        }
+    }
+
+    /// Create a node, without waiting for the render thread
+    ///
+    /// The node is built here, and added to the graph the next time
+    /// the render thread handles its messages.
+    pub fn create_node(
+        &self,
+        node_type: AudioNodeInit,
+        ch: ChannelInfo,
+    ) -> Result<NodeId, AudioContextError> {
+        self.collect_garbage();
+        let id = self.node_ids.borrow_mut().allocate();
+        // Nodes size their buffers after the render quantum
+        let node =
+            with_frames_per_block(self.frames_per_block, || self.make_node(id, node_type, ch))
+                .and_then(|mut node| {
+                    let info = NodeInfo::new(&mut *node.engine);
+                    self.send_checked(AudioRenderThreadMsg::AddNode(node))?;
+                    Ok(info)
+                });
+        match node {
+            Ok(info) => {
+                self.nodes.borrow_mut().insert(id, info);
+                Ok(id)
+            }
+            Err(error) => {
+                self.node_ids.borrow_mut().free(id);
+                Err(error)
+            }
+        }
+    }
+
+    fn make_node(
+        &self,
+        id: NodeId,
+        node_type: AudioNodeInit,
+        ch: ChannelInfo,
+    ) -> Result<NewNode, AudioContextError> {
+        let sample_rate = self.sample_rate;
+        let mut needs_listener = false;
+        let mut is_dest = false;
+        let engine: Box<dyn AudioNodeEngine> = match node_type {
+            AudioNodeInit::AnalyserNode(options) => Box::new(AnalyserNode::new(options, ch)),
+            AudioNodeInit::AudioBufferSourceNode(mut options) => {
+                options.buffer = self.match_sample_rate(options.buffer)?;
+                Box::new(AudioBufferSourceNode::new(options, ch, sample_rate))
+            }
+            AudioNodeInit::AudioWorkletNode(options) => {
+                options.validate()?;
+                Box::new(AudioWorkletNode::new(options, ch))
+            }
+            AudioNodeInit::BiquadFilterNode(options) => {
+                Box::new(BiquadFilterNode::new(options, ch, sample_rate))
+            }
+            AudioNodeInit::ConvolverNode(options) => Box::new(ConvolverNode::new(options, ch)?),
+            AudioNodeInit::DelayNode(options) => {
+                options.validate()?;
+                Box::new(DelayNode::new(options, ch, sample_rate))
+            }
+            AudioNodeInit::DynamicsCompressionNode(options) => {
+                Box::new(DynamicsCompressorNode::new(options, ch, sample_rate))
+            }
+            AudioNodeInit::GainNode(options) => Box::new(GainNode::new(options, ch, sample_rate)),
+            AudioNodeInit::StereoPannerNode(options) => {
+                Box::new(StereoPannerNode::new(options, ch, sample_rate))
+            }
+            AudioNodeInit::PannerNode(options) => {
+                needs_listener = true;
+                Box::new(PannerNode::new(options, ch, sample_rate))
+            }
+            AudioNodeInit::MediaStreamSourceNode(id) => {
+                let reader = (self.make_stream_reader)(id, sample_rate);
+                Box::new(MediaStreamSourceNode::new(reader, ch))
+            }
+            AudioNodeInit::MeterNode(options) => Box::new(MeterNode::new(options, ch, sample_rate)),
+            AudioNodeInit::OscillatorNode(options) => {
+                options.validate()?;
+                Box::new(OscillatorNode::new(options, ch, sample_rate))
+            }
+            AudioNodeInit::ChannelMergerNode(options) => {
+                Box::new(ChannelMergerNode::new(options, ch))
+            }
+            AudioNodeInit::ConstantSourceNode(options) => {
+                Box::new(ConstantSourceNode::new(options, ch, sample_rate))
+            }
+            AudioNodeInit::MediaStreamDestinationNode(socket) => {
+                is_dest = true;
+                Box::new(MediaStreamDestinationNode::new(
+                    socket,
+                    sample_rate,
+                    (self.make_sink)()?,
+                    ch,
+                ))
+            }
+            AudioNodeInit::ChannelSplitterNode => Box::new(ChannelSplitterNode::new(ch)),
+            AudioNodeInit::WaveShaperNode(options) => Box::new(WaveShaperNode::new(options, ch)),
+            AudioNodeInit::MediaElementSourceNode => Box::new(MediaElementSourceNode::new(ch)),
+            AudioNodeInit::IIRFilterNode(options) => Box::new(IIRFilterNode::new(options, ch)),
+            AudioNodeInit::AudioBuffer
+            | AudioNodeInit::PeriodicWave
+            | AudioNodeInit::ScriptProcessorNode => return Err(AudioContextError::UnsupportedNode),
+        };
+        Ok(NewNode {
+            id,
+            engine,
+            needs_listener,
+            is_dest,
+        })
     }
 
     // Resume audio processing.
@@ -196,68 +584,267 @@ impl AudioContext {
     // Suspend audio processing.
     make_state_change!(suspend, Suspended, Suspend);
 
-    // Stop audio processing and close render thread.
-    make_state_change!(close, Closed, Close);
+    /// Stop audio processing and close render thread, then deallocate
+    /// what it handed back on the way
+    pub fn close(&self) -> StateChangeResult {
+        self.state.set(ProcessingState::Closed);
+        let (tx, rx) = mpsc::channel();
+        let _ = self.send(AudioRenderThreadMsg::Close(tx));
+        let result = rx.recv().unwrap_or(Err(()));
+        self.collect_garbage();
+        result
+    }
 
-    pub fn message_node(&self, id: NodeId, msg: AudioNodeMessage) {
-        let _ = self.sender.send(AudioRenderThreadMsg::MessageNode(id, msg));
+    /// Tell the render thread that `node` is no longer used by the
+    /// control thread. The node keeps playing for as long as it can be
+    /// heard, for example until a source node ends, and is then removed
+    /// from the graph. `node` must not be used afterwards.
+    pub fn drop_node(&self, node: NodeId) {
+        if node != self.dest_node && node != self.listener {
+            self.nodes.borrow_mut().remove(&node);
+        }
+        let _ = self.send(AudioRenderThreadMsg::DropNode(node));
     }
 
-    pub fn connect_ports(&self, from: PortId<OutputPort>, to: PortId<InputPort>) {
-        let _ = self
-            .sender
-            .send(AudioRenderThreadMsg::ConnectPorts(from, to));
+    /// Send a message to a node, or fail if the node can't handle it
+    ///
+    /// Messages about params, automation events, channel configurations
+    /// and oscillator types are checked here, the render thread ignores
+    /// the ones that are invalid.
+    pub fn message_node(&self, id: NodeId, msg: AudioNodeMessage) -> Result<(), AudioContextError> {
+        self.check_message(id, &msg)?;
+        let msg = match msg {
+            AudioNodeMessage::AudioBufferSourceNode(AudioBufferSourceNodeMessage::SetBuffer(
+                buffer,
+            )) => AudioNodeMessage::AudioBufferSourceNode(AudioBufferSourceNodeMessage::SetBuffer(
+                self.match_sample_rate(buffer)?,
+            )),
+            AudioNodeMessage::ConvolverNode(ConvolverNodeMessage::SetBuffer(buffer)) => {
+                let kernel = match buffer {
+                    Some(ref buffer) => {
+                        Some(ConvolverKernel::new(buffer, self.render_quantum_size())?)
+                    }
+                    None => None,
+                };
+                AudioNodeMessage::ConvolverNode(ConvolverNodeMessage::SetKernel(kernel))
+            }
+            AudioNodeMessage::PannerNode(PannerNodeMessage::SetPanningModel(model)) => {
+                let hrtf = match model {
+                    PanningModel::HRTF => {
+                        Some(with_frames_per_block(self.frames_per_block, || {
+                            Box::new(HrtfPanner::new(self.sample_rate))
+                        }))
+                    }
+                    PanningModel::EqualPower => None,
+                };
+                AudioNodeMessage::PannerNode(PannerNodeMessage::SetPanner(model, hrtf))
+            }
+            AudioNodeMessage::SetParam(param, event) => {
+                AudioNodeMessage::InsertParamEvent(param, ParamEvent::new(event, self.sample_rate))
+            }
+            AudioNodeMessage::GetParamAutomation(param, query, tx) => {
+                let (snapshot_tx, snapshot_rx) = mpsc::channel();
+                let snapshot = Box::new(AutomationSnapshot::new());
+                let msg = AudioNodeMessage::SnapshotParamAutomation(param, snapshot, snapshot_tx);
+                self.send_checked(AudioRenderThreadMsg::MessageNode(id, msg))?;
+                // The render thread only copies the timeline, evaluating
+                // it can take a while
+                if let Ok(snapshot) = snapshot_rx.recv() {
+                    let _ = tx.send(snapshot.values(&query, self.sample_rate));
+                }
+                return Ok(());
+            }
+            msg => msg,
+        };
+        self.send_checked(AudioRenderThreadMsg::MessageNode(id, msg))
+    }
+
+    /// Check that `node` can be used and has the param `msg` is about,
+    /// if any, and that the param accepts the change
+    fn check_message(&self, node: NodeId, msg: &AudioNodeMessage) -> Result<(), AudioContextError> {
+        self.node_info(node, |info| {
+            Self::check_node_message(info.node_type, msg)?;
+            let param = match *msg {
+                AudioNodeMessage::GetParamValue(param, _)
+                | AudioNodeMessage::GetParamDescriptor(param, _)
+                | AudioNodeMessage::GetParamAutomation(param, _, _)
+                | AudioNodeMessage::SetParam(param, _)
+                | AudioNodeMessage::SetParamRate(param, _) => param,
+                _ => return Ok(()),
+            };
+            let descriptor = info
+                .param(param)
+                .ok_or(AudioContextError::UnknownParam(node, param))?;
+            match *msg {
+                AudioNodeMessage::SetParam(_, ref event) => event.validate()?,
+                AudioNodeMessage::GetParamAutomation(_, ref query, _) => query.validate()?,
+                AudioNodeMessage::SetParamRate(_, rate)
+                    if descriptor.fixed_rate && rate != descriptor.automation_rate =>
+                {
+                    return Err(ParamError::FixedAutomationRate.into());
+                }
+                _ => (),
+            }
+            Ok(())
+        })
+    }
+
+    /// Check the messages some types of nodes don't accept
+    fn check_node_message(
+        node_type: AudioNodeType,
+        msg: &AudioNodeMessage,
+    ) -> Result<(), AudioContextError> {
+        let fixed_channels = node_type == AudioNodeType::ChannelMergerNode
+            || node_type == AudioNodeType::ChannelSplitterNode;
+        let stereo = node_type == AudioNodeType::ConvolverNode
+            || node_type == AudioNodeType::DynamicsCompressionNode;
+        match *msg {
+            AudioNodeMessage::SetChannelCount(_) | AudioNodeMessage::SetChannelMode(_)
+                if fixed_channels =>
+            {
+                Err(AudioContextError::InvalidState(
+                    "Channel mergers and splitters have a fixed channel configuration",
+                ))
+            }
+            AudioNodeMessage::SetChannelInterpretation(_)
+                if node_type == AudioNodeType::ChannelSplitterNode =>
+            {
+                Err(AudioContextError::InvalidState(
+                    "Channel splitters have a fixed channel interpretation",
+                ))
+            }
+            AudioNodeMessage::SetChannelCount(count) if stereo && count > 2 => Err(
+                AudioContextError::NotSupported("This node can't have more than 2 channels"),
+            ),
+            AudioNodeMessage::SetChannelMode(ChannelCountMode::Max) if stereo => Err(
+                AudioContextError::NotSupported("This node can't use the max channel count mode"),
+            ),
+            AudioNodeMessage::OscillatorNode(OscillatorNodeMessage::SetOscillatorType(
+                OscillatorType::Custom,
+            )) => Err(AudioContextError::InvalidState(
+                "Use SetPeriodicWave for custom oscillators",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Resample a buffer for a source node to the context's rate here,
+    /// rather than on the render thread, so that it plays at the right
+    /// pitch without having to interpolate every sample
+    fn match_sample_rate(
+        &self,
+        buffer: Option<AudioBuffer>,
+    ) -> Result<Option<AudioBuffer>, AudioContextError> {
+        match buffer {
+            Some(ref buffer)
+                if resampler::needs_resampling(buffer.sample_rate, self.sample_rate) =>
+            {
+                buffer
+                    .resample(self.sample_rate, self.resample_quality)
+                    .map(Some)
+            }
+            buffer => Ok(buffer),
+        }
+    }
+
+    /// Schedule `event` on a param of `node`, or fail if the event
+    /// can't be scheduled
+    pub fn set_param(
+        &self,
+        node: NodeId,
+        param: ParamType,
+        event: UserAutomationEvent,
+    ) -> Result<(), AudioContextError> {
+        self.message_node(node, AudioNodeMessage::SetParam(param, event))
+    }
+
+    /// Change the automation rate of a param of `node`, or fail if it
+    /// is fixed
+    pub fn set_param_rate(
+        &self,
+        node: NodeId,
+        param: ParamType,
+        rate: ParamRate,
+    ) -> Result<(), AudioContextError> {
+        self.message_node(node, AudioNodeMessage::SetParamRate(param, rate))
+    }
+
+    /// The nominal range and default automation rate of a param of `node`
+    pub fn param_descriptor(
+        &self,
+        node: NodeId,
+        param: ParamType,
+    ) -> Result<ParamDescriptor, AudioContextError> {
+        self.node_info(node, |info| {
+            info.param(param)
+                .ok_or(AudioContextError::UnknownParam(node, param))
+        })
+    }
+
+    /// Connect an output of a node to an input or a param of another, or
+    /// fail if either doesn't exist
+    pub fn connect_ports(
+        &self,
+        from: PortId<OutputPort>,
+        to: PortId<InputPort>,
+    ) -> Result<(), AudioContextError> {
+        let output = from.node();
+        self.node_info(output, |info| match from.index() {
+            PortIndex::Port(port) if port < info.outputs => Ok(()),
+            PortIndex::Port(port) => Err(AudioContextError::UnknownPort(output, port)),
+            PortIndex::Param(void) | PortIndex::Listener(void) => match void {},
+        })?;
+        let input = to.node();
+        self.node_info(input, |info| match to.index() {
+            PortIndex::Port(port) if port < info.inputs => Ok(()),
+            PortIndex::Port(port) => Err(AudioContextError::UnknownPort(input, port)),
+            PortIndex::Param(param) => info
+                .param(param)
+                .map(|_| ())
+                .ok_or(AudioContextError::UnknownParam(input, param)),
+            // Only the render thread connects the listener
+            PortIndex::Listener(()) => Err(AudioContextError::UnknownPort(input, 0)),
+        })?;
+        self.send_checked(AudioRenderThreadMsg::ConnectPorts(from, to))
     }
 
     pub fn disconnect_all_from(&self, node: NodeId) {
-        let _ = self
-            .sender
-            .send(AudioRenderThreadMsg::DisconnectAllFrom(node));
+        let _ = self.send(AudioRenderThreadMsg::DisconnectAllFrom(node));
     }
 
     // /// Disconnect all outgoing connections from a node's output
     // ///
     // /// https://webaudio.github.io/web-audio-api/#dom-audionode-disconnect-output
     pub fn disconnect_output(&self, out: PortId<OutputPort>) {
-        let _ = self
-            .sender
-            .send(AudioRenderThreadMsg::DisconnectOutput(out));
+        let _ = self.send(AudioRenderThreadMsg::DisconnectOutput(out));
     }
 
     /// Disconnect connections from a node to another node
     ///
     /// https://webaudio.github.io/web-audio-api/#dom-audionode-disconnect-destinationnode
     pub fn disconnect_between(&self, from: NodeId, to: NodeId) {
-        let _ = self
-            .sender
-            .send(AudioRenderThreadMsg::DisconnectBetween(from, to));
+        let _ = self.send(AudioRenderThreadMsg::DisconnectBetween(from, to));
     }
 
     /// Disconnect connections from a node to another node's input
     ///
     /// https://webaudio.github.io/web-audio-api/#dom-audionode-disconnect-destinationparam
     pub fn disconnect_to(&self, from: NodeId, to: PortId<InputPort>) {
-        let _ = self
-            .sender
-            .send(AudioRenderThreadMsg::DisconnectTo(from, to));
+        let _ = self.send(AudioRenderThreadMsg::DisconnectTo(from, to));
     }
 
     /// Disconnect all outgoing connections from a node's output to another node
     ///
     /// https://webaudio.github.io/web-audio-api/#dom-audionode-disconnect-destinationnode-output
     pub fn disconnect_output_between(&self, out: PortId<OutputPort>, to: NodeId) {
-        let _ = self
-            .sender
-            .send(AudioRenderThreadMsg::DisconnectOutputBetween(out, to));
+        let _ = self.send(AudioRenderThreadMsg::DisconnectOutputBetween(out, to));
     }
 
     // /// Disconnect all outgoing connections from a node's output to another node's input
     // ///
     // /// https://webaudio.github.io/web-audio-api/#dom-audionode-disconnect-destinationnode-output-input
     pub fn disconnect_output_between_to(&self, out: PortId<OutputPort>, inp: PortId<InputPort>) {
-        let _ = self
-            .sender
-            .send(AudioRenderThreadMsg::DisconnectOutputBetweenTo(out, inp));
+        let _ = self.send(AudioRenderThreadMsg::DisconnectOutputBetweenTo(out, inp));
     }
 
     /// Asynchronously decodes the audio file data contained in the given
@@ -265,6 +852,7 @@ impl AudioContext {
     pub fn decode_audio_data(&self, data: Vec<u8>, callbacks: AudioDecoderCallbacks) {
         let mut options = AudioDecoderOptions::default();
         options.sample_rate = self.sample_rate;
+        options.resample_quality = self.resample_quality;
         let make_decoder = self.make_decoder.clone();
         Builder::new()
             .name("AudioDecoder".to_owned())
@@ -280,20 +868,135 @@ impl AudioContext {
         &self,
         callback: Box<dyn Fn(Box<dyn AsRef<[f32]>>) + Send + Sync + 'static>,
     ) {
-        let _ = self
-            .sender
-            .send(AudioRenderThreadMsg::SetSinkEosCallback(callback));
+        let _ = self.send(AudioRenderThreadMsg::SetSinkEosCallback(callback));
+    }
+
+    /// Report the progress of an offline context, `callback` is called
+    /// on the render thread with the number of frames rendered so far,
+    /// roughly every `interval` frames and once rendering is complete
+    ///
+    /// This does nothing for real time contexts.
+    pub fn set_progress_callback(
+        &self,
+        interval: usize,
+        callback: Box<dyn Fn(usize) + Send + Sync + 'static>,
+    ) {
+        let _ = self.send(AudioRenderThreadMsg::SetSinkProgressCallback(
+            interval, callback,
+        ));
+    }
+
+    /// Stream the output of an offline context to `writer`, for example
+    /// a `WavWriter`, instead of keeping it all in memory
+    ///
+    /// This must be called before rendering starts, otherwise `callback`
+    /// gets an error and the writer is dropped. `callback` is called
+    /// on the render thread once the whole render has been written,
+    /// and the EOS callback then gets an empty buffer.
+    pub fn set_render_writer<F: FnOnce(io::Result<()>) + Send + 'static>(
+        &self,
+        writer: Box<dyn RenderWriter>,
+        callback: F,
+    ) {
+        let _ = self.send(AudioRenderThreadMsg::SetSinkWriter(
+            writer,
+            SendBoxFnOnce::from(callback),
+        ));
+    }
+
+    /// Suspend an offline context when rendering reaches `suspend_time`,
+    /// rounded up to a render quantum boundary
+    ///
+    /// `callback` is called on the render thread once it is suspended,
+    /// and `state` reports it suspended from then on.
+    /// The graph can then be changed, and rendering continues from that
+    /// exact frame with `resume`. This fails for real time contexts, for
+    /// negative times, times that were already rendered or are past the
+    /// end, and if a suspension is already scheduled for that quantum.
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-offlineaudiocontext-suspend
+    pub fn suspend_at<F: FnOnce() + Send + 'static>(
+        &self,
+        suspend_time: f64,
+        callback: F,
+    ) -> StateChangeResult {
+        let self_suspensions = self.self_suspensions.clone();
+        let callback = move || {
+            self_suspensions.fetch_add(1, Ordering::SeqCst);
+            callback()
+        };
+        let (tx, rx) = mpsc::channel();
+        let _ = self.send(AudioRenderThreadMsg::SuspendAt(
+            suspend_time,
+            SendBoxFnOnce::new(callback),
+            tx,
+        ));
+        rx.recv().unwrap_or(Err(()))
+    }
+
+    /// The id of the audio output device, `None` being the default device
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-audiocontext-sinkid
+    pub fn sink_id(&self) -> Option<String> {
+        let (tx, rx) = mpsc::channel();
+        let _ = self.send(AudioRenderThreadMsg::GetSinkId(tx));
+        rx.recv().unwrap_or(None)
+    }
+
+    /// Switch the output to another audio output device, or to the
+    /// default one for `None`, without interrupting rendering. On error
+    /// the output stays on the current device.
+    ///
+    /// The device is found and its output created here, the render
+    /// thread only swaps it in.
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-audiocontext-setsinkid
+    pub fn set_sink_id(&self, sink_id: Option<String>) -> Result<(), AudioSinkError> {
+        let output = (self.make_sink_output)(sink_id.as_deref())?;
+        let (tx, rx) = mpsc::channel();
+        let _ = self.send(AudioRenderThreadMsg::SetSinkOutput(output, tx));
+        rx.recv().unwrap_or(Err(AudioSinkError::SetSinkIdFailed))
+    }
+
+    /// How long rendering takes compared to real time, since the
+    /// context started
+    pub fn render_capacity(&self) -> RenderCapacityReport {
+        let (tx, rx) = mpsc::channel();
+        let _ = self.send(AudioRenderThreadMsg::GetRenderCapacity(tx));
+        rx.recv().unwrap_or_default()
+    }
+
+    /// Call `callback` on the render thread with a report of the render
+    /// load every `update_interval` seconds of context time, replacing
+    /// any previous callback
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-audiorendercapacity-start
+    pub fn start_render_capacity(
+        &self,
+        update_interval: f64,
+        callback: Box<dyn Fn(RenderCapacityReport) + Send + Sync + 'static>,
+    ) {
+        let _ = self.send(AudioRenderThreadMsg::StartRenderCapacity(
+            update_interval,
+            callback,
+        ));
+    }
+
+    /// https://webaudio.github.io/web-audio-api/#dom-audiorendercapacity-stop
+    pub fn stop_render_capacity(&self) {
+        let _ = self.send(AudioRenderThreadMsg::StopRenderCapacity);
     }
 
     fn set_mute(&self, val: bool) {
-        let _ = self.sender.send(AudioRenderThreadMsg::SetMute(val));
+        let _ = self.send(AudioRenderThreadMsg::SetMute(val));
     }
 }
 
 impl Drop for AudioContext {
     fn drop(&mut self) {
         let (tx, _) = mpsc::channel();
-        let _ = self.sender.send(AudioRenderThreadMsg::Close(tx));
+        let _ = self.send(AudioRenderThreadMsg::Close(tx));
+        self.collect_garbage();
         let _ = self
             .backend_chan
             .lock()
@@ -314,15 +1017,11 @@ impl MediaInstance for AudioContext {
 
     fn suspend(&self) -> Result<(), ()> {
         let (tx, _) = mpsc::channel();
-        self.sender
-            .send(AudioRenderThreadMsg::Suspend(tx))
-            .map_err(|_| ())
+        self.send(AudioRenderThreadMsg::Suspend(tx)).map_err(|_| ())
     }
 
     fn resume(&self) -> Result<(), ()> {
         let (tx, _) = mpsc::channel();
-        self.sender
-            .send(AudioRenderThreadMsg::Resume(tx))
-            .map_err(|_| ())
+        self.send(AudioRenderThreadMsg::Resume(tx)).map_err(|_| ())
     }
 }
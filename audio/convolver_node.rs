@@ -0,0 +1,388 @@
+use block::{frames_per_block_usize, Block, Chunk};
+use buffer_source_node::AudioBuffer;
+use context::AudioContextError;
+use fft::Fft;
+use log::warn;
+use node::{AudioNodeEngine, AudioNodeType, BlockInfo};
+use node::{ChannelCountMode, ChannelInfo};
+use num_complex::Complex32;
+use render_thread::Garbage;
+
+/// The impulse response is split into partitions of one render quantum,
+/// which are zero padded to twice their length before transforming, so
+/// that the overlap-save output of a block does not wrap around
+fn fft_size(partition_len: usize) -> usize {
+    2 * partition_len
+}
+
+/// The number of non-redundant bins of a real FFT of `fft_size`
+fn bins(partition_len: usize) -> usize {
+    partition_len + 1
+}
+
+#[derive(Clone, Debug)]
+pub struct ConvolverNodeOptions {
+    /// The impulse response, which must have 1, 2 or 4 channels
+    pub buffer: Option<AudioBuffer>,
+    /// Whether the impulse response is scaled by an equal-power
+    /// normalization when it is set
+    pub normalize: bool,
+}
+
+impl Default for ConvolverNodeOptions {
+    fn default() -> Self {
+        ConvolverNodeOptions {
+            buffer: None,
+            normalize: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ConvolverNodeMessage {
+    /// The context turns this into a `SetKernel` message
+    SetBuffer(Option<AudioBuffer>),
+    /// The transformed impulse response and its delay lines, computed
+    /// by the context so that the render thread doesn't have to
+    SetKernel(Option<ConvolverKernel>),
+    /// Only affects buffers set after this message
+    SetNormalize(bool),
+}
+
+/// The frequency domain partitions of an impulse response
+#[derive(Clone, Debug)]
+pub struct ConvolverKernel {
+    /// For every channel, `partitions` spectra of `bins` bins laid out
+    /// one after the other
+    spectra: Vec<Vec<Complex32>>,
+    partitions: usize,
+    /// The length of the impulse response, in frames
+    len: usize,
+    /// The equal-power normalization scale of the impulse response
+    scale: f32,
+    /// The frequency domain delay line of each input channel of the
+    /// node using the kernel, holding the spectra of the last
+    /// `partitions` inputs, allocated here so that the render thread
+    /// doesn't have to
+    delay_lines: [Vec<Complex32>; 2],
+}
+
+impl ConvolverKernel {
+    /// Transform `buffer` into partitions of `partition_len` frames, or
+    /// fail if the node can't use it
+    pub(crate) fn new(
+        buffer: &AudioBuffer,
+        partition_len: usize,
+    ) -> Result<Self, AudioContextError> {
+        let chans = buffer.chans();
+        if chans != 1 && chans != 2 && chans != 4 {
+            return Err(AudioContextError::NotSupported(
+                "ConvolverNode buffer must have 1, 2 or 4 channels",
+            ));
+        }
+
+        let mut fft = Fft::new(fft_size(partition_len));
+        let len = buffer.len();
+        let partitions = (len + partition_len - 1) / partition_len;
+        let bins = bins(partition_len);
+        let mut padded = vec![0.; fft_size(partition_len)];
+        let spectra = buffer
+            .buffers
+            .iter()
+            .map(|data| {
+                let mut spectra = vec![Complex32::new(0., 0.); partitions * bins];
+                for (chunk, spectrum) in data.chunks(partition_len).zip(spectra.chunks_mut(bins)) {
+                    padded[..chunk.len()].copy_from_slice(chunk);
+                    for p in &mut padded[chunk.len()..] {
+                        *p = 0.;
+                    }
+                    fft.real_forward(&padded, spectrum);
+                }
+                spectra
+            })
+            .collect();
+
+        Ok(ConvolverKernel {
+            spectra,
+            partitions,
+            len,
+            scale: normalization_scale(buffer),
+            delay_lines: [
+                vec![Complex32::new(0., 0.); partitions * bins],
+                vec![Complex32::new(0., 0.); partitions * bins],
+            ],
+        })
+    }
+
+    fn chans(&self) -> usize {
+        self.spectra.len()
+    }
+}
+
+/// https://webaudio.github.io/web-audio-api/#calculatenormalizationscale
+fn normalization_scale(buffer: &AudioBuffer) -> f32 {
+    const GAIN_CALIBRATION: f32 = 0.00125;
+    const GAIN_CALIBRATION_SAMPLE_RATE: f32 = 44100.;
+    const MIN_POWER: f32 = 0.000125;
+
+    let power: f32 = buffer
+        .buffers
+        .iter()
+        .flat_map(|data| data.iter())
+        .map(|x| x * x)
+        .sum();
+    let mut power = (power / (buffer.chans() as usize * buffer.len()) as f32).sqrt();
+    if !power.is_finite() || power < MIN_POWER {
+        power = MIN_POWER;
+    }
+
+    let mut scale = GAIN_CALIBRATION / power;
+    scale *= GAIN_CALIBRATION_SAMPLE_RATE / buffer.sample_rate;
+    if buffer.chans() == 4 {
+        scale *= 0.5;
+    }
+    scale
+}
+
+/// https://webaudio.github.io/web-audio-api/#ConvolverNode
+///
+/// This is a uniformly partitioned convolution in the frequency domain:
+/// every input block is transformed once and kept in a frequency domain
+/// delay line, and each output block is the sum of the delay line entries
+/// multiplied with the corresponding impulse response partition,
+/// transformed back with overlap-save.
+///
+/// The input is at most stereo. Impulse responses with 4 channels are
+/// "true stereo", channels 0 and 1 are the responses of the left input
+/// to the left and right outputs, and channels 2 and 3 those of the right
+/// input.
+#[derive(AudioNodeCommon)]
+pub(crate) struct ConvolverNode {
+    channel_info: ChannelInfo,
+    normalize: bool,
+    kernel: Option<ConvolverKernel>,
+    /// The scale applied to the output, depending on whether the
+    /// kernel was normalized when it was set
+    scale: f32,
+    /// The render quantum size
+    partition_len: usize,
+    fft: Fft,
+    /// The last two input blocks of each input channel, in the time domain
+    inputs: [Vec<f32>; 2],
+    /// The partition of the delay lines that is written next
+    delay_line_index: usize,
+    /// The number of channels of the last non silent input
+    input_chans: u8,
+    /// How many silent frames have been input in a row, once this
+    /// exceeds the impulse response length the output is silent
+    silent_frames: usize,
+    spectrum: Vec<Complex32>,
+    output: Vec<f32>,
+    /// What the last message replaced
+    garbage: Option<Garbage>,
+}
+
+impl ConvolverNode {
+    pub fn new(
+        options: ConvolverNodeOptions,
+        channel_info: ChannelInfo,
+    ) -> Result<Self, AudioContextError> {
+        let partition_len = frames_per_block_usize();
+        let kernel = match options.buffer {
+            Some(ref buffer) => Some(ConvolverKernel::new(buffer, partition_len)?),
+            None => None,
+        };
+        let fft_size = fft_size(partition_len);
+        let mut node = Self {
+            channel_info,
+            normalize: options.normalize,
+            kernel: None,
+            scale: 1.,
+            partition_len,
+            fft: Fft::new(fft_size),
+            inputs: [vec![0.; fft_size], vec![0.; fft_size]],
+            delay_line_index: 0,
+            input_chans: 1,
+            silent_frames: 0,
+            spectrum: vec![Complex32::new(0., 0.); bins(partition_len)],
+            output: vec![0.; fft_size],
+            garbage: None,
+        };
+        node.set_kernel(kernel);
+        Ok(node)
+    }
+
+    fn set_kernel(&mut self, kernel: Option<ConvolverKernel>) {
+        self.scale = match kernel {
+            Some(ref kernel) if self.normalize => kernel.scale,
+            _ => 1.,
+        };
+        if let Some(previous) = ::std::mem::replace(&mut self.kernel, kernel) {
+            self.garbage = Some(Garbage::ConvolverKernel(previous));
+        }
+        // the delay lines of the new kernel are silent already
+        for input in self.inputs.iter_mut() {
+            for i in input.iter_mut() {
+                *i = 0.;
+            }
+        }
+        self.delay_line_index = 0;
+        self.silent_frames = 0;
+    }
+
+    fn handle_convolver_message(&mut self, message: ConvolverNodeMessage, _sample_rate: f32) {
+        match message {
+            ConvolverNodeMessage::SetBuffer(buffer) => {
+                warn!("Convolver buffers must be set through the context");
+                self.garbage = buffer.map(Garbage::Buffer);
+            }
+            ConvolverNodeMessage::SetKernel(kernel) => self.set_kernel(kernel),
+            ConvolverNodeMessage::SetNormalize(normalize) => self.normalize = normalize,
+        }
+    }
+
+    /// Transform a block of input and push it onto the delay lines
+    /// of the kernel
+    fn push_input(&mut self, block: &Block) {
+        let delay_lines = &mut self.kernel.as_mut().unwrap().delay_lines;
+        let bins = bins(self.partition_len);
+        let offset = self.delay_line_index * bins;
+        for (chan, (input, delay_line)) in self.inputs.iter_mut().zip(delay_lines).enumerate() {
+            input.copy_within(self.partition_len.., 0);
+            let new = &mut input[self.partition_len..];
+            if block.is_silence() {
+                for n in new.iter_mut() {
+                    *n = 0.;
+                }
+            } else if chan < self.input_chans as usize {
+                new.copy_from_slice(block.data_chan(chan as u8));
+            } else {
+                // mono input, which is upmixed to stereo
+                new.copy_from_slice(block.data_chan(0));
+            }
+            self.fft
+                .real_forward(input, &mut delay_line[offset..offset + bins]);
+        }
+    }
+
+    /// Accumulate the convolution of an input channel with a channel of
+    /// the impulse response into `self.spectrum`
+    fn accumulate(&mut self, input_chan: usize, kernel_chan: usize) {
+        let kernel = self.kernel.as_ref().unwrap();
+        let bins = bins(self.partition_len);
+        let delay_line = &kernel.delay_lines[input_chan];
+        let spectra = &kernel.spectra[kernel_chan];
+        for partition in 0..kernel.partitions {
+            // The input from `partition` blocks ago is delayed by
+            // the same amount as this partition of the impulse response
+            let index = (self.delay_line_index + kernel.partitions - partition) % kernel.partitions;
+            let x = &delay_line[index * bins..(index + 1) * bins];
+            let h = &spectra[partition * bins..(partition + 1) * bins];
+            for ((acc, x), h) in self.spectrum.iter_mut().zip(x).zip(h) {
+                *acc += x * h;
+            }
+        }
+    }
+}
+
+impl AudioNodeEngine for ConvolverNode {
+    fn node_type(&self) -> AudioNodeType {
+        AudioNodeType::ConvolverNode
+    }
+
+    fn is_actively_processing(&self) -> bool {
+        match self.kernel {
+            Some(ref kernel) => self.silent_frames < kernel.len + self.partition_len,
+            None => false,
+        }
+    }
+
+    fn process(&mut self, mut inputs: Chunk, _info: &BlockInfo) -> Chunk {
+        debug_assert!(inputs.len() == 1);
+
+        let (kernel_chans, kernel_len, partitions) = match self.kernel {
+            Some(ref kernel) if kernel.partitions > 0 => {
+                (kernel.chans(), kernel.len, kernel.partitions)
+            }
+            _ => {
+                inputs.blocks[0] = Block::default();
+                return inputs;
+            }
+        };
+
+        let mut block = inputs.blocks[0].take();
+        if block.is_silence() {
+            if self.silent_frames >= kernel_len + self.partition_len {
+                return inputs;
+            }
+            self.silent_frames += self.partition_len;
+        } else {
+            self.silent_frames = 0;
+            if block.chan_count() > 2 {
+                block.mix(2, self.channel_info.interpretation);
+            }
+            self.input_chans = block.chan_count();
+        }
+
+        self.push_input(&block);
+
+        let output_chans = if self.input_chans == 1 && kernel_chans == 1 {
+            1
+        } else {
+            2
+        };
+        let mut output = Block::for_channels_explicit(output_chans);
+        for chan in 0..output_chans as usize {
+            for s in self.spectrum.iter_mut() {
+                *s = Complex32::new(0., 0.);
+            }
+            // The delay line of the right input mirrors the left one
+            // for mono inputs, so the paths are the same in both cases
+            match kernel_chans {
+                1 => self.accumulate(chan, 0),
+                2 => self.accumulate(chan, chan),
+                _ => {
+                    self.accumulate(0, chan);
+                    self.accumulate(1, chan + 2);
+                }
+            }
+            self.fft.real_inverse(&self.spectrum, &mut self.output);
+            // overlap-save: only the second half is free of
+            // circular convolution artifacts
+            let scale = self.scale;
+            for (out, sample) in output
+                .data_chan_mut(chan as u8)
+                .iter_mut()
+                .zip(&self.output[self.partition_len..])
+            {
+                *out = sample * scale;
+            }
+        }
+
+        self.delay_line_index = (self.delay_line_index + 1) % partitions;
+        inputs.blocks[0] = output;
+        inputs
+    }
+
+    fn set_channel_count(&mut self, c: u8) {
+        if c > 2 {
+            warn!("convolver nodes cannot have more than 2 channels");
+            return;
+        }
+        self.channel_info.count = c;
+    }
+
+    fn set_channel_count_mode(&mut self, m: ChannelCountMode) {
+        if m == ChannelCountMode::Max {
+            warn!("convolver nodes cannot have a channel count mode of max");
+            return;
+        }
+        self.channel_info.mode = m;
+    }
+
+    make_message_handler!(ConvolverNode: handle_convolver_message);
+
+    fn take_garbage(&mut self) -> Option<Garbage> {
+        self.garbage.take()
+    }
+}
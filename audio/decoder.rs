@@ -1,4 +1,5 @@
 use boxfnonce::SendBoxFnOnce;
+use resampler::ResampleQuality;
 use std::sync::Mutex;
 
 #[derive(Debug, PartialEq)]
@@ -114,13 +115,17 @@ impl AudioDecoderCallbacksBuilder {
 }
 
 pub struct AudioDecoderOptions {
+    /// The sample rate to resample the decoded audio to.
     pub sample_rate: f32,
+    /// How to resample it, see `resampler::Resampler`.
+    pub resample_quality: ResampleQuality,
 }
 
 impl Default for AudioDecoderOptions {
     fn default() -> Self {
         AudioDecoderOptions {
             sample_rate: 44100.,
+            resample_quality: Default::default(),
         }
     }
 }
@@ -0,0 +1,233 @@
+use block::{frames_per_block_usize, Block, Chunk, Tick};
+use context::AudioContextError;
+use node::{AudioNodeEngine, BlockInfo};
+use node::{AudioNodeType, ChannelInfo};
+use param::{Param, ParamDescriptor, ParamType};
+
+/// From https://webaudio.github.io/web-audio-api/#dom-baseaudiocontext-createdelay
+pub const MAX_DELAY_TIME: f64 = 180.;
+
+#[derive(Copy, Clone, Debug)]
+pub struct DelayNodeOptions {
+    /// The maximum delay time in seconds, this sizes the delay line
+    pub max_delay_time: f64,
+    /// The initial value for the delay_time AudioParam, in seconds
+    pub delay_time: f32,
+}
+
+impl Default for DelayNodeOptions {
+    fn default() -> Self {
+        DelayNodeOptions {
+            max_delay_time: 1.,
+            delay_time: 0.,
+        }
+    }
+}
+
+impl DelayNodeOptions {
+    /// Check that the delay line can be allocated
+    pub fn validate(&self) -> Result<(), AudioContextError> {
+        if self.max_delay_time > 0. && self.max_delay_time < MAX_DELAY_TIME {
+            Ok(())
+        } else {
+            Err(AudioContextError::NotSupported(
+                "max_delay_time must be in (0, 180)",
+            ))
+        }
+    }
+}
+
+/// https://webaudio.github.io/web-audio-api/#DelayNode
+///
+/// The delay line is a ring buffer per channel, holding enough frames
+/// for the maximum delay time plus the block currently being written.
+///
+/// When the node is part of a cycle in the graph, it is split into
+/// a "DelayReader" and a "DelayWriter" half (see `process_cycle_output`
+/// and `process_cycle_input`), and the delay time is clamped to at least
+/// one render quantum.
+#[derive(AudioNodeCommon)]
+pub(crate) struct DelayNode {
+    channel_info: ChannelInfo,
+    delay_time: Param,
+    /// The maximum delay, in frames
+    max_delay_frames: f64,
+    /// One ring buffer per channel, all of the same length, allocated
+    /// for the channel count up front
+    buffers: Vec<Vec<f32>>,
+    /// How many of the buffers are in use, the delay line has as many
+    /// channels as the input with the most channels so far
+    chans: usize,
+    /// The index at which the first frame of the next block
+    /// will be written
+    write_index: usize,
+    /// How many silent frames have been written in a row, once this
+    /// exceeds the length of the delay line the output is silent
+    silent_frames: usize,
+}
+
+impl DelayNode {
+    pub fn new(options: DelayNodeOptions, channel_info: ChannelInfo, sample_rate: f32) -> Self {
+        debug_assert!(options.validate().is_ok());
+        let max_delay_frames = (options.max_delay_time * sample_rate as f64).ceil();
+        let mut delay_time =
+            ParamDescriptor::of(AudioNodeType::DelayNode, ParamType::DelayTime, sample_rate)
+                .unwrap();
+        delay_time.max_value = options.max_delay_time as f32;
+        let mut node = Self {
+            channel_info,
+            delay_time: Param::with_descriptor(options.delay_time, delay_time),
+            max_delay_frames,
+            buffers: Vec::new(),
+            chans: 0,
+            write_index: 0,
+            silent_frames: 0,
+        };
+        let len = node.buffer_len();
+        node.buffers = vec![vec![0.; len]; node.channel_info.count as usize];
+        node
+    }
+
+    /// The length of each channel's ring buffer. We keep an extra frame
+    /// around so that interpolating at the maximum delay is possible.
+    fn buffer_len(&self) -> usize {
+        self.max_delay_frames as usize + frames_per_block_usize() + 1
+    }
+
+    /// Whether the delay line only contains silence
+    fn is_drained(&self) -> bool {
+        self.chans == 0 || self.silent_frames >= self.buffer_len()
+    }
+
+    /// Copy a block of input into the delay line
+    fn write(&mut self, mut block: Block) {
+        if block.is_silence() {
+            if self.is_drained() {
+                return;
+            }
+            self.silent_frames += frames_per_block_usize();
+        } else {
+            self.silent_frames = 0;
+        }
+
+        let len = self.buffer_len();
+        let chans = block.chan_count() as usize;
+        if chans > self.buffers.len() {
+            // only inputs with more channels than the channel count,
+            // with a channel count mode other than explicit, get here
+            self.buffers.resize(chans, vec![0.; len]);
+        }
+        if chans > self.chans {
+            self.chans = chans;
+        } else if chans < self.chans {
+            block.mix(self.chans as u8, self.channel_info.interpretation);
+        }
+
+        for (chan, buffer) in self.buffers[..self.chans].iter_mut().enumerate() {
+            let mut index = self.write_index;
+            for frame in 0..frames_per_block_usize() {
+                buffer[index] = block.data_chan_frame(frame, chan as u8);
+                index += 1;
+                if index == len {
+                    index = 0;
+                }
+            }
+        }
+    }
+
+    /// Read a block of output from the delay line, relative to the
+    /// current write index.
+    ///
+    /// `min_delay` is the minimum delay in frames, which is one render
+    /// quantum when breaking a cycle.
+    fn read(&mut self, info: &BlockInfo, min_delay: f64) -> Block {
+        if self.is_drained() {
+            // keep the timeline moving even if we're not outputting anything
+            for frame in 0..frames_per_block_usize() {
+                self.delay_time.update(info, Tick(frame as u64));
+            }
+            return Block::default();
+        }
+
+        let len = self.buffer_len();
+        let sample_rate = info.sample_rate as f64;
+        let mut block = Block::for_channels_explicit(self.chans as u8);
+        for frame in 0..frames_per_block_usize() {
+            self.delay_time.update(info, Tick(frame as u64));
+            let mut delay = self.delay_time.value() as f64 * sample_rate;
+            if !(delay >= min_delay) {
+                // also catches NaN
+                delay = min_delay;
+            } else if delay > self.max_delay_frames {
+                delay = self.max_delay_frames;
+            }
+
+            // The position of the frame to read, made positive by adding
+            // the buffer length so that we can wrap with a modulo
+            let pos = (self.write_index + frame + len) as f64 - delay;
+            let lo = pos.floor();
+            let interp_factor = (pos - lo) as f32;
+            let lo = lo as usize % len;
+            let hi = (lo + 1) % len;
+            for (chan, buffer) in self.buffers[..self.chans].iter().enumerate() {
+                block.data_chan_mut(chan as u8)[frame] =
+                    (1. - interp_factor) * buffer[lo] + interp_factor * buffer[hi];
+            }
+        }
+        block
+    }
+
+    fn advance(&mut self) {
+        self.write_index = (self.write_index + frames_per_block_usize()) % self.buffer_len();
+    }
+}
+
+impl AudioNodeEngine for DelayNode {
+    fn node_type(&self) -> AudioNodeType {
+        AudioNodeType::DelayNode
+    }
+
+    fn is_actively_processing(&self) -> bool {
+        !self.is_drained()
+    }
+
+    fn process(&mut self, mut inputs: Chunk, info: &BlockInfo) -> Chunk {
+        debug_assert!(inputs.len() == 1);
+
+        // Write first, so that delays shorter than a render quantum
+        // can read from the current block
+        self.write(inputs.blocks[0].take());
+        inputs.blocks[0] = self.read(info, 0.);
+        self.advance();
+        inputs
+    }
+
+    fn can_break_cycles(&self) -> bool {
+        true
+    }
+
+    fn process_cycle_output(&mut self, info: &BlockInfo) -> Chunk {
+        let mut chunk = Chunk::default();
+        chunk
+            .blocks
+            .push(self.read(info, frames_per_block_usize() as f64));
+        chunk
+    }
+
+    fn process_cycle_input(&mut self, mut inputs: Chunk, _: &BlockInfo) {
+        debug_assert!(inputs.len() == 1);
+        self.write(inputs.blocks[0].take());
+        self.advance();
+    }
+
+    fn param_types(&self) -> Vec<ParamType> {
+        vec![ParamType::DelayTime]
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
+        match id {
+            ParamType::DelayTime => Some(&mut self.delay_time),
+            _ => None,
+        }
+    }
+}
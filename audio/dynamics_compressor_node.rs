@@ -0,0 +1,466 @@
+use block::{frames_per_block_usize, Chunk, Tick, MAX_FRAMES_PER_BLOCK};
+use log::warn;
+use node::{AudioNodeEngine, AudioNodeType, BlockInfo};
+use node::{ChannelCountMode, ChannelInfo};
+use param::{Param, ParamType};
+use std::mem;
+use std::sync::mpsc::Sender;
+
+/// The look-ahead of the detector, in seconds. This is what
+/// browsers use, it is not specified.
+const PRE_DELAY: f32 = 0.006;
+
+#[derive(Copy, Clone, Debug)]
+pub struct DynamicsCompressorNodeOptions {
+    /// The initial value for the attack AudioParam, in seconds
+    pub attack: f32,
+    /// The initial value for the knee AudioParam, in dB
+    pub knee: f32,
+    /// The initial value for the ratio AudioParam
+    pub ratio: f32,
+    /// The initial value for the release AudioParam, in seconds
+    pub release: f32,
+    /// The initial value for the threshold AudioParam, in dB
+    pub threshold: f32,
+}
+
+impl Default for DynamicsCompressorNodeOptions {
+    fn default() -> Self {
+        DynamicsCompressorNodeOptions {
+            attack: 0.003,
+            knee: 30.,
+            ratio: 12.,
+            release: 0.25,
+            threshold: -24.,
+        }
+    }
+}
+
+pub enum DynamicsCompressorNodeMessage {
+    /// Reply with the current gain reduction, in dB
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-dynamicscompressornode-reduction
+    GetReduction(Sender<f32>),
+}
+
+fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.)
+}
+
+fn linear_to_db(linear: f32) -> f32 {
+    if linear > 0. {
+        20. * linear.log10()
+    } else {
+        -1000.
+    }
+}
+
+/// The static compression curve, computed from the threshold, knee and
+/// ratio params
+///
+/// Below the threshold the curve is linear, above the knee it has a
+/// slope of 1 / ratio in the dB domain, and in between it is an
+/// exponential curve whose slope matches at both ends.
+#[derive(Copy, Clone, Debug)]
+struct CompressionCurve {
+    linear_threshold: f32,
+    knee_threshold: f32,
+    knee_threshold_db: f32,
+    /// The output level of the knee at knee_threshold, in dB
+    y_knee_threshold_db: f32,
+    slope: f32,
+    k: f32,
+}
+
+impl CompressionCurve {
+    fn new(threshold: f32, knee: f32, ratio: f32) -> Self {
+        // clamp to nominal range
+        // https://webaudio.github.io/web-audio-api/#DynamicsCompressorNode
+        let threshold = threshold.max(-100.).min(0.);
+        let knee = knee.max(0.).min(40.);
+        let ratio = ratio.max(1.).min(20.);
+
+        let knee_threshold_db = threshold + knee;
+        let mut curve = CompressionCurve {
+            linear_threshold: db_to_linear(threshold),
+            knee_threshold: db_to_linear(knee_threshold_db),
+            knee_threshold_db,
+            y_knee_threshold_db: 0.,
+            slope: 1. / ratio,
+            k: 0.,
+        };
+        curve.k = curve.k_at_slope(curve.slope);
+        curve.y_knee_threshold_db = linear_to_db(curve.knee_curve(curve.knee_threshold, curve.k));
+        curve
+    }
+
+    /// The exponential curve of the knee, for x above the threshold
+    fn knee_curve(&self, x: f32, k: f32) -> f32 {
+        if x < self.linear_threshold {
+            return x;
+        }
+        self.linear_threshold + (1. - (-k * (x - self.linear_threshold)).exp()) / k
+    }
+
+    /// The slope of the knee curve at x, in the dB domain
+    fn slope_at(&self, x: f32, k: f32) -> f32 {
+        if x < self.linear_threshold {
+            return 1.;
+        }
+        let x2 = x * 1.001;
+        let x_db = linear_to_db(x);
+        let x2_db = linear_to_db(x2);
+        let y_db = linear_to_db(self.knee_curve(x, k));
+        let y2_db = linear_to_db(self.knee_curve(x2, k));
+        (y2_db - y_db) / (x2_db - x_db)
+    }
+
+    /// Find the k for which the knee curve reaches the desired slope
+    /// at the end of the knee, so that the curve is continuous
+    fn k_at_slope(&self, slope: f32) -> f32 {
+        let x = self.knee_threshold;
+        let mut min_k: f32 = 0.1;
+        let mut max_k: f32 = 10000.;
+        let mut k = 5.;
+        for _ in 0..15 {
+            if self.slope_at(x, k) < slope {
+                // k is too high
+                max_k = k;
+            } else {
+                min_k = k;
+            }
+            k = (min_k * max_k).sqrt();
+        }
+        k
+    }
+
+    /// Apply the curve to a (positive) linear level
+    fn apply(&self, x: f32) -> f32 {
+        if x < self.knee_threshold {
+            self.knee_curve(x, self.k)
+        } else {
+            let y_db =
+                self.y_knee_threshold_db + self.slope * (linear_to_db(x) - self.knee_threshold_db);
+            db_to_linear(y_db)
+        }
+    }
+
+    /// The gain that makes a full scale signal full scale again
+    /// after compression, tempered like browsers do
+    fn makeup_gain(&self) -> f32 {
+        (1. / self.apply(1.)).powf(0.6)
+    }
+}
+
+/// https://webaudio.github.io/web-audio-api/#DynamicsCompressorNode
+///
+/// The detector looks at the peak of all input channels, and the gain
+/// it computes is applied to the input delayed by `PRE_DELAY`, so that
+/// the compressor reacts before transients reach the output.
+#[derive(AudioNodeCommon)]
+pub(crate) struct DynamicsCompressorNode {
+    channel_info: ChannelInfo,
+    attack: Param,
+    knee: Param,
+    ratio: Param,
+    release: Param,
+    threshold: Param,
+    curve: CompressionCurve,
+    makeup_gain: f32,
+    /// The look-ahead delay line of each of the (at most) two channels
+    pre_delay: [Vec<f32>; 2],
+    /// How many channels of the delay line are in use, as many as the
+    /// input with the most channels so far
+    chans: u8,
+    pre_delay_index: usize,
+    /// The current gain reduction, in dB (zero or negative)
+    reduction: f32,
+    /// How many silent frames have been input in a row
+    silent_frames: usize,
+}
+
+impl DynamicsCompressorNode {
+    pub fn new(
+        options: DynamicsCompressorNodeOptions,
+        channel_info: ChannelInfo,
+        sample_rate: f32,
+    ) -> Self {
+        let curve = CompressionCurve::new(options.threshold, options.knee, options.ratio);
+        let pre_delay_len = (PRE_DELAY * sample_rate).round() as usize;
+        let node = AudioNodeType::DynamicsCompressionNode;
+        Self {
+            channel_info,
+            attack: Param::for_node(node, ParamType::Attack, options.attack, sample_rate),
+            knee: Param::for_node(node, ParamType::Knee, options.knee, sample_rate),
+            ratio: Param::for_node(node, ParamType::Ratio, options.ratio, sample_rate),
+            release: Param::for_node(node, ParamType::Release, options.release, sample_rate),
+            threshold: Param::for_node(node, ParamType::Threshold, options.threshold, sample_rate),
+            makeup_gain: curve.makeup_gain(),
+            curve,
+            pre_delay: [vec![0.; pre_delay_len], vec![0.; pre_delay_len]],
+            chans: 1,
+            pre_delay_index: 0,
+            reduction: 0.,
+            silent_frames: 0,
+        }
+    }
+
+    pub fn update_parameters(&mut self, info: &BlockInfo) {
+        let mut changed = self.threshold.update(info, Tick(0));
+        changed |= self.knee.update(info, Tick(0));
+        changed |= self.ratio.update(info, Tick(0));
+        self.attack.update(info, Tick(0));
+        self.release.update(info, Tick(0));
+
+        if changed {
+            self.curve = CompressionCurve::new(
+                self.threshold.value(),
+                self.knee.value(),
+                self.ratio.value(),
+            );
+            self.makeup_gain = self.curve.makeup_gain();
+        }
+    }
+
+    fn pre_delay_len(&self) -> usize {
+        self.pre_delay[0].len()
+    }
+
+    /// The one pole smoothing coefficient for a time constant in seconds
+    fn coefficient(time: f32, sample_rate: f32) -> f32 {
+        let time = time.max(0.).min(1.);
+        if time == 0. {
+            0.
+        } else {
+            (-1. / (time * sample_rate)).exp()
+        }
+    }
+
+    fn handle_compressor_message(
+        &mut self,
+        message: DynamicsCompressorNodeMessage,
+        _sample_rate: f32,
+    ) {
+        match message {
+            DynamicsCompressorNodeMessage::GetReduction(tx) => {
+                let _ = tx.send(self.reduction);
+            }
+        }
+    }
+}
+
+impl AudioNodeEngine for DynamicsCompressorNode {
+    fn node_type(&self) -> AudioNodeType {
+        AudioNodeType::DynamicsCompressionNode
+    }
+
+    fn is_actively_processing(&self) -> bool {
+        self.silent_frames < self.pre_delay_len()
+    }
+
+    fn process(&mut self, mut inputs: Chunk, info: &BlockInfo) -> Chunk {
+        debug_assert!(inputs.len() == 1);
+
+        self.update_parameters(info);
+        let attack = Self::coefficient(self.attack.value(), info.sample_rate);
+        let release = Self::coefficient(self.release.value(), info.sample_rate);
+
+        let pre_delay_len = self.pre_delay_len();
+        if inputs.blocks[0].is_silence() {
+            if self.silent_frames >= pre_delay_len {
+                // Nothing left in the delay line, only let the
+                // reduction recover for the meters
+                self.reduction *= release.powi(frames_per_block_usize() as i32);
+                return inputs;
+            }
+            self.silent_frames += frames_per_block_usize();
+        } else {
+            self.silent_frames = 0;
+        }
+
+        let mut block = inputs.blocks[0].take();
+        // the channel count mode is never max, there are at most 2 channels
+        let chans = block.chan_count();
+        debug_assert!(chans <= 2);
+        if chans > self.chans {
+            self.chans = chans;
+        } else if chans < self.chans {
+            block.mix(self.chans, self.channel_info.interpretation);
+        }
+        block.explicit_repeat();
+
+        // The detector looks at the peak of all channels
+        let mut gains = [0f32; MAX_FRAMES_PER_BLOCK as usize];
+        let gains = &mut gains[..frames_per_block_usize()];
+        for chan in 0..self.chans {
+            for (level, input) in gains.iter_mut().zip(block.data_chan(chan)) {
+                *level = level.max(input.abs());
+            }
+        }
+        for gain in gains.iter_mut() {
+            let level = *gain;
+            let target = if level > 0. {
+                linear_to_db(self.curve.apply(level) / level).min(0.)
+            } else {
+                0.
+            };
+            // attack when the reduction increases, release otherwise
+            let coefficient = if target < self.reduction {
+                attack
+            } else {
+                release
+            };
+            self.reduction = target + coefficient * (self.reduction - target);
+            *gain = db_to_linear(self.reduction) * self.makeup_gain;
+        }
+
+        for chan in 0..self.chans {
+            let delay_line = &mut self.pre_delay[chan as usize];
+            let mut index = self.pre_delay_index;
+            for (sample, gain) in block.data_chan_mut(chan).iter_mut().zip(gains.iter()) {
+                if pre_delay_len != 0 {
+                    mem::swap(&mut delay_line[index], sample);
+                    index += 1;
+                    if index == pre_delay_len {
+                        index = 0;
+                    }
+                }
+                *sample *= gain;
+            }
+        }
+        if pre_delay_len != 0 {
+            self.pre_delay_index =
+                (self.pre_delay_index + frames_per_block_usize()) % pre_delay_len;
+        }
+
+        inputs.blocks[0] = block;
+        inputs
+    }
+
+    fn param_types(&self) -> Vec<ParamType> {
+        vec![
+            ParamType::Threshold,
+            ParamType::Knee,
+            ParamType::Ratio,
+            ParamType::Attack,
+            ParamType::Release,
+        ]
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
+        match id {
+            ParamType::Attack => Some(&mut self.attack),
+            ParamType::Knee => Some(&mut self.knee),
+            ParamType::Ratio => Some(&mut self.ratio),
+            ParamType::Release => Some(&mut self.release),
+            ParamType::Threshold => Some(&mut self.threshold),
+            _ => None,
+        }
+    }
+
+    fn set_channel_count(&mut self, c: u8) {
+        if c > 2 {
+            warn!("compressor nodes cannot have more than 2 channels");
+            return;
+        }
+        self.channel_info.count = c;
+    }
+
+    fn set_channel_count_mode(&mut self, m: ChannelCountMode) {
+        if m == ChannelCountMode::Max {
+            warn!("compressor nodes cannot have a channel count mode of max");
+            return;
+        }
+        self.channel_info.mode = m;
+    }
+
+    make_message_handler!(DynamicsCompressionNode: handle_compressor_message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::Block;
+    use std::sync::mpsc;
+
+    const RATE: f32 = 48000.;
+
+    fn db_at(curve: &CompressionCurve, db: f32) -> f32 {
+        linear_to_db(curve.apply(db_to_linear(db)))
+    }
+
+    #[test]
+    fn static_curve() {
+        // hard knee: linear up to the threshold, 1 / ratio above it
+        let hard = CompressionCurve::new(-20., 0., 4.);
+        assert!((db_at(&hard, -40.) + 40.).abs() < 1e-3);
+        assert!((db_at(&hard, -20.) + 20.).abs() < 1e-3);
+        assert!((db_at(&hard, -10.) + 17.5).abs() < 1e-3);
+        assert!((db_at(&hard, 0.) + 15.).abs() < 1e-3);
+
+        // soft knee: below the input within the knee, continuous at its
+        // end, and the ratio's slope above it
+        let soft = CompressionCurve::new(-30., 10., 10.);
+        assert!((db_at(&soft, -40.) + 40.).abs() < 1e-3);
+        let knee = db_at(&soft, -25.);
+        assert!(knee < -25. && knee > -30.);
+        assert!((db_at(&soft, -20.01) - db_at(&soft, -19.99)).abs() < 0.01);
+        let slope = (db_at(&soft, -5.) - db_at(&soft, -15.)) / 10.;
+        assert!((slope - 0.1).abs() < 1e-3);
+
+        // ratio is clamped to its nominal range
+        let clamped = CompressionCurve::new(-20., 0., 100.);
+        assert!((db_at(&clamped, 0.) + 19.).abs() < 1e-3);
+    }
+
+    fn run(node: &mut DynamicsCompressorNode, frame: &mut usize, seconds: f32, value: f32) -> f32 {
+        let frames = frames_per_block_usize();
+        let mut last = 0.;
+        for _ in 0..(seconds * RATE) as usize / frames {
+            let mut chunk = Chunk::default();
+            chunk.blocks.push(if value == 0. {
+                Block::default()
+            } else {
+                Block::for_vec(vec![value; frames])
+            });
+            let info = BlockInfo {
+                sample_rate: RATE,
+                frame: Tick(*frame as u64),
+                time: *frame as f64 / RATE as f64,
+            };
+            let mut chunk = node.process(chunk, &info);
+            if !chunk.blocks[0].is_silence() {
+                last = *chunk.blocks[0].data_mut().last().unwrap();
+            }
+            *frame += frames;
+        }
+        last
+    }
+
+    fn reduction(node: &mut DynamicsCompressorNode) -> f32 {
+        let (tx, rx) = mpsc::channel();
+        node.handle_compressor_message(DynamicsCompressorNodeMessage::GetReduction(tx), RATE);
+        rx.recv().unwrap()
+    }
+
+    #[test]
+    fn reports_reduction() {
+        let options = DynamicsCompressorNodeOptions::default();
+        let mut node = DynamicsCompressorNode::new(options, ChannelInfo::default(), RATE);
+        assert_eq!(reduction(&mut node), 0.);
+
+        // a full scale signal settles on the static curve's reduction,
+        // and the makeup gain brings it back to full scale
+        let mut frame = 0;
+        let output = run(&mut node, &mut frame, 0.5, 1.);
+        let expected = linear_to_db(node.curve.apply(1.));
+        assert!(expected < -1.);
+        assert!((reduction(&mut node) - expected).abs() < 0.01);
+        assert!((output - db_to_linear(expected) * node.makeup_gain).abs() < 1e-3);
+
+        // the reduction recovers once the input is quiet
+        run(&mut node, &mut frame, 2., 0.);
+        assert!(reduction(&mut node) > -0.1);
+        assert!(!node.is_actively_processing());
+    }
+}
@@ -0,0 +1,108 @@
+use num_complex::Complex32;
+use std::f32::consts::PI;
+
+/// An iterative radix-2 FFT of a fixed, power of two, size
+///
+/// The twiddle factors and bit reversal permutation are computed
+/// once on construction, so processing does not allocate.
+pub struct Fft {
+    size: usize,
+    /// exp(-2πik/size) for k in 0..size/2
+    twiddles: Vec<Complex32>,
+    /// The bit reversed index of every index
+    bit_reversed: Vec<usize>,
+    /// Scratch space for the real transforms
+    scratch: Vec<Complex32>,
+}
+
+impl Fft {
+    pub fn new(size: usize) -> Self {
+        assert!(
+            size >= 2 && size & (size - 1) == 0,
+            "FFT size must be a power of two"
+        );
+        let twiddles = (0..size / 2)
+            .map(|k| Complex32::from_polar(&1., &(-2. * PI * k as f32 / size as f32)))
+            .collect();
+        let bits = size.trailing_zeros();
+        let bit_reversed = (0..size)
+            .map(|i| i.reverse_bits() >> (usize::max_value().count_ones() - bits))
+            .collect();
+        Self {
+            size,
+            twiddles,
+            bit_reversed,
+            scratch: vec![Complex32::new(0., 0.); size],
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// In-place unnormalized complex FFT. The inverse transform
+    /// must be scaled by 1 / size by the caller.
+    pub fn process(&self, data: &mut [Complex32], inverse: bool) {
+        debug_assert!(data.len() == self.size);
+        for i in 0..self.size {
+            let j = self.bit_reversed[i];
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= self.size {
+            let half = len / 2;
+            let stride = self.size / len;
+            for start in (0..self.size).step_by(len) {
+                for k in 0..half {
+                    let mut twiddle = self.twiddles[k * stride];
+                    if inverse {
+                        twiddle = twiddle.conj();
+                    }
+                    let a = data[start + k];
+                    let b = data[start + k + half] * twiddle;
+                    data[start + k] = a + b;
+                    data[start + k + half] = a - b;
+                }
+            }
+            len *= 2;
+        }
+    }
+
+    /// Forward transform of a real signal
+    ///
+    /// `output` receives the size / 2 + 1 non-redundant bins,
+    /// the rest are their complex conjugates.
+    pub fn real_forward(&mut self, input: &[f32], output: &mut [Complex32]) {
+        debug_assert!(input.len() == self.size);
+        debug_assert!(output.len() == self.size / 2 + 1);
+        for (s, i) in self.scratch.iter_mut().zip(input) {
+            *s = Complex32::new(*i, 0.);
+        }
+        let mut scratch = std::mem::replace(&mut self.scratch, Vec::new());
+        self.process(&mut scratch, false);
+        output.copy_from_slice(&scratch[..self.size / 2 + 1]);
+        self.scratch = scratch;
+    }
+
+    /// Inverse of `real_forward`, normalized so that a round trip
+    /// returns the original signal
+    pub fn real_inverse(&mut self, input: &[Complex32], output: &mut [f32]) {
+        debug_assert!(input.len() == self.size / 2 + 1);
+        debug_assert!(output.len() == self.size);
+        let half = self.size / 2;
+        self.scratch[..half + 1].copy_from_slice(input);
+        for k in 1..half {
+            self.scratch[self.size - k] = input[k].conj();
+        }
+        let mut scratch = std::mem::replace(&mut self.scratch, Vec::new());
+        self.process(&mut scratch, true);
+        let scale = 1. / self.size as f32;
+        for (o, s) in output.iter_mut().zip(&scratch) {
+            *o = s.re * scale;
+        }
+        self.scratch = scratch;
+    }
+}
@@ -1,9 +1,9 @@
-use block::Chunk;
-use block::Tick;
+use block::{Chunk, Tick, MAX_FRAMES_PER_BLOCK};
 use node::AudioNodeEngine;
 use node::BlockInfo;
 use node::{AudioNodeType, ChannelInfo};
 use param::{Param, ParamType};
+use simd;
 
 #[derive(Copy, Clone, Debug)]
 pub struct GainNodeOptions {
@@ -23,10 +23,15 @@ pub(crate) struct GainNode {
 }
 
 impl GainNode {
-    pub fn new(options: GainNodeOptions, channel_info: ChannelInfo) -> Self {
+    pub fn new(options: GainNodeOptions, channel_info: ChannelInfo, sample_rate: f32) -> Self {
         Self {
             channel_info,
-            gain: Param::new(options.gain),
+            gain: Param::for_node(
+                AudioNodeType::GainNode,
+                ParamType::Gain,
+                options.gain,
+                sample_rate,
+            ),
         }
     }
 
@@ -47,24 +52,39 @@ impl AudioNodeEngine for GainNode {
             return inputs;
         }
 
-        {
-            let mut iter = inputs.blocks[0].iter();
-            let mut gain = self.gain.value();
+        let frames = inputs.blocks[0].frames();
+        let mut gains = [0.; MAX_FRAMES_PER_BLOCK as usize];
+        let gains = &mut gains[..frames];
+        let mut gain = self.gain.value();
+        let mut constant = true;
+        for tick in 0..frames {
+            if self.update_parameters(info, Tick(tick as u64)) {
+                gain = self.gain.value();
+                constant &= tick == 0 || gain == gains[0];
+            }
+            gains[tick] = gain;
+        }
 
-            while let Some(mut frame) = iter.next() {
-                if self.update_parameters(info, frame.tick()) {
-                    gain = self.gain.value();
-                }
-                frame.mutate_with(|sample, _| *sample = *sample * gain);
+        let data = inputs.blocks[0].data_mut();
+        if constant {
+            simd::scale(data, gains[0]);
+        } else {
+            // a repeated block only holds one channel
+            for chan in data.chunks_mut(frames) {
+                simd::mul(chan, gains);
             }
         }
         inputs
     }
 
-    fn get_param(&mut self, id: ParamType) -> &mut Param {
+    fn param_types(&self) -> Vec<ParamType> {
+        vec![ParamType::Gain]
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
         match id {
-            ParamType::Gain => &mut self.gain,
-            _ => panic!("Unknown param {:?} for GainNode", id),
+            ParamType::Gain => Some(&mut self.gain),
+            _ => None,
         }
     }
 }
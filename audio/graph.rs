@@ -1,13 +1,19 @@
 use block::{Block, Chunk};
 use destination_node::DestinationNode;
+use graph_snapshot::{
+    ConnectionSnapshot, ConnectionTarget, GraphSnapshot, NodeSnapshot, ParamSnapshot,
+};
 use listener::AudioListenerNode;
+use log::warn;
 use node::{AudioNodeEngine, BlockInfo, ChannelCountMode, ChannelInterpretation};
 use param::ParamType;
+use petgraph::algo::tarjan_scc;
 use petgraph::graph::DefaultIx;
 use petgraph::stable_graph::NodeIndex;
 use petgraph::stable_graph::StableGraph;
-use petgraph::visit::{DfsPostOrder, EdgeRef, Reversed};
+use petgraph::visit::{EdgeFiltered, EdgeRef, IntoEdgeReferences, VisitMap, Visitable};
 use petgraph::Direction;
+use serde::{Serialize, Serializer};
 use smallvec::SmallVec;
 use std::cell::{RefCell, RefMut};
 use std::{cmp, fmt, hash};
@@ -30,6 +36,16 @@ impl NodeId {
     pub(crate) fn listener(self) -> PortId<InputPort> {
         PortId(self, PortIndex::Listener(()))
     }
+    /// A number identifying the node, for display purposes
+    pub fn index(self) -> usize {
+        self.0.index()
+    }
+}
+
+impl Serialize for NodeId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.index() as u64)
+    }
 }
 
 /// A zero-indexed "port" for a node. Most nodes have one
@@ -54,6 +70,13 @@ impl<Kind: PortKind> PortId<Kind> {
     pub fn node(&self) -> NodeId {
         self.0
     }
+
+    pub fn index(&self) -> PortIndex<Kind>
+    where
+        Kind: Copy,
+    {
+        self.1
+    }
 }
 
 pub trait PortKind {
@@ -96,10 +119,34 @@ pub struct AudioGraph {
     dest_id: NodeId,
     dests: Vec<NodeId>,
     listener_id: NodeId,
+    /// Nodes the control thread no longer references, which
+    /// are removed once they can't be heard anymore
+    released: Vec<NodeId>,
+    /// The cycles found the last time the graph was processed, `None`
+    /// once nodes or connections changed since
+    cycles: Option<Cycles>,
 }
 
 pub(crate) struct Node {
-    node: RefCell<Box<dyn AudioNodeEngine>>,
+    /// `None` for the slots of nodes which were removed, which stay in
+    /// the graph until their id is reused
+    node: RefCell<Option<Box<dyn AudioNodeEngine>>>,
+}
+
+type VisitedMap = <StableGraph<Node, Edge> as Visitable>::Map;
+
+/// The cycles found in the graph, which are kept until the graph
+/// changes
+///
+/// https://webaudio.github.io/web-audio-api/#cycles
+struct Cycles {
+    /// Nodes that are part of a cycle and can break it. These are
+    /// processed as their output is needed, and their inputs are
+    /// consumed once the rest of the graph has been processed.
+    breakers: VisitedMap,
+    /// Nodes that are part of a cycle which can't be broken,
+    /// these output silence
+    muted: VisitedMap,
 }
 
 /// An edge in the graph
@@ -157,9 +204,47 @@ struct Connection {
     cache: RefCell<Option<Block>>,
 }
 
+/// Hands out the ids of new nodes on the control thread, so that
+/// nodes can be created without waiting for the render thread
+///
+/// The id of a removed node is only reused once the render thread
+/// reports it was removed, with `free`.
+pub(crate) struct NodeIdAllocator {
+    next: usize,
+    free: Vec<NodeId>,
+}
+
+impl NodeIdAllocator {
+    pub fn new(graph: &AudioGraph) -> Self {
+        NodeIdAllocator {
+            next: graph.graph.node_count(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn allocate(&mut self) -> NodeId {
+        match self.free.pop() {
+            Some(id) => id,
+            None => {
+                self.next += 1;
+                NodeId(NodeIndex::new(self.next - 1))
+            }
+        }
+    }
+
+    pub fn free(&mut self, id: NodeId) {
+        self.free.push(id);
+    }
+}
+
+/// The number of nodes and edges the graph has room for before it
+/// needs to allocate on the render thread
+const NODE_CAPACITY: usize = 256;
+const EDGE_CAPACITY: usize = 512;
+
 impl AudioGraph {
     pub fn new(channel_count: u8) -> Self {
-        let mut graph = StableGraph::new();
+        let mut graph = StableGraph::with_capacity(NODE_CAPACITY, EDGE_CAPACITY);
         let dest_id =
             NodeId(graph.add_node(Node::new(Box::new(DestinationNode::new(channel_count)))));
         let listener_id = NodeId(graph.add_node(Node::new(Box::new(AudioListenerNode::new()))));
@@ -168,18 +253,36 @@ impl AudioGraph {
             dest_id,
             dests: vec![dest_id],
             listener_id,
+            released: Vec::with_capacity(NODE_CAPACITY),
+            cycles: None,
         }
     }
 
-    /// Create a node, obtain its id
-    pub(crate) fn add_node(&mut self, node: Box<dyn AudioNodeEngine>) -> NodeId {
-        NodeId(self.graph.add_node(Node::new(node)))
+    /// Add a node with an id obtained from a `NodeIdAllocator`
+    ///
+    /// Nodes are never removed from the underlying graph, their slot is
+    /// emptied instead, so ids are indices that are either vacant or
+    /// past the end of the graph.
+    pub(crate) fn add_node(&mut self, id: NodeId, node: Box<dyn AudioNodeEngine>) {
+        while self.graph.node_count() <= id.index() {
+            self.graph.add_node(Node::vacant());
+        }
+        self.cycles = None;
+        let mut slot = self.graph[id.0].node.borrow_mut();
+        debug_assert!(slot.is_none(), "Node ids must not be reused while in use");
+        *slot = Some(node);
     }
 
     /// Connect an output port to an input port
     ///
-    /// The edge goes *from* the output port *to* the input port, connecting two nodes
+    /// The edge goes *from* the output port *to* the input port, connecting two nodes.
+    /// Connections to or from ports the nodes don't have are ignored.
     pub fn add_edge(&mut self, out: PortId<OutputPort>, inp: PortId<InputPort>) {
+        if !self.has_ports(out, inp) {
+            warn!("Ignoring a connection from {:?} to {:?}", out, inp);
+            return;
+        }
+        self.cycles = None;
         let edge = self
             .graph
             .edges(out.node().0)
@@ -202,10 +305,31 @@ impl AudioGraph {
         }
     }
 
+    /// Whether both nodes are in the graph and have these ports
+    fn has_ports(&self, out: PortId<OutputPort>, inp: PortId<InputPort>) -> bool {
+        let has_output = match self.node_mut(out.node()) {
+            Some(node) => match out.1 {
+                PortIndex::Port(port) => port < node.output_count(),
+                PortIndex::Param(void) | PortIndex::Listener(void) => match void {},
+            },
+            None => false,
+        };
+        let has_input = match self.node_mut(inp.node()) {
+            Some(mut node) => match inp.1 {
+                PortIndex::Port(port) => port < node.input_count(),
+                PortIndex::Param(param) => node.get_param(param).is_some(),
+                PortIndex::Listener(()) => true,
+            },
+            None => false,
+        };
+        has_output && has_input
+    }
+
     /// Disconnect all outgoing connections from a node
     ///
     /// https://webaudio.github.io/web-audio-api/#dom-audionode-disconnect
     pub fn disconnect_all_from(&mut self, node: NodeId) {
+        self.cycles = None;
         let edges = self.graph.edges(node.0).map(|e| e.id()).collect::<Vec<_>>();
         for edge in edges {
             self.graph.remove_edge(edge);
@@ -216,6 +340,7 @@ impl AudioGraph {
     // ///
     // /// https://webaudio.github.io/web-audio-api/#dom-audionode-disconnect-output
     pub fn disconnect_output(&mut self, out: PortId<OutputPort>) {
+        self.cycles = None;
         let candidates: Vec<_> = self
             .graph
             .edges(out.node().0)
@@ -237,6 +362,7 @@ impl AudioGraph {
     ///
     /// https://webaudio.github.io/web-audio-api/#dom-audionode-disconnect-destinationnode
     pub fn disconnect_between(&mut self, from: NodeId, to: NodeId) {
+        self.cycles = None;
         let edge = self
             .graph
             .edges(from.0)
@@ -251,6 +377,7 @@ impl AudioGraph {
     ///
     /// https://webaudio.github.io/web-audio-api/#dom-audionode-disconnect-destinationnode-output
     pub fn disconnect_output_between(&mut self, out: PortId<OutputPort>, to: NodeId) {
+        self.cycles = None;
         let edge = self
             .graph
             .edges(out.node().0)
@@ -274,6 +401,7 @@ impl AudioGraph {
     ///
     /// https://webaudio.github.io/web-audio-api/#dom-audionode-disconnect-destinationparam
     pub fn disconnect_to(&mut self, node: NodeId, inp: PortId<InputPort>) {
+        self.cycles = None;
         let edge = self
             .graph
             .edges(node.0)
@@ -300,6 +428,7 @@ impl AudioGraph {
         out: PortId<OutputPort>,
         inp: PortId<InputPort>,
     ) {
+        self.cycles = None;
         let edge = self
             .graph
             .edges(out.node().0)
@@ -317,6 +446,145 @@ impl AudioGraph {
         }
     }
 
+    /// Mark a node as no longer referenced by the control thread
+    ///
+    /// The node keeps playing for as long as it can be heard, and is
+    /// removed from the graph afterwards by `collect_released_nodes`.
+    /// Its id must not be used anymore, as it may be reused by
+    /// nodes created later.
+    pub fn release_node(&mut self, node: NodeId) {
+        if node == self.dest_id || node == self.listener_id || self.released.contains(&node) {
+            return;
+        }
+        self.released.push(node);
+    }
+
+    /// Remove the released nodes which can't be heard anymore: the nodes
+    /// which aren't connected to anything downstream, and the nodes with
+    /// nothing connected upstream that are not actively processing, like
+    /// source nodes that have ended or effects whose tail is over
+    ///
+    /// Removing a node can make the nodes it was connected to removable,
+    /// these are removed as well. `removed` is called with each removed
+    /// node, so that it can be deallocated elsewhere, after which its
+    /// id can be reused.
+    ///
+    /// https://webaudio.github.io/web-audio-api/#AudioNode-actively-processing
+    pub(crate) fn collect_released_nodes<F>(&mut self, mut removed: F)
+    where
+        F: FnMut(NodeId, Box<dyn AudioNodeEngine>),
+    {
+        loop {
+            let graph = &self.graph;
+            let dests = &self.dests;
+            let listener_id = self.listener_id;
+            let removable = self.released.iter().position(|node| {
+                let has_outputs = dests.contains(node) || graph.edges(node.0).next().is_some();
+                let has_inputs = graph
+                    .edges_directed(node.0, Direction::Incoming)
+                    .any(|e| e.source() != listener_id.0);
+                !has_outputs || !has_inputs && !graph[node.0].engine().is_actively_processing()
+            });
+            match removable {
+                Some(index) => {
+                    let node = self.released.swap_remove(index);
+                    self.dests.retain(|dest| *dest != node);
+                    if let Some(engine) = self.vacate(node) {
+                        removed(node, engine);
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Disconnect a node from everything, and empty its slot
+    ///
+    /// The slot stays in the graph, so that removing a node doesn't
+    /// change the indices of the nodes added after it.
+    fn vacate(&mut self, node: NodeId) -> Option<Box<dyn AudioNodeEngine>> {
+        self.cycles = None;
+        for &direction in &[Direction::Outgoing, Direction::Incoming] {
+            loop {
+                let edge = self
+                    .graph
+                    .edges_directed(node.0, direction)
+                    .next()
+                    .map(|e| e.id());
+                match edge {
+                    Some(edge) => {
+                        self.graph.remove_edge(edge);
+                    }
+                    None => break,
+                }
+            }
+        }
+        self.graph[node.0].node.borrow_mut().take()
+    }
+
+    /// Describe the nodes of the graph and how they are connected
+    pub fn snapshot(&self) -> GraphSnapshot {
+        let nodes = self
+            .graph
+            .node_indices()
+            .filter(|ix| !self.graph[*ix].is_vacant())
+            .map(|ix| {
+                let mut node = self.graph[ix].engine();
+                let params = node
+                    .param_types()
+                    .into_iter()
+                    .filter_map(|param| {
+                        let value = node.get_param(param)?.value();
+                        Some(ParamSnapshot { param, value })
+                    })
+                    .collect();
+                NodeSnapshot {
+                    id: NodeId(ix),
+                    node_type: node.node_type(),
+                    channel_count: node.channel_count(),
+                    channel_count_mode: node.channel_count_mode(),
+                    channel_interpretation: node.channel_interpretation(),
+                    input_count: node.input_count(),
+                    output_count: node.output_count(),
+                    params,
+                }
+            })
+            .collect();
+
+        let mut connections = Vec::new();
+        for edge in (&self.graph).edge_references() {
+            for connection in &edge.weight().connections {
+                let output = match connection.output_idx {
+                    PortIndex::Port(output) => output,
+                    _ => unreachable!(),
+                };
+                let target = match connection.input_idx {
+                    PortIndex::Port(input) => ConnectionTarget::Input(input),
+                    PortIndex::Param(param) => ConnectionTarget::Param(param),
+                    PortIndex::Listener(()) => ConnectionTarget::Listener,
+                };
+                connections.push(ConnectionSnapshot {
+                    from: NodeId(edge.source()),
+                    output,
+                    to: NodeId(edge.target()),
+                    target,
+                });
+            }
+        }
+        connections.sort();
+
+        GraphSnapshot { nodes, connections }
+    }
+
+    /// The number of nodes in the graph, including the destination
+    /// and the listener
+    pub fn node_count(&self) -> usize {
+        self.graph
+            .node_indices()
+            .filter(|ix| !self.graph[*ix].is_vacant())
+            .count()
+    }
+
     /// Get the id of the destination node in this graph
     ///
     /// All graphs have a destination node, with one input port
@@ -347,169 +615,333 @@ impl AudioGraph {
         // children's output
         //
         // This will only visit each node once
-        let reversed = Reversed(&self.graph);
+        //
+        // Cycles are handled by not following the inputs of the nodes
+        // breaking them; these are instead visited once everything reachable
+        // from the destinations has been processed.
+        let cycles = match self.cycles.take() {
+            Some(cycles) => cycles,
+            None => self.find_cycles(),
+        };
+        let mut visited = self.graph.visit_map();
+        let mut stack = Vec::new();
+        let mut deferred = Vec::new();
 
-        let mut blocks: SmallVec<[SmallVec<[Block; 1]>; 1]> = SmallVec::new();
-        let mut output_counts: SmallVec<[u32; 1]> = SmallVec::new();
+        for dest in &self.dests {
+            self.visit(
+                dest.0,
+                &cycles,
+                &mut visited,
+                &mut stack,
+                &mut deferred,
+                info,
+            );
+        }
 
-        let mut visit = DfsPostOrder::empty(reversed);
+        // Now that the output of every node breaking a cycle has been consumed,
+        // compute their inputs. This may in turn reach other nodes breaking cycles.
+        let mut i = 0;
+        while i < deferred.len() {
+            let ix = deferred[i];
+            let sources: SmallVec<[NodeIndex<DefaultIx>; 2]> = self
+                .graph
+                .edges_directed(ix, Direction::Incoming)
+                .map(|e| e.source())
+                .collect();
+            for source in sources {
+                self.visit(
+                    source,
+                    &cycles,
+                    &mut visited,
+                    &mut stack,
+                    &mut deferred,
+                    info,
+                );
+            }
+            let mut curr = self.graph[ix].engine();
+            let chunk = self.collect_inputs(ix, &mut *curr);
+            curr.process_cycle_input(chunk, info);
+            i += 1;
+        }
 
-        for dest in &self.dests {
-            visit.move_to(dest.0);
-
-            while let Some(ix) = visit.next(reversed) {
-                let mut curr = self.graph[ix].node.borrow_mut();
-
-                let mut chunk = Chunk::default();
-                chunk
-                    .blocks
-                    .resize(curr.input_count() as usize, Default::default());
-
-                // if we have inputs, collect all the computed blocks
-                // and construct a Chunk
-
-                // set up scratch space to store all the blocks
-                blocks.clear();
-                blocks.resize(curr.input_count() as usize, Default::default());
-
-                let mode = curr.channel_count_mode();
-                let count = curr.channel_count();
-                let interpretation = curr.channel_interpretation();
-
-                // all edges to this node are from its dependencies
-                for edge in self.graph.edges_directed(ix, Direction::Incoming) {
-                    let edge = edge.weight();
-                    for connection in &edge.connections {
-                        let mut block = connection
-                            .cache
-                            .borrow_mut()
-                            .take()
-                            .expect("Cache should have been filled from traversal");
-
-                        match connection.input_idx {
-                            PortIndex::Port(idx) => {
-                                blocks[idx as usize].push(block);
-                            }
-                            PortIndex::Param(param) => {
-                                // param inputs are downmixed to mono
-                                // https://webaudio.github.io/web-audio-api/#dom-audionode-connect-destinationparam-output
-                                block.mix(1, ChannelInterpretation::Speakers);
-                                curr.get_param(param).add_block(block)
-                            }
-                            PortIndex::Listener(_) => curr.set_listenerdata(block),
-                        }
-                    }
+        self.cycles = Some(cycles);
+
+        // The destination node stores its output on itself, extract it.
+        self.graph[self.dest_id.0]
+            .engine()
+            .destination_data()
+            .expect("Destination node should have data cached")
+    }
+
+    /// Find the cycles in the graph, and how each of them is to be handled
+    ///
+    /// https://webaudio.github.io/web-audio-api/#cycles
+    fn find_cycles(&self) -> Cycles {
+        let mut cycles = Cycles {
+            breakers: self.graph.visit_map(),
+            muted: self.graph.visit_map(),
+        };
+        let mut cyclic = false;
+        let is_cycle = |scc: &[NodeIndex<DefaultIx>]| {
+            scc.len() > 1 || self.graph.find_edge(scc[0], scc[0]).is_some()
+        };
+
+        for scc in tarjan_scc(&self.graph) {
+            if !is_cycle(&scc) {
+                continue;
+            }
+            cyclic = true;
+            for ix in scc {
+                if self.graph[ix].engine().can_break_cycles() {
+                    cycles.breakers.visit(ix);
                 }
+            }
+        }
 
-                for (i, mut blocks) in blocks.drain().enumerate() {
-                    if blocks.len() == 0 {
-                        if mode == ChannelCountMode::Explicit {
-                            // It's silence, but mix it anyway
-                            chunk.blocks[i].mix(count, interpretation);
-                        }
-                    } else if blocks.len() == 1 {
-                        chunk.blocks[i] = blocks.pop().expect("`blocks` had length 1");
-                        match mode {
-                            ChannelCountMode::Explicit => {
-                                chunk.blocks[i].mix(count, interpretation);
-                            }
-                            ChannelCountMode::ClampedMax => {
-                                if chunk.blocks[i].chan_count() > count {
-                                    chunk.blocks[i].mix(count, interpretation);
-                                }
-                            }
-                            // It's one channel, it maxes itself
-                            ChannelCountMode::Max => (),
-                        }
-                    } else {
-                        let mix_count = match mode {
-                            ChannelCountMode::Explicit => count,
-                            _ => {
-                                let mut max = 0; // max channel count
-                                for block in &blocks {
-                                    max = cmp::max(max, block.chan_count());
-                                }
-                                if mode == ChannelCountMode::ClampedMax {
-                                    max = cmp::min(max, count);
-                                }
-                                max
-                            }
-                        };
-                        let block = blocks.into_iter().fold(Block::default(), |acc, mut block| {
-                            block.mix(mix_count, interpretation);
-                            acc.sum(block)
-                        });
-                        chunk.blocks[i] = block;
-                    }
+        if !cyclic {
+            return cycles;
+        }
+
+        // Any cycles left after splitting the nodes breaking cycles
+        // do not contain a DelayNode, and must be muted
+        let breakers = &cycles.breakers;
+        let split = EdgeFiltered::from_fn(&self.graph, |e| !breakers.is_visited(&e.target()));
+        let mut muted = self.graph.visit_map();
+        for scc in tarjan_scc(&split) {
+            if is_cycle(&scc) {
+                for ix in scc {
+                    muted.visit(ix);
                 }
+            }
+        }
+        cycles.muted = muted;
+        cycles
+    }
 
-                // actually run the node engine
-                let mut out = curr.process(chunk, info);
+    /// Process all the nodes `start` depends on, followed by `start` itself,
+    /// skipping the nodes that have already been visited this block
+    fn visit(
+        &self,
+        start: NodeIndex<DefaultIx>,
+        cycles: &Cycles,
+        visited: &mut VisitedMap,
+        stack: &mut Vec<(NodeIndex<DefaultIx>, bool)>,
+        deferred: &mut Vec<NodeIndex<DefaultIx>>,
+        info: &BlockInfo,
+    ) {
+        stack.push((start, false));
+
+        while let Some((ix, ready)) = stack.pop() {
+            let breaks_cycle = cycles.breakers.is_visited(&ix);
+            let muted = cycles.muted.is_visited(&ix);
+            if ready {
+                let mut curr = self.graph[ix].engine();
+                let out = if breaks_cycle {
+                    deferred.push(ix);
+                    curr.process_cycle_output(info)
+                } else if muted {
+                    let mut chunk = Chunk::default();
+                    chunk
+                        .blocks
+                        .resize(curr.output_count() as usize, Default::default());
+                    chunk
+                } else {
+                    let chunk = self.collect_inputs(ix, &mut *curr);
+                    curr.process(chunk, info)
+                };
+                self.store_outputs(ix, &*curr, out);
+                continue;
+            }
 
-                assert_eq!(out.len(), curr.output_count() as usize);
-                if curr.output_count() == 0 {
-                    continue;
+            if !visited.visit(ix) {
+                // already processed
+                continue;
+            }
+            stack.push((ix, true));
+            // The inputs of nodes breaking cycles are visited later,
+            // muted nodes do not need their inputs at all
+            if breaks_cycle || muted {
+                continue;
+            }
+            for edge in self.graph.edges_directed(ix, Direction::Incoming) {
+                if !visited.is_visited(&edge.source()) {
+                    stack.push((edge.source(), false));
                 }
+            }
+        }
+    }
 
-                // Count how many output connections fan out from each port
-                // This is so that we don't have to needlessly clone audio buffers
-                //
-                // If this is inefficient, we can instead maintain this data
-                // cached on the node
-                output_counts.clear();
-                output_counts.resize(curr.output_count() as usize, 0);
-                for edge in self.graph.edges(ix) {
-                    let edge = edge.weight();
-                    for conn in &edge.connections {
-                        if let PortIndex::Port(idx) = conn.output_idx {
-                            output_counts[idx as usize] += 1;
-                        } else {
-                            unreachable!()
+    /// Construct the input Chunk for a node from the blocks cached on
+    /// its incoming connections, mixing them as necessary
+    fn collect_inputs(&self, ix: NodeIndex<DefaultIx>, curr: &mut dyn AudioNodeEngine) -> Chunk {
+        let mut chunk = Chunk::default();
+        chunk
+            .blocks
+            .resize(curr.input_count() as usize, Default::default());
+
+        // if we have inputs, collect all the computed blocks
+        // and construct a Chunk
+
+        // set up scratch space to store all the blocks
+        let mut blocks: SmallVec<[SmallVec<[Block; 1]>; 1]> = SmallVec::new();
+        blocks.resize(curr.input_count() as usize, Default::default());
+
+        let mode = curr.channel_count_mode();
+        let count = curr.channel_count();
+        let interpretation = curr.channel_interpretation();
+
+        // all edges to this node are from its dependencies
+        for edge in self.graph.edges_directed(ix, Direction::Incoming) {
+            let edge = edge.weight();
+            for connection in &edge.connections {
+                let mut block = connection
+                    .cache
+                    .borrow_mut()
+                    .take()
+                    .expect("Cache should have been filled from traversal");
+
+                match connection.input_idx {
+                    PortIndex::Port(idx) => {
+                        blocks[idx as usize].push(block);
+                    }
+                    PortIndex::Param(param) => {
+                        // param inputs are downmixed to mono
+                        // https://webaudio.github.io/web-audio-api/#dom-audionode-connect-destinationparam-output
+                        block.mix(1, ChannelInterpretation::Speakers);
+                        if let Some(param) = curr.get_param(param) {
+                            param.add_block(block)
                         }
                     }
+                    PortIndex::Listener(_) => curr.set_listenerdata(block),
                 }
+            }
+        }
 
-                // all the edges from this node go to nodes which depend on it,
-                // i.e. the nodes it outputs to. Store the blocks for retrieval.
-                for edge in self.graph.edges(ix) {
-                    let edge = edge.weight();
-                    for conn in &edge.connections {
-                        if let PortIndex::Port(idx) = conn.output_idx {
-                            output_counts[idx as usize] -= 1;
-                            // if there are no consumers left after this, take the data
-                            let block = if output_counts[idx as usize] == 0 {
-                                out[conn.output_idx].take()
-                            } else {
-                                out[conn.output_idx].clone()
-                            };
-                            *conn.cache.borrow_mut() = Some(block);
-                        } else {
-                            unreachable!()
+        for (i, mut blocks) in blocks.drain().enumerate() {
+            if blocks.len() == 0 {
+                if mode == ChannelCountMode::Explicit {
+                    // It's silence, but mix it anyway
+                    chunk.blocks[i].mix(count, interpretation);
+                }
+            } else if blocks.len() == 1 {
+                chunk.blocks[i] = blocks.pop().expect("`blocks` had length 1");
+                match mode {
+                    ChannelCountMode::Explicit => {
+                        chunk.blocks[i].mix(count, interpretation);
+                    }
+                    ChannelCountMode::ClampedMax => {
+                        if chunk.blocks[i].chan_count() > count {
+                            chunk.blocks[i].mix(count, interpretation);
                         }
                     }
+                    // It's one channel, it maxes itself
+                    ChannelCountMode::Max => (),
+                }
+            } else {
+                let mix_count = match mode {
+                    ChannelCountMode::Explicit => count,
+                    _ => {
+                        let mut max = 0; // max channel count
+                        for block in &blocks {
+                            max = cmp::max(max, block.chan_count());
+                        }
+                        if mode == ChannelCountMode::ClampedMax {
+                            max = cmp::min(max, count);
+                        }
+                        max
+                    }
+                };
+                let block = blocks.into_iter().fold(Block::default(), |acc, mut block| {
+                    block.mix(mix_count, interpretation);
+                    acc.sum(block)
+                });
+                chunk.blocks[i] = block;
+            }
+        }
+
+        chunk
+    }
+
+    /// Store the output of a node on its outgoing connections, for
+    /// retrieval by the nodes which depend on it
+    fn store_outputs(&self, ix: NodeIndex<DefaultIx>, curr: &dyn AudioNodeEngine, mut out: Chunk) {
+        assert_eq!(out.len(), curr.output_count() as usize);
+        if curr.output_count() == 0 {
+            return;
+        }
+
+        // Count how many output connections fan out from each port
+        // This is so that we don't have to needlessly clone audio buffers
+        //
+        // If this is inefficient, we can instead maintain this data
+        // cached on the node
+        let mut output_counts: SmallVec<[u32; 1]> = SmallVec::new();
+        output_counts.resize(curr.output_count() as usize, 0);
+        for edge in self.graph.edges(ix) {
+            let edge = edge.weight();
+            for conn in &edge.connections {
+                if let PortIndex::Port(idx) = conn.output_idx {
+                    output_counts[idx as usize] += 1;
+                } else {
+                    unreachable!()
+                }
+            }
+        }
+
+        // all the edges from this node go to nodes which depend on it,
+        // i.e. the nodes it outputs to. Store the blocks for retrieval.
+        for edge in self.graph.edges(ix) {
+            let edge = edge.weight();
+            for conn in &edge.connections {
+                if let PortIndex::Port(idx) = conn.output_idx {
+                    output_counts[idx as usize] -= 1;
+                    // if there are no consumers left after this, take the data
+                    let block = if output_counts[idx as usize] == 0 {
+                        out[conn.output_idx].take()
+                    } else {
+                        out[conn.output_idx].clone()
+                    };
+                    *conn.cache.borrow_mut() = Some(block);
+                } else {
+                    unreachable!()
                 }
             }
         }
-        // The destination node stores its output on itself, extract it.
-        self.graph[self.dest_id.0]
-            .node
-            .borrow_mut()
-            .destination_data()
-            .expect("Destination node should have data cached")
     }
 
-    /// Obtain a mutable reference to a node
-    pub(crate) fn node_mut(&self, ix: NodeId) -> RefMut<Box<dyn AudioNodeEngine>> {
-        self.graph[ix.0].node.borrow_mut()
+    /// Obtain a mutable reference to a node, if it wasn't removed
+    pub(crate) fn node_mut(&self, ix: NodeId) -> Option<RefMut<dyn AudioNodeEngine>> {
+        let node = self.graph.node_weight(ix.0)?;
+        if node.is_vacant() {
+            return None;
+        }
+        Some(node.engine())
     }
 }
 
 impl Node {
     pub fn new(node: Box<dyn AudioNodeEngine>) -> Self {
         Node {
-            node: RefCell::new(node),
+            node: RefCell::new(Some(node)),
+        }
+    }
+
+    fn vacant() -> Self {
+        Node {
+            node: RefCell::new(None),
         }
     }
+
+    fn is_vacant(&self) -> bool {
+        self.node.borrow().is_none()
+    }
+
+    /// Vacant nodes have no connections, so they are never processed
+    fn engine(&self) -> RefMut<dyn AudioNodeEngine> {
+        RefMut::map(self.node.borrow_mut(), |node| {
+            &mut **node.as_mut().expect("Vacant nodes are not processed")
+        })
+    }
 }
 
 impl Edge {
@@ -529,3 +961,60 @@ impl Connection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::Tick;
+    use gain_node::GainNode;
+
+    #[test]
+    fn connections_to_missing_ports_are_ignored() {
+        let mut graph = AudioGraph::new(2);
+        let mut ids = NodeIdAllocator::new(&graph);
+        let gain = ids.allocate();
+        let engine = GainNode::new(Default::default(), Default::default(), 44100.);
+        graph.add_node(gain, Box::new(engine));
+        let missing = ids.allocate();
+
+        graph.add_edge(gain.output(0), graph.dest_id().input(0));
+        graph.add_edge(gain.output(1), graph.dest_id().input(0));
+        graph.add_edge(gain.output(0), gain.param(ParamType::Frequency));
+        graph.add_edge(missing.output(0), gain.input(0));
+        graph.add_edge(gain.output(0), missing.input(0));
+        assert_eq!(graph.snapshot().connections.len(), 1);
+
+        let info = BlockInfo {
+            sample_rate: 44100.,
+            frame: Tick(0),
+            time: 0.,
+        };
+        graph.process(&info);
+    }
+
+    #[test]
+    fn cycles_are_kept_until_the_graph_changes() {
+        let mut graph = AudioGraph::new(2);
+        let mut ids = NodeIdAllocator::new(&graph);
+        let gain = ids.allocate();
+        let engine = GainNode::new(Default::default(), Default::default(), 44100.);
+        graph.add_node(gain, Box::new(engine));
+        graph.add_edge(gain.output(0), graph.dest_id().input(0));
+        graph.add_edge(gain.output(0), gain.input(0));
+
+        let info = BlockInfo {
+            sample_rate: 44100.,
+            frame: Tick(0),
+            time: 0.,
+        };
+        graph.process(&info);
+        assert!(graph.cycles.as_ref().unwrap().muted.is_visited(&gain.0));
+        graph.process(&info);
+        assert!(graph.cycles.is_some());
+
+        graph.disconnect_between(gain, gain);
+        assert!(graph.cycles.is_none());
+        graph.process(&info);
+        assert!(!graph.cycles.as_ref().unwrap().muted.is_visited(&gain.0));
+    }
+}
@@ -0,0 +1,107 @@
+use graph::NodeId;
+use node::{AudioNodeType, ChannelCountMode, ChannelInterpretation};
+use param::ParamType;
+use serde_json;
+use std::fmt::Write;
+
+/// The state of the render side graph at the time it was taken,
+/// as returned by `AudioContext::graph_snapshot`
+///
+/// Nodes and connections are sorted, so that snapshots of the
+/// same graph always serialize the same way.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct GraphSnapshot {
+    pub nodes: Vec<NodeSnapshot>,
+    pub connections: Vec<ConnectionSnapshot>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NodeSnapshot {
+    pub id: NodeId,
+    pub node_type: AudioNodeType,
+    pub channel_count: u8,
+    pub channel_count_mode: ChannelCountMode,
+    pub channel_interpretation: ChannelInterpretation,
+    pub input_count: u32,
+    pub output_count: u32,
+    pub params: Vec<ParamSnapshot>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct ParamSnapshot {
+    pub param: ParamType,
+    /// The value the param had in the last render quantum
+    pub value: f32,
+}
+
+/// What an output of a node is connected to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum ConnectionTarget {
+    Input(u32),
+    Param(ParamType),
+    /// The implicit connection from the AudioListener to PannerNodes
+    Listener,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct ConnectionSnapshot {
+    pub from: NodeId,
+    pub output: u32,
+    pub to: NodeId,
+    pub target: ConnectionTarget,
+}
+
+impl GraphSnapshot {
+    pub fn node(&self, id: NodeId) -> Option<&NodeSnapshot> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("snapshots are always serializable")
+    }
+
+    /// A Graphviz graph, with params and listener connections
+    /// drawn as dashed edges
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        dot.push_str("digraph AudioGraph {\n");
+        dot.push_str("    rankdir=LR;\n");
+        dot.push_str("    node [shape=box];\n");
+        for node in &self.nodes {
+            let mut label = format!(
+                "{:?} {}\\n{} channels, {:?}, {:?}",
+                node.node_type,
+                node.id.index(),
+                node.channel_count,
+                node.channel_count_mode,
+                node.channel_interpretation
+            );
+            for param in &node.params {
+                let _ = write!(label, "\\n{:?} = {}", param.param, param.value);
+            }
+            let _ = writeln!(dot, "    n{} [label=\"{}\"];", node.id.index(), label);
+        }
+        for connection in &self.connections {
+            let (label, style) = match connection.target {
+                ConnectionTarget::Input(input) => {
+                    (format!("{} -> {}", connection.output, input), "")
+                }
+                ConnectionTarget::Param(param) => (
+                    format!("{} -> {:?}", connection.output, param),
+                    ", style=dashed",
+                ),
+                ConnectionTarget::Listener => ("listener".to_owned(), ", style=dashed"),
+            };
+            let _ = writeln!(
+                dot,
+                "    n{} -> n{} [label=\"{}\"{}];",
+                connection.from.index(),
+                connection.to.index(),
+                label,
+                style
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
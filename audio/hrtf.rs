@@ -0,0 +1,570 @@
+//! Head related transfer functions, for the HRTF panning model
+//!
+//! https://webaudio.github.io/web-audio-api/#Spatialization-hrtf-panning
+//!
+//! With the `kemar` feature, the head related impulse responses are the
+//! compact set of the MIT Media Lab's KEMAR measurements (Gardner and
+//! Martin, 1994), embedded from `resources/kemar_compact.bin`, see
+//! `MEASURED`. They are interpolated over the azimuth/elevation grid of
+//! the measurements.
+//!
+//! Without it, as a fallback, the impulse responses are synthesized on
+//! the same grid from the spherical head and pinna model of Brown and Duda
+//! ("A Structural Model for Binaural Sound Synthesis", 1998). This only
+//! approximates measured binaural rendering, and lacks the detail of
+//! real pinnae.
+
+use block::frames_per_block_usize;
+#[cfg(not(feature = "kemar"))]
+use fft::Fft;
+#[cfg(not(feature = "kemar"))]
+use num_complex::Complex32;
+#[cfg(feature = "kemar")]
+use resampler::{self, ResampleQuality};
+#[cfg(not(feature = "kemar"))]
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex, Weak};
+
+/// The elevations of the KEMAR measurements, in degrees, and how many
+/// azimuths were measured all around at each of them, every
+/// 360 / count degrees starting from the front
+const GRID: [(f32, usize); 14] = [
+    (-40., 56),
+    (-30., 60),
+    (-20., 72),
+    (-10., 72),
+    (0., 72),
+    (10., 72),
+    (20., 72),
+    (30., 60),
+    (40., 56),
+    (50., 45),
+    (60., 36),
+    (70., 24),
+    (80., 12),
+    (90., 1),
+];
+
+/// The length of the impulse responses, in frames, at 44.1kHz
+const KERNEL_LEN_44100: usize = 128;
+
+/// The compact KEMAR set: for each elevation of `GRID`, the impulse
+/// responses of the azimuths from 0 to 180 degrees, the other side
+/// being mirrored. Each one is KERNEL_LEN_44100 interleaved (left, right)
+/// frames of 16 bits little endian samples at 44.1kHz, which is the data
+/// chunk of the set's `H<elevation>e<azimuth>a.wav` files.
+#[cfg(feature = "kemar")]
+static MEASURED: &[u8] = include_bytes!("resources/kemar_compact.bin");
+
+/// Samples below this fraction of an impulse response's peak are
+/// considered to come before its onset
+#[cfg(feature = "kemar")]
+const ONSET_THRESHOLD: f32 = 0.1;
+
+/// All synthesized kernels are delayed by this many frames, at 44.1kHz,
+/// so that the fractional delays of the pinna echoes don't wrap around
+#[cfg(not(feature = "kemar"))]
+const BULK_DELAY_44100: f32 = 4.;
+
+/// The head radius, in meters
+#[cfg(not(feature = "kemar"))]
+const HEAD_RADIUS: f32 = 0.0875;
+#[cfg(not(feature = "kemar"))]
+const SPEED_OF_SOUND: f32 = 343.;
+/// The ears are a bit behind the center of the head
+#[cfg(not(feature = "kemar"))]
+const EAR_AZIMUTH: f32 = 100.;
+/// The pinna echoes (reflection coefficient, A, B, D) from Brown and
+/// Duda, the delays being in frames at 44.1kHz
+#[cfg(not(feature = "kemar"))]
+const PINNA_ECHOES: [(f32, f32, f32, f32); 5] = [
+    (0.5, 1., 2., 1.),
+    (-1., 5., 4., 0.5),
+    (0.5, 5., 7., 0.5),
+    (-0.25, 5., 11., 0.5),
+    (0.25, 5., 13., 0.5),
+];
+
+/// The impulse responses and interaural delays for a single direction
+#[derive(Clone, Default)]
+pub(crate) struct HrtfKernel {
+    left: Vec<f32>,
+    right: Vec<f32>,
+    /// In frames
+    left_delay: f32,
+    right_delay: f32,
+}
+
+impl HrtfKernel {
+    fn zeroed(len: usize) -> Self {
+        HrtfKernel {
+            left: vec![0.; len],
+            right: vec![0.; len],
+            left_delay: 0.,
+            right_delay: 0.,
+        }
+    }
+
+    /// The kernel for the mirrored direction, left and right swapped
+    fn mirrored(&self) -> Self {
+        HrtfKernel {
+            left: self.right.clone(),
+            right: self.left.clone(),
+            left_delay: self.right_delay,
+            right_delay: self.left_delay,
+        }
+    }
+
+    fn clear(&mut self) {
+        for s in self.left.iter_mut().chain(self.right.iter_mut()) {
+            *s = 0.;
+        }
+        self.left_delay = 0.;
+        self.right_delay = 0.;
+    }
+
+    fn copy_from(&mut self, other: &HrtfKernel) {
+        self.left.copy_from_slice(&other.left);
+        self.right.copy_from_slice(&other.right);
+        self.left_delay = other.left_delay;
+        self.right_delay = other.right_delay;
+    }
+
+    /// self += other * weight
+    fn accumulate(&mut self, other: &HrtfKernel, weight: f32) {
+        for (s, o) in self.left.iter_mut().zip(&other.left) {
+            *s += o * weight;
+        }
+        for (s, o) in self.right.iter_mut().zip(&other.right) {
+            *s += o * weight;
+        }
+        self.left_delay += other.left_delay * weight;
+        self.right_delay += other.right_delay * weight;
+    }
+}
+
+/// A unit vector pointing towards azimuth and elevation, in degrees,
+/// as (right, up, front)
+#[cfg(not(feature = "kemar"))]
+fn direction(azimuth: f32, elevation: f32) -> (f32, f32, f32) {
+    let (az, el) = (azimuth.to_radians(), elevation.to_radians());
+    (az.sin() * el.cos(), el.sin(), az.cos() * el.cos())
+}
+
+lazy_static! {
+    /// The databases in use, at most one per sample rate
+    static ref DATABASES: Mutex<Vec<Weak<HrtfDatabase>>> = Mutex::new(Vec::new());
+}
+
+/// The impulse responses for every direction of the grid,
+/// for a given sample rate
+pub(crate) struct HrtfDatabase {
+    sample_rate: f32,
+    kernel_len: usize,
+    /// The longest interaural delay, in frames
+    max_delay: f32,
+    /// Where the kernels of each elevation of `GRID` start in `kernels`
+    ring_starts: Vec<usize>,
+    /// Indexed by elevation, then azimuth
+    kernels: Vec<HrtfKernel>,
+}
+
+impl HrtfDatabase {
+    /// The database for `sample_rate`, shared with the other panners
+    /// using the same sample rate. This is expensive the first time,
+    /// and must not be called on the render thread.
+    pub fn shared(sample_rate: f32) -> Arc<Self> {
+        let mut databases = DATABASES.lock().unwrap();
+        databases.retain(|database| database.upgrade().is_some());
+        let existing = databases
+            .iter()
+            .filter_map(|database| database.upgrade())
+            .find(|database| database.sample_rate == sample_rate);
+        existing.unwrap_or_else(|| {
+            let database = Arc::new(Self::new(sample_rate));
+            databases.push(Arc::downgrade(&database));
+            database
+        })
+    }
+
+    fn new(sample_rate: f32) -> Self {
+        let mut ring_starts = Vec::with_capacity(GRID.len());
+        let mut kernels = Vec::new();
+        for (ring, &(elevation, count)) in GRID.iter().enumerate() {
+            ring_starts.push(kernels.len());
+            // The measurements are symmetric, only the right half of
+            // each ring is computed and the other one is mirrored
+            let half = count / 2 + 1;
+            for a in 0..half {
+                let azimuth = a as f32 * 360. / count as f32;
+                kernels.push(Self::kernel_for(ring, a, azimuth, elevation, sample_rate));
+            }
+            for a in half..count {
+                let mirrored = kernels[ring_starts[ring] + count - a].mirrored();
+                kernels.push(mirrored);
+            }
+        }
+
+        // Only the interaural delays matter, remove the common one
+        let min_delay = kernels
+            .iter()
+            .map(|k| k.left_delay.min(k.right_delay))
+            .fold(f32::INFINITY, f32::min);
+        let mut max_delay: f32 = 0.;
+        for kernel in &mut kernels {
+            kernel.left_delay -= min_delay;
+            kernel.right_delay -= min_delay;
+            max_delay = max_delay.max(kernel.left_delay).max(kernel.right_delay);
+        }
+
+        HrtfDatabase {
+            sample_rate,
+            kernel_len: kernels[0].left.len(),
+            max_delay,
+            ring_starts,
+            kernels,
+        }
+    }
+
+    /// The kernel measured at the `index`th azimuth of `ring`, with
+    /// the onset of both impulse responses moved to their delays, so that
+    /// interpolating between directions doesn't comb filter
+    #[cfg(feature = "kemar")]
+    fn kernel_for(
+        ring: usize,
+        index: usize,
+        _azimuth: f32,
+        _elevation: f32,
+        sample_rate: f32,
+    ) -> HrtfKernel {
+        let position: usize = GRID[..ring].iter().map(|&(_, count)| count / 2 + 1).sum();
+        let frame_bytes = 4;
+        let start = (position + index) * KERNEL_LEN_44100 * frame_bytes;
+        let data = &MEASURED[start..start + KERNEL_LEN_44100 * frame_bytes];
+        let sample = |frame: usize, ear: usize| {
+            let i = frame * frame_bytes + ear * 2;
+            f32::from(i16::from_le_bytes([data[i], data[i + 1]])) / 32768.
+        };
+
+        let ratio = sample_rate / 44100.;
+        let kernel_len = (KERNEL_LEN_44100 as f32 * ratio).ceil() as usize;
+        let mut ears = [(Vec::new(), 0.), (Vec::new(), 0.)];
+        for (ear, &mut (ref mut ir, ref mut delay)) in ears.iter_mut().enumerate() {
+            let measured: Vec<f32> = (0..KERNEL_LEN_44100).map(|f| sample(f, ear)).collect();
+            // a context rate Speex can't resample to keeps the measured one
+            let resampled =
+                resampler::resample(&measured, 44100., sample_rate, ResampleQuality::Best)
+                    .unwrap_or_else(|_| measured.clone());
+            let peak = resampled.iter().fold(0f32, |max, s| max.max(s.abs()));
+            let onset = resampled
+                .iter()
+                .position(|s| s.abs() >= peak * ONSET_THRESHOLD)
+                .unwrap_or(0);
+            // keep a couple of frames before the onset, for the ringing
+            // of the resampling filter
+            let onset = onset.saturating_sub((2. * ratio).ceil() as usize);
+            *ir = resampled[onset..].to_vec();
+            ir.resize(kernel_len, 0.);
+            *delay = onset as f32;
+        }
+        let [(left, left_delay), (right, right_delay)] = ears;
+        HrtfKernel {
+            left,
+            right,
+            left_delay,
+            right_delay,
+        }
+    }
+
+    /// The synthesized kernel for a source at azimuth and elevation
+    #[cfg(not(feature = "kemar"))]
+    fn kernel_for(
+        _ring: usize,
+        _index: usize,
+        azimuth: f32,
+        elevation: f32,
+        sample_rate: f32,
+    ) -> HrtfKernel {
+        let ratio = sample_rate / 44100.;
+        let kernel_len = (KERNEL_LEN_44100 as f32 * ratio).ceil() as usize;
+        let fft_size = (2 * kernel_len).next_power_of_two();
+        let mut fft = Fft::new(fft_size);
+        let mut spectrum = vec![Complex32::new(0., 0.); fft_size / 2 + 1];
+        let mut impulse = vec![0.; fft_size];
+
+        let mut kernel = HrtfKernel::default();
+        for &(ear_azimuth, is_left) in &[(-EAR_AZIMUTH, true), (EAR_AZIMUTH, false)] {
+            let delay = Self::ear_response(
+                azimuth,
+                elevation,
+                ear_azimuth,
+                sample_rate,
+                fft_size,
+                &mut spectrum,
+            );
+            fft.real_inverse(&spectrum, &mut impulse);
+            let mut ir: Vec<f32> = impulse[..kernel_len].to_vec();
+            // fade out the end, so that truncation doesn't click
+            let fade = kernel_len / 4;
+            for i in 0..fade {
+                ir[kernel_len - fade + i] *= 0.5 * (1. + (PI * i as f32 / fade as f32).cos());
+            }
+            if is_left {
+                kernel.left = ir;
+                kernel.left_delay = delay;
+            } else {
+                kernel.right = ir;
+                kernel.right_delay = delay;
+            }
+        }
+        kernel
+    }
+
+    /// Fill `spectrum` with the frequency response of the ear at
+    /// `ear_azimuth` for a source at azimuth and elevation, and return
+    /// the interaural delay of that ear, in frames
+    #[cfg(not(feature = "kemar"))]
+    fn ear_response(
+        azimuth: f32,
+        elevation: f32,
+        ear_azimuth: f32,
+        sample_rate: f32,
+        fft_size: usize,
+        spectrum: &mut [Complex32],
+    ) -> f32 {
+        let ratio = sample_rate / 44100.;
+        let source = direction(azimuth, elevation);
+        let ear = direction(ear_azimuth, 0.);
+        let cos_incidence = source.0 * ear.0 + source.1 * ear.1 + source.2 * ear.2;
+        let incidence = cos_incidence.max(-1.).min(1.).acos();
+
+        // Head shadow, a one pole one zero filter whose high frequency
+        // gain depends on the angle of incidence
+        const ALPHA_MIN: f32 = 0.1;
+        const THETA_MIN: f32 = 150.;
+        let alpha = (1. + ALPHA_MIN / 2.)
+            + (1. - ALPHA_MIN / 2.)
+                * (incidence.to_degrees() / THETA_MIN * 180.)
+                    .to_radians()
+                    .cos();
+        let omega_0 = SPEED_OF_SOUND / HEAD_RADIUS;
+
+        // Woodworth's formula for the time of arrival at the ear,
+        // offset so that it is never negative
+        let head_delay = HEAD_RADIUS / SPEED_OF_SOUND;
+        let delay = if incidence < PI / 2. {
+            head_delay * (1. - incidence.cos())
+        } else {
+            head_delay * (1. + incidence - PI / 2.)
+        };
+
+        // The pinna echoes depend on the azimuth relative to the ear,
+        // folded to the front, and on the elevation
+        let mut lateral = (azimuth - ear_azimuth + 90.).to_radians();
+        lateral = lateral.sin().asin();
+        let elevation = elevation.to_radians();
+
+        for (k, bin) in spectrum.iter_mut().enumerate() {
+            let omega = 2. * PI * k as f32 * sample_rate / fft_size as f32;
+            let shadow =
+                Complex32::new(2. * omega_0, alpha * omega) / Complex32::new(2. * omega_0, omega);
+            let mut pinna = Complex32::new(1., 0.);
+            for &(rho, a, b, d) in PINNA_ECHOES.iter() {
+                let tau = (a * (lateral / 2.).cos() * (d * (PI / 2. - elevation)).sin() + b)
+                    * ratio
+                    / sample_rate;
+                pinna += Complex32::from_polar(&rho, &(-omega * tau));
+            }
+            let bulk = Complex32::from_polar(&1., &(-omega * BULK_DELAY_44100 / 44100.));
+            *bin = shadow * pinna * bulk;
+        }
+        delay * sample_rate
+    }
+
+    fn kernel(&self, ring: usize, azimuth_index: usize) -> &HrtfKernel {
+        &self.kernels[self.ring_starts[ring] + azimuth_index % GRID[ring].1]
+    }
+
+    /// out += the kernel of `ring` at `azimuth`, linearly interpolated
+    /// between the two closest azimuths of the ring, times `weight`
+    fn accumulate_ring(&self, ring: usize, azimuth: f32, weight: f32, out: &mut HrtfKernel) {
+        let a = azimuth * GRID[ring].1 as f32 / 360.;
+        let a0 = a.floor();
+        let a_factor = a - a0;
+        let a0 = a0 as usize;
+        out.accumulate(self.kernel(ring, a0), weight * (1. - a_factor));
+        out.accumulate(self.kernel(ring, a0 + 1), weight * a_factor);
+    }
+
+    /// Interpolation between the two closest azimuths of the two
+    /// closest elevations of the grid
+    fn interpolate(&self, azimuth: f32, elevation: f32, out: &mut HrtfKernel) {
+        let mut azimuth = azimuth % 360.;
+        if azimuth < 0. {
+            azimuth += 360.;
+        }
+        // there are no measurements below the lowest elevation
+        let elevation = elevation.max(GRID[0].0).min(90.);
+        let e0 = GRID[..GRID.len() - 1]
+            .iter()
+            .rposition(|&(e, _)| e <= elevation)
+            .unwrap_or(0);
+        let e_factor = (elevation - GRID[e0].0) / (GRID[e0 + 1].0 - GRID[e0].0);
+
+        out.clear();
+        self.accumulate_ring(e0, azimuth, 1. - e_factor, out);
+        self.accumulate_ring(e0 + 1, azimuth, e_factor, out);
+    }
+}
+
+/// Binaural rendering of one or two input channels
+///
+/// The direction is sampled once per render quantum, and the output
+/// is crossfaded between the previous and the new direction.
+pub struct HrtfPanner {
+    database: Arc<HrtfDatabase>,
+    current: HrtfKernel,
+    previous: HrtfKernel,
+    direction: Option<(f32, f32)>,
+    /// For each ear, enough past input for the longest interaural
+    /// delay and the kernel, followed by the current block
+    history: [Vec<f32>; 2],
+    /// The input of the current block, delayed
+    delayed: Vec<f32>,
+    scratch: Vec<f32>,
+}
+
+impl HrtfPanner {
+    pub(crate) fn new(sample_rate: f32) -> Self {
+        let database = HrtfDatabase::shared(sample_rate);
+        let max_delay = database.max_delay.ceil() as usize;
+        let history_len = database.kernel_len + max_delay + 2 + frames_per_block_usize();
+        let kernel_len = database.kernel_len;
+        HrtfPanner {
+            database,
+            current: HrtfKernel::zeroed(kernel_len),
+            previous: HrtfKernel::zeroed(kernel_len),
+            direction: None,
+            history: [vec![0.; history_len], vec![0.; history_len]],
+            delayed: vec![0.; kernel_len - 1 + frames_per_block_usize()],
+            scratch: vec![0.; frames_per_block_usize()],
+        }
+    }
+
+    /// How long the output lasts after the input stops, in frames
+    pub fn tail_len(&self) -> usize {
+        self.history[0].len()
+    }
+
+    /// Convolve the history of `ear` with `kernel`, delayed by
+    /// `delay` frames, into `out`
+    fn render(history: &[f32], kernel: &[f32], delay: f32, delayed: &mut [f32], out: &mut [f32]) {
+        // the delayed input, from kernel.len() - 1 frames before the
+        // start of the block to its end
+        let start = history.len() - delayed.len();
+        for (i, d) in delayed.iter_mut().enumerate() {
+            let pos = (start + i) as f32 - delay;
+            let lo = pos.floor();
+            let frac = pos - lo;
+            let lo = lo as usize;
+            // the delay can be zero, for sources right in front of an ear
+            let next = history[(lo + 1).min(history.len() - 1)];
+            *d = (1. - frac) * history[lo] + frac * next;
+        }
+        let len = kernel.len();
+        for (n, o) in out.iter_mut().enumerate() {
+            let mut sum = 0.;
+            for (k, h) in kernel.iter().enumerate() {
+                sum += h * delayed[n + len - 1 - k];
+            }
+            *o = sum;
+        }
+    }
+
+    /// Pan a block of input to `left` and `right`, at azimuth and
+    /// elevation in degrees. For stereo inputs, each ear only hears
+    /// its channel; for mono inputs `left` and `right` must hold the
+    /// same data.
+    pub fn pan(&mut self, azimuth: f32, elevation: f32, left: &mut [f32], right: &mut [f32]) {
+        for (history, input) in self.history.iter_mut().zip(&[&*left, &*right]) {
+            history.copy_within(frames_per_block_usize().., 0);
+            let len = history.len();
+            history[len - frames_per_block_usize()..].copy_from_slice(input);
+        }
+
+        let changed = self.direction != Some((azimuth, elevation));
+        if changed {
+            ::std::mem::swap(&mut self.current, &mut self.previous);
+            self.database
+                .interpolate(azimuth, elevation, &mut self.current);
+            if self.direction.is_none() {
+                // nothing to crossfade from
+                self.previous.copy_from(&self.current);
+            }
+            self.direction = Some((azimuth, elevation));
+        }
+
+        for (ear, out) in [&mut *left, &mut *right].iter_mut().enumerate() {
+            let (kernel, delay) = if ear == 0 {
+                (&self.current.left, self.current.left_delay)
+            } else {
+                (&self.current.right, self.current.right_delay)
+            };
+            Self::render(&self.history[ear], kernel, delay, &mut self.delayed, out);
+            if changed {
+                let (kernel, delay) = if ear == 0 {
+                    (&self.previous.left, self.previous.left_delay)
+                } else {
+                    (&self.previous.right, self.previous.right_delay)
+                };
+                Self::render(
+                    &self.history[ear],
+                    kernel,
+                    delay,
+                    &mut self.delayed,
+                    &mut self.scratch,
+                );
+                let len = out.len() as f32;
+                for (i, (o, p)) in out.iter_mut().zip(&self.scratch).enumerate() {
+                    let x = i as f32 / len;
+                    *o = x * *o + (1. - x) * p;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_over_the_grid() {
+        let database = HrtfDatabase::new(44100.);
+        let mut kernel = HrtfKernel::zeroed(database.kernel_len);
+
+        // On the grid, the measurement itself
+        database.interpolate(10., 0., &mut kernel);
+        let expected = database.kernel(4, 2);
+        assert_eq!(kernel.left, expected.left);
+        assert_eq!(kernel.right_delay, expected.right_delay);
+
+        // Halfway between two elevations with different azimuth steps
+        database.interpolate(30., 45., &mut kernel);
+        let mut expected = HrtfKernel::zeroed(database.kernel_len);
+        database.accumulate_ring(8, 30., 1., &mut expected);
+        let lower_delay = expected.left_delay;
+        expected.clear();
+        database.accumulate_ring(9, 30., 1., &mut expected);
+        assert!((kernel.left_delay - (lower_delay + expected.left_delay) / 2.).abs() < 1e-4);
+
+        // Both sides are mirrors of each other
+        let mut mirrored = HrtfKernel::zeroed(database.kernel_len);
+        database.interpolate(37., 15., &mut kernel);
+        database.interpolate(-37., 15., &mut mirrored);
+        for (l, r) in kernel.left.iter().zip(&mirrored.right) {
+            assert!((l - r).abs() < 1e-6);
+        }
+        assert!((kernel.left_delay - mirrored.right_delay).abs() < 1e-4);
+    }
+}
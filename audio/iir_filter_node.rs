@@ -1,8 +1,11 @@
 use block::Chunk;
 use log::warn;
-use node::{AudioNodeEngine, AudioNodeType, BlockInfo, ChannelInfo};
+use node::FrequencyResponse;
+use node::{AudioNodeEngine, AudioNodeType, BlockInfo, ChannelInfo, QUIESCENT_LEVEL};
 use num_complex::Complex64;
 use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::mpsc::Sender;
 use std::sync::Arc;
 
 const MAX_COEFFS: usize = 20;
@@ -13,6 +16,12 @@ pub struct IIRFilterNodeOptions {
     pub feedback: Arc<Vec<f64>>,
 }
 
+#[derive(Clone, Debug)]
+pub enum IIRFilterNodeMessage {
+    /// The response of the filter at these frequencies, in Hz
+    GetFrequencyResponse(Vec<f32>, Sender<FrequencyResponse>),
+}
+
 #[derive(Clone)]
 struct IIRFilter {
     feedforward: Arc<Vec<f64>>,
@@ -70,11 +79,20 @@ impl IIRFilter {
 
         output as f32
     }
+
+    fn is_quiescent(&self) -> bool {
+        self.inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .all(|v| v.abs() < QUIESCENT_LEVEL)
+    }
 }
 
 #[derive(AudioNodeCommon)]
 pub struct IIRFilterNode {
     channel_info: ChannelInfo,
+    feedforward: Arc<Vec<f64>>,
+    feedback: Arc<Vec<f64>>,
     filters: Vec<IIRFilter>,
 }
 
@@ -116,6 +134,8 @@ impl IIRFilterNode {
 
         Self {
             filters: vec![filter; channel_info.count as usize],
+            feedforward: options.feedforward,
+            feedback: options.feedback,
             channel_info,
         }
     }
@@ -137,20 +157,63 @@ impl IIRFilterNode {
                 mag_response[idx] = std::f32::NAN;
                 phase_response[idx] = std::f32::NAN;
             } else {
-                let f = (-f as f64) * std::f64::consts::PI;
-                let z = Complex64::new(f64::cos(f), f64::sin(f));
-                let numerator = Self::sum(feedforward, z);
-                let denominator = Self::sum(feedback, z);
-
-                let response = numerator / denominator;
+                let response = Self::response_at(feedforward, feedback, f as f64 * PI);
                 mag_response[idx] = response.norm() as f32;
                 phase_response[idx] = response.arg() as f32;
             }
         });
     }
 
+    /// The response of the filter at the angular frequency `omega`,
+    /// in radians per sample
+    fn response_at(feedforward: &[f64], feedback: &[f64], omega: f64) -> Complex64 {
+        // z^-1 on the unit circle
+        let z = Complex64::new(f64::cos(-omega), f64::sin(-omega));
+        let numerator = Self::sum(feedforward, z);
+        let denominator = Self::sum(feedback, z);
+        numerator / denominator
+    }
+
+    /// The response of the filter at each of `frequency_hz`, NaN outside
+    /// of [0, nyquist]
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-iirfilternode-getfrequencyresponse
+    fn frequency_response(
+        feedforward: &[f64],
+        feedback: &[f64],
+        frequency_hz: &[f32],
+        sample_rate: f32,
+    ) -> FrequencyResponse {
+        let nyquist = sample_rate / 2.;
+        let mut response = FrequencyResponse::with_capacity(frequency_hz.len());
+        for &f in frequency_hz {
+            if f < 0. || f > nyquist || f.is_nan() {
+                response.push(None);
+            } else {
+                let omega = 2. * PI * f as f64 / sample_rate as f64;
+                response.push(Some(Self::response_at(feedforward, feedback, omega)));
+            }
+        }
+        response
+    }
+
+    fn handle_message(&mut self, message: IIRFilterNodeMessage, sample_rate: f32) {
+        match message {
+            IIRFilterNodeMessage::GetFrequencyResponse(frequency_hz, tx) => {
+                let response = Self::frequency_response(
+                    &self.feedforward,
+                    &self.feedback,
+                    &frequency_hz,
+                    sample_rate,
+                );
+                let _ = tx.send(response);
+            }
+        }
+    }
+
+    /// The polynomial c[0] + c[1] * z + c[2] * z^2 + ...
     fn sum(coeffs: &[f64], z: Complex64) -> Complex64 {
-        coeffs.iter().fold(Complex64::new(0.0, 0.0), |acc, &coeff| {
+        coeffs.iter().rev().fold(Complex64::new(0.0, 0.0), |acc, &coeff| {
             acc * z + Complex64::new(coeff, 0.0)
         })
     }
@@ -161,6 +224,10 @@ impl AudioNodeEngine for IIRFilterNode {
         AudioNodeType::IIRFilterNode
     }
 
+    fn is_actively_processing(&self) -> bool {
+        !self.filters.iter().all(IIRFilter::is_quiescent)
+    }
+
     fn process(&mut self, inputs: Chunk, _info: &BlockInfo) -> Chunk {
         debug_assert!(inputs.len() == 1);
 
@@ -179,4 +246,21 @@ impl AudioNodeEngine for IIRFilterNode {
         }
         inputs
     }
+
+    make_message_handler!(IIRFilterNode: handle_message);
+}
+
+/// The response of an IIRFilterNode with these options at each of
+/// `frequency_hz`, for a context running at `sample_rate`
+pub fn frequency_response(
+    options: &IIRFilterNodeOptions,
+    sample_rate: f32,
+    frequency_hz: &[f32],
+) -> FrequencyResponse {
+    IIRFilterNode::frequency_response(
+        &options.feedforward,
+        &options.feedback,
+        frequency_hz,
+        sample_rate,
+    )
 }
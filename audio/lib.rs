@@ -9,10 +9,14 @@ extern crate servo_media_player as player;
 extern crate boxfnonce;
 extern crate byte_slice_cast;
 extern crate euclid;
+#[macro_use]
+extern crate lazy_static;
 extern crate log;
 extern crate num_complex;
 extern crate num_traits;
 extern crate petgraph;
+extern crate serde;
+extern crate serde_json;
 extern crate smallvec;
 extern crate speexdsp_resampler;
 #[macro_use]
@@ -27,29 +31,55 @@ pub mod buffer_source_node;
 pub mod channel_node;
 pub mod constant_source_node;
 pub mod context;
+pub mod convolver_node;
 pub mod decoder;
+pub mod delay_node;
 pub mod destination_node;
+pub mod dynamics_compressor_node;
+pub mod fft;
 pub mod gain_node;
 pub mod graph;
+pub mod graph_snapshot;
+pub mod hrtf;
 pub mod iir_filter_node;
 pub mod listener;
 pub mod media_element_source_node;
 pub mod media_stream_destination_node;
 pub mod media_stream_source_node;
+pub mod meter_node;
 pub mod node;
 pub mod offline_sink;
 pub mod oscillator_node;
 pub mod panner_node;
 pub mod param;
+pub mod render_capacity;
 pub mod render_thread;
+pub mod resampler;
+pub mod simd;
 pub mod sink;
+mod spsc;
 pub mod stereo_panner;
+mod triple_buffer;
+pub mod wav_writer;
 pub mod wave_shaper_node;
+pub mod worklet_node;
 
 pub trait AudioBackend {
     type Sink: sink::AudioSink + 'static;
     fn make_decoder() -> Box<dyn decoder::AudioDecoder>;
     fn make_sink() -> Result<Self::Sink, sink::AudioSinkError>;
+    /// Find the audio output device with the given id, as enumerated by
+    /// the backend's `MediaDeviceMonitor`, or the default device for
+    /// `None`, and prepare an output of `Self::Sink` for it. This can
+    /// block, it is called on the control thread.
+    fn make_sink_output(
+        sink_id: Option<&str>,
+    ) -> Result<sink::AudioSinkOutput, sink::AudioSinkError> {
+        Ok(sink::AudioSinkOutput::new(
+            sink_id.map(|id| id.to_owned()),
+            None,
+        ))
+    }
     fn make_streamreader(
         id: servo_media_streams::MediaStreamId,
         sample_rate: f32,
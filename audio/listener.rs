@@ -73,18 +73,32 @@ impl AudioNodeEngine for AudioListenerNode {
         0
     }
 
-    fn get_param(&mut self, id: ParamType) -> &mut Param {
+    fn param_types(&self) -> Vec<ParamType> {
+        vec![
+            ParamType::Position(ParamDir::X),
+            ParamType::Position(ParamDir::Y),
+            ParamType::Position(ParamDir::Z),
+            ParamType::Forward(ParamDir::X),
+            ParamType::Forward(ParamDir::Y),
+            ParamType::Forward(ParamDir::Z),
+            ParamType::Up(ParamDir::X),
+            ParamType::Up(ParamDir::Y),
+            ParamType::Up(ParamDir::Z),
+        ]
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
         match id {
-            ParamType::Position(ParamDir::X) => &mut self.position_x,
-            ParamType::Position(ParamDir::Y) => &mut self.position_y,
-            ParamType::Position(ParamDir::Z) => &mut self.position_z,
-            ParamType::Forward(ParamDir::X) => &mut self.forward_x,
-            ParamType::Forward(ParamDir::Y) => &mut self.forward_y,
-            ParamType::Forward(ParamDir::Z) => &mut self.forward_z,
-            ParamType::Up(ParamDir::X) => &mut self.up_x,
-            ParamType::Up(ParamDir::Y) => &mut self.up_y,
-            ParamType::Up(ParamDir::Z) => &mut self.up_z,
-            _ => panic!("Unknown param {:?} for AudioListenerNode", id),
+            ParamType::Position(ParamDir::X) => Some(&mut self.position_x),
+            ParamType::Position(ParamDir::Y) => Some(&mut self.position_y),
+            ParamType::Position(ParamDir::Z) => Some(&mut self.position_z),
+            ParamType::Forward(ParamDir::X) => Some(&mut self.forward_x),
+            ParamType::Forward(ParamDir::Y) => Some(&mut self.forward_y),
+            ParamType::Forward(ParamDir::Z) => Some(&mut self.forward_z),
+            ParamType::Up(ParamDir::X) => Some(&mut self.up_x),
+            ParamType::Up(ParamDir::Y) => Some(&mut self.up_y),
+            ParamType::Up(ParamDir::Z) => Some(&mut self.up_z),
+            _ => None,
         }
     }
 }
@@ -1,4 +1,4 @@
-use block::{Block, Chunk, FRAMES_PER_BLOCK};
+use block::{frames_per_block, Block, Chunk};
 use node::{AudioNodeEngine, AudioNodeType, BlockInfo, ChannelInfo};
 use player::audio::AudioRenderer;
 use std::collections::hash_map::Entry;
@@ -47,6 +47,10 @@ impl AudioNodeEngine for MediaElementSourceNode {
         AudioNodeType::MediaElementSourceNode
     }
 
+    fn is_actively_processing(&self) -> bool {
+        true
+    }
+
     fn process(&mut self, mut inputs: Chunk, _info: &BlockInfo) -> Chunk {
         debug_assert!(inputs.len() == 0);
 
@@ -60,14 +64,14 @@ impl AudioNodeEngine for MediaElementSourceNode {
 
         let len = buffers[0].len();
 
-        let frames_per_block = FRAMES_PER_BLOCK.0 as usize;
+        let frames_per_block = frames_per_block().0 as usize;
         let samples_to_copy = if self.playback_offset + frames_per_block > len {
             len - self.playback_offset
         } else {
             frames_per_block
         };
         let next_offset = self.playback_offset + samples_to_copy;
-        if samples_to_copy == FRAMES_PER_BLOCK.0 as usize {
+        if samples_to_copy == frames_per_block {
             // copy entire chan
             let mut block = Block::empty();
             for chan in 0..chans {
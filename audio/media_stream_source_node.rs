@@ -2,7 +2,6 @@ use crate::AudioStreamReader;
 use block::Chunk;
 use node::{AudioNodeEngine, BlockInfo};
 use node::{AudioNodeType, ChannelInfo};
-use param::{Param, ParamType};
 
 #[derive(AudioNodeCommon)]
 pub(crate) struct MediaStreamSourceNode {
@@ -26,6 +25,10 @@ impl AudioNodeEngine for MediaStreamSourceNode {
         AudioNodeType::MediaStreamSourceNode
     }
 
+    fn is_actively_processing(&self) -> bool {
+        true
+    }
+
     fn process(&mut self, mut inputs: Chunk, _: &BlockInfo) -> Chunk {
         debug_assert!(inputs.len() == 0);
 
@@ -43,8 +46,4 @@ impl AudioNodeEngine for MediaStreamSourceNode {
     fn input_count(&self) -> u32 {
         0
     }
-
-    fn get_param(&mut self, _: ParamType) -> &mut Param {
-        panic!("No params on MediaStreamSourceNode");
-    }
 }
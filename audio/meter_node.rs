@@ -0,0 +1,592 @@
+use block::{frames_per_block_usize, Chunk};
+use log::warn;
+use node::{AudioNodeEngine, AudioNodeType, BlockInfo, ChannelInfo, ChannelInterpretation};
+use std::f64::consts::PI;
+use triple_buffer::{self, Reader, Writer};
+use wave_shaper_node::lowpass_kernel;
+
+pub struct MeterNodeOptions {
+    /// How often the measurements are published, in seconds
+    pub update_interval: f64,
+    /// Where the node publishes its measurements, see `Meter::channel`
+    pub publisher: Option<MeterPublisher>,
+}
+
+impl Default for MeterNodeOptions {
+    fn default() -> Self {
+        MeterNodeOptions {
+            update_interval: 0.1,
+            publisher: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum MeterNodeMessage {
+    SetUpdateInterval(f64),
+    /// Start measuring the integrated loudness and the maximum true
+    /// peaks again
+    Reset,
+}
+
+/// The levels of a channel, in dBFS
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelLevels {
+    /// The highest sample since the previous measurements
+    pub peak: f32,
+    /// The RMS level since the previous measurements
+    pub rms: f32,
+    /// The highest true peak since the previous measurements, in dBTP
+    pub true_peak: f32,
+    /// The highest true peak since the node was created or reset, in dBTP
+    pub max_true_peak: f32,
+}
+
+/// The measurements published by a MeterNode
+///
+/// Loudness is measured following ITU-R BS.1770-4 and EBU R 128.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurements {
+    /// The context time at the end of the measured audio, in seconds
+    pub time: f64,
+    pub channels: Vec<ChannelLevels>,
+    /// The loudness of the last 400ms, in LUFS
+    pub momentary: f64,
+    /// The loudness of the last 3s, in LUFS
+    pub short_term: f64,
+    /// The gated loudness since the node was created or reset, in LUFS
+    pub integrated: f64,
+}
+
+/// The render thread end of a `Meter`
+pub struct MeterPublisher {
+    writer: Writer<Measurements>,
+}
+
+/// Reads the measurements of a MeterNode from any thread, without
+/// locking
+pub struct Meter {
+    reader: Reader<Measurements>,
+}
+
+impl Meter {
+    /// Create a meter, and the publisher to pass to its node in
+    /// `MeterNodeOptions::publisher`
+    pub fn channel() -> (Meter, MeterPublisher) {
+        let (writer, reader) = triple_buffer::triple_buffer(Measurements {
+            time: 0.,
+            channels: Vec::new(),
+            momentary: f64::NEG_INFINITY,
+            short_term: f64::NEG_INFINITY,
+            integrated: f64::NEG_INFINITY,
+        });
+        (Meter { reader }, MeterPublisher { writer })
+    }
+
+    /// The last published measurements
+    pub fn measurements(&mut self) -> &Measurements {
+        self.reader.read()
+    }
+}
+
+/// The loudness of a mean square, weighted and summed over the channels
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10. * energy.log10()
+}
+
+/// A biquad of the K-weighting filter, in transposed direct form II
+#[derive(Clone)]
+struct KFilter {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl KFilter {
+    /// The high shelf modelling the acoustic effect of the head
+    fn pre_filter(sample_rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain / 20.);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1. + k / q + k * k;
+        KFilter {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2. * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+            z: [0.; 2],
+        }
+    }
+
+    /// The RLB high pass
+    fn rlb_filter(sample_rate: f64) -> Self {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1. + k / q + k * k;
+        KFilter {
+            b: [1., -2., 1.],
+            a: [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+            z: [0.; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The length of the interpolation filter of the true peak meter
+const TRUE_PEAK_TAPS: usize = 129;
+const TRUE_PEAK_OVERSAMPLING: usize = 4;
+
+/// Finds the peaks between samples, by upsampling 4 times
+struct TruePeak {
+    kernel: Vec<f32>,
+    history: Vec<f32>,
+}
+
+impl TruePeak {
+    fn new(frames: usize) -> Self {
+        let kernel = lowpass_kernel(TRUE_PEAK_TAPS, 0.5 / TRUE_PEAK_OVERSAMPLING as f64);
+        let kept = Self::kept();
+        let mut history = Vec::with_capacity(kept + frames);
+        history.resize(kept, 0.);
+        TruePeak { kernel, history }
+    }
+
+    /// The number of past samples the filter needs
+    fn kept() -> usize {
+        TRUE_PEAK_TAPS / TRUE_PEAK_OVERSAMPLING
+    }
+
+    /// The highest absolute value of the upsampled `input`
+    fn process(&mut self, input: &[f32]) -> f32 {
+        let kept = Self::kept();
+        self.history.extend_from_slice(input);
+        let mut peak: f32 = 0.;
+        for n in 0..input.len() {
+            let past = self.history[..=kept + n].iter().rev();
+            for phase in 0..TRUE_PEAK_OVERSAMPLING {
+                let taps = self
+                    .kernel
+                    .iter()
+                    .skip(phase)
+                    .step_by(TRUE_PEAK_OVERSAMPLING);
+                let sample: f32 = taps.zip(past.clone()).map(|(k, x)| k * x).sum();
+                peak = peak.max(sample.abs() * TRUE_PEAK_OVERSAMPLING as f32);
+            }
+        }
+        let len = self.history.len();
+        self.history.drain(..len - kept);
+        peak
+    }
+}
+
+struct ChannelMeter {
+    pre_filter: KFilter,
+    rlb_filter: KFilter,
+    true_peak: TruePeak,
+    /// The highest absolute sample since the last measurements
+    peak: f32,
+    sum_squares: f64,
+    /// The highest true peak since the last measurements
+    interval_true_peak: f32,
+    max_true_peak: f32,
+}
+
+impl ChannelMeter {
+    fn new(sample_rate: f64, frames: usize) -> Self {
+        ChannelMeter {
+            pre_filter: KFilter::pre_filter(sample_rate),
+            rlb_filter: KFilter::rlb_filter(sample_rate),
+            true_peak: TruePeak::new(frames),
+            peak: 0.,
+            sum_squares: 0.,
+            interval_true_peak: 0.,
+            max_true_peak: 0.,
+        }
+    }
+
+    /// Measure `samples`, adding their K-weighted energy times `weight`
+    /// to `energy`
+    fn process(&mut self, samples: &[f32], weight: f64, energy: &mut [f64]) {
+        for (&sample, energy) in samples.iter().zip(energy.iter_mut()) {
+            self.peak = self.peak.max(sample.abs());
+            self.sum_squares += sample as f64 * sample as f64;
+            let weighted = self
+                .rlb_filter
+                .process(self.pre_filter.process(sample as f64));
+            *energy += weight * weighted * weighted;
+        }
+        let true_peak = self.true_peak.process(samples);
+        self.interval_true_peak = self.interval_true_peak.max(true_peak);
+        self.max_true_peak = self.max_true_peak.max(true_peak);
+    }
+
+    fn levels(&self, frames: usize) -> ChannelLevels {
+        ChannelLevels {
+            peak: 20. * self.peak.log10(),
+            rms: (10. * (self.sum_squares / frames as f64).log10()) as f32,
+            true_peak: 20. * self.interval_true_peak.log10(),
+            max_true_peak: 20. * self.max_true_peak.log10(),
+        }
+    }
+
+    fn start_interval(&mut self) {
+        self.peak = 0.;
+        self.sum_squares = 0.;
+        self.interval_true_peak = 0.;
+    }
+}
+
+/// The number of 100ms segments in the short-term window
+const SHORT_TERM_SEGMENTS: usize = 30;
+/// The number of 100ms segments in the momentary window, and in the
+/// gating blocks of the integrated loudness
+const MOMENTARY_SEGMENTS: usize = 4;
+
+/// Gating blocks quieter than this are ignored
+const ABSOLUTE_GATE: f64 = -70.;
+/// Gating blocks this far below the loudness of the blocks above the
+/// absolute gate are ignored
+const RELATIVE_GATE: f64 = -10.;
+/// The gating blocks are sorted by loudness in bins of this width,
+/// which is the precision of the relative gate
+const GATE_BIN_WIDTH: f64 = 0.1;
+const GATE_BINS: usize = 1000;
+
+/// The gating blocks in a range of loudness
+#[derive(Clone, Copy, Default)]
+struct GateBin {
+    blocks: u64,
+    energy: f64,
+}
+
+/// The momentary, short-term and integrated loudness
+///
+/// The energy is summed over segments of 100ms, the overlapping 400ms
+/// windows of the momentary loudness being the gating blocks of the
+/// integrated loudness. Those are kept in a histogram, so that the
+/// measurement runs in constant memory.
+struct Loudness {
+    segment_frames: usize,
+    frames_in_segment: usize,
+    segment_energy: f64,
+    /// The mean energy of the last segments, the oldest at `next`
+    segments: [f64; SHORT_TERM_SEGMENTS],
+    next: usize,
+    /// The number of segments since the last reset
+    count: usize,
+    histogram: Vec<GateBin>,
+}
+
+impl Loudness {
+    fn new(sample_rate: f64) -> Self {
+        Loudness {
+            segment_frames: (sample_rate / 10.).round() as usize,
+            frames_in_segment: 0,
+            segment_energy: 0.,
+            segments: [0.; SHORT_TERM_SEGMENTS],
+            next: 0,
+            count: 0,
+            histogram: vec![GateBin::default(); GATE_BINS],
+        }
+    }
+
+    fn reset(&mut self) {
+        for bin in &mut self.histogram {
+            *bin = GateBin::default();
+        }
+        self.count = 0;
+    }
+
+    /// Add the K-weighted energy of each frame
+    fn process(&mut self, energy: &[f64]) {
+        for &frame in energy {
+            self.segment_energy += frame;
+            self.frames_in_segment += 1;
+            if self.frames_in_segment == self.segment_frames {
+                self.end_segment();
+            }
+        }
+    }
+
+    fn end_segment(&mut self) {
+        self.segments[self.next] = self.segment_energy / self.segment_frames as f64;
+        self.next = (self.next + 1) % SHORT_TERM_SEGMENTS;
+        self.segment_energy = 0.;
+        self.frames_in_segment = 0;
+        self.count += 1;
+        if self.count >= MOMENTARY_SEGMENTS {
+            self.add_gating_block(self.mean_energy(MOMENTARY_SEGMENTS));
+        }
+    }
+
+    fn add_gating_block(&mut self, energy: f64) {
+        let loudness = loudness(energy);
+        if loudness <= ABSOLUTE_GATE {
+            return;
+        }
+        let bin = ((loudness - ABSOLUTE_GATE) / GATE_BIN_WIDTH) as usize;
+        let bin = &mut self.histogram[bin.min(GATE_BINS - 1)];
+        bin.blocks += 1;
+        bin.energy += energy;
+    }
+
+    /// The mean energy of the last `segments` segments
+    fn mean_energy(&self, segments: usize) -> f64 {
+        (1..=segments)
+            .map(|i| self.segments[(self.next + SHORT_TERM_SEGMENTS - i) % SHORT_TERM_SEGMENTS])
+            .sum::<f64>()
+            / segments as f64
+    }
+
+    fn momentary(&self) -> f64 {
+        loudness(self.mean_energy(MOMENTARY_SEGMENTS))
+    }
+
+    fn short_term(&self) -> f64 {
+        loudness(self.mean_energy(SHORT_TERM_SEGMENTS))
+    }
+
+    fn integrated(&self) -> f64 {
+        let gated_loudness = |bins: &[GateBin]| {
+            let blocks: u64 = bins.iter().map(|bin| bin.blocks).sum();
+            let energy: f64 = bins.iter().map(|bin| bin.energy).sum();
+            if blocks == 0 {
+                f64::NEG_INFINITY
+            } else {
+                loudness(energy / blocks as f64)
+            }
+        };
+        let threshold = gated_loudness(&self.histogram) + RELATIVE_GATE;
+        let first = ((threshold - ABSOLUTE_GATE) / GATE_BIN_WIDTH)
+            .ceil()
+            .max(0.) as usize;
+        gated_loudness(&self.histogram[first.min(GATE_BINS)..])
+    }
+}
+
+#[derive(AudioNodeCommon)]
+pub(crate) struct MeterNode {
+    channel_info: ChannelInfo,
+    sample_rate: f64,
+    publisher: Option<MeterPublisher>,
+    /// The number of frames between measurements
+    interval: usize,
+    frames_in_interval: usize,
+    channels: Vec<ChannelMeter>,
+    loudness: Loudness,
+    /// The K-weighted energy of each frame of the current block
+    energy: Vec<f64>,
+    /// Stands for the samples of silent blocks
+    silence: Vec<f32>,
+}
+
+impl MeterNode {
+    pub fn new(options: MeterNodeOptions, channel_info: ChannelInfo, sample_rate: f32) -> Self {
+        let frames = frames_per_block_usize();
+        Self {
+            channel_info,
+            sample_rate: sample_rate as f64,
+            publisher: options.publisher,
+            interval: Self::interval_frames(options.update_interval, sample_rate),
+            frames_in_interval: 0,
+            channels: Vec::new(),
+            loudness: Loudness::new(sample_rate as f64),
+            energy: vec![0.; frames],
+            silence: vec![0.; frames],
+        }
+    }
+
+    fn interval_frames(interval: f64, sample_rate: f32) -> usize {
+        ((interval * sample_rate as f64).round() as usize).max(1)
+    }
+
+    fn handle_meter_message(&mut self, message: MeterNodeMessage, sample_rate: f32) {
+        match message {
+            MeterNodeMessage::SetUpdateInterval(interval) => {
+                if interval > 0. {
+                    self.interval = Self::interval_frames(interval, sample_rate);
+                } else {
+                    warn!("Ignoring invalid update interval {}", interval);
+                }
+            }
+            MeterNodeMessage::Reset => {
+                self.loudness.reset();
+                for channel in &mut self.channels {
+                    channel.max_true_peak = 0.;
+                }
+            }
+        }
+    }
+
+    /// The weight of each channel in the loudness, surround channels
+    /// being louder and the LFE channel being left out
+    fn channel_weight(&self, chan: usize, count: usize) -> f64 {
+        if self.channel_info.interpretation == ChannelInterpretation::Discrete {
+            return 1.;
+        }
+        match (count, chan) {
+            (4, 2) | (4, 3) | (5, 3) | (5, 4) | (6, 4) | (6, 5) => 1.41,
+            (6, 3) => 0.,
+            _ => 1.,
+        }
+    }
+
+    fn publish(&mut self, time: f64) {
+        let frames = self.frames_in_interval;
+        let publisher = match self.publisher {
+            Some(ref mut publisher) => publisher,
+            None => return,
+        };
+        let measurements = publisher.writer.back_mut();
+        measurements.time = time;
+        measurements.channels.clear();
+        measurements
+            .channels
+            .extend(self.channels.iter().map(|channel| channel.levels(frames)));
+        measurements.momentary = self.loudness.momentary();
+        measurements.short_term = self.loudness.short_term();
+        measurements.integrated = self.loudness.integrated();
+        publisher.writer.publish();
+    }
+}
+
+impl AudioNodeEngine for MeterNode {
+    fn node_type(&self) -> AudioNodeType {
+        AudioNodeType::MeterNode
+    }
+
+    fn process(&mut self, inputs: Chunk, info: &BlockInfo) -> Chunk {
+        debug_assert!(inputs.len() == 1);
+
+        if self.publisher.is_none() {
+            return inputs;
+        }
+
+        let block = &inputs.blocks[0];
+        let count = block.chan_count() as usize;
+        let frames = block.frames();
+        if self.channels.len() != count {
+            let (sample_rate, block_frames) = (self.sample_rate, self.energy.len());
+            self.channels
+                .resize_with(count, || ChannelMeter::new(sample_rate, block_frames));
+        }
+
+        for energy in &mut self.energy {
+            *energy = 0.;
+        }
+        for chan in 0..count {
+            let weight = self.channel_weight(chan, count);
+            let samples = if block.is_silence() {
+                &self.silence[..frames]
+            } else {
+                block.data_chan(chan as u8)
+            };
+            self.channels[chan].process(samples, weight, &mut self.energy[..frames]);
+        }
+        self.loudness.process(&self.energy[..frames]);
+
+        self.frames_in_interval += frames;
+        if self.frames_in_interval >= self.interval {
+            self.publish(info.time + frames as f64 / self.sample_rate);
+            self.frames_in_interval = 0;
+            for channel in &mut self.channels {
+                channel.start_interval();
+            }
+        }
+
+        // the meter doesn't modify its input
+        inputs
+    }
+
+    make_message_handler!(MeterNode: handle_meter_message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::{Block, Tick};
+
+    const RATE: f32 = 48000.;
+
+    /// Meter `seconds` of a sine with the given frequency and phase
+    fn run(node: &mut MeterNode, frame: &mut usize, seconds: f32, frequency: f64, phase: f64) {
+        let frames = frames_per_block_usize();
+        let blocks = (seconds * RATE) as usize / frames;
+        for _ in 0..blocks {
+            let samples = (*frame..*frame + frames)
+                .map(|i| (2. * PI * frequency * i as f64 / RATE as f64 + phase).sin() as f32)
+                .collect();
+            let mut chunk = Chunk::default();
+            chunk.blocks.push(Block::for_vec(samples));
+            let info = BlockInfo {
+                sample_rate: RATE,
+                frame: Tick(*frame as u64),
+                time: *frame as f64 / RATE as f64,
+            };
+            node.process(chunk, &info);
+            *frame += frames;
+        }
+    }
+
+    fn meter() -> (Meter, MeterNode) {
+        let (meter, publisher) = Meter::channel();
+        let options = MeterNodeOptions {
+            publisher: Some(publisher),
+            ..Default::default()
+        };
+        let node = MeterNode::new(options, ChannelInfo::default(), RATE);
+        (meter, node)
+    }
+
+    #[test]
+    fn full_scale_sine() {
+        // BS.1770 calibration: a full scale 997 Hz sine in one channel
+        // measures -3.01 LKFS
+        let (mut meter, mut node) = meter();
+        let mut frame = 0;
+        run(&mut node, &mut frame, 4., 997., 0.);
+        let measurements = meter.measurements().clone();
+        let end = frame as f64 / RATE as f64;
+        assert!(measurements.time <= end && measurements.time > end - 0.1);
+        assert!((measurements.momentary + 3.01).abs() < 0.05);
+        assert!((measurements.short_term + 3.01).abs() < 0.05);
+        assert!((measurements.integrated + 3.01).abs() < 0.05);
+        let levels = measurements.channels[0];
+        assert!(levels.peak > -0.01 && levels.peak <= 0.);
+        assert!((levels.rms + 3.01).abs() < 0.01);
+        assert!(levels.true_peak.abs() < 0.1);
+
+        // Silence is below the absolute gate, only the blocks overlapping
+        // the end of the sine lower the integrated loudness
+        run(&mut node, &mut frame, 4., 0., 0.);
+        let measurements = meter.measurements();
+        assert_eq!(measurements.momentary, f64::NEG_INFINITY);
+        assert!((measurements.integrated + 3.18).abs() < 0.05);
+        assert_eq!(measurements.channels[0].peak, f32::NEG_INFINITY);
+        assert!(measurements.channels[0].max_true_peak.abs() < 0.1);
+    }
+
+    #[test]
+    fn true_peak_between_samples() {
+        // A quarter of the sample rate, sampled 45 degrees away from the
+        // peaks
+        let (mut meter, mut node) = meter();
+        let mut frame = 0;
+        run(&mut node, &mut frame, 1., RATE as f64 / 4., PI / 4.);
+        let levels = meter.measurements().channels[0];
+        assert!((levels.peak + 3.01).abs() < 0.01);
+        assert!(levels.true_peak.abs() < 0.1);
+    }
+}
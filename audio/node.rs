@@ -1,37 +1,50 @@
+use analyser_node::{AnalyserNodeMessage, AnalyserNodeOptions};
 use biquad_filter_node::{BiquadFilterNodeMessage, BiquadFilterNodeOptions};
 use block::{Block, Chunk, Tick};
 use boxfnonce::SendBoxFnOnce;
 use buffer_source_node::{AudioBufferSourceNodeMessage, AudioBufferSourceNodeOptions};
 use channel_node::ChannelNodeOptions;
 use constant_source_node::ConstantSourceNodeOptions;
+use convolver_node::{ConvolverNodeMessage, ConvolverNodeOptions};
+use delay_node::DelayNodeOptions;
+use dynamics_compressor_node::{DynamicsCompressorNodeMessage, DynamicsCompressorNodeOptions};
 use gain_node::GainNodeOptions;
-use iir_filter_node::IIRFilterNodeOptions;
+use iir_filter_node::{IIRFilterNodeMessage, IIRFilterNodeOptions};
 use media_element_source_node::MediaElementSourceNodeMessage;
+use meter_node::{MeterNodeMessage, MeterNodeOptions};
+use num_complex::Complex64;
 use oscillator_node::{OscillatorNodeMessage, OscillatorNodeOptions};
 use panner_node::{PannerNodeMessage, PannerNodeOptions};
-use param::{Param, ParamRate, ParamType, UserAutomationEvent};
+use param::UserAutomationEvent;
+use param::{
+    AutomationQuery, AutomationSnapshot, Param, ParamDescriptor, ParamEvent, ParamRate, ParamType,
+};
+use render_thread::Garbage;
 use servo_media_streams::{MediaSocket, MediaStreamId};
 use std::sync::mpsc::Sender;
 use stereo_panner::StereoPannerOptions;
 use wave_shaper_node::{WaveShaperNodeMessage, WaveShaperNodeOptions};
+use worklet_node::AudioWorkletNodeOptions;
 
 /// Information required to construct an audio node
 pub enum AudioNodeInit {
-    AnalyserNode(Box<dyn FnMut(Block) + Send>),
+    AnalyserNode(AnalyserNodeOptions),
     BiquadFilterNode(BiquadFilterNodeOptions),
     AudioBuffer,
     AudioBufferSourceNode(AudioBufferSourceNodeOptions),
+    AudioWorkletNode(AudioWorkletNodeOptions),
     ChannelMergerNode(ChannelNodeOptions),
     ChannelSplitterNode,
     ConstantSourceNode(ConstantSourceNodeOptions),
-    ConvolverNode,
-    DelayNode,
-    DynamicsCompressionNode,
+    ConvolverNode(ConvolverNodeOptions),
+    DelayNode(DelayNodeOptions),
+    DynamicsCompressionNode(DynamicsCompressorNodeOptions),
     GainNode(GainNodeOptions),
     IIRFilterNode(IIRFilterNodeOptions),
     MediaElementSourceNode,
     MediaStreamDestinationNode(Box<dyn MediaSocket>),
     MediaStreamSourceNode(MediaStreamId),
+    MeterNode(MeterNodeOptions),
     OscillatorNode(OscillatorNodeOptions),
     PannerNode(PannerNodeOptions),
     PeriodicWave,
@@ -41,7 +54,7 @@ pub enum AudioNodeInit {
 }
 
 /// Type of AudioNodeEngine.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum AudioNodeType {
     /// Not a constructable node
     AudioListenerNode,
@@ -49,6 +62,7 @@ pub enum AudioNodeType {
     BiquadFilterNode,
     AudioBuffer,
     AudioBufferSourceNode,
+    AudioWorkletNode,
     ChannelMergerNode,
     ChannelSplitterNode,
     ConstantSourceNode,
@@ -61,6 +75,7 @@ pub enum AudioNodeType {
     MediaElementSourceNode,
     MediaStreamDestinationNode,
     MediaStreamSourceNode,
+    MeterNode,
     OscillatorNode,
     PannerNode,
     PeriodicWave,
@@ -69,14 +84,14 @@ pub enum AudioNodeType {
     WaveShaperNode,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
 pub enum ChannelCountMode {
     Max,
     ClampedMax,
     Explicit,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
 pub enum ChannelInterpretation {
     Discrete,
     Speakers,
@@ -119,31 +134,78 @@ pub(crate) trait AudioNodeCommon {
     fn channel_info_mut(&mut self) -> &mut ChannelInfo;
 }
 
+/// The level below which the state of a recursive filter is
+/// considered silent, about -180dB
+pub(crate) const QUIESCENT_LEVEL: f64 = 1e-9;
+
 /// This trait represents the common features of all audio nodes.
 pub(crate) trait AudioNodeEngine: Send + AudioNodeCommon {
     fn node_type(&self) -> AudioNodeType;
 
     fn process(&mut self, inputs: Chunk, info: &BlockInfo) -> Chunk;
 
-    fn message(&mut self, msg: AudioNodeMessage, sample_rate: f32) {
+    /// Handle a message from the control thread
+    ///
+    /// Messages about params the node doesn't have are ignored, the
+    /// control thread reports them as errors before sending them.
+    /// What the message replaces is handed to `dispose`, to be
+    /// deallocated on the control thread.
+    fn message(
+        &mut self,
+        msg: AudioNodeMessage,
+        sample_rate: f32,
+        dispose: &mut dyn FnMut(Garbage),
+    ) {
         match msg {
             AudioNodeMessage::GetParamValue(id, tx) => {
-                let _ = tx.send(self.get_param(id).value());
+                if let Some(param) = self.get_param(id) {
+                    let _ = tx.send(param.value());
+                }
+            }
+            AudioNodeMessage::GetParamDescriptor(id, tx) => {
+                if let Some(param) = self.get_param(id) {
+                    let _ = tx.send(param.descriptor());
+                }
+            }
+            AudioNodeMessage::SnapshotParamAutomation(id, mut snapshot, tx) => {
+                if let Some(param) = self.get_param(id) {
+                    param.snapshot_into(&mut snapshot);
+                    let _ = tx.send(snapshot);
+                }
             }
             AudioNodeMessage::SetChannelCount(c) => self.set_channel_count(c),
             AudioNodeMessage::SetChannelMode(c) => self.set_channel_count_mode(c),
             AudioNodeMessage::SetChannelInterpretation(c) => self.set_channel_interpretation(c),
             AudioNodeMessage::SetParam(id, event) => {
-                self.get_param(id).insert_event(event.to_event(sample_rate))
+                if let Some(param) = self.get_param(id) {
+                    param.insert_event(event.to_event(sample_rate), dispose)
+                }
+            }
+            AudioNodeMessage::InsertParamEvent(id, event) => {
+                if let Some(param) = self.get_param(id) {
+                    param.insert_event(event.into_event(), dispose)
+                }
+            }
+            AudioNodeMessage::SetParamRate(id, rate) => {
+                if let Some(param) = self.get_param(id) {
+                    param.set_rate(rate)
+                }
             }
-            AudioNodeMessage::SetParamRate(id, rate) => self.get_param(id).set_rate(rate),
             _ => self.message_specific(msg, sample_rate),
         }
+        if let Some(garbage) = self.take_garbage() {
+            dispose(garbage);
+        }
     }
 
     /// Messages specific to this node
     fn message_specific(&mut self, _: AudioNodeMessage, _sample_rate: f32) {}
 
+    /// What the last message specific to this node replaced, if anything
+    fn take_garbage(&mut self) -> Option<Garbage> {
+        None
+    }
+
     fn input_count(&self) -> u32 {
         1
     }
@@ -174,36 +236,127 @@ pub(crate) trait AudioNodeEngine: Send + AudioNodeCommon {
         self.channel_info_mut().mode = m;
     }
 
+    /// Whether the node may output sound even if its inputs stay
+    /// silent: a source node that has not ended, or a node with
+    /// tail time left
+    ///
+    /// Nodes released by the control thread are removed from the
+    /// graph once this is false and nothing is connected to them.
+    ///
+    /// https://webaudio.github.io/web-audio-api/#actively-processing
+    fn is_actively_processing(&self) -> bool {
+        false
+    }
+
     /// If we're the destination node, extract the contained data
     fn destination_data(&mut self) -> Option<Chunk> {
         None
     }
 
-    fn get_param(&mut self, _: ParamType) -> &mut Param {
-        panic!("No params on node {:?}", self.node_type())
+    /// Whether this node may be split into a reader and a writer half
+    /// so that it can be part of a cycle in the graph.
+    ///
+    /// Only DelayNodes can do this.
+    ///
+    /// https://webaudio.github.io/web-audio-api/#cycles
+    fn can_break_cycles(&self) -> bool {
+        false
+    }
+
+    /// The "DelayReader" half of a node breaking a cycle, this
+    /// produces the output for the block before any of the inputs
+    /// have been computed
+    fn process_cycle_output(&mut self, _: &BlockInfo) -> Chunk {
+        panic!("{:?} can't break cycles", self.node_type())
+    }
+
+    /// The "DelayWriter" half of a node breaking a cycle, this consumes
+    /// the inputs for the block once they have been computed
+    fn process_cycle_input(&mut self, _: Chunk, _: &BlockInfo) {
+        panic!("{:?} can't break cycles", self.node_type())
+    }
+
+    /// The param `id`, or `None` if the node doesn't have it
+    fn get_param(&mut self, _: ParamType) -> Option<&mut Param> {
+        None
     }
 
-    fn set_listenerdata(&mut self, _: Block) {
-        panic!("can't accept listener connections")
+    /// The params `get_param` accepts
+    fn param_types(&self) -> Vec<ParamType> {
+        Vec::new()
     }
+
+    /// The listener's params, for the nodes the AudioListener is
+    /// connected to when they are added to the graph
+    fn set_listenerdata(&mut self, _: Block) {}
 }
 
 pub enum AudioNodeMessage {
+    AnalyserNode(AnalyserNodeMessage),
     AudioBufferSourceNode(AudioBufferSourceNodeMessage),
     AudioScheduledSourceNode(AudioScheduledSourceNodeMessage),
     BiquadFilterNode(BiquadFilterNodeMessage),
+    ConvolverNode(ConvolverNodeMessage),
+    DynamicsCompressionNode(DynamicsCompressorNodeMessage),
     GetParamValue(ParamType, Sender<f32>),
+    GetParamDescriptor(ParamType, Sender<ParamDescriptor>),
+    /// The values of a param's scheduled automation at future times,
+    /// see `param::AutomationQuery`
+    GetParamAutomation(ParamType, AutomationQuery, Sender<Vec<f32>>),
+    /// What `GetParamAutomation` is turned into by
+    /// `AudioContext::message_node`, the query is evaluated on the
+    /// returned snapshot
+    SnapshotParamAutomation(
+        ParamType,
+        Box<AutomationSnapshot>,
+        Sender<Box<AutomationSnapshot>>,
+    ),
+    IIRFilterNode(IIRFilterNodeMessage),
     MediaElementSourceNode(MediaElementSourceNodeMessage),
+    MeterNode(MeterNodeMessage),
     OscillatorNode(OscillatorNodeMessage),
     PannerNode(PannerNodeMessage),
     SetChannelCount(u8),
     SetChannelMode(ChannelCountMode),
     SetChannelInterpretation(ChannelInterpretation),
     SetParam(ParamType, UserAutomationEvent),
+    /// What `SetParam` is turned into by `AudioContext::message_node`
+    InsertParamEvent(ParamType, ParamEvent),
     SetParamRate(ParamType, ParamRate),
     WaveShaperNode(WaveShaperNodeMessage),
 }
 
+/// The response of a filter node at a list of frequencies, see
+/// https://webaudio.github.io/web-audio-api/#dom-biquadfilternode-getfrequencyresponse
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrequencyResponse {
+    /// The gain at each frequency, NaN for frequencies outside
+    /// [0, nyquist]
+    pub magnitude: Vec<f32>,
+    /// The phase at each frequency, in radians
+    pub phase: Vec<f32>,
+}
+
+impl FrequencyResponse {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        FrequencyResponse {
+            magnitude: Vec::with_capacity(capacity),
+            phase: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Add the response at the next frequency, `None` if it is out
+    /// of range
+    pub(crate) fn push(&mut self, response: Option<Complex64>) {
+        let (magnitude, phase) = match response {
+            Some(response) => (response.norm() as f32, response.arg() as f32),
+            None => (f32::NAN, f32::NAN),
+        };
+        self.magnitude.push(magnitude);
+        self.phase.push(phase);
+    }
+}
+
 pub struct OnEndedCallback(pub SendBoxFnOnce<'static, ()>);
 
 impl OnEndedCallback {
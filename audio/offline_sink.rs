@@ -1,9 +1,11 @@
-use block::{Chunk, FRAMES_PER_BLOCK_USIZE};
-use render_thread::AudioRenderThreadMsg;
+use block::{frames_per_block_usize, Chunk};
+use boxfnonce::SendBoxFnOnce;
+use render_thread::RenderThreadWaker;
 use servo_media_streams::MediaSocket;
-use sink::{AudioSink, AudioSinkError};
+use sink::{AudioSink, AudioSinkError, AudioSinkOutput};
 use std::cell::{Cell, RefCell};
-use std::sync::mpsc::Sender;
+use std::io;
+use wav_writer::RenderWriter;
 
 pub struct ProcessedAudio(Box<[f32]>);
 
@@ -13,6 +15,10 @@ impl AsRef<[f32]> for ProcessedAudio {
     }
 }
 
+/// Called once a `RenderWriter` has written the whole render,
+/// with the first error it ran into, if any
+pub type RenderWriterCallback = SendBoxFnOnce<'static, (io::Result<()>,)>;
+
 pub struct OfflineAudioSink {
     buffer: RefCell<Option<Vec<f32>>>,
     channel_count: usize,
@@ -20,6 +26,12 @@ pub struct OfflineAudioSink {
     length: usize,
     rendered_blocks: Cell<usize>,
     eos_callback: RefCell<Option<Box<dyn Fn(Box<dyn AsRef<[f32]>>) + Send + Sync + 'static>>>,
+    /// Called with the number of rendered frames, every `interval` frames
+    progress_callback: RefCell<Option<(usize, Box<dyn Fn(usize) + Send + Sync + 'static>)>>,
+    /// When set, the rendered audio is streamed to this writer
+    /// instead of being kept in `buffer`
+    writer: RefCell<Option<(Box<dyn RenderWriter>, RenderWriterCallback)>>,
+    writer_error: RefCell<Option<io::Error>>,
 }
 
 impl OfflineAudioSink {
@@ -31,12 +43,44 @@ impl OfflineAudioSink {
             length,
             rendered_blocks: Cell::new(0),
             eos_callback: RefCell::new(None),
+            progress_callback: RefCell::new(None),
+            writer: RefCell::new(None),
+            writer_error: RefCell::new(None),
         }
     }
+
+    /// The length of the rendered audio, in sample-frames
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Report the number of frames rendered so far roughly every
+    /// `interval` frames, and once rendering is complete
+    pub fn set_progress_callback(
+        &self,
+        interval: usize,
+        callback: Box<dyn Fn(usize) + Send + Sync + 'static>,
+    ) {
+        *self.progress_callback.borrow_mut() = Some((interval.max(1), callback));
+    }
+
+    /// Stream the rendered audio to `writer` instead of keeping it in
+    /// memory, the EOS callback then gets an empty buffer. Writers set
+    /// after rendering started would miss its beginning, `callback` gets
+    /// an error for those.
+    pub fn set_writer(&self, writer: Box<dyn RenderWriter>, callback: RenderWriterCallback) {
+        if self.rendered_blocks.get() > 0 {
+            return callback.call(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "rendering has already started",
+            )));
+        }
+        *self.writer.borrow_mut() = Some((writer, callback));
+    }
 }
 
 impl AudioSink for OfflineAudioSink {
-    fn init(&self, _: f32, _: Sender<AudioRenderThreadMsg>) -> Result<(), AudioSinkError> {
+    fn init(&self, _: f32, _: RenderThreadWaker) -> Result<(), AudioSinkError> {
         Ok(())
     }
     fn init_stream(&self, _: u8, _: f32, _: Box<dyn MediaSocket>) -> Result<(), AudioSinkError> {
@@ -54,18 +98,19 @@ impl AudioSink for OfflineAudioSink {
 
     fn has_enough_data(&self) -> bool {
         self.has_enough_data.get()
-            || (self.rendered_blocks.get() * FRAMES_PER_BLOCK_USIZE >= self.length)
+            || (self.rendered_blocks.get() * frames_per_block_usize() >= self.length)
     }
 
     fn push_data(&self, mut chunk: Chunk) -> Result<(), AudioSinkError> {
-        let offset = self.rendered_blocks.get() * FRAMES_PER_BLOCK_USIZE;
-        let (last, copy_len) = if self.length - offset <= FRAMES_PER_BLOCK_USIZE {
+        let offset = self.rendered_blocks.get() * frames_per_block_usize();
+        let (last, copy_len) = if self.length - offset <= frames_per_block_usize() {
             (true, self.length - offset)
         } else {
-            (false, FRAMES_PER_BLOCK_USIZE)
+            (false, frames_per_block_usize())
         };
         let mut buffer = self.buffer.borrow_mut();
-        if buffer.is_none() {
+        let mut writer = self.writer.borrow_mut();
+        if buffer.is_none() && writer.is_none() {
             *buffer = Some(vec![0.; self.channel_count * self.length]);
         }
         if chunk.len() == 0 {
@@ -74,7 +119,15 @@ impl AudioSink for OfflineAudioSink {
         if chunk.blocks[0].is_empty() {
             chunk.blocks[0].explicit_silence();
         }
-        if let Some(ref mut buffer) = *buffer {
+        if let Some((ref mut writer, _)) = *writer {
+            let mut error = self.writer_error.borrow_mut();
+            // stop writing after the first error
+            if error.is_none() {
+                if let Err(e) = writer.write_block(&chunk.blocks[0], copy_len) {
+                    *error = Some(e);
+                }
+            }
+        } else if let Some(ref mut buffer) = *buffer {
             for channel_number in 0..self.channel_count {
                 let channel_offset = offset + (channel_number * self.length);
                 let channel_data = &mut buffer[channel_offset..channel_offset + copy_len];
@@ -84,9 +137,25 @@ impl AudioSink for OfflineAudioSink {
         };
         self.rendered_blocks.set(self.rendered_blocks.get() + 1);
 
+        if let Some((interval, ref callback)) = *self.progress_callback.borrow() {
+            let rendered = offset + copy_len;
+            // whether we just crossed a multiple of the interval
+            if last || rendered / interval != offset / interval {
+                callback(rendered);
+            }
+        }
+
         if last {
+            if let Some((writer, callback)) = writer.take() {
+                let result = match self.writer_error.borrow_mut().take() {
+                    Some(error) => Err(error),
+                    None => writer.finish(),
+                };
+                callback.call(result);
+            }
             if let Some(callback) = self.eos_callback.borrow_mut().take() {
-                let processed_audio = ProcessedAudio(buffer.take().unwrap().into_boxed_slice());
+                let buffer = buffer.take().unwrap_or_default();
+                let processed_audio = ProcessedAudio(buffer.into_boxed_slice());
                 callback(Box::new(processed_audio));
             }
         }
@@ -100,4 +169,16 @@ impl AudioSink for OfflineAudioSink {
     ) {
         *self.eos_callback.borrow_mut() = Some(callback);
     }
+
+    fn set_output(&self, _: AudioSinkOutput) -> Result<(), AudioSinkError> {
+        Err(AudioSinkError::SetSinkIdFailed)
+    }
+
+    fn sink_id(&self) -> Option<String> {
+        None
+    }
+
+    fn underrun_count(&self) -> u64 {
+        0
+    }
 }
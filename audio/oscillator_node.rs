@@ -1,13 +1,216 @@
 use block::{Chunk, Tick};
+use context::AudioContextError;
+use fft::Fft;
+use log::warn;
 use node::{AudioNodeEngine, AudioScheduledSourceNodeMessage, BlockInfo, OnEndedCallback};
 use node::{AudioNodeType, ChannelInfo, ShouldPlay};
-use num_traits::cast::NumCast;
+use num_complex::Complex32;
 use param::{Param, ParamType};
+use render_thread::Garbage;
+use std::f32::consts::PI;
+use std::iter;
+use std::sync::Arc;
 
-#[derive(Clone, Debug)]
+/// https://webaudio.github.io/web-audio-api/#dictdef-periodicwaveoptions
+#[derive(Clone, Debug, Default)]
 pub struct PeriodicWaveOptions {
-    // XXX https://webaudio.github.io/web-audio-api/#dictdef-periodicwaveoptions
+    /// The cosine terms, the first element (DC offset) is ignored
+    pub real: Option<Vec<f32>>,
+    /// The sine terms, the first element is ignored
+    pub imag: Option<Vec<f32>>,
+    pub disable_normalization: bool,
+}
+
+/// The size of a single wave table
+const TABLE_SIZE: usize = 2048;
+/// The highest partial a wave table of TABLE_SIZE can represent
+const MAX_PARTIALS: usize = TABLE_SIZE / 2 - 1;
+
+/// A single period of a waveform, containing no partials above `partials`
+#[derive(Debug)]
+struct WaveTable {
+    partials: usize,
+    /// TABLE_SIZE samples, plus the first one repeated at the end
+    /// so that interpolation doesn't need to wrap
+    samples: Vec<f32>,
+}
+
+impl WaveTable {
+    /// Linearly interpolated value at `phase`, in [0, 1)
+    fn value_at(&self, phase: f64) -> f32 {
+        let pos = phase * TABLE_SIZE as f64;
+        // phase might round up to 1
+        let index = pos as usize % TABLE_SIZE;
+        let interp_factor = (pos - index as f64) as f32;
+        (1. - interp_factor) * self.samples[index] + interp_factor * self.samples[index + 1]
+    }
+}
+
+/// https://webaudio.github.io/web-audio-api/#PeriodicWave
+///
+/// The waveform is stored as a set of band-limited wave tables, two per
+/// octave, so that the oscillator can pick one without partials above
+/// the Nyquist frequency for the frequency it is playing at.
+///
+/// This is cheap to clone, and can be created off the render thread.
+#[derive(Clone, Debug)]
+pub struct PeriodicWave {
+    /// Sorted by decreasing number of partials
+    tables: Arc<Vec<WaveTable>>,
+}
+
+impl PeriodicWave {
+    /// Build the wave, or fail if `real` and `imag` don't have the
+    /// same length, of at least 2
+    pub fn new(options: PeriodicWaveOptions) -> Result<Self, AudioContextError> {
+        let (real, imag) = match (options.real, options.imag) {
+            (Some(real), Some(imag)) => (real, imag),
+            (Some(real), None) => {
+                let imag = vec![0.; real.len()];
+                (real, imag)
+            }
+            (None, Some(imag)) => (vec![0.; imag.len()], imag),
+            (None, None) => (vec![0., 0.], vec![0., 1.]),
+        };
+        if real.len() != imag.len() || real.len() < 2 {
+            return Err(AudioContextError::IndexSize(
+                "PeriodicWave real and imag must have the same length, of at least 2",
+            ));
+        }
+        Ok(Self::from_coefficients(
+            &real,
+            &imag,
+            !options.disable_normalization,
+        ))
+    }
+
+    /// The wave for one of the built-in oscillator types
+    fn for_type(oscillator_type: &OscillatorType) -> Option<Self> {
+        let waves = &*BUILT_IN_WAVES;
+        match *oscillator_type {
+            OscillatorType::Sine => Some(waves[0].clone()),
+            OscillatorType::Square => Some(waves[1].clone()),
+            OscillatorType::Sawtooth => Some(waves[2].clone()),
+            OscillatorType::Triangle => Some(waves[3].clone()),
+            OscillatorType::Custom => None,
+        }
+    }
+
+    /// https://webaudio.github.io/web-audio-api/#oscillator-coefficients
+    fn build_for_type(oscillator_type: &OscillatorType) -> Option<Self> {
+        let len = MAX_PARTIALS + 1;
+        let real = vec![0.; len];
+        let imag: Vec<f32> = match *oscillator_type {
+            OscillatorType::Sine => {
+                return Some(Self::from_coefficients(&[0., 0.], &[0., 1.], true))
+            }
+            OscillatorType::Square => iter::once(0.)
+                .chain((1..len).map(|n| if n % 2 == 1 { 4. / (n as f32 * PI) } else { 0. }))
+                .collect(),
+            OscillatorType::Sawtooth => iter::once(0.)
+                .chain((1..len).map(|n| {
+                    let sign = if n % 2 == 1 { 1. } else { -1. };
+                    sign * 2. / (n as f32 * PI)
+                }))
+                .collect(),
+            OscillatorType::Triangle => iter::once(0.)
+                .chain((1..len).map(|n| {
+                    let n = n as f32;
+                    8. * (n * PI / 2.).sin() / (n * PI).powi(2)
+                }))
+                .collect(),
+            OscillatorType::Custom => return None,
+        };
+        Some(Self::from_coefficients(&real, &imag, true))
+    }
+
+    /// https://webaudio.github.io/web-audio-api/#waveform-generation
+    fn from_coefficients(real: &[f32], imag: &[f32], normalize: bool) -> Self {
+        // The highest partial that is actually present
+        let max_partials = (1..real.len().min(MAX_PARTIALS + 1))
+            .rev()
+            .find(|&k| real[k] != 0. || imag[k] != 0.)
+            .unwrap_or(1);
+
+        // The number of partials in each table, the first table contains
+        // all of them, and the following ones half an octave less each
+        let mut partials = vec![max_partials];
+        let mut band = 0;
+        loop {
+            let p = (MAX_PARTIALS as f64 / 2f64.powf(band as f64 / 2.)) as usize;
+            if p < 1 {
+                break;
+            }
+            if p < *partials.last().unwrap() {
+                partials.push(p);
+            }
+            band += 1;
+        }
+
+        let mut fft = Fft::new(TABLE_SIZE);
+        let mut spectrum = vec![Complex32::new(0., 0.); TABLE_SIZE / 2 + 1];
+        let mut scale = None;
+        let tables = partials
+            .into_iter()
+            .map(|partials| {
+                for (k, bin) in spectrum.iter_mut().enumerate() {
+                    // x[n] = sum(a[k] cos(2πkn/N) + b[k] sin(2πkn/N)),
+                    // and the inverse transform scales by 1 / N
+                    *bin = if k >= 1 && k <= partials {
+                        let half = TABLE_SIZE as f32 / 2.;
+                        Complex32::new(real[k] * half, -imag[k] * half)
+                    } else {
+                        Complex32::new(0., 0.)
+                    };
+                }
+                let mut samples = vec![0.; TABLE_SIZE + 1];
+                fft.real_inverse(&spectrum, &mut samples[..TABLE_SIZE]);
+
+                // Every table is scaled by the same factor, computed from the
+                // one with all the partials
+                let scale = *scale.get_or_insert_with(|| {
+                    let max = samples.iter().fold(0f32, |max, s| max.max(s.abs()));
+                    if normalize && max > 0. {
+                        1. / max
+                    } else {
+                        1.
+                    }
+                });
+                for s in samples.iter_mut() {
+                    *s *= scale;
+                }
+                samples[TABLE_SIZE] = samples[0];
+                WaveTable { partials, samples }
+            })
+            .collect();
+
+        PeriodicWave {
+            tables: Arc::new(tables),
+        }
+    }
+
+    /// The table with the most partials that are all below the Nyquist
+    /// frequency when playing at `frequency`
+    fn table_for(&self, frequency: f64, sample_rate: f64) -> Option<&WaveTable> {
+        let max_partials = sample_rate / 2. / frequency.abs();
+        self.tables
+            .iter()
+            .find(|table| table.partials as f64 <= max_partials)
+    }
 }
+
+lazy_static! {
+    /// The waves of the built-in types, in the order of `OscillatorType`.
+    /// They are built together the first time an oscillator is created,
+    /// on the control thread, and shared by all oscillators.
+    static ref BUILT_IN_WAVES: [PeriodicWave; 4] = [
+        PeriodicWave::build_for_type(&OscillatorType::Sine).unwrap(),
+        PeriodicWave::build_for_type(&OscillatorType::Square).unwrap(),
+        PeriodicWave::build_for_type(&OscillatorType::Sawtooth).unwrap(),
+        PeriodicWave::build_for_type(&OscillatorType::Triangle).unwrap(),
+    ];
+}
+
 #[derive(Clone, Debug)]
 pub enum OscillatorType {
     Sine,
@@ -22,7 +225,20 @@ pub struct OscillatorNodeOptions {
     pub oscillator_type: OscillatorType,
     pub freq: f32,
     pub detune: f32,
-    pub periodic_wave_options: Option<PeriodicWaveOptions>,
+    /// The wave to use, which makes the type `Custom`
+    pub periodic_wave: Option<PeriodicWave>,
+}
+
+impl OscillatorNodeOptions {
+    /// Check that the options describe a wave
+    pub fn validate(&self) -> Result<(), AudioContextError> {
+        match (&self.oscillator_type, &self.periodic_wave) {
+            (&OscillatorType::Custom, &None) => Err(AudioContextError::InvalidState(
+                "Custom oscillators need a PeriodicWave",
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
 impl Default for OscillatorNodeOptions {
@@ -31,7 +247,7 @@ impl Default for OscillatorNodeOptions {
             oscillator_type: OscillatorType::Sine,
             freq: 440.,
             detune: 0.,
-            periodic_wave_options: None,
+            periodic_wave: None,
         }
     }
 }
@@ -39,14 +255,18 @@ impl Default for OscillatorNodeOptions {
 #[derive(Clone, Debug)]
 pub enum OscillatorNodeMessage {
     SetOscillatorType(OscillatorType),
+    /// Sets the type to `Custom`
+    SetPeriodicWave(PeriodicWave),
 }
 
 #[derive(AudioScheduledSourceNode, AudioNodeCommon)]
 pub(crate) struct OscillatorNode {
     channel_info: ChannelInfo,
     oscillator_type: OscillatorType,
+    wave: PeriodicWave,
     frequency: Param,
     detune: Param,
+    /// The position in the current period, in [0, 1)
     phase: f64,
     /// Time at which the source should start playing.
     start_at: Option<Tick>,
@@ -54,30 +274,71 @@ pub(crate) struct OscillatorNode {
     stop_at: Option<Tick>,
     /// The ended event callback.
     onended_callback: Option<OnEndedCallback>,
+    /// Whether the source has ended, it won't play again.
+    ended: bool,
+    /// The wave the last message replaced
+    garbage: Option<Garbage>,
 }
 
 impl OscillatorNode {
-    pub fn new(options: OscillatorNodeOptions, channel_info: ChannelInfo) -> Self {
+    pub fn new(
+        options: OscillatorNodeOptions,
+        channel_info: ChannelInfo,
+        sample_rate: f32,
+    ) -> Self {
+        debug_assert!(options.validate().is_ok());
+        let node = AudioNodeType::OscillatorNode;
+        // Build the built-in waves now, so that changing the type later on
+        // doesn't have to
+        lazy_static::initialize(&BUILT_IN_WAVES);
+        let (oscillator_type, wave) = match options.periodic_wave {
+            Some(wave) => (OscillatorType::Custom, wave),
+            None => {
+                let wave = PeriodicWave::for_type(&options.oscillator_type)
+                    .expect("InvalidStateError: Custom oscillators need a PeriodicWave");
+                (options.oscillator_type, wave)
+            }
+        };
         Self {
             channel_info,
-            oscillator_type: options.oscillator_type,
-            frequency: Param::new(options.freq.into()),
-            detune: Param::new(options.detune.into()),
+            oscillator_type,
+            wave,
+            frequency: Param::for_node(node, ParamType::Frequency, options.freq, sample_rate),
+            detune: Param::for_node(node, ParamType::Detune, options.detune, sample_rate),
             phase: 0.,
             start_at: None,
             stop_at: None,
             onended_callback: None,
+            ended: false,
+            garbage: None,
         }
     }
 
     pub fn update_parameters(&mut self, info: &BlockInfo, tick: Tick) -> bool {
-        self.frequency.update(info, tick)
+        let mut changed = self.frequency.update(info, tick);
+        changed |= self.detune.update(info, tick);
+        changed
+    }
+
+    /// https://webaudio.github.io/web-audio-api/#computedoscfrequency
+    fn computed_frequency(&self) -> f64 {
+        self.frequency.value() as f64 * 2f64.powf(self.detune.value() as f64 / 1200.)
     }
 
     fn handle_oscillator_message(&mut self, message: OscillatorNodeMessage, _sample_rate: f32) {
         match message {
-            OscillatorNodeMessage::SetOscillatorType(o) => {
-                self.oscillator_type = o;
+            OscillatorNodeMessage::SetOscillatorType(o) => match PeriodicWave::for_type(&o) {
+                Some(wave) => {
+                    let previous = ::std::mem::replace(&mut self.wave, wave);
+                    self.garbage = Some(Garbage::PeriodicWave(previous));
+                    self.oscillator_type = o;
+                }
+                None => warn!("Use SetPeriodicWave for custom oscillators"),
+            },
+            OscillatorNodeMessage::SetPeriodicWave(wave) => {
+                self.oscillator_type = OscillatorType::Custom;
+                let previous = ::std::mem::replace(&mut self.wave, wave);
+                self.garbage = Some(Garbage::PeriodicWave(previous));
             }
         }
     }
@@ -88,10 +349,11 @@ impl AudioNodeEngine for OscillatorNode {
         AudioNodeType::OscillatorNode
     }
 
+    fn is_actively_processing(&self) -> bool {
+        self.start_at.is_some() && !self.ended
+    }
+
     fn process(&mut self, mut inputs: Chunk, info: &BlockInfo) -> Chunk {
-        // XXX Implement this properly and according to self.options
-        // as defined in https://webaudio.github.io/web-audio-api/#oscillatornode
-        use std::f64::consts::PI;
         debug_assert!(inputs.len() == 0);
         inputs.blocks.push(Default::default());
         let (start_at, stop_at) = match self.should_play_at(info.frame) {
@@ -104,18 +366,16 @@ impl AudioNodeEngine for OscillatorNode {
         {
             inputs.blocks[0].explicit_silence();
             let mut iter = inputs.blocks[0].iter();
-
-            // Convert all our parameters to the target type for calculations
-            let vol: f32 = 1.0;
             let sample_rate = info.sample_rate as f64;
-            let two_pi = 2.0 * PI;
 
-            // We're carrying a phase with up to 2pi around instead of working
+            // We're carrying a phase in [0, 1) around instead of working
             // on the sample offset. High sample offsets cause too much inaccuracy when
             // converted to floating point numbers and then iterated over in 1-steps
             //
             // Also, if the frequency changes the phase should not
-            let mut step = two_pi * self.frequency.value() as f64 / sample_rate;
+            let wave = self.wave.clone();
+            let mut frequency = self.computed_frequency();
+            let mut table = wave.table_for(frequency, sample_rate);
             while let Some(mut frame) = iter.next() {
                 let tick = frame.tick();
                 if tick < start_at {
@@ -125,68 +385,88 @@ impl AudioNodeEngine for OscillatorNode {
                 }
 
                 if self.update_parameters(info, tick) {
-                    step = two_pi * self.frequency.value() as f64 / sample_rate;
+                    frequency = self.computed_frequency();
+                    table = wave.table_for(frequency, sample_rate);
                 }
-                let mut value = vol;
-                match self.oscillator_type {
-                    OscillatorType::Sine => {
-                        value = vol * f32::sin(NumCast::from(self.phase).unwrap());
-                    }
-
-                    OscillatorType::Square => {
-                        if self.phase >= PI && self.phase < two_pi {
-                            value = vol * 1.0;
-                        } else if self.phase > 0.0 && self.phase < PI {
-                            value = vol * (-1.0);
-                        }
-                    }
-
-                    OscillatorType::Sawtooth => {
-                        value = vol * ((self.phase as f64) / (PI)) as f32;
-                    }
-
-                    OscillatorType::Triangle => {
-                        if self.phase >= 0. && self.phase < PI / 2. {
-                            value = vol * 2.0 * ((self.phase as f64) / (PI)) as f32;
-                        } else if self.phase >= PI / 2. && self.phase < PI {
-                            value =
-                                vol * (1. - (((self.phase as f64) - (PI / 2.)) * (2. / PI)) as f32);
-                        } else if self.phase >= PI && self.phase < (3. * PI / 2.) {
-                            value = vol
-                                * -1.
-                                * (1. - (((self.phase as f64) - (PI / 2.)) * (2. / PI)) as f32);
-                        } else if self.phase >= 3. * PI / 2. && self.phase < 2. * PI {
-                            value = vol * (-2.0) * ((self.phase as f64) / (PI)) as f32;
-                        }
-                    }
 
-                    OscillatorType::Custom => {}
+                // Frequencies above the Nyquist frequency are silent
+                if let Some(table) = table {
+                    let value = table.value_at(self.phase);
+                    frame.mutate_with(|sample, _| *sample = value);
                 }
 
-                frame.mutate_with(|sample, _| *sample = value);
-
-                self.phase += step;
-                if self.phase >= two_pi {
-                    self.phase -= two_pi;
+                if frequency.is_finite() {
+                    self.phase += frequency / sample_rate;
+                    self.phase -= self.phase.floor();
                 }
             }
         }
         inputs
     }
-
     fn input_count(&self) -> u32 {
         0
     }
 
-    fn get_param(&mut self, id: ParamType) -> &mut Param {
+    fn param_types(&self) -> Vec<ParamType> {
+        vec![ParamType::Frequency, ParamType::Detune]
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
         match id {
-            ParamType::Frequency => &mut self.frequency,
-            ParamType::Detune => &mut self.detune,
-            _ => panic!("Unknown param {:?} for OscillatorNode", id),
+            ParamType::Frequency => Some(&mut self.frequency),
+            ParamType::Detune => Some(&mut self.detune),
+            _ => None,
         }
     }
     make_message_handler!(
         AudioScheduledSourceNode: handle_source_node_message,
         OscillatorNode: handle_oscillator_message
     );
+
+    fn take_garbage(&mut self) -> Option<Garbage> {
+        self.garbage.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_sine_matches_the_built_in_one() {
+        let custom = PeriodicWave::new(PeriodicWaveOptions {
+            real: Some(vec![0., 0.]),
+            imag: Some(vec![0., 1.]),
+            disable_normalization: false,
+        })
+        .unwrap();
+        let sine = PeriodicWave::for_type(&OscillatorType::Sine).unwrap();
+        assert_eq!(custom.tables.len(), sine.tables.len());
+        for (custom, sine) in custom.tables.iter().zip(sine.tables.iter()) {
+            for i in 0..100 {
+                let phase = i as f64 / 100.;
+                assert!((custom.value_at(phase) - sine.value_at(phase)).abs() < 1e-6);
+            }
+        }
+        let peak = (0..TABLE_SIZE)
+            .map(|i| sine.tables[0].value_at(i as f64 / TABLE_SIZE as f64))
+            .fold(0f32, |peak, x| peak.max(x.abs()));
+        assert!((peak - 1.).abs() < 1e-3);
+
+        let mismatched = PeriodicWaveOptions {
+            real: Some(vec![0., 0., 0.]),
+            imag: Some(vec![0., 1.]),
+            disable_normalization: false,
+        };
+        match PeriodicWave::new(mismatched) {
+            Err(AudioContextError::IndexSize(_)) => {}
+            _ => panic!("mismatched coefficients were accepted"),
+        }
+        let short = PeriodicWaveOptions {
+            real: None,
+            imag: Some(vec![0.]),
+            disable_normalization: false,
+        };
+        assert!(PeriodicWave::new(short).is_err());
+    }
 }
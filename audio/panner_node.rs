@@ -1,8 +1,11 @@
-use block::{Block, Chunk, Tick, FRAMES_PER_BLOCK};
+use block::{frames_per_block, frames_per_block_usize, Block, Chunk, Tick, MAX_FRAMES_PER_BLOCK};
 use euclid::default::Vector3D;
+use hrtf::HrtfPanner;
+use log::warn;
 use node::{AudioNodeEngine, AudioNodeMessage, BlockInfo};
 use node::{AudioNodeType, ChannelInfo};
 use param::{Param, ParamDir, ParamType};
+use render_thread::Garbage;
 use std::f32::consts::PI;
 
 // .normalize(), but it takes into account zero vectors
@@ -47,7 +50,12 @@ pub struct PannerNodeOptions {
 }
 
 pub enum PannerNodeMessage {
+    /// The context turns this into a `SetPanner` message
     SetPanningModel(PanningModel),
+    /// The panning model, with the state of the HRTF panner created by
+    /// the context for the HRTF model so that the render thread doesn't
+    /// have to
+    SetPanner(PanningModel, Option<Box<HrtfPanner>>),
     SetDistanceModel(DistanceModel),
     SetRefDistance(f64),
     SetMaxDistance(f64),
@@ -96,23 +104,63 @@ pub(crate) struct PannerNode {
     cone_outer_angle: f64,
     cone_outer_gain: f64,
     listener_data: Option<Block>,
+    /// Only there for the HRTF panning model, it is created on the
+    /// control thread with the node or when the model changes
+    hrtf: Option<Box<HrtfPanner>>,
+    /// How many silent frames have been input in a row, the output
+    /// of the HRTF panner lasts a bit longer than its input
+    silent_frames: usize,
+    /// What the last message replaced
+    garbage: Option<Garbage>,
 }
 
 impl PannerNode {
-    pub fn new(options: PannerNodeOptions, channel_info: ChannelInfo) -> Self {
-        if options.panning_model == PanningModel::HRTF {
-            log::warn!("HRTF requested but not supported")
-        }
+    pub fn new(options: PannerNodeOptions, channel_info: ChannelInfo, sample_rate: f32) -> Self {
+        let node = AudioNodeType::PannerNode;
+        let hrtf = match options.panning_model {
+            PanningModel::HRTF => Some(Box::new(HrtfPanner::new(sample_rate))),
+            PanningModel::EqualPower => None,
+        };
         Self {
             channel_info,
             panning_model: options.panning_model,
             distance_model: options.distance_model,
-            position_x: Param::new(options.position_x),
-            position_y: Param::new(options.position_y),
-            position_z: Param::new(options.position_z),
-            orientation_x: Param::new(options.orientation_x),
-            orientation_y: Param::new(options.orientation_y),
-            orientation_z: Param::new(options.orientation_z),
+            position_x: Param::for_node(
+                node,
+                ParamType::Position(ParamDir::X),
+                options.position_x,
+                sample_rate,
+            ),
+            position_y: Param::for_node(
+                node,
+                ParamType::Position(ParamDir::Y),
+                options.position_y,
+                sample_rate,
+            ),
+            position_z: Param::for_node(
+                node,
+                ParamType::Position(ParamDir::Z),
+                options.position_z,
+                sample_rate,
+            ),
+            orientation_x: Param::for_node(
+                node,
+                ParamType::Orientation(ParamDir::X),
+                options.orientation_x,
+                sample_rate,
+            ),
+            orientation_y: Param::for_node(
+                node,
+                ParamType::Orientation(ParamDir::Y),
+                options.orientation_y,
+                sample_rate,
+            ),
+            orientation_z: Param::for_node(
+                node,
+                ParamType::Orientation(ParamDir::Z),
+                options.orientation_z,
+                sample_rate,
+            ),
             ref_distance: options.ref_distance,
             max_distance: options.max_distance,
             rolloff_factor: options.rolloff_factor,
@@ -120,9 +168,27 @@ impl PannerNode {
             cone_outer_angle: options.cone_outer_angle,
             cone_outer_gain: options.cone_outer_gain,
             listener_data: None,
+            hrtf,
+            silent_frames: 0,
+            garbage: None,
         }
     }
 
+    /// Switch to the panning model `model`, keeping the state of the
+    /// HRTF panner if it was already in use
+    fn set_panner(&mut self, model: PanningModel, hrtf: Option<Box<HrtfPanner>>) {
+        self.panning_model = model;
+        let unused = match model {
+            PanningModel::HRTF if self.hrtf.is_none() => {
+                self.hrtf = hrtf;
+                None
+            }
+            PanningModel::HRTF => hrtf,
+            PanningModel::EqualPower => self.hrtf.take(),
+        };
+        self.garbage = unused.map(Garbage::HrtfPanner);
+    }
+
     pub fn update_parameters(&mut self, info: &BlockInfo, tick: Tick) -> bool {
         let mut changed = self.position_x.update(info, tick);
         changed |= self.position_y.update(info, tick);
@@ -206,7 +272,8 @@ impl PannerNode {
         if source_orientation == Vector3D::zero()
             || (self.cone_inner_angle == 360. && self.cone_outer_angle == 360.)
         {
-            return 0.;
+            // without a cone, the source sounds the same in every direction
+            return 1.;
         }
 
         let normalized_source_orientation = normalize_zero(source_orientation);
@@ -275,6 +342,13 @@ impl AudioNodeEngine for PannerNode {
         AudioNodeType::PannerNode
     }
 
+    fn is_actively_processing(&self) -> bool {
+        match self.hrtf {
+            Some(ref hrtf) => self.silent_frames < hrtf.tail_len(),
+            None => false,
+        }
+    }
+
     fn process(&mut self, mut inputs: Chunk, info: &BlockInfo) -> Chunk {
         debug_assert!(inputs.len() == 1);
 
@@ -295,6 +369,11 @@ impl AudioNodeEngine for PannerNode {
         {
             let block = &mut inputs.blocks[0];
 
+            if block.is_silence() {
+                self.silent_frames += frames_per_block_usize();
+            } else {
+                self.silent_frames = 0;
+            }
             block.explicit_repeat();
 
             let mono = if block.chan_count() == 1 {
@@ -308,8 +387,8 @@ impl AudioNodeEngine for PannerNode {
             let distance_gain_fn = self.distance_gain_fn();
 
             if self.panning_model == PanningModel::EqualPower {
-                let (l, r) = block.data_mut().split_at_mut(FRAMES_PER_BLOCK.0 as usize);
-                for frame in 0..FRAMES_PER_BLOCK.0 {
+                let (l, r) = block.data_mut().split_at_mut(frames_per_block().0 as usize);
+                for frame in 0..frames_per_block().0 {
                     let frame = Tick(frame);
                     self.update_parameters(info, frame);
                     let data = listener_data.listener_data(frame);
@@ -365,6 +444,36 @@ impl AudioNodeEngine for PannerNode {
                     l[index] = l[index] * distance_gain as f32 * cone_gain as f32;
                     r[index] = r[index] * distance_gain as f32 * cone_gain as f32;
                 }
+            } else {
+                // https://webaudio.github.io/web-audio-api/#Spatialization-hrtf-panning
+                // The direction is sampled at the start of the block, the
+                // gains on every frame
+                let mut direction = (0., 0.);
+                let mut gains = [0f32; MAX_FRAMES_PER_BLOCK as usize];
+                let gains = &mut gains[..frames_per_block_usize()];
+                for frame in 0..frames_per_block().0 {
+                    let frame = Tick(frame);
+                    self.update_parameters(info, frame);
+                    let data = listener_data.listener_data(frame);
+                    let (azimuth, elevation, dist) = self.azimuth_elevation_distance(data);
+                    if frame.0 == 0 {
+                        direction = (azimuth, elevation);
+                    }
+                    let distance_gain = distance_gain_fn(self, dist, rolloff_factor);
+                    gains[frame.0 as usize] = (distance_gain * self.cone_gain(data)) as f32;
+                }
+
+                let (l, r) = block.data_mut().split_at_mut(frames_per_block().0 as usize);
+                if mono {
+                    r.copy_from_slice(l);
+                }
+                if let Some(ref mut hrtf) = self.hrtf {
+                    hrtf.pan(direction.0, direction.1, l, r);
+                }
+                for (frame, gain) in gains.iter().enumerate() {
+                    l[frame] *= gain;
+                    r[frame] *= gain;
+                }
             }
         }
 
@@ -375,15 +484,26 @@ impl AudioNodeEngine for PannerNode {
         1
     }
 
-    fn get_param(&mut self, id: ParamType) -> &mut Param {
+    fn param_types(&self) -> Vec<ParamType> {
+        vec![
+            ParamType::Position(ParamDir::X),
+            ParamType::Position(ParamDir::Y),
+            ParamType::Position(ParamDir::Z),
+            ParamType::Orientation(ParamDir::X),
+            ParamType::Orientation(ParamDir::Y),
+            ParamType::Orientation(ParamDir::Z),
+        ]
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
         match id {
-            ParamType::Position(ParamDir::X) => &mut self.position_x,
-            ParamType::Position(ParamDir::Y) => &mut self.position_y,
-            ParamType::Position(ParamDir::Z) => &mut self.position_z,
-            ParamType::Orientation(ParamDir::X) => &mut self.orientation_x,
-            ParamType::Orientation(ParamDir::Y) => &mut self.orientation_y,
-            ParamType::Orientation(ParamDir::Z) => &mut self.orientation_z,
-            _ => panic!("Unknown param {:?} for PannerNode", id),
+            ParamType::Position(ParamDir::X) => Some(&mut self.position_x),
+            ParamType::Position(ParamDir::Y) => Some(&mut self.position_y),
+            ParamType::Position(ParamDir::Z) => Some(&mut self.position_z),
+            ParamType::Orientation(ParamDir::X) => Some(&mut self.orientation_x),
+            ParamType::Orientation(ParamDir::Y) => Some(&mut self.orientation_y),
+            ParamType::Orientation(ParamDir::Z) => Some(&mut self.orientation_z),
+            _ => None,
         }
     }
 
@@ -394,12 +514,13 @@ impl AudioNodeEngine for PannerNode {
     fn message_specific(&mut self, message: AudioNodeMessage, _sample_rate: f32) {
         match message {
             AudioNodeMessage::PannerNode(p) => match p {
-                PannerNodeMessage::SetPanningModel(p) => {
-                    if p == PanningModel::HRTF {
-                        log::warn!("HRTF requested but not supported");
-                    }
-                    self.panning_model = p;
+                PannerNodeMessage::SetPanningModel(PanningModel::EqualPower) => {
+                    self.set_panner(PanningModel::EqualPower, None)
                 }
+                PannerNodeMessage::SetPanningModel(PanningModel::HRTF) => {
+                    warn!("The HRTF panning model must be set through the context")
+                }
+                PannerNodeMessage::SetPanner(p, hrtf) => self.set_panner(p, hrtf),
                 PannerNodeMessage::SetDistanceModel(d) => self.distance_model = d,
                 PannerNodeMessage::SetRefDistance(val) => self.ref_distance = val,
                 PannerNodeMessage::SetMaxDistance(val) => self.max_distance = val,
@@ -411,4 +532,8 @@ impl AudioNodeEngine for PannerNode {
             _ => (),
         }
     }
+
+    fn take_garbage(&mut self) -> Option<Garbage> {
+        self.garbage.take()
+    }
 }
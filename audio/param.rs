@@ -1,9 +1,12 @@
 use block::Block;
 use block::Tick;
-use block::FRAMES_PER_BLOCK_USIZE;
-use node::BlockInfo;
+use block::{frames_per_block, frames_per_block_usize};
+use log::warn;
+use node::{AudioNodeType, BlockInfo};
+use render_thread::Garbage;
+use std::sync::Arc;
 
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize)]
 pub enum ParamType {
     Frequency,
     Detune,
@@ -16,25 +19,154 @@ pub enum ParamType {
     Up(ParamDir),
     Orientation(ParamDir),
     Offset,
+    DelayTime,
+    Threshold,
+    Knee,
+    Ratio,
+    Attack,
+    Release,
+    /// A param of an AudioWorkletNode, by the index of its descriptor
+    Custom(u32),
 }
 
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize)]
 pub enum ParamDir {
     X,
     Y,
     Z,
 }
 
+/// The nominal range and automation rate of a param
+///
+/// https://webaudio.github.io/web-audio-api/#AudioParam-attributes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParamDescriptor {
+    pub default_value: f32,
+    pub min_value: f32,
+    pub max_value: f32,
+    pub automation_rate: ParamRate,
+    /// Whether the automation rate can't be changed
+    pub fixed_rate: bool,
+}
+
+impl ParamDescriptor {
+    /// An a-rate param with the given range
+    pub fn new(default_value: f32, min_value: f32, max_value: f32) -> Self {
+        ParamDescriptor {
+            default_value,
+            min_value,
+            max_value,
+            automation_rate: ParamRate::ARate,
+            fixed_rate: false,
+        }
+    }
+
+    /// An a-rate param with any value
+    pub fn unbounded(default_value: f32) -> Self {
+        ParamDescriptor::new(default_value, f32::MIN, f32::MAX)
+    }
+
+    /// The same range, for a param that is always k-rate
+    fn fixed_krate(self) -> Self {
+        ParamDescriptor {
+            automation_rate: ParamRate::KRate,
+            fixed_rate: true,
+            ..self
+        }
+    }
+
+    /// The descriptor the spec gives `param` on nodes of type `node`,
+    /// in a context running at `sample_rate`
+    ///
+    /// The params of AudioWorkletNodes are described by their options,
+    /// and the maximum of a DelayNode's delay time by its options.
+    pub fn of(node: AudioNodeType, param: ParamType, sample_rate: f32) -> Option<Self> {
+        let nyquist = sample_rate / 2.;
+        let max_detune = 1200. * f32::MAX.log2();
+        let unbounded = ParamDescriptor::unbounded;
+        let descriptor = match (node, param) {
+            (AudioNodeType::AudioBufferSourceNode, ParamType::Detune) => {
+                unbounded(0.).fixed_krate()
+            }
+            (AudioNodeType::AudioBufferSourceNode, ParamType::PlaybackRate) => {
+                unbounded(1.).fixed_krate()
+            }
+            (AudioNodeType::AudioListenerNode, ParamType::Position(_)) => unbounded(0.),
+            (AudioNodeType::AudioListenerNode, ParamType::Forward(ParamDir::Z)) => unbounded(-1.),
+            (AudioNodeType::AudioListenerNode, ParamType::Forward(_)) => unbounded(0.),
+            (AudioNodeType::AudioListenerNode, ParamType::Up(ParamDir::Y)) => unbounded(1.),
+            (AudioNodeType::AudioListenerNode, ParamType::Up(_)) => unbounded(0.),
+            (AudioNodeType::BiquadFilterNode, ParamType::Frequency) => {
+                ParamDescriptor::new(350., 0., nyquist)
+            }
+            (AudioNodeType::BiquadFilterNode, ParamType::Detune)
+            | (AudioNodeType::OscillatorNode, ParamType::Detune) => {
+                ParamDescriptor::new(0., -max_detune, max_detune)
+            }
+            (AudioNodeType::BiquadFilterNode, ParamType::Q) => unbounded(1.),
+            (AudioNodeType::BiquadFilterNode, ParamType::Gain) => {
+                ParamDescriptor::new(0., f32::MIN, 40. * f32::MAX.log10())
+            }
+            (AudioNodeType::ConstantSourceNode, ParamType::Offset) => unbounded(1.),
+            (AudioNodeType::DelayNode, ParamType::DelayTime) => ParamDescriptor::new(0., 0., 1.),
+            (AudioNodeType::DynamicsCompressionNode, ParamType::Threshold) => {
+                ParamDescriptor::new(-24., -100., 0.).fixed_krate()
+            }
+            (AudioNodeType::DynamicsCompressionNode, ParamType::Knee) => {
+                ParamDescriptor::new(30., 0., 40.).fixed_krate()
+            }
+            (AudioNodeType::DynamicsCompressionNode, ParamType::Ratio) => {
+                ParamDescriptor::new(12., 1., 20.).fixed_krate()
+            }
+            (AudioNodeType::DynamicsCompressionNode, ParamType::Attack) => {
+                ParamDescriptor::new(0.003, 0., 1.).fixed_krate()
+            }
+            (AudioNodeType::DynamicsCompressionNode, ParamType::Release) => {
+                ParamDescriptor::new(0.25, 0., 1.).fixed_krate()
+            }
+            (AudioNodeType::GainNode, ParamType::Gain) => unbounded(1.),
+            (AudioNodeType::OscillatorNode, ParamType::Frequency) => {
+                ParamDescriptor::new(440., -nyquist, nyquist)
+            }
+            (AudioNodeType::PannerNode, ParamType::Position(_)) => unbounded(0.),
+            (AudioNodeType::PannerNode, ParamType::Orientation(ParamDir::X)) => unbounded(1.),
+            (AudioNodeType::PannerNode, ParamType::Orientation(_)) => unbounded(0.),
+            (AudioNodeType::StereoPannerNode, ParamType::Pan) => ParamDescriptor::new(0., -1., 1.),
+            _ => return None,
+        };
+        Some(descriptor)
+    }
+}
+
+/// Invalid automation requests
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamError {
+    /// Exponential ramps can't reach 0, this is a RangeError.
+    ExponentialRampToZero,
+    /// The automation rate of this param can't be changed, this is an
+    /// InvalidStateError.
+    FixedAutomationRate,
+    /// An automation query's times must be finite, and the interval of
+    /// a range positive, this is a RangeError.
+    InvalidAutomationQuery,
+    /// An automation query asked for more than
+    /// MAX_AUTOMATION_QUERY_VALUES values, this is a NotSupportedError.
+    TooManyAutomationValues,
+    /// Value curves need at least two values, this is an
+    /// InvalidStateError.
+    ShortValueCurve,
+    /// The duration of a value curve must be positive, this is a
+    /// RangeError.
+    InvalidCurveDuration,
+}
+
 /// An AudioParam.
 ///
 /// https://webaudio.github.io/web-audio-api/#AudioParam
 pub struct Param {
-    val: f32,
+    timeline: AutomationTimeline,
+    descriptor: ParamDescriptor,
     kind: ParamRate,
-    events: Vec<AutomationEvent>,
-    current_event: usize,
-    event_start_time: Tick,
-    event_start_value: f32,
     /// Cache of inputs from connect()ed nodes
     blocks: Vec<Block>,
     /// The value of all connect()ed inputs mixed together, for this frame
@@ -54,28 +186,20 @@ pub enum ParamRate {
 
 impl Param {
     pub fn new(val: f32) -> Self {
-        Param {
-            val,
-            kind: ParamRate::ARate,
-            events: vec![],
-            current_event: 0,
-            event_start_time: Tick(0),
-            event_start_value: val,
-            blocks: Vec::new(),
-            block_mix_val: 0.,
-            summed: false,
-            dirty: false,
-        }
+        Param::with_descriptor(val, ParamDescriptor::unbounded(val))
     }
 
     pub fn new_krate(val: f32) -> Self {
+        let mut param = Param::new(val);
+        param.kind = ParamRate::KRate;
+        param
+    }
+
+    pub fn with_descriptor(val: f32, descriptor: ParamDescriptor) -> Self {
         Param {
-            val,
-            kind: ParamRate::KRate,
-            events: vec![],
-            current_event: 0,
-            event_start_time: Tick(0),
-            event_start_value: val,
+            timeline: AutomationTimeline::new(val),
+            descriptor,
+            kind: descriptor.automation_rate,
             blocks: Vec::new(),
             block_mix_val: 0.,
             summed: false,
@@ -83,6 +207,17 @@ impl Param {
         }
     }
 
+    /// The param `param` of a node of type `node`, see `ParamDescriptor::of`
+    pub fn for_node(node: AudioNodeType, param: ParamType, val: f32, sample_rate: f32) -> Self {
+        let descriptor = ParamDescriptor::of(node, param, sample_rate)
+            .unwrap_or_else(|| panic!("{:?} has no {:?} param", node, param));
+        Param::with_descriptor(val, descriptor)
+    }
+
+    pub fn descriptor(&self) -> ParamDescriptor {
+        self.descriptor
+    }
+
     /// Update the value of this param to the next
     ///
     /// Invariant: This should be called with monotonically increasing
@@ -119,11 +254,176 @@ impl Param {
             false
         };
 
-        if self.events.len() <= self.current_event {
-            return changed;
+        self.timeline.advance(block.absolute_tick(tick)) || changed
+    }
+
+    pub fn value(&self) -> f32 {
+        // the data from connect()ed audionodes is first mixed
+        // together in update(), and then mixed with the actual param value
+        // https://webaudio.github.io/web-audio-api/#dom-audionode-connect-destinationparam-output
+        self.clamp(self.timeline.val + self.block_mix_val)
+    }
+
+    /// Clamp to the nominal range
+    ///
+    /// https://webaudio.github.io/web-audio-api/#computedvalue
+    fn clamp(&self, value: f32) -> f32 {
+        value
+            .max(self.descriptor.min_value)
+            .min(self.descriptor.max_value)
+    }
+
+    pub fn set_rate(&mut self, rate: ParamRate) {
+        if self.descriptor.fixed_rate && rate != self.kind {
+            warn!("Ignoring a change of a fixed automation rate");
+            return;
+        }
+        self.kind = rate;
+    }
+
+    pub fn get_rate(&self) -> ParamRate {
+        self.kind
+    }
+
+    /// Schedule `event`, the curves of the events it cancels are
+    /// handed to `dispose`
+    pub(crate) fn insert_event(
+        &mut self,
+        event: AutomationEvent,
+        dispose: &mut dyn FnMut(Garbage),
+    ) {
+        if let Err(error) = event.validate() {
+            warn!("Ignoring an invalid automation event: {:?}", error);
+            if let AutomationEvent::SetValueCurveAtTime(curve, ..) = event {
+                dispose(Garbage::Curve(curve));
+            }
+            return;
+        }
+        self.dirty |= self.timeline.insert_event(event, dispose);
+    }
+
+    /// Copy the automation state into `snapshot`, which
+    /// `AutomationSnapshot::values` evaluates queries on
+    ///
+    /// The curves are shared, and `snapshot` has room for as many events
+    /// as a param holds before reallocating, so this doesn't allocate.
+    pub(crate) fn snapshot_into(&self, snapshot: &mut AutomationSnapshot) {
+        let timeline = &mut snapshot.timeline;
+        timeline.events.clear();
+        timeline.events.extend(self.timeline.events.iter().cloned());
+        timeline.val = self.timeline.val;
+        timeline.current_event = self.timeline.current_event;
+        timeline.event_start_time = self.timeline.event_start_time;
+        timeline.event_start_value = self.timeline.event_start_value;
+        timeline.tick = self.timeline.tick;
+        snapshot.stride = match self.kind {
+            ParamRate::ARate => 1,
+            ParamRate::KRate => frames_per_block().0,
+        };
+        snapshot.min_value = self.descriptor.min_value;
+        snapshot.max_value = self.descriptor.max_value;
+    }
+
+    pub(crate) fn add_block(&mut self, block: Block) {
+        debug_assert!(block.chan_count() == 1);
+        // summed only becomes true during a node's process() call,
+        // but add_block is called during graph traversal before processing,
+        // so if summed is true that means we've moved on to the next block
+        // and should clear our inputs
+        if self.summed {
+            self.blocks.clear();
+        }
+        self.blocks.push(block)
+    }
+
+    /// Flush an entire block of values into a buffer
+    ///
+    /// Only for use with AudioListener.
+    ///
+    /// Invariant: `block` must be a render quantum length array filled with silence
+    pub(crate) fn flush_to_block(&mut self, info: &BlockInfo, block: &mut [f32]) {
+        // common case
+        if self.timeline.is_done() && self.blocks.is_empty() {
+            if self.timeline.val != 0. {
+                for tick in 0..(frames_per_block_usize()) {
+                    // ideally this can use some kind of vectorized memset()
+                    block[tick] = self.timeline.val;
+                }
+            }
+        // if the value is zero, our buffer is already zeroed
+        } else {
+            for tick in 0..(frames_per_block_usize()) {
+                self.update(info, Tick(tick as u64));
+                block[tick] = self.timeline.val;
+            }
+        }
+    }
+}
+
+/// How many events a param can hold before scheduling one more
+/// reallocates on the render thread
+const EVENTS_CAPACITY: usize = 64;
+
+/// The scheduled automation events of a param, and how far along
+/// rendering is in them
+#[derive(Clone)]
+struct AutomationTimeline {
+    val: f32,
+    events: Vec<AutomationEvent>,
+    current_event: usize,
+    event_start_time: Tick,
+    event_start_value: f32,
+    /// The last tick the timeline was advanced to
+    tick: Option<Tick>,
+}
+
+impl AutomationTimeline {
+    fn new(val: f32) -> Self {
+        AutomationTimeline {
+            val,
+            events: Vec::with_capacity(EVENTS_CAPACITY),
+            current_event: 0,
+            event_start_time: Tick(0),
+            event_start_value: val,
+            tick: None,
+        }
+    }
+
+    /// Whether there are no more events to run
+    fn is_done(&self) -> bool {
+        self.current_event >= self.events.len()
+    }
+
+    /// The first tick after the last one the timeline was advanced to
+    /// at which it may move to another event, if any
+    ///
+    /// Until then, the value only depends on the tick.
+    fn next_boundary(&self) -> Option<Tick> {
+        let tick = self.tick?;
+        self.events[self.current_event.min(self.events.len())..]
+            .iter()
+            .flat_map(|event| {
+                let done = event.done_time().map(|done| done + 1);
+                Some(event.time())
+                    .into_iter()
+                    .chain(event.start_time())
+                    .chain(done)
+            })
+            .filter(|&boundary| boundary > tick)
+            .min()
+    }
+
+    /// Compute the value at `current_tick`
+    ///
+    /// Invariant: This should be called with increasing ticks.
+    ///
+    /// Returns true if the value changed
+    fn advance(&mut self, current_tick: Tick) -> bool {
+        self.tick = Some(current_tick);
+        if self.is_done() {
+            return false;
         }
 
-        let current_tick = block.absolute_tick(tick);
         let mut current_event = &self.events[self.current_event];
 
         // move to next event if necessary
@@ -155,7 +455,7 @@ impl Param {
                         move_next = true;
                     } else {
                         // This is a SetTarget event before its start time, ignore
-                        return changed;
+                        return false;
                     }
                 }
             }
@@ -168,7 +468,7 @@ impl Param {
                     // may need to move multiple times
                     continue;
                 } else {
-                    return changed;
+                    return false;
                 }
             }
             break;
@@ -182,23 +482,12 @@ impl Param {
         )
     }
 
-    pub fn value(&self) -> f32 {
-        // the data from connect()ed audionodes is first mixed
-        // together in update(), and then mixed with the actual param value
-        // https://webaudio.github.io/web-audio-api/#dom-audionode-connect-destinationparam-output
-        self.val + self.block_mix_val
-    }
-
-    pub fn set_rate(&mut self, rate: ParamRate) {
-        self.kind = rate;
-    }
-
-    pub(crate) fn insert_event(&mut self, event: AutomationEvent) {
+    /// Returns true if the value changed
+    fn insert_event(&mut self, event: AutomationEvent, dispose: &mut dyn FnMut(Garbage)) -> bool {
         if let AutomationEvent::SetValue(val) = event {
             self.val = val;
             self.event_start_value = val;
-            self.dirty = true;
-            return;
+            return true;
         }
 
         let time = event.time();
@@ -214,7 +503,11 @@ impl Param {
         // doesn't handle cases for when this lands inside a running
         // event
         if let Some(is_hold) = event.cancel_event() {
-            self.events.truncate(idx);
+            for event in self.events.drain(idx..) {
+                if let AutomationEvent::SetValueCurveAtTime(curve, ..) = event {
+                    dispose(Garbage::Curve(curve));
+                }
+            }
             if !is_hold {
                 // If we cancelled the current event, reset
                 // the value to what it was before
@@ -223,47 +516,179 @@ impl Param {
                 }
             }
             // don't actually insert the event
-            return;
+            return false;
         }
         self.events.insert(idx, event);
         // XXXManishearth handle inserting events with a time before that
         // of the current one
+        false
     }
 
-    pub(crate) fn add_block(&mut self, block: Block) {
-        debug_assert!(block.chan_count() == 1);
-        // summed only becomes true during a node's process() call,
-        // but add_block is called during graph traversal before processing,
-        // so if summed is true that means we've moved on to the next block
-        // and should clear our inputs
-        if self.summed {
-            self.blocks.clear();
+    /// The values at `ticks`, advancing a copy of the timeline every
+    /// `stride` ticks like rendering does, from where it is now
+    ///
+    /// Ticks that rendering is already past get the current value.
+    fn values_at(&self, ticks: &[Tick], stride: u64) -> Vec<f32> {
+        // Advance through the ticks in order, automation depends on the
+        // value the previous event ended at
+        let mut order: Vec<usize> = (0..ticks.len()).collect();
+        order.sort_by_key(|&i| ticks[i]);
+
+        let mut timeline = self.clone();
+        let mut next = match self.tick {
+            Some(tick) => Tick((tick.0 / stride + 1) * stride),
+            None => Tick(0),
+        };
+        let mut values = vec![0.; ticks.len()];
+        for i in order {
+            let target = ticks[i];
+            while next <= target {
+                if timeline.is_done() {
+                    // Nothing is going to change anymore
+                    next = Tick((target.0 / stride + 1) * stride);
+                    break;
+                }
+                let event = timeline.current_event;
+                timeline.advance(next);
+                next = next + stride;
+                if timeline.current_event != event {
+                    // Moving to another event can take two steps
+                    continue;
+                }
+                // Skip to the last tick before the next event boundary,
+                // the value in between doesn't depend on the ticks before
+                let until = match timeline.next_boundary() {
+                    Some(boundary) if boundary.0 <= target.0 => boundary - 1,
+                    _ => target,
+                };
+                let until = Tick(until.0 / stride * stride);
+                if until > next {
+                    next = until;
+                }
+            }
+            values[i] = timeline.val;
         }
-        self.blocks.push(block)
+        values
     }
+}
 
-    /// Flush an entire block of values into a buffer
-    ///
-    /// Only for use with AudioListener.
-    ///
-    /// Invariant: `block` must be a FRAMES_PER_BLOCK length array filled with silence
-    pub(crate) fn flush_to_block(&mut self, info: &BlockInfo, block: &mut [f32]) {
-        // common case
-        if self.current_event >= self.events.len() && self.blocks.is_empty() {
-            if self.val != 0. {
-                for tick in 0..(FRAMES_PER_BLOCK_USIZE) {
-                    // ideally this can use some kind of vectorized memset()
-                    block[tick] = self.val;
+/// A copy of a param's automation as rendering left it, for
+/// `AudioNodeMessage::GetParamAutomation` queries to be evaluated on
+/// the control thread
+pub struct AutomationSnapshot {
+    timeline: AutomationTimeline,
+    /// How often the timeline is advanced, in ticks
+    stride: u64,
+    min_value: f32,
+    max_value: f32,
+}
+
+impl AutomationSnapshot {
+    pub(crate) fn new() -> Self {
+        AutomationSnapshot {
+            timeline: AutomationTimeline::new(0.),
+            stride: 1,
+            min_value: f32::MIN,
+            max_value: f32::MAX,
+        }
+    }
+
+    /// The values the automation will take at `query`'s times, carrying
+    /// on from where rendering is, without the connect()ed inputs
+    pub(crate) fn values(&self, query: &AutomationQuery, sample_rate: f32) -> Vec<f32> {
+        let mut values = self
+            .timeline
+            .values_at(&query.ticks(sample_rate), self.stride);
+        for value in &mut values {
+            *value = value.max(self.min_value).min(self.max_value);
+        }
+        values
+    }
+}
+
+/// When to evaluate a param's automation
+#[derive(Clone, Debug, PartialEq)]
+pub enum AutomationQuery {
+    /// At each of these times, in seconds
+    Times(Vec<f64>),
+    /// From `start` to `end` seconds, every `interval` seconds
+    Range { start: f64, end: f64, interval: f64 },
+}
+
+/// The most values an automation query can ask for
+pub const MAX_AUTOMATION_QUERY_VALUES: usize = 1 << 16;
+
+impl AutomationQuery {
+    /// Check that the query can be evaluated
+    pub fn validate(&self) -> Result<(), ParamError> {
+        let count = match *self {
+            AutomationQuery::Times(ref times) => {
+                if times.iter().any(|time| !time.is_finite()) {
+                    return Err(ParamError::InvalidAutomationQuery);
                 }
+                times.len()
             }
-        // if the value is zero, our buffer is already zeroed
-        } else {
-            for tick in 0..(FRAMES_PER_BLOCK_USIZE) {
-                self.update(info, Tick(tick as u64));
-                block[tick] = self.val;
+            AutomationQuery::Range {
+                start,
+                end,
+                interval,
+            } => {
+                if !start.is_finite() || !end.is_finite() || !interval.is_finite() || interval <= 0.
+                {
+                    return Err(ParamError::InvalidAutomationQuery);
+                }
+                let count = (end - start) / interval + 1.;
+                if count > MAX_AUTOMATION_QUERY_VALUES as f64 {
+                    return Err(ParamError::TooManyAutomationValues);
+                }
+                count.max(0.) as usize
             }
+        };
+        if count > MAX_AUTOMATION_QUERY_VALUES {
+            return Err(ParamError::TooManyAutomationValues);
         }
+        Ok(())
     }
+
+    /// The ticks of a valid query
+    fn ticks(&self, sample_rate: f32) -> Vec<Tick> {
+        match *self {
+            AutomationQuery::Times(ref times) => times
+                .iter()
+                .map(|&time| Tick::from_time(time, sample_rate))
+                .collect(),
+            AutomationQuery::Range {
+                start,
+                end,
+                interval,
+            } => {
+                let count = ((end - start) / interval).floor().max(-1.) as i64 + 1;
+                (0..count)
+                    .map(|i| Tick::from_time(start + i as f64 * interval, sample_rate))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Evaluate the automation of a param with the `default` value,
+/// and `events` scheduled on it in order, at `query`'s times
+///
+/// This is what `AudioNodeMessage::GetParamAutomation` computes for an
+/// a-rate param without a nominal range before rendering starts. Invalid
+/// events are skipped, like the render thread does.
+pub fn evaluate_automation(
+    default: f32,
+    events: &[UserAutomationEvent],
+    sample_rate: f32,
+    query: &AutomationQuery,
+) -> Result<Vec<f32>, ParamError> {
+    query.validate()?;
+    let mut timeline = AutomationTimeline::new(default);
+    for event in events.iter().filter(|event| event.validate().is_ok()) {
+        timeline.insert_event(event.clone().to_event(sample_rate), &mut drop);
+    }
+    Ok(timeline.values_at(&query.ticks(sample_rate), 1))
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
@@ -280,7 +705,7 @@ pub(crate) enum AutomationEvent {
     RampToValueAtTime(RampKind, f32, Tick),
     SetTargetAtTime(f32, Tick, /* time constant, units of Tick */ f64),
     SetValueCurveAtTime(
-        Vec<f32>,
+        Arc<[f32]>,
         /* start time */ Tick,
         /* duration */ Tick,
     ),
@@ -301,6 +726,24 @@ pub enum UserAutomationEvent {
 }
 
 impl UserAutomationEvent {
+    /// Check that the event can be scheduled
+    pub fn validate(&self) -> Result<(), ParamError> {
+        match *self {
+            UserAutomationEvent::RampToValueAtTime(RampKind::Exponential, val, _) if val == 0. => {
+                Err(ParamError::ExponentialRampToZero)
+            }
+            UserAutomationEvent::SetValueCurveAtTime(ref values, ..) if values.len() < 2 => {
+                Err(ParamError::ShortValueCurve)
+            }
+            UserAutomationEvent::SetValueCurveAtTime(_, _, duration)
+                if !duration.is_finite() || duration <= 0. =>
+            {
+                Err(ParamError::InvalidCurveDuration)
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub(crate) fn to_event(self, rate: f32) -> AutomationEvent {
         match self {
             UserAutomationEvent::SetValue(val) => AutomationEvent::SetValue(val),
@@ -312,7 +755,7 @@ impl UserAutomationEvent {
             }
             UserAutomationEvent::SetValueCurveAtTime(values, start, duration) => {
                 AutomationEvent::SetValueCurveAtTime(
-                    values,
+                    values.into(),
                     Tick::from_time(start, rate),
                     Tick::from_time(duration, rate),
                 )
@@ -334,7 +777,34 @@ impl UserAutomationEvent {
     }
 }
 
+/// An event converted to ticks on the control thread, so that the
+/// render thread doesn't allocate its curve, see
+/// `AudioNodeMessage::InsertParamEvent`
+pub struct ParamEvent(AutomationEvent);
+
+impl ParamEvent {
+    pub(crate) fn new(event: UserAutomationEvent, sample_rate: f32) -> Self {
+        ParamEvent(event.to_event(sample_rate))
+    }
+
+    pub(crate) fn into_event(self) -> AutomationEvent {
+        self.0
+    }
+}
+
 impl AutomationEvent {
+    pub fn validate(&self) -> Result<(), ParamError> {
+        match *self {
+            AutomationEvent::RampToValueAtTime(RampKind::Exponential, val, _) if val == 0. => {
+                Err(ParamError::ExponentialRampToZero)
+            }
+            AutomationEvent::SetValueCurveAtTime(ref values, ..) if values.len() < 2 => {
+                Err(ParamError::ShortValueCurve)
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// The time of the event used for ordering
     pub fn time(&self) -> Tick {
         match *self {
@@ -440,6 +910,11 @@ impl AutomationEvent {
                 *value = val + (event_start_value - val) * exp.exp() as f32;
                 true
             }
+            AutomationEvent::SetValueCurveAtTime(ref values, _, duration) if duration.0 == 0 => {
+                // shorter than a frame, the curve is over already
+                *value = values[values.len() - 1];
+                true
+            }
             AutomationEvent::SetValueCurveAtTime(ref values, start, duration) => {
                 let progress = ((((current_tick.0 as f32) - (start.0 as f32)) as f32)
                     / (duration.0 as f32)) as f32;
@@ -463,3 +938,166 @@ impl AutomationEvent {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f32 = 1000.;
+
+    fn at(events: &[UserAutomationEvent], times: &[f64]) -> Vec<f32> {
+        evaluate_automation(1., events, RATE, &AutomationQuery::Times(times.to_vec())).unwrap()
+    }
+
+    #[test]
+    fn no_events_hold_the_default() {
+        assert_eq!(at(&[], &[0., 1., 100.]), vec![1., 1., 1.]);
+        let events = [UserAutomationEvent::SetValue(0.5)];
+        assert_eq!(at(&events, &[0., 1.]), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn set_value_at_time_steps() {
+        let events = [
+            UserAutomationEvent::SetValueAtTime(2., 0.1),
+            UserAutomationEvent::SetValueAtTime(3., 0.2),
+        ];
+        assert_eq!(
+            at(&events, &[0.05, 0.1, 0.15, 0.2, 1.]),
+            vec![1., 2., 2., 3., 3.]
+        );
+    }
+
+    #[test]
+    fn linear_ramp() {
+        let events = [
+            UserAutomationEvent::SetValueAtTime(0., 0.),
+            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1., 1.),
+        ];
+        let values = at(&events, &[0., 0.25, 0.5, 1., 2.]);
+        let expected = [0., 0.25, 0.5, 1., 1.];
+        for (value, expected) in values.iter().zip(&expected) {
+            assert!((value - expected).abs() < 1e-3, "{} != {}", value, expected);
+        }
+    }
+
+    #[test]
+    fn set_target_approaches_target() {
+        let events = [UserAutomationEvent::SetTargetAtTime(0., 0., 0.1)];
+        let values = at(&events, &[0.1, 0.2]);
+        assert!((values[0] - (-1f32).exp()).abs() < 1e-3);
+        assert!((values[1] - (-2f32).exp()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn times_in_any_order() {
+        let events = [
+            UserAutomationEvent::SetValueAtTime(0., 0.),
+            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1., 1.),
+        ];
+        let sorted = at(&events, &[0.2, 0.4, 0.8]);
+        let shuffled = at(&events, &[0.8, 0.2, 0.4]);
+        assert_eq!(shuffled, vec![sorted[2], sorted[0], sorted[1]]);
+    }
+
+    #[test]
+    fn range_at_resolution() {
+        let events = [
+            UserAutomationEvent::SetValueAtTime(0., 0.),
+            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1., 1.),
+        ];
+        let query = AutomationQuery::Range {
+            start: 0.,
+            end: 1.,
+            interval: 0.25,
+        };
+        let range = evaluate_automation(1., &events, RATE, &query).unwrap();
+        assert_eq!(range, at(&events, &[0., 0.25, 0.5, 0.75, 1.]));
+    }
+
+    #[test]
+    fn values_clamped_to_nominal_range() {
+        let mut pan = Param::for_node(AudioNodeType::StereoPannerNode, ParamType::Pan, 0., RATE);
+        pan.insert_event(UserAutomationEvent::SetValue(3.).to_event(RATE), &mut drop);
+        let info = BlockInfo {
+            sample_rate: RATE,
+            frame: Tick(0),
+            time: 0.,
+        };
+        pan.update(&info, Tick(0));
+        assert_eq!(pan.value(), 1.);
+    }
+
+    #[test]
+    fn fixed_rate_and_invalid_events_ignored() {
+        let node = AudioNodeType::AudioBufferSourceNode;
+        let mut rate = Param::for_node(node, ParamType::PlaybackRate, 1., RATE);
+        rate.set_rate(ParamRate::ARate);
+        assert_eq!(rate.get_rate(), ParamRate::KRate);
+
+        let ramp = UserAutomationEvent::RampToValueAtTime(RampKind::Exponential, 0., 1.);
+        assert_eq!(ramp.validate(), Err(ParamError::ExponentialRampToZero));
+        assert_eq!(at(&[ramp], &[0.5, 2.]), vec![1., 1.]);
+
+        let empty = UserAutomationEvent::SetValueCurveAtTime(vec![], 0., 1.);
+        assert_eq!(empty.validate(), Err(ParamError::ShortValueCurve));
+        assert_eq!(at(&[empty], &[0.5]), vec![1.]);
+        let instant = UserAutomationEvent::SetValueCurveAtTime(vec![0., 2.], 0., 0.);
+        assert_eq!(instant.validate(), Err(ParamError::InvalidCurveDuration));
+
+        // Shorter than a frame once in ticks, the curve ends right away
+        let mut timeline = AutomationTimeline::new(1.);
+        let curve = AutomationEvent::SetValueCurveAtTime(vec![0., 2.].into(), Tick(1), Tick(0));
+        timeline.insert_event(curve, &mut drop);
+        assert_eq!(
+            timeline.values_at(&[Tick(0), Tick(1), Tick(2)], 1),
+            vec![1., 2., 2.]
+        );
+    }
+
+    #[test]
+    fn cancelled_curves_disposed() {
+        let mut gain = Param::for_node(AudioNodeType::GainNode, ParamType::Gain, 1., RATE);
+        let mut disposed = Vec::new();
+        let curve = UserAutomationEvent::SetValueCurveAtTime(vec![0., 1.], 1., 1.);
+        gain.insert_event(curve.to_event(RATE), &mut |g| disposed.push(g));
+        let cancel = UserAutomationEvent::CancelScheduledValues(0.5);
+        gain.insert_event(cancel.to_event(RATE), &mut |g| disposed.push(g));
+        match disposed.as_slice() {
+            [Garbage::Curve(curve)] => assert_eq!(**curve, [0., 1.]),
+            _ => panic!("the curve wasn't disposed"),
+        }
+    }
+
+    #[test]
+    fn distant_times_and_invalid_queries() {
+        // An hour at RATE, this used to be evaluated tick by tick
+        let events = [
+            UserAutomationEvent::SetValueAtTime(0., 1.),
+            UserAutomationEvent::SetTargetAtTime(2., 2., 1.),
+        ];
+        let values = at(&events, &[1.5, 3600.]);
+        assert_eq!(values[0], 0.);
+        assert!((values[1] - 2.).abs() < 1e-6);
+
+        let query = |start, end, interval| {
+            let query = AutomationQuery::Range {
+                start,
+                end,
+                interval,
+            };
+            evaluate_automation(1., &events, RATE, &query).map(|values| values.len())
+        };
+        assert_eq!(query(0., 1., 0.5), Ok(3));
+        assert_eq!(query(1., 0., 0.5), Ok(0));
+        assert_eq!(query(0., 1., 0.), Err(ParamError::InvalidAutomationQuery));
+        assert_eq!(
+            query(0., 1., f64::NAN),
+            Err(ParamError::InvalidAutomationQuery)
+        );
+        assert_eq!(
+            query(0., 1e9, 1e-3),
+            Err(ParamError::TooManyAutomationValues)
+        );
+    }
+}
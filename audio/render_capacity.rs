@@ -0,0 +1,138 @@
+use block::frames_per_block;
+use std::time::Duration;
+
+/// How close the render thread is to not keeping up with real time,
+/// over a measurement period
+///
+/// Loads are the time spent processing the graph for a render quantum,
+/// divided by the duration of a render quantum. A load of 1 or more
+/// means the quantum took longer to render than to play.
+///
+/// https://webaudio.github.io/web-audio-api/#AudioRenderCapacityEvent
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderCapacityReport {
+    /// The context time at which the period started
+    pub timestamp: f64,
+    /// The number of render quanta rendered in the period
+    pub quanta: u64,
+    pub average_load: f64,
+    pub peak_load: f64,
+    /// The ratio of render quanta which went over budget
+    pub underrun_ratio: f64,
+    /// The number of underruns reported by the sink in the period,
+    /// when it had to play while no data was available
+    pub underrun_count: u64,
+}
+
+#[derive(Default)]
+struct LoadMeter {
+    timestamp: f64,
+    quanta: u64,
+    total_load: f64,
+    peak_load: f64,
+    over_budget: u64,
+    /// The underrun count of the sink when the period started
+    underruns: u64,
+}
+
+impl LoadMeter {
+    fn new(timestamp: f64, underruns: u64) -> Self {
+        LoadMeter {
+            timestamp,
+            underruns,
+            ..Default::default()
+        }
+    }
+
+    fn record(&mut self, load: f64) {
+        self.quanta += 1;
+        self.total_load += load;
+        self.peak_load = self.peak_load.max(load);
+        if load > 1. {
+            self.over_budget += 1;
+        }
+    }
+
+    fn report(&self, underruns: u64) -> RenderCapacityReport {
+        let quanta = self.quanta.max(1) as f64;
+        RenderCapacityReport {
+            timestamp: self.timestamp,
+            quanta: self.quanta,
+            average_load: self.total_load / quanta,
+            peak_load: self.peak_load,
+            underrun_ratio: self.over_budget as f64 / quanta,
+            underrun_count: underruns.saturating_sub(self.underruns),
+        }
+    }
+}
+
+struct PeriodicReport {
+    meter: LoadMeter,
+    /// The length of a period, in render quanta
+    interval: u64,
+    callback: Box<dyn Fn(RenderCapacityReport) + Send + Sync + 'static>,
+}
+
+/// Measures the load of the render thread since the context started,
+/// and optionally reports it periodically
+pub(crate) struct RenderCapacity {
+    /// The duration of a render quantum, in seconds
+    budget: f64,
+    total: LoadMeter,
+    periodic: Option<PeriodicReport>,
+}
+
+impl RenderCapacity {
+    pub fn new(sample_rate: f32) -> Self {
+        RenderCapacity {
+            budget: frames_per_block().0 as f64 / sample_rate as f64,
+            total: LoadMeter::default(),
+            periodic: None,
+        }
+    }
+
+    /// Record the time it took to render the quantum starting at `time`
+    pub fn record(&mut self, elapsed: Duration, time: f64, underruns: u64) {
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        let load = elapsed / self.budget;
+        self.total.record(load);
+
+        if let Some(ref mut periodic) = self.periodic {
+            periodic.meter.record(load);
+            if periodic.meter.quanta >= periodic.interval {
+                (periodic.callback)(periodic.meter.report(underruns));
+                periodic.meter = LoadMeter::new(time + self.budget, underruns);
+            }
+        }
+    }
+
+    /// The load since the context started
+    pub fn report(&self, underruns: u64) -> RenderCapacityReport {
+        self.total.report(underruns)
+    }
+
+    /// Call `callback` with a report every `update_interval` seconds of
+    /// context time, rounded to a render quantum, returning the callback
+    /// it replaces
+    pub fn start(
+        &mut self,
+        update_interval: f64,
+        callback: Box<dyn Fn(RenderCapacityReport) + Send + Sync + 'static>,
+        time: f64,
+        underruns: u64,
+    ) -> Option<Box<dyn Fn(RenderCapacityReport) + Send + Sync + 'static>> {
+        let interval = (update_interval / self.budget).round().max(1.) as u64;
+        let previous = self.periodic.take().map(|periodic| periodic.callback);
+        self.periodic = Some(PeriodicReport {
+            meter: LoadMeter::new(time, underruns),
+            interval,
+            callback,
+        });
+        previous
+    }
+
+    /// Stop reporting, returning the callback
+    pub fn stop(&mut self) -> Option<Box<dyn Fn(RenderCapacityReport) + Send + Sync + 'static>> {
+        self.periodic.take().map(|periodic| periodic.callback)
+    }
+}
@@ -1,37 +1,48 @@
-use analyser_node::AnalyserNode;
-use biquad_filter_node::BiquadFilterNode;
-use block::{Chunk, Tick, FRAMES_PER_BLOCK};
-use buffer_source_node::AudioBufferSourceNode;
-use channel_node::{ChannelMergerNode, ChannelSplitterNode};
-use constant_source_node::ConstantSourceNode;
+use block::{frames_per_block, set_frames_per_block, Chunk, Tick};
+use boxfnonce::SendBoxFnOnce;
+use buffer_source_node::AudioBuffer;
 use context::{AudioContextOptions, ProcessingState, StateChangeResult};
-use gain_node::GainNode;
+use convolver_node::ConvolverKernel;
 use graph::{AudioGraph, InputPort, NodeId, OutputPort, PortId};
-use iir_filter_node::IIRFilterNode;
-use media_element_source_node::MediaElementSourceNode;
-use media_stream_destination_node::MediaStreamDestinationNode;
-use media_stream_source_node::MediaStreamSourceNode;
-use node::{AudioNodeEngine, AudioNodeInit, AudioNodeMessage};
-use node::{BlockInfo, ChannelInfo};
-use offline_sink::OfflineAudioSink;
-use oscillator_node::OscillatorNode;
-use panner_node::PannerNode;
-use servo_media_streams::{MediaSocket, MediaStreamId};
-use sink::{AudioSink, AudioSinkError};
-use std::sync::mpsc::{Receiver, Sender};
-use stereo_panner::StereoPannerNode;
-use wave_shaper_node::WaveShaperNode;
-use {AudioBackend, AudioStreamReader};
-
-pub enum AudioRenderThreadMsg {
-    CreateNode(AudioNodeInit, Sender<NodeId>, ChannelInfo),
+use graph_snapshot::GraphSnapshot;
+use hrtf::HrtfPanner;
+use log::warn;
+use node::BlockInfo;
+use node::{AudioNodeEngine, AudioNodeMessage};
+use offline_sink::{OfflineAudioSink, RenderWriterCallback};
+use oscillator_node::PeriodicWave;
+use render_capacity::{RenderCapacity, RenderCapacityReport};
+use servo_media_streams::MediaSocket;
+use sink::{AudioSink, AudioSinkError, AudioSinkOutput};
+use spsc::{Consumer, Producer};
+use std::io;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread::{self, Thread};
+use std::time::Instant;
+use wav_writer::RenderWriter;
+use AudioBackend;
+
+/// The number of messages that can be queued for the render thread,
+/// and of values it can queue for deallocation on the control thread
+pub(crate) const QUEUE_CAPACITY: usize = 1024;
+
+/// Messages from the control thread, delivered through a lock-free
+/// queue, see `AudioContext::send`
+///
+/// Queries and state changes reply through the channel they carry,
+/// everything else, including node creation, is handled without the
+/// control thread waiting for it.
+pub(crate) enum AudioRenderThreadMsg {
+    AddNode(NewNode),
     ConnectPorts(PortId<OutputPort>, PortId<InputPort>),
     MessageNode(NodeId, AudioNodeMessage),
     Resume(Sender<StateChangeResult>),
     Suspend(Sender<StateChangeResult>),
     Close(Sender<StateChangeResult>),
-    SinkNeedData,
     GetCurrentTime(Sender<f64>),
+    GetGraphSnapshot(Sender<GraphSnapshot>),
+    DropNode(NodeId),
 
     DisconnectAllFrom(NodeId),
     DisconnectOutput(PortId<OutputPort>),
@@ -41,8 +52,65 @@ pub enum AudioRenderThreadMsg {
     DisconnectOutputBetweenTo(PortId<OutputPort>, PortId<InputPort>),
 
     SetSinkEosCallback(Box<dyn Fn(Box<dyn AsRef<[f32]>>) + Send + Sync + 'static>),
+    SetSinkProgressCallback(usize, Box<dyn Fn(usize) + Send + Sync + 'static>),
+    SetSinkWriter(Box<dyn RenderWriter>, RenderWriterCallback),
+    SuspendAt(f64, SendBoxFnOnce<'static, ()>, Sender<StateChangeResult>),
 
     SetMute(bool),
+
+    SetSinkOutput(AudioSinkOutput, Sender<Result<(), AudioSinkError>>),
+    GetSinkId(Sender<Option<String>>),
+
+    GetRenderCapacity(Sender<RenderCapacityReport>),
+    StartRenderCapacity(
+        f64,
+        Box<dyn Fn(RenderCapacityReport) + Send + Sync + 'static>,
+    ),
+    StopRenderCapacity,
+}
+
+/// A node built on the control thread, to be added to the graph
+pub(crate) struct NewNode {
+    pub id: NodeId,
+    pub engine: Box<dyn AudioNodeEngine>,
+    /// Whether the node needs the output of the listener, like panners
+    pub needs_listener: bool,
+    /// Whether the node is rendered even when nothing downstream
+    /// is, like MediaStreamDestinationNodes
+    pub is_dest: bool,
+}
+
+/// Values the render thread is done with, sent back to the control
+/// thread so that deallocating them doesn't happen while rendering
+// The payloads are only held so that they are dropped on the control thread
+#[allow(dead_code)]
+pub(crate) enum Garbage {
+    /// A node removed from the graph, whose id can be reused
+    Node(NodeId, Box<dyn AudioNodeEngine>),
+    /// A render capacity callback which was replaced or stopped
+    RenderCapacityCallback(Box<dyn Fn(RenderCapacityReport) + Send + Sync + 'static>),
+    /// A message for a node that was removed
+    Message(AudioNodeMessage),
+    /// A buffer a node replaced or doesn't use
+    Buffer(AudioBuffer),
+    ConvolverKernel(ConvolverKernel),
+    HrtfPanner(Box<HrtfPanner>),
+    PeriodicWave(PeriodicWave),
+    /// Wave shaper curves
+    Samples(Vec<f32>),
+    /// Cancelled automation curves
+    Curve(Arc<[f32]>),
+}
+
+/// Wakes up the render thread while it waits for messages, which
+/// sinks use to ask for more data
+#[derive(Clone)]
+pub struct RenderThreadWaker(Thread);
+
+impl RenderThreadWaker {
+    pub fn wake(&self) {
+        self.0.unpark()
+    }
 }
 
 pub enum Sink {
@@ -51,14 +119,10 @@ pub enum Sink {
 }
 
 impl AudioSink for Sink {
-    fn init(
-        &self,
-        sample_rate: f32,
-        sender: Sender<AudioRenderThreadMsg>,
-    ) -> Result<(), AudioSinkError> {
+    fn init(&self, sample_rate: f32, waker: RenderThreadWaker) -> Result<(), AudioSinkError> {
         match *self {
-            Sink::RealTime(ref sink) => sink.init(sample_rate, sender),
-            Sink::Offline(ref sink) => Ok(sink.init(sample_rate, sender).unwrap()),
+            Sink::RealTime(ref sink) => sink.init(sample_rate, waker),
+            Sink::Offline(ref sink) => Ok(sink.init(sample_rate, waker).unwrap()),
         }
     }
 
@@ -103,18 +167,44 @@ impl AudioSink for Sink {
             Sink::Offline(ref sink) => sink.set_eos_callback(callback),
         }
     }
+
+    fn set_output(&self, output: AudioSinkOutput) -> Result<(), AudioSinkError> {
+        match *self {
+            Sink::RealTime(ref sink) => sink.set_output(output),
+            Sink::Offline(ref sink) => sink.set_output(output),
+        }
+    }
+
+    fn sink_id(&self) -> Option<String> {
+        match *self {
+            Sink::RealTime(ref sink) => sink.sink_id(),
+            Sink::Offline(ref sink) => sink.sink_id(),
+        }
+    }
+
+    fn underrun_count(&self) -> u64 {
+        match *self {
+            Sink::RealTime(ref sink) => sink.underrun_count(),
+            Sink::Offline(ref sink) => sink.underrun_count(),
+        }
+    }
 }
 
-pub struct AudioRenderThread {
+pub(crate) struct AudioRenderThread {
     pub graph: AudioGraph,
     pub sink: Sink,
-    pub sink_factory: Box<dyn Fn() -> Result<Box<dyn AudioSink + 'static>, AudioSinkError>>,
-    pub reader_factory: Box<dyn Fn(MediaStreamId, f32) -> Box<dyn AudioStreamReader + Send>>,
+    /// Where deallocations are deferred to
+    pub garbage: Producer<Garbage>,
     pub state: ProcessingState,
     pub sample_rate: f32,
     pub current_time: f64,
     pub current_frame: Tick,
     pub muted: bool,
+    /// The frames at which an offline context suspends itself, with
+    /// the callbacks to call when it does, sorted by frame
+    pub suspensions: Vec<(Tick, SendBoxFnOnce<'static, ()>)>,
+    /// How long rendering takes compared to real time
+    capacity: RenderCapacity,
 }
 
 impl AudioRenderThread {
@@ -122,33 +212,46 @@ impl AudioRenderThread {
     ///
     /// You must call .event_loop() on this to run it!
     fn prepare_thread<B: AudioBackend>(
-        sender: Sender<AudioRenderThreadMsg>,
+        garbage: Producer<Garbage>,
         sample_rate: f32,
         graph: AudioGraph,
         options: AudioContextOptions,
+        output: Option<AudioSinkOutput>,
     ) -> Result<Self, AudioSinkError> {
-        let sink_factory = Box::new(|| B::make_sink().map(|s| Box::new(s) as Box<dyn AudioSink>));
-        let reader_factory = Box::new(|id, sample_rate| B::make_streamreader(id, sample_rate));
+        let sink_factory = || B::make_sink().map(|s| Box::new(s) as Box<dyn AudioSink>);
         let sink = match options {
-            AudioContextOptions::RealTimeAudioContext(_) => Sink::RealTime(sink_factory()?),
+            AudioContextOptions::RealTimeAudioContext(_) => {
+                let sink = sink_factory()?;
+                if let Some(output) = output {
+                    let sink_id = output.sink_id().map(|id| id.to_owned());
+                    if sink.set_output(output).is_err() {
+                        warn!(
+                            "Could not use audio output {:?}, using the default",
+                            sink_id
+                        );
+                    }
+                }
+                Sink::RealTime(sink)
+            }
             AudioContextOptions::DummyAudioContext(_) => Sink::RealTime(sink_factory()?),
             AudioContextOptions::OfflineAudioContext(options) => Sink::Offline(
                 OfflineAudioSink::new(options.channels as usize, options.length),
             ),
         };
 
-        sink.init(sample_rate, sender)?;
+        sink.init(sample_rate, RenderThreadWaker(thread::current()))?;
 
         Ok(Self {
             graph,
             sink,
-            sink_factory,
-            reader_factory,
+            garbage,
             state: ProcessingState::Suspended,
             sample_rate,
             current_time: 0.,
             current_frame: Tick(0),
             muted: false,
+            suspensions: Vec::new(),
+            capacity: RenderCapacity::new(sample_rate),
         })
     }
 
@@ -156,13 +259,16 @@ impl AudioRenderThread {
     ///
     /// In case something fails, it will instead start a thread with a dummy backend
     pub fn start<B: AudioBackend>(
-        event_queue: Receiver<AudioRenderThreadMsg>,
-        sender: Sender<AudioRenderThreadMsg>,
+        event_queue: Consumer<AudioRenderThreadMsg>,
+        garbage: Producer<Garbage>,
         sample_rate: f32,
+        frames_per_block: Tick,
         graph: AudioGraph,
         options: AudioContextOptions,
+        output: Option<AudioSinkOutput>,
     ) {
-        let mut thread = Self::prepare_thread::<B>(sender.clone(), sample_rate, graph, options)
+        set_frames_per_block(frames_per_block);
+        let mut thread = Self::prepare_thread::<B>(garbage, sample_rate, graph, options, output)
             .expect("Could not start audio render thread");
         thread.event_loop(event_queue)
     }
@@ -171,60 +277,32 @@ impl AudioRenderThread {
 
     make_render_thread_state_change!(suspend, Suspended, stop);
 
-    fn create_node(&mut self, node_type: AudioNodeInit, ch: ChannelInfo) -> NodeId {
-        let mut needs_listener = false;
-        let mut is_dest = false;
-        let node: Box<dyn AudioNodeEngine> = match node_type {
-            AudioNodeInit::AnalyserNode(sender) => Box::new(AnalyserNode::new(sender, ch)),
-            AudioNodeInit::AudioBufferSourceNode(options) => {
-                Box::new(AudioBufferSourceNode::new(options, ch))
-            }
-            AudioNodeInit::BiquadFilterNode(options) => {
-                Box::new(BiquadFilterNode::new(options, ch, self.sample_rate))
-            }
-            AudioNodeInit::GainNode(options) => Box::new(GainNode::new(options, ch)),
-            AudioNodeInit::StereoPannerNode(options) => {
-                Box::new(StereoPannerNode::new(options, ch))
-            }
-            AudioNodeInit::PannerNode(options) => {
-                needs_listener = true;
-                Box::new(PannerNode::new(options, ch))
-            }
-            AudioNodeInit::MediaStreamSourceNode(id) => {
-                let reader = (self.reader_factory)(id, self.sample_rate);
-                Box::new(MediaStreamSourceNode::new(reader, ch))
-            }
-            AudioNodeInit::OscillatorNode(options) => Box::new(OscillatorNode::new(options, ch)),
-            AudioNodeInit::ChannelMergerNode(options) => {
-                Box::new(ChannelMergerNode::new(options, ch))
-            }
-            AudioNodeInit::ConstantSourceNode(options) => {
-                Box::new(ConstantSourceNode::new(options, ch))
-            }
-            AudioNodeInit::MediaStreamDestinationNode(socket) => {
-                is_dest = true;
-                Box::new(MediaStreamDestinationNode::new(
-                    socket,
-                    self.sample_rate,
-                    (self.sink_factory)().unwrap(),
-                    ch,
-                ))
-            }
-            AudioNodeInit::ChannelSplitterNode => Box::new(ChannelSplitterNode::new(ch)),
-            AudioNodeInit::WaveShaperNode(options) => Box::new(WaveShaperNode::new(options, ch)),
-            AudioNodeInit::MediaElementSourceNode => Box::new(MediaElementSourceNode::new(ch)),
-            AudioNodeInit::IIRFilterNode(options) => Box::new(IIRFilterNode::new(options, ch)),
-            _ => unimplemented!(),
-        };
-        let id = self.graph.add_node(node);
-        if needs_listener {
+    fn add_node(&mut self, node: NewNode) {
+        self.graph.add_node(node.id, node.engine);
+        if node.needs_listener {
             let listener = self.graph.listener_id().output(0);
-            self.graph.add_edge(listener, id.listener());
+            self.graph.add_edge(listener, node.id.listener());
+        }
+        if node.is_dest {
+            self.graph.add_extra_dest(node.id);
         }
-        if is_dest {
-            self.graph.add_extra_dest(id);
+    }
+
+    /// Defer deallocating `garbage` to the control thread. If its queue
+    /// is full, it is dropped here instead, and removed nodes then never
+    /// have their id reused.
+    fn dispose(garbage_queue: &Producer<Garbage>, garbage: Garbage) {
+        if let Err(garbage) = garbage_queue.push(garbage) {
+            warn!("Deallocating on the render thread, the control thread is not keeping up");
+            drop(garbage);
         }
-        id
+    }
+
+    fn collect_released_nodes(&mut self) {
+        let garbage = &self.garbage;
+        self.graph.collect_released_nodes(|id, engine| {
+            Self::dispose(garbage, Garbage::Node(id, engine));
+        });
     }
 
     fn connect_ports(&mut self, output: PortId<OutputPort>, input: PortId<InputPort>) {
@@ -241,21 +319,62 @@ impl AudioRenderThread {
             frame: self.current_frame,
             time: self.current_time,
         };
-        self.graph.process(&info)
+        let data = self.graph.process(&info);
+        self.collect_released_nodes();
+        data
     }
 
     fn set_mute(&mut self, val: bool) -> () {
         self.muted = val;
     }
 
-    fn event_loop(&mut self, event_queue: Receiver<AudioRenderThreadMsg>) {
+    /// Schedule an offline context to suspend itself at `time`, rounded
+    /// up to a render quantum boundary
+    ///
+    /// https://webaudio.github.io/web-audio-api/#dom-offlineaudiocontext-suspend
+    fn suspend_at(&mut self, time: f64, callback: SendBoxFnOnce<'static, ()>) -> StateChangeResult {
+        let length = match self.sink {
+            Sink::Offline(ref sink) => sink.length() as u64,
+            Sink::RealTime(_) => return Err(()),
+        };
+        if time.is_nan() || time < 0. {
+            return Err(());
+        }
+        let quantum = frames_per_block().0;
+        let frame = (time * self.sample_rate as f64 / quantum as f64).ceil() as u64 * quantum;
+        let frame = Tick(frame);
+        if frame < self.current_frame || frame.0 >= length {
+            return Err(());
+        }
+        match self
+            .suspensions
+            .binary_search_by_key(&frame, |&(tick, _)| tick)
+        {
+            // There can only be one suspension per render quantum
+            Ok(_) => Err(()),
+            Err(index) => {
+                self.suspensions.insert(index, (frame, callback));
+                Ok(())
+            }
+        }
+    }
+
+    /// The callback of the suspension scheduled for the current
+    /// render quantum, if any
+    fn take_suspension(&mut self) -> Option<SendBoxFnOnce<'static, ()>> {
+        match self.suspensions.first() {
+            Some(&(frame, _)) if frame <= self.current_frame => {}
+            _ => return None,
+        }
+        Some(self.suspensions.remove(0).1)
+    }
+
+    fn event_loop(&mut self, event_queue: Consumer<AudioRenderThreadMsg>) {
         let sample_rate = self.sample_rate;
         let handle_msg = move |context: &mut Self, msg: AudioRenderThreadMsg| -> bool {
             let mut break_loop = false;
             match msg {
-                AudioRenderThreadMsg::CreateNode(node_type, tx, ch) => {
-                    let _ = tx.send(context.create_node(node_type, ch));
-                }
+                AudioRenderThreadMsg::AddNode(node) => context.add_node(node),
                 AudioRenderThreadMsg::ConnectPorts(output, input) => {
                     context.connect_ports(output, input);
                 }
@@ -269,15 +388,24 @@ impl AudioRenderThread {
                     let _ = tx.send(context.suspend());
                     break_loop = true;
                 }
+                AudioRenderThreadMsg::GetGraphSnapshot(tx) => {
+                    let _ = tx.send(context.graph.snapshot());
+                }
                 AudioRenderThreadMsg::GetCurrentTime(response) => {
-                    response.send(context.current_time).unwrap()
+                    let _ = response.send(context.current_time);
                 }
                 AudioRenderThreadMsg::MessageNode(id, msg) => {
-                    context.graph.node_mut(id).message(msg, sample_rate)
+                    let garbage = &context.garbage;
+                    match context.graph.node_mut(id) {
+                        Some(mut node) => {
+                            node.message(msg, sample_rate, &mut |g| Self::dispose(garbage, g))
+                        }
+                        None => Self::dispose(garbage, Garbage::Message(msg)),
+                    }
                 }
-                AudioRenderThreadMsg::SinkNeedData => {
-                    // Do nothing. This will simply unblock the thread so we
-                    // can restart the non-blocking event loop.
+                AudioRenderThreadMsg::DropNode(id) => {
+                    context.graph.release_node(id);
+                    context.collect_released_nodes();
                 }
                 AudioRenderThreadMsg::DisconnectAllFrom(id) => {
                     context.graph.disconnect_all_from(id)
@@ -298,46 +426,106 @@ impl AudioRenderThread {
                 AudioRenderThreadMsg::SetSinkEosCallback(callback) => {
                     context.sink.set_eos_callback(callback);
                 }
+                AudioRenderThreadMsg::SetSinkProgressCallback(interval, callback) => {
+                    if let Sink::Offline(ref sink) = context.sink {
+                        sink.set_progress_callback(interval, callback);
+                    }
+                }
+                AudioRenderThreadMsg::SetSinkWriter(writer, callback) => match context.sink {
+                    Sink::Offline(ref sink) => sink.set_writer(writer, callback),
+                    Sink::RealTime(_) => callback.call(Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "only offline contexts can render to a writer",
+                    ))),
+                },
+                AudioRenderThreadMsg::SuspendAt(time, callback, tx) => {
+                    let _ = tx.send(context.suspend_at(time, callback));
+                }
                 AudioRenderThreadMsg::SetMute(val) => {
                     context.set_mute(val);
                 }
+                AudioRenderThreadMsg::SetSinkOutput(output, tx) => {
+                    let _ = tx.send(context.sink.set_output(output));
+                }
+                AudioRenderThreadMsg::GetSinkId(tx) => {
+                    let _ = tx.send(context.sink.sink_id());
+                }
+                AudioRenderThreadMsg::GetRenderCapacity(tx) => {
+                    let _ = tx.send(context.capacity.report(context.sink.underrun_count()));
+                }
+                AudioRenderThreadMsg::StartRenderCapacity(update_interval, callback) => {
+                    let underruns = context.sink.underrun_count();
+                    let previous = context.capacity.start(
+                        update_interval,
+                        callback,
+                        context.current_time,
+                        underruns,
+                    );
+                    if let Some(previous) = previous {
+                        Self::dispose(&context.garbage, Garbage::RenderCapacityCallback(previous));
+                    }
+                }
+                AudioRenderThreadMsg::StopRenderCapacity => {
+                    if let Some(previous) = context.capacity.stop() {
+                        Self::dispose(&context.garbage, Garbage::RenderCapacityCallback(previous));
+                    }
+                }
             };
 
             break_loop
         };
 
-        loop {
+        'render: loop {
+            // Checked before draining the queue, so that no message
+            // queued before the control thread went away is missed
+            let disconnected = event_queue.is_disconnected();
+
+            // Handle the messages queued since the last render quantum,
+            // there are at most QUEUE_CAPACITY of them
+            while let Some(msg) = event_queue.pop() {
+                if handle_msg(self, msg) {
+                    break 'render;
+                }
+            }
+
+            if disconnected {
+                break;
+            }
+
             if self.sink.has_enough_data() || self.state == ProcessingState::Suspended {
                 // If we are not processing audio or
                 // if we have already pushed enough data into the audio sink
                 // we wait for messages coming from the control thread or
-                // the audio sink. The audio sink will notify whenever it
-                // needs more data.
-                if let Ok(msg) = event_queue.recv() {
-                    if handle_msg(self, msg) {
-                        break;
-                    }
-                }
+                // the audio sink. Both wake the thread up, the audio sink
+                // whenever it needs more data.
+                thread::park();
             } else {
-                // If we have not pushed enough data into the audio sink yet,
-                // we process the control message queue
-                if let Ok(msg) = event_queue.try_recv() {
-                    if handle_msg(self, msg) {
-                        break;
-                    }
-                }
-
                 if self.state == ProcessingState::Suspended {
                     // Bail out if we just suspended processing.
                     continue;
                 }
 
+                if let Some(callback) = self.take_suspension() {
+                    // Offline contexts suspend themselves before rendering
+                    // the quantum they were asked to, until resumed by
+                    // the control thread.
+                    let _ = self.suspend();
+                    callback.call();
+                    continue;
+                }
+
                 // push into the audio sink the result of processing a
                 // render quantum.
+                let start = Instant::now();
                 let data = self.process();
+                self.capacity.record(
+                    start.elapsed(),
+                    self.current_time,
+                    self.sink.underrun_count(),
+                );
                 if self.sink.push_data(data).is_ok() {
                     // increment current frame by the render quantum size.
-                    self.current_frame += FRAMES_PER_BLOCK;
+                    self.current_frame += frames_per_block();
                     self.current_time = self.current_frame / self.sample_rate as f64;
                 } else {
                     eprintln!("Could not push data to audio sink");
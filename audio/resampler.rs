@@ -0,0 +1,213 @@
+//! Sample rate conversion, with Speex's resampler
+//!
+//! Used to bring decoded audio and `AudioBuffer`s that don't match the
+//! context's sample rate to it.
+
+use context::AudioContextError;
+use log::warn;
+use speexdsp_resampler::State;
+
+/// The lowest sample rate buffers and contexts can have
+///
+/// https://webaudio.github.io/web-audio-api/#dom-baseaudiocontext-samplerate
+pub const MIN_SAMPLE_RATE: f32 = 3000.;
+/// The highest sample rate buffers and contexts can have
+pub const MAX_SAMPLE_RATE: f32 = 768000.;
+
+/// The tradeoff between quality and speed of a `Resampler`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResampleQuality {
+    /// Fast enough for real time use, with audible aliasing for large
+    /// rate changes.
+    Fast,
+    /// Speex's default, a good balance for most content.
+    Medium,
+    /// For music, at about twice the cost of `Medium`.
+    High,
+    /// The best Speex can do, for offline processing.
+    Best,
+}
+
+impl ResampleQuality {
+    /// The Speex quality level, between 0 and 10
+    fn speex_quality(self) -> usize {
+        match self {
+            ResampleQuality::Fast => 1,
+            ResampleQuality::Medium => 4,
+            ResampleQuality::High => 7,
+            ResampleQuality::Best => 10,
+        }
+    }
+}
+
+impl Default for ResampleQuality {
+    fn default() -> Self {
+        ResampleQuality::Medium
+    }
+}
+
+/// Speex only supports whole sample rates
+fn speex_rate(sample_rate: f32) -> usize {
+    sample_rate.round() as usize
+}
+
+/// Fail with a NotSupportedError for rates outside of the spec's range,
+/// Speex can't resample from or to those
+fn check_sample_rate(sample_rate: f32) -> Result<(), AudioContextError> {
+    if (MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+        Ok(())
+    } else {
+        Err(AudioContextError::NotSupported(
+            "Sample rates must be between 3000 and 768000",
+        ))
+    }
+}
+
+/// Whether audio at `from` needs resampling to be played at `to`
+pub fn needs_resampling(from: f32, to: f32) -> bool {
+    speex_rate(from) != speex_rate(to)
+}
+
+/// Resample planar audio that arrives in parts, each channel separately
+pub struct Resampler {
+    state: State,
+    from: u64,
+    to: u64,
+    /// The number of frames consumed and produced so far, per channel
+    consumed: Vec<u64>,
+    produced: Vec<u64>,
+}
+
+impl Resampler {
+    pub fn new(
+        channels: usize,
+        from: f32,
+        to: f32,
+        quality: ResampleQuality,
+    ) -> Result<Self, AudioContextError> {
+        check_sample_rate(from)?;
+        check_sample_rate(to)?;
+        let (from, to) = (speex_rate(from), speex_rate(to));
+        let mut state = State::new(channels, from, to, quality.speex_quality())
+            .map_err(|_| AudioContextError::NotSupported("Couldn't create resampler"))?;
+        // Don't output the filter's latency as leading silence
+        state.skip_zeros();
+        Ok(Self {
+            state,
+            from: from as u64,
+            to: to as u64,
+            consumed: vec![0; channels],
+            produced: vec![0; channels],
+        })
+    }
+
+    /// Resample the next part of channel `chan`
+    ///
+    /// Because of the filter's latency, the output lags behind the input,
+    /// call `flush` after the last part to get the rest.
+    pub fn process(&mut self, chan: usize, input: &[f32]) -> Vec<f32> {
+        let mut output = vec![0.; self.output_len(input.len() as u64) as usize + 1];
+        let (mut read, mut written) = (0, 0);
+        loop {
+            let (r, w) =
+                match self
+                    .state
+                    .process_float(chan, &input[read..], &mut output[written..])
+                {
+                    Ok(progress) => progress,
+                    Err(_) => {
+                        // The rates were checked in `new`, only a channel out
+                        // of range gets here
+                        warn!("Resampling channel {} failed", chan);
+                        break;
+                    }
+                };
+            read += r;
+            written += w;
+            if read == input.len() {
+                break;
+            }
+            if written == output.len() || (r, w) == (0, 0) {
+                let len = output.len();
+                output.resize(len + len / 2 + 1, 0.);
+            }
+        }
+        output.truncate(written);
+        self.consumed[chan] += read as u64;
+        self.produced[chan] += written as u64;
+        output
+    }
+
+    /// The rest of channel `chan`, so that all in all it is as long as
+    /// its input at the new rate
+    pub fn flush(&mut self, chan: usize) -> Vec<f32> {
+        let total = self.output_len(self.consumed[chan]);
+        let remaining = total.saturating_sub(self.produced[chan]) as usize;
+        let silence = vec![0.; self.state.get_input_latency()];
+        let mut output = self.process(chan, &silence);
+        output.resize(remaining, 0.);
+        self.produced[chan] = total;
+        output
+    }
+
+    /// The number of frames `frames` input frames become
+    fn output_len(&self, frames: u64) -> u64 {
+        (frames * self.to + self.from - 1) / self.from
+    }
+}
+
+/// Resample a whole channel from the `from` to the `to` sample rate
+pub fn resample(
+    input: &[f32],
+    from: f32,
+    to: f32,
+    quality: ResampleQuality,
+) -> Result<Vec<f32>, AudioContextError> {
+    if !needs_resampling(from, to) {
+        return Ok(input.to_vec());
+    }
+    let mut resampler = Resampler::new(1, from, to, quality)?;
+    let mut output = resampler.process(0, input);
+    output.extend(resampler.flush(0));
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn keeps_length_and_pitch() {
+        let frequency = 1000.;
+        let input: Vec<f32> = (0..44100)
+            .map(|i| (2. * PI * frequency * i as f32 / 44100.).sin())
+            .collect();
+        let output = resample(&input, 44100., 48000., ResampleQuality::default()).unwrap();
+        assert_eq!(output.len(), 48000);
+
+        // away from the edges, where the filter sees the signal's ends,
+        // the rising zero crossings are a millisecond apart
+        let crossings: Vec<f32> = (1000..47000)
+            .filter(|&i| output[i - 1] < 0. && output[i] >= 0.)
+            .map(|i| i as f32 - output[i] / (output[i] - output[i - 1]))
+            .collect();
+        let periods = (crossings.len() - 1) as f32;
+        let period = (crossings[crossings.len() - 1] - crossings[0]) / periods;
+        assert!((48000. / period - frequency).abs() < 0.1);
+        let peak = output[1000..47000]
+            .iter()
+            .fold(0f32, |max, s| max.max(s.abs()));
+        assert!((peak - 1.).abs() < 0.01);
+    }
+
+    #[test]
+    fn rejects_rates_out_of_range() {
+        for &(from, to) in &[(0., 48000.), (44100., 1000.), (1e6, 48000.)] {
+            match resample(&[0.; 128], from, to, ResampleQuality::Fast) {
+                Err(AudioContextError::NotSupported(_)) => {}
+                _ => panic!("resampled from {} to {}", from, to),
+            }
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Vectorized kernels for the hot loops of blocks and nodes
+//!
+//! Each kernel evaluates the same expression as its scalar version,
+//! in the same order, so results are identical whichever is used.
+//! SSE is always available on x86_64, other architectures use the
+//! scalar kernels.
+
+/// `dst[i] += src[i]`
+pub fn add(dst: &mut [f32], src: &[f32]) {
+    assert_eq!(dst.len(), src.len());
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        sse::add(dst, src)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    scalar::add(dst, src)
+}
+
+/// `data[i] *= gain`
+pub fn scale(data: &mut [f32], gain: f32) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        sse::scale(data, gain)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    scalar::scale(data, gain)
+}
+
+/// `data[i] *= gains[i]`
+pub fn mul(data: &mut [f32], gains: &[f32]) {
+    assert_eq!(data.len(), gains.len());
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        sse::mul(data, gains)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    scalar::mul(data, gains)
+}
+
+/// `dst[i] += gain * src[i]`
+pub fn add_scaled(dst: &mut [f32], src: &[f32], gain: f32) {
+    assert_eq!(dst.len(), src.len());
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        sse::add_scaled(dst, src, gain)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    scalar::add_scaled(dst, src, gain)
+}
+
+/// `dst[i] = gain * (a[i] + b[i])`
+pub fn sum2_scaled(dst: &mut [f32], a: &[f32], b: &[f32], gain: f32) {
+    assert!(dst.len() == a.len() && dst.len() == b.len());
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        sse::sum2_scaled(dst, a, b, gain)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    scalar::sum2_scaled(dst, a, b, gain)
+}
+
+/// `dst[i] += gain * (a[i] + b[i])`
+pub fn add_sum2_scaled(dst: &mut [f32], a: &[f32], b: &[f32], gain: f32) {
+    assert!(dst.len() == a.len() && dst.len() == b.len());
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        sse::add_sum2_scaled(dst, a, b, gain)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    scalar::add_sum2_scaled(dst, a, b, gain)
+}
+
+/// `dst[i] = gain * (a[i] + b[i] + c[i] + d[i])`
+pub fn sum4_scaled(dst: &mut [f32], src: [&[f32]; 4], gain: f32) {
+    assert!(src.iter().all(|s| s.len() == dst.len()));
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        sse::sum4_scaled(dst, src, gain)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    scalar::sum4_scaled(dst, src, gain)
+}
+
+/// Interleave `channels` planar channels of `frames` frames from `src`
+/// into `dst`
+pub fn interleave(dst: &mut [f32], src: &[f32], channels: usize, frames: usize) {
+    assert!(dst.len() == channels * frames && src.len() == dst.len());
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        if channels == 2 {
+            let (l, r) = src.split_at(frames);
+            return sse::interleave2(dst, l, r);
+        }
+    }
+    scalar::interleave(dst, src, channels, frames)
+}
+
+pub(crate) mod scalar {
+    pub fn add(dst: &mut [f32], src: &[f32]) {
+        for (d, s) in dst.iter_mut().zip(src) {
+            *d += *s;
+        }
+    }
+
+    pub fn scale(data: &mut [f32], gain: f32) {
+        for d in data {
+            *d *= gain;
+        }
+    }
+
+    pub fn mul(data: &mut [f32], gains: &[f32]) {
+        for (d, g) in data.iter_mut().zip(gains) {
+            *d *= *g;
+        }
+    }
+
+    pub fn add_scaled(dst: &mut [f32], src: &[f32], gain: f32) {
+        for (d, s) in dst.iter_mut().zip(src) {
+            *d += gain * *s;
+        }
+    }
+
+    pub fn sum2_scaled(dst: &mut [f32], a: &[f32], b: &[f32], gain: f32) {
+        for ((d, a), b) in dst.iter_mut().zip(a).zip(b) {
+            *d = gain * (*a + *b);
+        }
+    }
+
+    pub fn add_sum2_scaled(dst: &mut [f32], a: &[f32], b: &[f32], gain: f32) {
+        for ((d, a), b) in dst.iter_mut().zip(a).zip(b) {
+            *d += gain * (*a + *b);
+        }
+    }
+
+    pub fn sum4_scaled(dst: &mut [f32], src: [&[f32]; 4], gain: f32) {
+        for (i, d) in dst.iter_mut().enumerate() {
+            *d = gain * (src[0][i] + src[1][i] + src[2][i] + src[3][i]);
+        }
+    }
+
+    pub fn interleave(dst: &mut [f32], src: &[f32], channels: usize, frames: usize) {
+        for chan in 0..channels {
+            let src = &src[chan * frames..(chan + 1) * frames];
+            for (frame, s) in src.iter().enumerate() {
+                dst[frame * channels + chan] = *s;
+            }
+        }
+    }
+}
+
+/// The kernels process 4 samples at a time with unaligned loads and
+/// stores, and leave the remainder to the scalar kernels
+#[cfg(target_arch = "x86_64")]
+mod sse {
+    use super::scalar;
+    use std::arch::x86_64::*;
+
+    pub unsafe fn add(dst: &mut [f32], src: &[f32]) {
+        let n = dst.len() & !3;
+        let (d, s) = (dst.as_mut_ptr(), src.as_ptr());
+        for i in (0..n).step_by(4) {
+            let sum = _mm_add_ps(_mm_loadu_ps(d.add(i)), _mm_loadu_ps(s.add(i)));
+            _mm_storeu_ps(d.add(i), sum);
+        }
+        scalar::add(&mut dst[n..], &src[n..]);
+    }
+
+    pub unsafe fn scale(data: &mut [f32], gain: f32) {
+        let n = data.len() & !3;
+        let d = data.as_mut_ptr();
+        let g = _mm_set1_ps(gain);
+        for i in (0..n).step_by(4) {
+            _mm_storeu_ps(d.add(i), _mm_mul_ps(_mm_loadu_ps(d.add(i)), g));
+        }
+        scalar::scale(&mut data[n..], gain);
+    }
+
+    pub unsafe fn mul(data: &mut [f32], gains: &[f32]) {
+        let n = data.len() & !3;
+        let (d, g) = (data.as_mut_ptr(), gains.as_ptr());
+        for i in (0..n).step_by(4) {
+            let product = _mm_mul_ps(_mm_loadu_ps(d.add(i)), _mm_loadu_ps(g.add(i)));
+            _mm_storeu_ps(d.add(i), product);
+        }
+        scalar::mul(&mut data[n..], &gains[n..]);
+    }
+
+    pub unsafe fn add_scaled(dst: &mut [f32], src: &[f32], gain: f32) {
+        let n = dst.len() & !3;
+        let (d, s) = (dst.as_mut_ptr(), src.as_ptr());
+        let g = _mm_set1_ps(gain);
+        for i in (0..n).step_by(4) {
+            let scaled = _mm_mul_ps(g, _mm_loadu_ps(s.add(i)));
+            _mm_storeu_ps(d.add(i), _mm_add_ps(_mm_loadu_ps(d.add(i)), scaled));
+        }
+        scalar::add_scaled(&mut dst[n..], &src[n..], gain);
+    }
+
+    pub unsafe fn sum2_scaled(dst: &mut [f32], a: &[f32], b: &[f32], gain: f32) {
+        let n = dst.len() & !3;
+        let (d, pa, pb) = (dst.as_mut_ptr(), a.as_ptr(), b.as_ptr());
+        let g = _mm_set1_ps(gain);
+        for i in (0..n).step_by(4) {
+            let sum = _mm_add_ps(_mm_loadu_ps(pa.add(i)), _mm_loadu_ps(pb.add(i)));
+            _mm_storeu_ps(d.add(i), _mm_mul_ps(g, sum));
+        }
+        scalar::sum2_scaled(&mut dst[n..], &a[n..], &b[n..], gain);
+    }
+
+    pub unsafe fn add_sum2_scaled(dst: &mut [f32], a: &[f32], b: &[f32], gain: f32) {
+        let n = dst.len() & !3;
+        let (d, pa, pb) = (dst.as_mut_ptr(), a.as_ptr(), b.as_ptr());
+        let g = _mm_set1_ps(gain);
+        for i in (0..n).step_by(4) {
+            let sum = _mm_add_ps(_mm_loadu_ps(pa.add(i)), _mm_loadu_ps(pb.add(i)));
+            let scaled = _mm_mul_ps(g, sum);
+            _mm_storeu_ps(d.add(i), _mm_add_ps(_mm_loadu_ps(d.add(i)), scaled));
+        }
+        scalar::add_sum2_scaled(&mut dst[n..], &a[n..], &b[n..], gain);
+    }
+
+    pub unsafe fn sum4_scaled(dst: &mut [f32], src: [&[f32]; 4], gain: f32) {
+        let n = dst.len() & !3;
+        let d = dst.as_mut_ptr();
+        let s = [
+            src[0].as_ptr(),
+            src[1].as_ptr(),
+            src[2].as_ptr(),
+            src[3].as_ptr(),
+        ];
+        let g = _mm_set1_ps(gain);
+        for i in (0..n).step_by(4) {
+            let mut sum = _mm_add_ps(_mm_loadu_ps(s[0].add(i)), _mm_loadu_ps(s[1].add(i)));
+            sum = _mm_add_ps(sum, _mm_loadu_ps(s[2].add(i)));
+            sum = _mm_add_ps(sum, _mm_loadu_ps(s[3].add(i)));
+            _mm_storeu_ps(d.add(i), _mm_mul_ps(g, sum));
+        }
+        let rest = [&src[0][n..], &src[1][n..], &src[2][n..], &src[3][n..]];
+        scalar::sum4_scaled(&mut dst[n..], rest, gain);
+    }
+
+    pub unsafe fn interleave2(dst: &mut [f32], l: &[f32], r: &[f32]) {
+        let frames = l.len();
+        let n = frames & !3;
+        let (d, pl, pr) = (dst.as_mut_ptr(), l.as_ptr(), r.as_ptr());
+        for i in (0..n).step_by(4) {
+            let (l, r) = (_mm_loadu_ps(pl.add(i)), _mm_loadu_ps(pr.add(i)));
+            _mm_storeu_ps(d.add(2 * i), _mm_unpacklo_ps(l, r));
+            _mm_storeu_ps(d.add(2 * i + 4), _mm_unpackhi_ps(l, r));
+        }
+        for i in n..frames {
+            dst[2 * i] = l[i];
+            dst[2 * i + 1] = r[i];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lengths around the vector width, and whole render quanta
+    const LENGTHS: &[usize] = &[0, 1, 3, 4, 5, 7, 8, 127, 128, 131, 256];
+
+    fn noise(len: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed.wrapping_mul(0x9E37_79B9) | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 8) as f32 / (1 << 23) as f32 - 1.
+            })
+            .collect()
+    }
+
+    fn assert_bitwise_eq(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for (i, (a, b)) in a.iter().zip(b).enumerate() {
+            assert_eq!(a.to_bits(), b.to_bits(), "sample {}: {} != {}", i, a, b);
+        }
+    }
+
+    #[test]
+    fn add_matches_scalar() {
+        for &len in LENGTHS {
+            let src = noise(len, 1);
+            let mut simd = noise(len, 2);
+            let mut reference = simd.clone();
+            add(&mut simd, &src);
+            scalar::add(&mut reference, &src);
+            assert_bitwise_eq(&simd, &reference);
+        }
+    }
+
+    #[test]
+    fn gains_match_scalar() {
+        for &len in LENGTHS {
+            let gains = noise(len, 3);
+            let mut simd = noise(len, 4);
+            let mut reference = simd.clone();
+            scale(&mut simd, 0.3);
+            scalar::scale(&mut reference, 0.3);
+            assert_bitwise_eq(&simd, &reference);
+            mul(&mut simd, &gains);
+            scalar::mul(&mut reference, &gains);
+            assert_bitwise_eq(&simd, &reference);
+            add_scaled(&mut simd, &gains, 1.7);
+            scalar::add_scaled(&mut reference, &gains, 1.7);
+            assert_bitwise_eq(&simd, &reference);
+        }
+    }
+
+    #[test]
+    fn mixing_matches_scalar() {
+        for &len in LENGTHS {
+            let (a, b, c, d) = (noise(len, 5), noise(len, 6), noise(len, 7), noise(len, 8));
+            let mut simd = vec![0.; len];
+            let mut reference = vec![0.; len];
+            sum2_scaled(&mut simd, &a, &b, 0.5);
+            scalar::sum2_scaled(&mut reference, &a, &b, 0.5);
+            assert_bitwise_eq(&simd, &reference);
+            add_sum2_scaled(&mut simd, &c, &d, 0.7);
+            scalar::add_sum2_scaled(&mut reference, &c, &d, 0.7);
+            assert_bitwise_eq(&simd, &reference);
+            sum4_scaled(&mut simd, [&a, &b, &c, &d], 0.25);
+            scalar::sum4_scaled(&mut reference, [&a, &b, &c, &d], 0.25);
+            assert_bitwise_eq(&simd, &reference);
+        }
+    }
+
+    #[test]
+    fn interleave_matches_scalar() {
+        for &len in LENGTHS {
+            for channels in 1..7 {
+                let src = noise(len * channels, 9);
+                let mut simd = vec![0.; len * channels];
+                let mut reference = vec![0.; len * channels];
+                interleave(&mut simd, &src, channels, len);
+                scalar::interleave(&mut reference, &src, channels, len);
+                assert_bitwise_eq(&simd, &reference);
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use block::Chunk;
-use render_thread::AudioRenderThreadMsg;
+use render_thread::RenderThreadWaker;
 use servo_media_streams::MediaSocket;
-use std::sync::mpsc::Sender;
+use std::any::Any;
 
 #[derive(Debug, PartialEq)]
 pub enum AudioSinkError {
@@ -11,14 +11,49 @@ pub enum AudioSinkError {
     BufferPushFailed,
     /// Could not move to a different state.
     StateChangeFailed,
+    /// There is no output device with the requested sink id, or this
+    /// kind of sink cannot be routed to a device.
+    SetSinkIdFailed,
+}
+
+/// The output of a sink for an audio output device
+///
+/// Finding the device and creating its output can block, so this is
+/// prepared on the control thread by `AudioBackend::make_sink_output`,
+/// and the render thread only swaps it in with `AudioSink::set_output`.
+pub struct AudioSinkOutput {
+    sink_id: Option<String>,
+    output: Option<Box<dyn Any + Send>>,
+}
+
+impl AudioSinkOutput {
+    /// `output` is specific to the backend, and `None` for sinks that
+    /// don't play to a device and only remember the id
+    pub fn new(sink_id: Option<String>, output: Option<Box<dyn Any + Send>>) -> Self {
+        AudioSinkOutput { sink_id, output }
+    }
+
+    pub fn sink_id(&self) -> Option<&str> {
+        self.sink_id.as_deref()
+    }
+
+    /// Take the backend specific output, if it is a `T`
+    pub fn take_output<T: 'static>(&mut self) -> Option<T> {
+        match self.output.take()?.downcast::<T>() {
+            Ok(output) => Some(*output),
+            Err(output) => {
+                self.output = Some(output);
+                None
+            }
+        }
+    }
 }
 
 pub trait AudioSink: Send {
-    fn init(
-        &self,
-        sample_rate: f32,
-        render_thread_channel: Sender<AudioRenderThreadMsg>,
-    ) -> Result<(), AudioSinkError>;
+    /// Prepare the sink for playback. `waker` must be woken up whenever
+    /// the sink needs more data, as the render thread sleeps while
+    /// `has_enough_data` is true.
+    fn init(&self, sample_rate: f32, waker: RenderThreadWaker) -> Result<(), AudioSinkError>;
     fn init_stream(
         &self,
         channels: u8,
@@ -33,4 +68,13 @@ pub trait AudioSink: Send {
         &self,
         callback: Box<dyn Fn(Box<dyn AsRef<[f32]>>) + Send + Sync + 'static>,
     );
+    /// Route the output to the device `output` was prepared for. This
+    /// can be called before `init`, and while playing, in which case the
+    /// switch happens between buffers.
+    fn set_output(&self, output: AudioSinkOutput) -> Result<(), AudioSinkError>;
+    /// The id of the output device, `None` being the default device
+    fn sink_id(&self) -> Option<String>;
+    /// The number of times the device had to play while no data
+    /// was available, since the sink was created
+    fn underrun_count(&self) -> u64;
 }
@@ -0,0 +1,237 @@
+//! A bounded single producer, single consumer queue
+//!
+//! Pushing and popping never block, lock or allocate, so both ends
+//! can be used from the render thread. Only `Producer::push_wait`
+//! blocks, for the control thread to wait for room in the queue.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+struct Ring<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// The number of values popped so far, only written by the consumer
+    head: AtomicUsize,
+    /// The number of values pushed so far, only written by the producer
+    tail: AtomicUsize,
+    /// Set when either end is dropped
+    disconnected: AtomicBool,
+    /// Set while the producer waits for room in `push_wait`
+    producer_waiting: AtomicBool,
+    /// The thread waiting in `push_wait`, which the consumer wakes up
+    /// when it pops a value
+    producer: Mutex<Option<Thread>>,
+}
+
+impl<T> Ring<T> {
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.slots[index % self.slots.len()].get()
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        let head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        let mut index = head;
+        while index != tail {
+            unsafe { ptr::drop_in_place((*self.slot(index)).as_mut_ptr()) };
+            index = index.wrapping_add(1);
+        }
+    }
+}
+
+pub(crate) struct Producer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+pub(crate) struct Consumer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+// Each end is only used from one thread at a time, and the slots are
+// handed over through `head` and `tail`.
+unsafe impl<T: Send> Send for Producer<T> {}
+unsafe impl<T: Send> Send for Consumer<T> {}
+
+/// Create a queue holding at most `capacity` values
+pub(crate) fn channel<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0, "the queue needs room for at least one value");
+    let slots = (0..capacity)
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let ring = Arc::new(Ring {
+        slots,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        disconnected: AtomicBool::new(false),
+        producer_waiting: AtomicBool::new(false),
+        producer: Mutex::new(None),
+    });
+    (Producer { ring: ring.clone() }, Consumer { ring })
+}
+
+impl<T> Producer<T> {
+    /// Queue `value`, or give it back if the queue is full
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let ring = &*self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        let head = ring.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == ring.slots.len() {
+            return Err(value);
+        }
+        unsafe { (*ring.slot(tail)).as_mut_ptr().write(value) };
+        ring.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Queue `value`, waiting for the consumer to make room if the
+    /// queue is full, or give it back if the consumer was dropped
+    ///
+    /// `wake` is called before waiting, to wake up the consumer.
+    pub fn push_wait<F: Fn()>(&self, mut value: T, wake: F) -> Result<(), T> {
+        loop {
+            value = match self.push(value) {
+                Ok(()) => return Ok(()),
+                Err(value) if self.is_disconnected() => return Err(value),
+                Err(value) => value,
+            };
+            *self.ring.producer.lock().unwrap() = Some(thread::current());
+            self.ring.producer_waiting.store(true, Ordering::SeqCst);
+            // the consumer may have made room before it could see
+            // that we are waiting
+            value = match self.push(value) {
+                Ok(()) => {
+                    self.ring.producer_waiting.store(false, Ordering::SeqCst);
+                    return Ok(());
+                }
+                Err(value) => value,
+            };
+            wake();
+            // the timeout covers the consumer missing us while we
+            // were setting up
+            thread::park_timeout(Duration::from_millis(10));
+            self.ring.producer_waiting.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Whether the consumer was dropped, values pushed since then
+    /// are never going to be read
+    pub fn is_disconnected(&self) -> bool {
+        self.ring.disconnected.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Consumer<T> {
+    pub fn pop(&self) -> Option<T> {
+        let ring = &*self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        let tail = ring.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let value = unsafe { (*ring.slot(head)).as_ptr().read() };
+        ring.head.store(head.wrapping_add(1), Ordering::SeqCst);
+        if ring.producer_waiting.load(Ordering::SeqCst) {
+            // never block here, the producer sets itself up briefly
+            if let Ok(producer) = ring.producer.try_lock() {
+                if let Some(ref producer) = *producer {
+                    producer.unpark();
+                }
+            }
+        }
+        Some(value)
+    }
+
+    /// Whether the producer was dropped, once this is true, `pop`
+    /// returns the remaining values and then `None` forever
+    pub fn is_disconnected(&self) -> bool {
+        self.ring.disconnected.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.ring.disconnected.store(true, Ordering::Release);
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.ring.disconnected.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_around() {
+        let (producer, consumer) = channel(3);
+        for i in 0..10 {
+            assert_eq!(producer.push(2 * i), Ok(()));
+            assert_eq!(producer.push(2 * i + 1), Ok(()));
+            assert_eq!(consumer.pop(), Some(2 * i));
+            assert_eq!(consumer.pop(), Some(2 * i + 1));
+        }
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn full_queue_gives_values_back() {
+        let (producer, consumer) = channel(2);
+        assert_eq!(producer.push(1), Ok(()));
+        assert_eq!(producer.push(2), Ok(()));
+        assert_eq!(producer.push(3), Err(3));
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(producer.push(3), Ok(()));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+
+        // waiting for room until the consumer pops
+        let (producer, consumer) = channel(1);
+        assert_eq!(producer.push(1), Ok(()));
+        let popper = thread::spawn(move || {
+            let mut values = Vec::new();
+            while values.len() < 2 {
+                match consumer.pop() {
+                    Some(value) => values.push(value),
+                    None => thread::yield_now(),
+                }
+            }
+            values
+        });
+        assert_eq!(producer.push_wait(2, || ()), Ok(()));
+        assert_eq!(popper.join().unwrap(), vec![1, 2]);
+        // nobody is going to make room anymore
+        assert_eq!(producer.push(3), Ok(()));
+        assert_eq!(producer.push_wait(4, || ()), Err(4));
+    }
+
+    #[test]
+    fn drops_queued_values() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        struct Counted(Arc<AtomicUsize>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let (producer, consumer) = channel(4);
+        for _ in 0..3 {
+            assert!(producer.push(Counted(dropped.clone())).is_ok());
+        }
+        drop(consumer.pop());
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+        drop(producer);
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+        drop(consumer);
+        assert_eq!(dropped.load(Ordering::SeqCst), 3);
+    }
+}
@@ -1,4 +1,4 @@
-use block::{Chunk, Tick, FRAMES_PER_BLOCK};
+use block::{frames_per_block, Chunk, Tick};
 use node::{AudioNodeEngine, BlockInfo};
 use node::{AudioNodeType, ChannelInfo};
 use param::{Param, ParamType};
@@ -22,10 +22,15 @@ pub(crate) struct StereoPannerNode {
 }
 
 impl StereoPannerNode {
-    pub fn new(options: StereoPannerOptions, channel_info: ChannelInfo) -> Self {
+    pub fn new(options: StereoPannerOptions, channel_info: ChannelInfo, sample_rate: f32) -> Self {
         Self {
             channel_info,
-            pan: Param::new(options.pan),
+            pan: Param::for_node(
+                AudioNodeType::StereoPannerNode,
+                ParamType::Pan,
+                options.pan,
+                sample_rate,
+            ),
         }
     }
 
@@ -55,9 +60,9 @@ impl AudioNodeEngine for StereoPannerNode {
                 false
             };
 
-            let (l, r) = block.data_mut().split_at_mut(FRAMES_PER_BLOCK.0 as usize);
+            let (l, r) = block.data_mut().split_at_mut(frames_per_block().0 as usize);
             let mut pan = self.pan.value();
-            for frame in 0..FRAMES_PER_BLOCK.0 {
+            for frame in 0..frames_per_block().0 {
                 let frame = Tick(frame);
                 if self.update_parameters(info, frame) {
                     pan = self.pan.value();
@@ -115,10 +120,14 @@ impl AudioNodeEngine for StereoPannerNode {
         1
     }
 
-    fn get_param(&mut self, id: ParamType) -> &mut Param {
+    fn param_types(&self) -> Vec<ParamType> {
+        vec![ParamType::Pan]
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
         match id {
-            ParamType::Pan => &mut self.pan,
-            _ => panic!("Unknown param {:?} for PannerNode", id),
+            ParamType::Pan => Some(&mut self.pan),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,84 @@
+//! A triple buffer, sharing the latest value written by one thread
+//! with another one
+//!
+//! The writer fills a back buffer and publishes it by swapping it with
+//! the middle one, the reader swaps the middle buffer with its front one
+//! when something new was published. Neither side ever blocks, locks or
+//! allocates, and the reader always sees the last published value.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Set in `middle` when the writer published a buffer the reader
+/// hasn't taken yet
+const FRESH: usize = 4;
+const INDEX: usize = 3;
+
+struct Buffers<T> {
+    slots: [UnsafeCell<T>; 3],
+    /// The index of the middle buffer, and the `FRESH` bit
+    middle: AtomicUsize,
+}
+
+pub(crate) struct Writer<T> {
+    buffers: Arc<Buffers<T>>,
+    back: usize,
+}
+
+pub(crate) struct Reader<T> {
+    buffers: Arc<Buffers<T>>,
+    front: usize,
+}
+
+// The back and front buffers are only used by their own end, and the
+// middle one is handed over through `middle`.
+unsafe impl<T: Send> Send for Writer<T> {}
+unsafe impl<T: Send> Send for Reader<T> {}
+
+/// Create a triple buffer, all three buffers starting as `initial`
+pub(crate) fn triple_buffer<T: Clone>(initial: T) -> (Writer<T>, Reader<T>) {
+    let buffers = Arc::new(Buffers {
+        slots: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        middle: AtomicUsize::new(1),
+    });
+    (
+        Writer {
+            buffers: buffers.clone(),
+            back: 0,
+        },
+        Reader { buffers, front: 2 },
+    )
+}
+
+impl<T> Writer<T> {
+    /// The buffer to fill before calling `publish`, it holds whatever
+    /// was published two times ago
+    pub fn back_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.buffers.slots[self.back].get() }
+    }
+
+    /// Hand the back buffer over to the reader
+    pub fn publish(&mut self) {
+        let previous = self
+            .buffers
+            .middle
+            .swap(self.back | FRESH, Ordering::AcqRel);
+        self.back = previous & INDEX;
+    }
+}
+
+impl<T> Reader<T> {
+    /// The last published value
+    pub fn read(&mut self) -> &T {
+        if self.buffers.middle.load(Ordering::Relaxed) & FRESH != 0 {
+            let previous = self.buffers.middle.swap(self.front, Ordering::AcqRel);
+            self.front = previous & INDEX;
+        }
+        unsafe { &*self.buffers.slots[self.front].get() }
+    }
+}
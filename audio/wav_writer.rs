@@ -0,0 +1,351 @@
+use block::Block;
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Something an offline context can stream its output to, one render
+/// quantum at a time, instead of keeping the whole rendered buffer
+/// in memory
+pub trait RenderWriter: Send {
+    /// Write the first `frames` frames of each channel of `block`
+    fn write_block(&mut self, block: &Block, frames: usize) -> io::Result<()>;
+    /// Called once after the last block
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// How samples are stored in the file
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WavSampleFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl WavSampleFormat {
+    fn bytes_per_sample(&self) -> usize {
+        match *self {
+            WavSampleFormat::Int16 => 2,
+            WavSampleFormat::Int24 => 3,
+            WavSampleFormat::Float32 => 4,
+        }
+    }
+}
+
+/// The dither applied when quantizing to an integer format,
+/// it is ignored for floating point formats
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Dither {
+    None,
+    /// Triangular probability density function noise, one LSB wide
+    Triangular,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct WavWriterOptions {
+    pub format: WavSampleFormat,
+    pub dither: Dither,
+}
+
+impl Default for WavWriterOptions {
+    fn default() -> Self {
+        WavWriterOptions {
+            format: WavSampleFormat::Int16,
+            dither: Dither::Triangular,
+        }
+    }
+}
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+/// Streams interleaved samples to a RIFF WAVE file
+///
+/// The header is written up front with placeholder sizes, which are
+/// patched in `finalize`, so `inner` needs to be seekable.
+pub struct WavWriter<W: Write + Seek + Send> {
+    inner: W,
+    channels: u8,
+    format: WavSampleFormat,
+    dither: Dither,
+    /// Where the file starts in `inner`
+    start: u64,
+    /// The number of bytes of sample data written so far
+    data_len: u64,
+    /// Offset of the size of the data chunk
+    data_len_offset: u64,
+    /// Offset of the frame count in the fact chunk, for float formats
+    fact_offset: Option<u64>,
+    /// State of the dither noise generator (xorshift)
+    rng: u32,
+    /// The current block, interleaved, reused between blocks
+    samples: Vec<f32>,
+    bytes: Vec<u8>,
+}
+
+impl<W: Write + Seek + Send> WavWriter<W> {
+    pub fn new(
+        mut inner: W,
+        channels: u8,
+        sample_rate: f32,
+        options: WavWriterOptions,
+    ) -> io::Result<Self> {
+        assert!(channels > 0, "WAV files need at least one channel");
+        let format = options.format;
+        let bytes_per_sample = format.bytes_per_sample() as u32;
+        let sample_rate = sample_rate.round() as u32;
+        let block_align = channels as u32 * bytes_per_sample;
+        let (format_tag, fmt_len) = match format {
+            WavSampleFormat::Float32 => (WAVE_FORMAT_IEEE_FLOAT, 18),
+            _ => (WAVE_FORMAT_PCM, 16),
+        };
+
+        let start = inner.seek(SeekFrom::Current(0))?;
+        inner.write_all(b"RIFF")?;
+        // patched in finish
+        inner.write_all(&0u32.to_le_bytes())?;
+        inner.write_all(b"WAVE")?;
+
+        inner.write_all(b"fmt ")?;
+        inner.write_all(&(fmt_len as u32).to_le_bytes())?;
+        inner.write_all(&format_tag.to_le_bytes())?;
+        inner.write_all(&(channels as u16).to_le_bytes())?;
+        inner.write_all(&sample_rate.to_le_bytes())?;
+        inner.write_all(&(sample_rate * block_align).to_le_bytes())?;
+        inner.write_all(&(block_align as u16).to_le_bytes())?;
+        inner.write_all(&(bytes_per_sample as u16 * 8).to_le_bytes())?;
+        if fmt_len == 18 {
+            // no extension
+            inner.write_all(&0u16.to_le_bytes())?;
+        }
+
+        // non PCM files need a fact chunk, with the number of frames
+        let fact_offset = if format_tag == WAVE_FORMAT_IEEE_FLOAT {
+            inner.write_all(b"fact")?;
+            inner.write_all(&4u32.to_le_bytes())?;
+            let offset = inner.seek(SeekFrom::Current(0))?;
+            inner.write_all(&0u32.to_le_bytes())?;
+            Some(offset)
+        } else {
+            None
+        };
+
+        inner.write_all(b"data")?;
+        let data_len_offset = inner.seek(SeekFrom::Current(0))?;
+        inner.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter {
+            inner,
+            channels,
+            format,
+            dither: options.dither,
+            start,
+            data_len: 0,
+            data_len_offset,
+            fact_offset,
+            rng: 0x9E37_79B9,
+            samples: Vec::new(),
+            bytes: Vec::new(),
+        })
+    }
+
+    /// Uniform noise in [0, 1)
+    fn next_random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 8) as f32 / (1 << 24) as f32
+    }
+
+    fn quantize(&mut self, sample: f32, max: f32) -> i32 {
+        let mut value = sample * max;
+        if self.dither == Dither::Triangular {
+            value += self.next_random() - self.next_random();
+        }
+        value.round().max(-max - 1.).min(max) as i32
+    }
+
+    /// Write interleaved samples, `samples.len()` must be
+    /// a multiple of the channel count
+    pub fn write_interleaved(&mut self, samples: &[f32]) -> io::Result<()> {
+        debug_assert!(samples.len() % self.channels as usize == 0);
+        let mut bytes = ::std::mem::replace(&mut self.bytes, Vec::new());
+        bytes.clear();
+        for &sample in samples {
+            match self.format {
+                WavSampleFormat::Int16 => {
+                    let value = self.quantize(sample, i16::max_value() as f32) as i16;
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                WavSampleFormat::Int24 => {
+                    let value = self.quantize(sample, ((1 << 23) - 1) as f32);
+                    bytes.extend_from_slice(&value.to_le_bytes()[..3]);
+                }
+                WavSampleFormat::Float32 => bytes.extend_from_slice(&sample.to_le_bytes()),
+            }
+        }
+        let result = self.inner.write_all(&bytes);
+        self.data_len += bytes.len() as u64;
+        self.bytes = bytes;
+        result
+    }
+
+    /// Patch the sizes in the header, and return the inner writer
+    ///
+    /// Files are limited to 4GB, longer renders have their sizes
+    /// saturated, which most readers handle by reading to the end.
+    pub fn finalize(mut self) -> io::Result<W> {
+        let data_len = self.data_len;
+        // chunks are padded to an even size
+        if data_len % 2 == 1 {
+            self.inner.write_all(&[0])?;
+        }
+        let end = self.inner.seek(SeekFrom::Current(0))?;
+        let saturate = |len: u64| {
+            if len > u32::max_value() as u64 {
+                u32::max_value()
+            } else {
+                len as u32
+            }
+        };
+
+        self.inner.seek(SeekFrom::Start(self.start + 4))?;
+        self.inner
+            .write_all(&saturate(end - self.start - 8).to_le_bytes())?;
+        if let Some(offset) = self.fact_offset {
+            let frames =
+                data_len / (self.channels as usize * self.format.bytes_per_sample()) as u64;
+            self.inner.seek(SeekFrom::Start(offset))?;
+            self.inner.write_all(&saturate(frames).to_le_bytes())?;
+        }
+        self.inner.seek(SeekFrom::Start(self.data_len_offset))?;
+        self.inner.write_all(&saturate(data_len).to_le_bytes())?;
+        self.inner.seek(SeekFrom::Start(end))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write + Seek + Send> RenderWriter for WavWriter<W> {
+    fn write_block(&mut self, block: &Block, frames: usize) -> io::Result<()> {
+        let channels = self.channels as usize;
+        let mut samples = ::std::mem::replace(&mut self.samples, Vec::new());
+        samples.clear();
+        samples.resize(frames * channels, 0.);
+        if !block.is_silence() {
+            for chan in 0..channels.min(block.chan_count() as usize) {
+                let data = block.data_chan(chan as u8);
+                for (frame, sample) in data[..frames].iter().enumerate() {
+                    samples[frame * channels + chan] = *sample;
+                }
+            }
+        }
+        let result = self.write_interleaved(&samples);
+        self.samples = samples;
+        result
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.finalize().map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        let mut le = [0; 4];
+        le.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(le)
+    }
+
+    fn write(channels: u8, format: WavSampleFormat, dither: Dither, samples: &[f32]) -> Vec<u8> {
+        let options = WavWriterOptions { format, dither };
+        let mut writer =
+            WavWriter::new(Cursor::new(Vec::new()), channels, 44100., options).unwrap();
+        writer.write_interleaved(samples).unwrap();
+        writer.finalize().unwrap().into_inner()
+    }
+
+    #[test]
+    fn pcm_header_and_samples() {
+        let block = Block::for_channel_data(vec![vec![0., -1.], vec![1., 0.5]]);
+        let options = WavWriterOptions {
+            format: WavSampleFormat::Int16,
+            dither: Dither::None,
+        };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 2, 44100., options).unwrap();
+        writer.write_block(&block, 2).unwrap();
+        let bytes = writer.finalize().unwrap().into_inner();
+
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&bytes, 16), 16);
+        assert_eq!(u16_at(&bytes, 20), WAVE_FORMAT_PCM);
+        assert_eq!(u16_at(&bytes, 22), 2);
+        assert_eq!(u32_at(&bytes, 24), 44100);
+        assert_eq!(u32_at(&bytes, 28), 44100 * 4);
+        assert_eq!(u16_at(&bytes, 32), 4);
+        assert_eq!(u16_at(&bytes, 34), 16);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 8);
+        // the block's channels are interleaved
+        let samples: Vec<i16> = (0..4).map(|i| u16_at(&bytes, 44 + 2 * i) as i16).collect();
+        assert_eq!(samples, [0, 32767, -32767, 16384]);
+        assert_eq!(bytes.len(), 52);
+    }
+
+    #[test]
+    fn packs_24_bit_samples() {
+        let bytes = write(1, WavSampleFormat::Int24, Dither::None, &[1., -1., 0.]);
+        assert_eq!(u16_at(&bytes, 32), 3);
+        assert_eq!(u16_at(&bytes, 34), 24);
+        assert_eq!(u32_at(&bytes, 40), 9);
+        assert_eq!(
+            &bytes[44..53],
+            &[0xff, 0xff, 0x7f, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00]
+        );
+        // the odd sized data chunk is padded, and counted in the RIFF size
+        assert_eq!(bytes.len(), 54);
+        assert_eq!(u32_at(&bytes, 4), 46);
+    }
+
+    #[test]
+    fn float_files_have_a_fact_chunk() {
+        let bytes = write(2, WavSampleFormat::Float32, Dither::Triangular, &[0.25; 6]);
+        assert_eq!(u32_at(&bytes, 16), 18);
+        assert_eq!(u16_at(&bytes, 20), WAVE_FORMAT_IEEE_FLOAT);
+        assert_eq!(u16_at(&bytes, 34), 32);
+        assert_eq!(u16_at(&bytes, 36), 0);
+        assert_eq!(&bytes[38..42], b"fact");
+        assert_eq!(u32_at(&bytes, 42), 4);
+        assert_eq!(u32_at(&bytes, 46), 3);
+        assert_eq!(&bytes[50..54], b"data");
+        assert_eq!(u32_at(&bytes, 54), 24);
+        // floats are never dithered
+        assert!((0..6).all(|i| u32_at(&bytes, 58 + 4 * i) == 0.25f32.to_bits()));
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+    }
+
+    #[test]
+    fn dither_stays_within_one_lsb() {
+        let quarter_lsb = 0.25 / 32767.;
+        let bytes = write(
+            1,
+            WavSampleFormat::Int16,
+            Dither::Triangular,
+            &[quarter_lsb; 1000],
+        );
+        let samples: Vec<i16> = (0..1000)
+            .map(|i| u16_at(&bytes, 44 + 2 * i) as i16)
+            .collect();
+        assert!(samples.iter().all(|s| (-1..=1).contains(s)));
+        assert!(samples.contains(&-1) && samples.contains(&1));
+        let mean = samples.iter().map(|&s| s as f32).sum::<f32>() / 1000.;
+        assert!((mean - 0.25).abs() < 0.1);
+    }
+}
@@ -1,6 +1,8 @@
-use block::{Chunk, FRAMES_PER_BLOCK_USIZE};
+use block::{frames_per_block_usize, Chunk};
+use log::warn;
 use node::{AudioNodeEngine, AudioNodeType, BlockInfo, ChannelInfo};
-use speexdsp_resampler::State as SpeexResamplerState;
+use render_thread::Garbage;
+use std::f64::consts::PI;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum OverSampleType {
@@ -9,21 +11,229 @@ pub enum OverSampleType {
     Quadruple,
 }
 
-#[derive(Clone, Debug, PartialEq)]
-enum TailtimeBlocks {
-    Zero,
-    One,
-    Two,
+impl OverSampleType {
+    /// The number of times the rate is doubled
+    fn stages(&self) -> usize {
+        match self {
+            OverSampleType::None => 0,
+            OverSampleType::Double => 1,
+            OverSampleType::Quadruple => 2,
+        }
+    }
 }
 
-const OVERSAMPLING_QUALITY: usize = 0;
+/// The length of the filters of each doubling stage of the oversampler
+///
+/// The first stage has to remove everything above the original
+/// Nyquist frequency, with a narrow transition band. By then the
+/// signal is band limited, so the second stage only has to keep the
+/// images and aliases out of the audible band, which a much shorter
+/// filter does. The lengths are 1 modulo 4, which makes the latency
+/// a whole number of frames in both modes.
+const STAGE_TAPS: [usize; 2] = [129, 33];
+
+/// The cutoff of the filters of each stage, in cycles per sample at
+/// the higher rate
+const STAGE_CUTOFFS: [f64; 2] = [0.225, 0.25];
+
+/// A Blackman windowed sinc lowpass filter, with a gain of 1 at DC
+pub(crate) fn lowpass_kernel(taps: usize, cutoff: f64) -> Vec<f32> {
+    let m = (taps - 1) as f64;
+    let kernel: Vec<f64> = (0..taps)
+        .map(|i| {
+            let x = i as f64 - m / 2.;
+            let sinc = if x == 0. {
+                2. * cutoff
+            } else {
+                (2. * PI * cutoff * x).sin() / (PI * x)
+            };
+            let phase = 2. * PI * i as f64 / m;
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2. * phase).cos();
+            sinc * window
+        })
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.iter().map(|k| (k / sum) as f32).collect()
+}
 
-impl OverSampleType {
-    fn value(&self) -> usize {
-        match self {
-            OverSampleType::None => 1,
-            OverSampleType::Double => 2,
-            OverSampleType::Quadruple => 4,
+/// One doubling of the sample rate, with a linear phase FIR filter
+///
+/// Upsampling inserts a zero after every sample and filters the
+/// result, downsampling filters and drops every other sample. Both are
+/// done in polyphase form, only computing the products of samples that
+/// aren't zero and the output samples that are kept.
+struct OversamplingStage {
+    kernel: Vec<f32>,
+}
+
+impl OversamplingStage {
+    fn new(taps: usize, cutoff: f64) -> Self {
+        OversamplingStage {
+            kernel: lowpass_kernel(taps, cutoff),
+        }
+    }
+
+    /// The number of input samples the upsampling filter needs to
+    /// keep between blocks
+    fn up_history(&self) -> usize {
+        self.kernel.len() / 2
+    }
+
+    /// The number of input samples the downsampling filter needs to
+    /// keep between blocks
+    fn down_history(&self) -> usize {
+        self.kernel.len() - 1
+    }
+
+    /// The delay of each of the filters, in samples at the higher rate
+    fn latency(&self) -> usize {
+        (self.kernel.len() - 1) / 2
+    }
+
+    /// Upsample `input` to `output`, which is twice as long
+    ///
+    /// `history` holds the last `up_history` samples of the previous
+    /// input, and must have room for `input` as well.
+    fn upsample(&self, history: &mut Vec<f32>, input: &[f32], output: &mut [f32]) {
+        debug_assert!(output.len() == input.len() * 2);
+        let kept = self.up_history();
+        history.extend_from_slice(input);
+        for (n, frame) in output.chunks_mut(2).enumerate() {
+            let past = history[..=kept + n].iter().rev();
+            for (phase, out) in frame.iter_mut().enumerate() {
+                let taps = self.kernel.iter().skip(phase).step_by(2);
+                // Half of the samples are zeros, make up for the gain
+                *out = 2. * taps.zip(past.clone()).map(|(k, x)| k * x).sum::<f32>();
+            }
+        }
+        let len = history.len();
+        history.drain(..len - kept);
+    }
+
+    /// Downsample `input` to `output`, which is half as long
+    ///
+    /// `history` holds the last `down_history` samples of the previous
+    /// input, and must have room for `input` as well.
+    fn downsample(&self, history: &mut Vec<f32>, input: &[f32], output: &mut [f32]) {
+        debug_assert!(output.len() * 2 == input.len());
+        let kept = self.down_history();
+        history.extend_from_slice(input);
+        for (n, out) in output.iter_mut().enumerate() {
+            let past = history[..=kept + 2 * n].iter().rev();
+            *out = self.kernel.iter().zip(past).map(|(k, x)| k * x).sum();
+        }
+        let len = history.len();
+        history.drain(..len - kept);
+    }
+}
+
+/// The filter state of a channel, for each stage
+struct OversamplerChannel {
+    up: Vec<Vec<f32>>,
+    down: Vec<Vec<f32>>,
+}
+
+/// Runs the curve at 2 or 4 times the sample rate, so that the
+/// harmonics it adds above the Nyquist frequency are filtered out
+/// instead of aliasing back into the audible band
+struct Oversampler {
+    stages: Vec<OversamplingStage>,
+    channels: Vec<OversamplerChannel>,
+    /// The signal at each rate, the original one first
+    buffers: Vec<Vec<f32>>,
+    /// The number of frames in a block, which the buffers are sized for
+    frames: usize,
+}
+
+impl Oversampler {
+    fn new(oversample: &OverSampleType, frames: usize) -> Self {
+        let stages: Vec<_> = (0..oversample.stages())
+            .map(|i| OversamplingStage::new(STAGE_TAPS[i], STAGE_CUTOFFS[i]))
+            .collect();
+        let buffers = (0..=stages.len()).map(|i| vec![0.; frames << i]).collect();
+        Oversampler {
+            stages,
+            channels: Vec::new(),
+            buffers,
+            frames,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        !self.stages.is_empty()
+    }
+
+    /// The delay of the output compared to the input, in frames at the
+    /// original rate
+    fn latency(&self) -> usize {
+        // Each stage delays the signal by the latency of its two
+        // filters, at twice the rate of the stage below
+        let doubled = self
+            .stages
+            .iter()
+            .enumerate()
+            .map(|(i, stage)| (2 * stage.latency()) << (self.stages.len() - 1 - i))
+            .sum::<usize>();
+        doubled >> self.stages.len()
+    }
+
+    /// How long the output keeps ringing once the input is silent, in
+    /// frames at the original rate
+    fn tail_time(&self) -> usize {
+        // The impulse response of the whole chain is symmetric
+        // around the latency
+        2 * self.latency()
+    }
+
+    /// Make room for the state of `count` channels, the new ones
+    /// starting from silence
+    fn set_channel_count(&mut self, count: usize) {
+        let stages = &self.stages;
+        let frames = self.frames;
+        self.channels
+            .resize_with(count, || OversamplerChannel::new(stages, frames));
+    }
+
+    /// Oversample `data`, apply `f` at the higher rate, and bring the
+    /// result back to the original rate in place
+    fn process<F: FnOnce(&mut [f32])>(&mut self, chan: usize, data: &mut [f32], f: F) {
+        debug_assert!(data.len() == self.frames);
+        let channel = &mut self.channels[chan];
+        self.buffers[0].copy_from_slice(data);
+        for (i, stage) in self.stages.iter().enumerate() {
+            let (lower, higher) = self.buffers.split_at_mut(i + 1);
+            stage.upsample(&mut channel.up[i], &lower[i], &mut higher[0]);
+        }
+        f(self
+            .buffers
+            .last_mut()
+            .expect("There is at least one buffer"));
+        for (i, stage) in self.stages.iter().enumerate().rev() {
+            let (lower, higher) = self.buffers.split_at_mut(i + 1);
+            stage.downsample(&mut channel.down[i], &higher[0], &mut lower[i]);
+        }
+        data.copy_from_slice(&self.buffers[0]);
+    }
+}
+
+impl OversamplerChannel {
+    fn new(stages: &[OversamplingStage], frames: usize) -> Self {
+        let history = |len: usize, input: usize| {
+            let mut history = Vec::with_capacity(len + input);
+            history.resize(len, 0.);
+            history
+        };
+        OversamplerChannel {
+            up: stages
+                .iter()
+                .enumerate()
+                .map(|(i, stage)| history(stage.up_history(), frames << i))
+                .collect(),
+            down: stages
+                .iter()
+                .enumerate()
+                .map(|(i, stage)| history(stage.down_history(), frames << (i + 1)))
+                .collect(),
         }
     }
 }
@@ -54,12 +264,13 @@ pub enum WaveShaperNodeMessage {
 pub(crate) struct WaveShaperNode {
     curve_set: bool,
     curve: WaveShaperCurve,
-    #[allow(dead_code)]
-    oversample: OverSampleType,
     channel_info: ChannelInfo,
-    upsampler: Option<SpeexResamplerState>,
-    downsampler: Option<SpeexResamplerState>,
-    tailtime_blocks_left: TailtimeBlocks,
+    oversampler: Oversampler,
+    /// How many more frames of output the oversampling filters may
+    /// still produce from past input
+    tail_frames_left: usize,
+    /// The curve the last message replaced or didn't use
+    garbage: Option<Garbage>,
 }
 
 impl WaveShaperNode {
@@ -74,11 +285,10 @@ impl WaveShaperNode {
         Self {
             curve_set: options.curve.is_some(),
             curve: options.curve,
-            oversample: options.oversample,
             channel_info,
-            upsampler: None,
-            downsampler: None,
-            tailtime_blocks_left: TailtimeBlocks::Zero,
+            oversampler: Oversampler::new(&options.oversample, frames_per_block_usize()),
+            tail_frames_left: 0,
+            garbage: None,
         }
     }
 
@@ -86,10 +296,13 @@ impl WaveShaperNode {
         match message {
             WaveShaperNodeMessage::SetCurve(new_curve) => {
                 if self.curve_set && new_curve.is_some() {
-                    panic!("InvalidStateError: cant set curve if it was already set");
+                    warn!("Ignoring a curve, it was already set");
+                    self.garbage = new_curve.map(Garbage::Samples);
+                    return;
                 }
                 self.curve_set = new_curve.is_some();
-                self.curve = new_curve;
+                let previous = ::std::mem::replace(&mut self.curve, new_curve);
+                self.garbage = previous.map(Garbage::Samples);
             }
         }
     }
@@ -100,7 +313,11 @@ impl AudioNodeEngine for WaveShaperNode {
         AudioNodeType::WaveShaperNode
     }
 
-    fn process(&mut self, mut inputs: Chunk, info: &BlockInfo) -> Chunk {
+    fn is_actively_processing(&self) -> bool {
+        self.tail_frames_left > 0
+    }
+
+    fn process(&mut self, mut inputs: Chunk, _info: &BlockInfo) -> Chunk {
         debug_assert!(inputs.len() == 1);
 
         if self.curve.is_none() {
@@ -110,103 +327,49 @@ impl AudioNodeEngine for WaveShaperNode {
         let curve = &self.curve.as_ref().expect("Just checked for is_none()");
 
         if inputs.blocks[0].is_silence() {
-            if WaveShaperNode::silence_produces_nonsilent_output(curve) {
+            if self.tail_frames_left > 0 {
+                // The filters are still ringing from earlier input
                 inputs.blocks[0].explicit_silence();
-                self.tailtime_blocks_left = TailtimeBlocks::Two;
-            } else if self.tailtime_blocks_left != TailtimeBlocks::Zero {
+                self.tail_frames_left = self
+                    .tail_frames_left
+                    .saturating_sub(frames_per_block_usize());
+            } else if WaveShaperNode::silence_produces_nonsilent_output(curve) {
                 inputs.blocks[0].explicit_silence();
-
-                self.tailtime_blocks_left = match self.tailtime_blocks_left {
-                    TailtimeBlocks::Zero => TailtimeBlocks::Zero,
-                    TailtimeBlocks::One => TailtimeBlocks::Zero,
-                    TailtimeBlocks::Two => TailtimeBlocks::One,
-                }
             } else {
                 return inputs;
             }
         } else {
-            self.tailtime_blocks_left = TailtimeBlocks::Two;
+            self.tail_frames_left = self.oversampler.tail_time();
         }
 
         let block = &mut inputs.blocks[0];
         let channels = block.chan_count();
 
-        if self.oversample != OverSampleType::None {
-            let rate: usize = info.sample_rate as usize;
-            let sampling_factor = self.oversample.value();
-
-            if self.upsampler.is_none() {
-                self.upsampler = Some(
-                    SpeexResamplerState::new(
-                        channels as usize,
-                        rate,
-                        rate * sampling_factor,
-                        OVERSAMPLING_QUALITY,
-                    )
-                    .expect("Couldnt create upsampler"),
-                );
-            };
-
-            if self.downsampler.is_none() {
-                self.downsampler = Some(
-                    SpeexResamplerState::new(
-                        channels as usize,
-                        rate * sampling_factor,
-                        rate,
-                        OVERSAMPLING_QUALITY,
-                    )
-                    .expect("Couldnt create downsampler"),
-                );
-            };
-
-            let mut upsampler = self.upsampler.as_mut().unwrap();
-            let mut downsampler = self.downsampler.as_mut().unwrap();
-
-            let mut oversampled_buffer: Vec<f32> =
-                vec![0.; FRAMES_PER_BLOCK_USIZE * sampling_factor];
-
+        if self.oversampler.is_enabled() {
+            self.oversampler.set_channel_count(channels as usize);
             for chan in 0..channels {
-                let out_len = WaveShaperNode::resample(
-                    &mut upsampler,
-                    chan,
-                    block.data_chan(chan),
-                    &mut oversampled_buffer,
-                );
-
-                debug_assert!(
-                    out_len == 128 * sampling_factor,
-                    "Expected {} samples in output after upsampling, got: {}",
-                    128 * sampling_factor,
-                    out_len
-                );
-
-                WaveShaperNode::apply_curve(&mut oversampled_buffer, &curve);
-
-                let out_len = WaveShaperNode::resample(
-                    &mut downsampler,
-                    chan,
-                    &oversampled_buffer,
-                    &mut block.data_chan_mut(chan),
-                );
-
-                debug_assert!(
-                    out_len == 128,
-                    "Expected 128 samples in output after downsampling, got {}",
-                    out_len
-                );
+                self.oversampler
+                    .process(chan as usize, block.data_chan_mut(chan), |buf| {
+                        WaveShaperNode::apply_curve(buf, curve)
+                    });
             }
         } else {
-            WaveShaperNode::apply_curve(block.data_mut(), &curve);
+            WaveShaperNode::apply_curve(block.data_mut(), curve);
         }
 
         inputs
     }
 
     make_message_handler!(WaveShaperNode: handle_waveshaper_message);
+
+    fn take_garbage(&mut self) -> Option<Garbage> {
+        self.garbage.take()
+    }
 }
 
 impl WaveShaperNode {
-    fn silence_produces_nonsilent_output(curve: &Vec<f32>) -> bool {
+    /// Whether the curve maps silence to something else than silence
+    fn silence_produces_nonsilent_output(curve: &[f32]) -> bool {
         let len = curve.len();
         let len_halved = ((len - 1) as f32) / 2.;
         let curve_index: f32 = len_halved;
@@ -214,10 +377,10 @@ impl WaveShaperNode {
         let index_hi = index_lo + 1;
         let interp_factor: f32 = curve_index - index_lo as f32;
         let shaped_val = (1. - interp_factor) * curve[index_lo] + interp_factor * curve[index_hi];
-        shaped_val == 0.0
+        shaped_val != 0.0
     }
 
-    fn apply_curve(buf: &mut [f32], curve: &Vec<f32>) {
+    fn apply_curve(buf: &mut [f32], curve: &[f32]) {
         let len = curve.len();
         let len_halved = ((len - 1) as f32) / 2.;
         buf.iter_mut().for_each(|sample| {
@@ -235,16 +398,82 @@ impl WaveShaperNode {
             }
         });
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fft::Fft;
+    use num_complex::Complex32;
+    use std::f32::consts::PI;
+
+    const SIZE: usize = 4096;
+    const FRAMES: usize = 128;
+
+    /// How much of the power of a hard clipped sine at `bin` ends up
+    /// outside of its harmonics
+    fn aliasing(oversample: OverSampleType, bin: usize) -> f32 {
+        let curve = vec![-1., -1., 1., 1.];
+        let mut oversampler = Oversampler::new(&oversample, FRAMES);
+        oversampler.set_channel_count(1);
+        // Let the filters settle before measuring
+        let skipped = 4 * FRAMES;
+        let mut output: Vec<f32> = (0..skipped + SIZE)
+            .map(|i| (2. * PI * bin as f32 * i as f32 / SIZE as f32).sin())
+            .collect();
+        for block in output.chunks_mut(FRAMES) {
+            if oversampler.is_enabled() {
+                oversampler.process(0, block, |buf| WaveShaperNode::apply_curve(buf, &curve));
+            } else {
+                WaveShaperNode::apply_curve(block, &curve);
+            }
+        }
+
+        let mut fft = Fft::new(SIZE);
+        let mut spectrum = vec![Complex32::new(0., 0.); SIZE / 2 + 1];
+        fft.real_forward(&output[skipped..], &mut spectrum);
+        let power: Vec<f32> = spectrum.iter().map(|c| c.norm_sqr()).collect();
+        let total: f32 = power.iter().sum();
+        let harmonics: f32 = power.iter().skip(bin).step_by(bin).sum();
+        (total - harmonics) / total
+    }
 
-    fn resample(
-        st: &mut SpeexResamplerState,
-        chan: u8,
-        input: &[f32],
-        output: &mut [f32],
-    ) -> usize {
-        let (_in_len, out_len) = st
-            .process_float(chan as usize, input, output)
-            .expect("Resampling failed");
-        out_len
+    #[test]
+    fn oversampling_suppresses_aliasing() {
+        // Fundamentals from 3 kHz up to 18 kHz at 48 kHz
+        for &bin in &[257, 523, 1031, 1543] {
+            let none = aliasing(OverSampleType::None, bin);
+            let double = aliasing(OverSampleType::Double, bin);
+            let quadruple = aliasing(OverSampleType::Quadruple, bin);
+            assert!(double < none / 4., "{}: {} vs {}", bin, double, none);
+            assert!(
+                quadruple < double / 4.,
+                "{}: {} vs {}",
+                bin,
+                quadruple,
+                double
+            );
+        }
+    }
+
+    #[test]
+    fn impulse_response_fits_latency_and_tail() {
+        for oversample in &[OverSampleType::Double, OverSampleType::Quadruple] {
+            let mut oversampler = Oversampler::new(oversample, FRAMES);
+            oversampler.set_channel_count(1);
+            let mut output = vec![0.; 2 * FRAMES];
+            output[0] = 1.;
+            for block in output.chunks_mut(FRAMES) {
+                oversampler.process(0, block, |_| ());
+            }
+
+            let peak = (0..output.len())
+                .max_by(|&a, &b| output[a].abs().partial_cmp(&output[b].abs()).unwrap())
+                .unwrap();
+            assert_eq!(peak, oversampler.latency());
+            assert!(output[peak] > 0.5);
+            let tail = oversampler.tail_time();
+            assert!(output[tail + 1..].iter().all(|&s| s.abs() < 1e-6));
+        }
     }
 }
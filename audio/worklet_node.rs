@@ -0,0 +1,301 @@
+use block::{frames_per_block_usize, Block, Chunk, Tick};
+use context::AudioContextError;
+use node::{AudioNodeEngine, AudioNodeType, BlockInfo, ChannelInfo};
+use param::{Param, ParamDescriptor, ParamRate, ParamType};
+use std::any::Any;
+use std::f32;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// A message sent over a `MessagePort`
+pub type PortMessage = Box<dyn Any + Send>;
+
+/// One end of a bidirectional channel between the control thread
+/// and an `AudioWorkletProcessor`
+///
+/// https://webaudio.github.io/web-audio-api/#dom-audioworkletnode-port
+pub struct MessagePort {
+    sender: Sender<PortMessage>,
+    receiver: Receiver<PortMessage>,
+}
+
+impl MessagePort {
+    /// Create a pair of entangled ports, one is usually kept on the
+    /// control thread and the other given to the processor through
+    /// `AudioWorkletNodeOptions::port`
+    pub fn channel() -> (MessagePort, MessagePort) {
+        let (sender1, receiver1) = mpsc::channel();
+        let (sender2, receiver2) = mpsc::channel();
+        (
+            MessagePort {
+                sender: sender1,
+                receiver: receiver2,
+            },
+            MessagePort {
+                sender: sender2,
+                receiver: receiver1,
+            },
+        )
+    }
+
+    /// Messages posted after the other end has been dropped are discarded
+    pub fn post_message(&self, message: PortMessage) {
+        let _ = self.sender.send(message);
+    }
+
+    /// Never blocks, this is what the render thread uses
+    pub fn try_recv(&self) -> Option<PortMessage> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Blocks until a message is available, or the other end is dropped
+    pub fn recv(&self) -> Option<PortMessage> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// https://webaudio.github.io/web-audio-api/#dictdef-audioparamdescriptor
+#[derive(Clone, Debug)]
+pub struct AudioParamDescriptor {
+    pub name: String,
+    pub default_value: f32,
+    pub min_value: f32,
+    pub max_value: f32,
+    pub automation_rate: ParamRate,
+}
+
+impl AudioParamDescriptor {
+    pub fn new(name: &str) -> Self {
+        AudioParamDescriptor {
+            name: name.to_owned(),
+            default_value: 0.,
+            min_value: f32::MIN,
+            max_value: f32::MAX,
+            automation_rate: ParamRate::ARate,
+        }
+    }
+}
+
+/// User code running on the render thread
+///
+/// https://webaudio.github.io/web-audio-api/#audioworkletprocessor
+pub trait AudioWorkletProcessor: Send {
+    /// Render one quantum of audio
+    ///
+    /// `inputs` has one block per input of the node, already mixed
+    /// according to the node's channel settings. Unconnected inputs
+    /// are silent blocks, which don't have any data (see `Block::is_silence`).
+    ///
+    /// `outputs` has one explicitly silent block per output, to be
+    /// written to in place.
+    ///
+    /// `params` has the values of the params, in the order of their
+    /// descriptors. There is one value per frame for a-rate params,
+    /// and a single value for k-rate ones.
+    ///
+    /// Returning false means the processor is done, it will not be
+    /// called again and the node will output silence.
+    fn process(
+        &mut self,
+        inputs: &[Block],
+        outputs: &mut [Block],
+        params: &[Vec<f32>],
+        info: &BlockInfo,
+    ) -> bool;
+
+    /// Called on the render thread, before `process`, for every message
+    /// the control thread posted since the last render quantum
+    fn handle_message(&mut self, _message: PortMessage, _port: &MessagePort) {}
+}
+
+/// https://webaudio.github.io/web-audio-api/#dictdef-audioworkletnodeoptions
+pub struct AudioWorkletNodeOptions {
+    pub processor: Box<dyn AudioWorkletProcessor>,
+    pub number_of_inputs: u32,
+    pub number_of_outputs: u32,
+    /// The number of channels of each output. When this is not set
+    /// and the node has a single input and output, the output has
+    /// as many channels as the input, otherwise outputs are mono.
+    pub output_channel_count: Option<Vec<u8>>,
+    /// The params are addressed with `ParamType::Custom(index)`,
+    /// where index is the position of the descriptor in this list
+    pub parameter_descriptors: Vec<AudioParamDescriptor>,
+    /// The processor's end of the message channel
+    pub port: Option<MessagePort>,
+}
+
+impl AudioWorkletNodeOptions {
+    pub fn new(processor: Box<dyn AudioWorkletProcessor>) -> Self {
+        AudioWorkletNodeOptions {
+            processor,
+            number_of_inputs: 1,
+            number_of_outputs: 1,
+            output_channel_count: None,
+            parameter_descriptors: Vec::new(),
+            port: None,
+        }
+    }
+
+    /// Check that the node can be created with these options
+    pub fn validate(&self) -> Result<(), AudioContextError> {
+        if self.number_of_inputs == 0 && self.number_of_outputs == 0 {
+            return Err(AudioContextError::NotSupported(
+                "An AudioWorkletNode needs an input or an output",
+            ));
+        }
+        if let Some(ref counts) = self.output_channel_count {
+            if counts.len() != self.number_of_outputs as usize {
+                return Err(AudioContextError::IndexSize(
+                    "output_channel_count must have an entry per output",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The `ParamType` of the param with the given name, for use with
+    /// `AudioNodeMessage::SetParam` and `NodeId::param`
+    pub fn param_type(&self, name: &str) -> Option<ParamType> {
+        self.parameter_descriptors
+            .iter()
+            .position(|d| d.name == name)
+            .map(|index| ParamType::Custom(index as u32))
+    }
+}
+
+#[derive(AudioNodeCommon)]
+pub(crate) struct AudioWorkletNode {
+    channel_info: ChannelInfo,
+    processor: Box<dyn AudioWorkletProcessor>,
+    number_of_inputs: u32,
+    number_of_outputs: u32,
+    output_channel_count: Option<Vec<u8>>,
+    params: Vec<Param>,
+    /// The values handed to the processor, kept around to not
+    /// allocate on every quantum
+    param_values: Vec<Vec<f32>>,
+    port: Option<MessagePort>,
+    /// Whether the last call to `process` returned true
+    active: bool,
+}
+
+impl AudioWorkletNode {
+    pub fn new(options: AudioWorkletNodeOptions, channel_info: ChannelInfo) -> Self {
+        debug_assert!(options.validate().is_ok());
+        let params = options
+            .parameter_descriptors
+            .iter()
+            .map(|d| {
+                let descriptor = ParamDescriptor {
+                    default_value: d.default_value,
+                    min_value: d.min_value,
+                    max_value: d.max_value,
+                    automation_rate: d.automation_rate,
+                    fixed_rate: false,
+                };
+                Param::with_descriptor(d.default_value, descriptor)
+            })
+            .collect();
+        let param_values = options
+            .parameter_descriptors
+            .iter()
+            .map(|_| Vec::with_capacity(frames_per_block_usize()))
+            .collect();
+        Self {
+            channel_info,
+            processor: options.processor,
+            number_of_inputs: options.number_of_inputs,
+            number_of_outputs: options.number_of_outputs,
+            output_channel_count: options.output_channel_count,
+            params,
+            param_values,
+            port: options.port,
+            active: true,
+        }
+    }
+
+    fn update_param_values(&mut self, info: &BlockInfo) {
+        for (param, values) in self.params.iter_mut().zip(self.param_values.iter_mut()) {
+            values.clear();
+            let frames = match param.get_rate() {
+                ParamRate::KRate => 1,
+                ParamRate::ARate => frames_per_block_usize(),
+            };
+            for tick in 0..frames_per_block_usize() {
+                param.update(info, Tick(tick as u64));
+                if tick < frames {
+                    values.push(param.value());
+                }
+            }
+        }
+    }
+}
+
+impl AudioNodeEngine for AudioWorkletNode {
+    fn node_type(&self) -> AudioNodeType {
+        AudioNodeType::AudioWorkletNode
+    }
+
+    fn is_actively_processing(&self) -> bool {
+        self.active
+    }
+
+    fn process(&mut self, inputs: Chunk, info: &BlockInfo) -> Chunk {
+        debug_assert!(inputs.len() == self.number_of_inputs as usize);
+
+        if let Some(ref port) = self.port {
+            while let Some(message) = port.try_recv() {
+                self.processor.handle_message(message, port);
+            }
+        }
+
+        let mut outputs = Chunk::default();
+        for output in 0..self.number_of_outputs as usize {
+            let channels = match self.output_channel_count {
+                Some(ref counts) => counts[output],
+                None if self.number_of_inputs == 1 && self.number_of_outputs == 1 => {
+                    inputs.blocks[0].chan_count()
+                }
+                None => 1,
+            };
+            outputs.blocks.push(if self.active {
+                Block::for_channels_explicit(channels)
+            } else {
+                let mut block = Block::default();
+                block.repeat(channels);
+                block
+            });
+        }
+
+        self.update_param_values(info);
+        if self.active {
+            self.active = self.processor.process(
+                &inputs.blocks,
+                &mut outputs.blocks,
+                &self.param_values,
+                info,
+            );
+        }
+        outputs
+    }
+
+    fn input_count(&self) -> u32 {
+        self.number_of_inputs
+    }
+
+    fn output_count(&self) -> u32 {
+        self.number_of_outputs
+    }
+
+    fn param_types(&self) -> Vec<ParamType> {
+        (0..self.params.len() as u32)
+            .map(ParamType::Custom)
+            .collect()
+    }
+
+    fn get_param(&mut self, id: ParamType) -> Option<&mut Param> {
+        match id {
+            ParamType::Custom(index) => self.params.get_mut(index as usize),
+            _ => None,
+        }
+    }
+}
@@ -13,8 +13,8 @@ use servo_media::{Backend, BackendInit, SupportsMediaType};
 use servo_media_audio::block::{Block, Chunk};
 use servo_media_audio::context::{AudioContext, AudioContextOptions};
 use servo_media_audio::decoder::{AudioDecoder, AudioDecoderCallbacks, AudioDecoderOptions};
-use servo_media_audio::render_thread::AudioRenderThreadMsg;
-use servo_media_audio::sink::{AudioSink, AudioSinkError};
+use servo_media_audio::render_thread::RenderThreadWaker;
+use servo_media_audio::sink::{AudioSink, AudioSinkError, AudioSinkOutput};
 use servo_media_audio::{AudioBackend, AudioStreamReader};
 use servo_media_player::context::PlayerGLContext;
 use servo_media_player::{audio, video, Player, PlayerError, PlayerEvent, StreamType};
@@ -29,8 +29,9 @@ use servo_media_webrtc::{
     WebRtcDataChannelResult, WebRtcResult, WebRtcSignaller,
 };
 use std::any::Any;
+use std::cell::RefCell;
 use std::ops::Range;
-use std::sync::mpsc::{self, Sender};
+use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 
 pub struct DummyBackend;
@@ -88,8 +89,11 @@ impl Backend for DummyBackend {
         _: Option<Arc<Mutex<dyn video::VideoFrameRenderer>>>,
         _: Option<Arc<Mutex<dyn audio::AudioRenderer>>>,
         _: Box<dyn PlayerGLContext>,
+        sink_id: Option<String>,
     ) -> Arc<Mutex<dyn Player>> {
-        Arc::new(Mutex::new(DummyPlayer))
+        Arc::new(Mutex::new(DummyPlayer {
+            sink_id: RefCell::new(sink_id),
+        }))
     }
 
     fn create_audio_context(
@@ -127,7 +131,7 @@ impl AudioBackend for DummyBackend {
     }
 
     fn make_sink() -> Result<Self::Sink, AudioSinkError> {
-        Ok(DummyAudioSink)
+        Ok(DummyAudioSink::default())
     }
     fn make_streamreader(
         _id: MediaStreamId,
@@ -137,7 +141,10 @@ impl AudioBackend for DummyBackend {
     }
 }
 
-pub struct DummyPlayer;
+/// Accepts any sink id, and only records it
+pub struct DummyPlayer {
+    sink_id: RefCell<Option<String>>,
+}
 
 pub struct DummyStreamReader;
 
@@ -200,6 +207,15 @@ impl Player for DummyPlayer {
     fn set_video_track(&self, _: i32, _: bool) -> Result<(), PlayerError> {
         Ok(())
     }
+
+    fn set_sink_id(&self, sink_id: Option<&str>) -> Result<(), PlayerError> {
+        *self.sink_id.borrow_mut() = sink_id.map(|s| s.to_owned());
+        Ok(())
+    }
+
+    fn sink_id(&self) -> Option<String> {
+        self.sink_id.borrow().clone()
+    }
 }
 
 impl WebRtcBackend for DummyBackend {
@@ -250,10 +266,14 @@ impl Drop for DummyMediaStream {
     }
 }
 
-pub struct DummyAudioSink;
+/// Accepts any sink id, and only records it
+#[derive(Default)]
+pub struct DummyAudioSink {
+    sink_id: RefCell<Option<String>>,
+}
 
 impl AudioSink for DummyAudioSink {
-    fn init(&self, _: f32, _: Sender<AudioRenderThreadMsg>) -> Result<(), AudioSinkError> {
+    fn init(&self, _: f32, _: RenderThreadWaker) -> Result<(), AudioSinkError> {
         Ok(())
     }
     fn init_stream(&self, _: u8, _: f32, _: Box<dyn MediaSocket>) -> Result<(), AudioSinkError> {
@@ -272,6 +292,16 @@ impl AudioSink for DummyAudioSink {
         Ok(())
     }
     fn set_eos_callback(&self, _: Box<dyn Fn(Box<dyn AsRef<[f32]>>) + Send + Sync + 'static>) {}
+    fn set_output(&self, output: AudioSinkOutput) -> Result<(), AudioSinkError> {
+        *self.sink_id.borrow_mut() = output.sink_id().map(|s| s.to_owned());
+        Ok(())
+    }
+    fn sink_id(&self) -> Option<String> {
+        self.sink_id.borrow().clone()
+    }
+    fn underrun_count(&self) -> u64 {
+        0
+    }
 }
 
 pub struct DummyMediaOutput;
@@ -355,3 +385,146 @@ impl MediaDeviceMonitor for DummyMediaDeviceMonitor {
         Ok(vec![])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use servo_media_audio::context::ProcessingState;
+    use servo_media_audio::context::RealTimeAudioContextOptions;
+    use servo_media_audio::context::{AudioContextError, OfflineAudioContextOptions};
+    use servo_media_audio::gain_node::GainNodeOptions;
+    use servo_media_audio::node::{AudioNodeInit, AudioNodeMessage, BlockInfo};
+    use servo_media_audio::param::{AutomationQuery, ParamType, UserAutomationEvent};
+    use servo_media_audio::wav_writer::WavWriter;
+    use servo_media_audio::worklet_node::{AudioWorkletNodeOptions, AudioWorkletProcessor};
+    use std::io::{self, Cursor};
+
+    #[test]
+    fn switches_sink_id() {
+        let options = RealTimeAudioContextOptions {
+            sink_id: Some("speakers".to_owned()),
+            ..Default::default()
+        };
+        let context = DummyBackend.create_audio_context(
+            &ClientContextId::build(1, 1),
+            AudioContextOptions::RealTimeAudioContext(options),
+        );
+        let context = context.lock().unwrap();
+        assert_eq!(context.sink_id(), Some("speakers".to_owned()));
+        assert_eq!(context.set_sink_id(Some("headphones".to_owned())), Ok(()));
+        assert_eq!(context.sink_id(), Some("headphones".to_owned()));
+        assert_eq!(context.set_sink_id(None), Ok(()));
+        assert_eq!(context.sink_id(), None);
+    }
+
+    #[test]
+    fn evaluates_param_automation() {
+        let context = DummyBackend.create_audio_context(
+            &ClientContextId::build(1, 2),
+            AudioContextOptions::RealTimeAudioContext(Default::default()),
+        );
+        let context = context.lock().unwrap();
+        let gain = context
+            .create_node(
+                AudioNodeInit::GainNode(GainNodeOptions { gain: 1. }),
+                Default::default(),
+            )
+            .unwrap();
+        let event = UserAutomationEvent::SetValueCurveAtTime(vec![0., 2.], 100., 2.);
+        assert_eq!(context.set_param(gain, ParamType::Gain, event), Ok(()));
+        let (tx, rx) = mpsc::channel();
+        let query = AutomationQuery::Times(vec![101., 103.]);
+        let msg = AudioNodeMessage::GetParamAutomation(ParamType::Gain, query, tx);
+        assert_eq!(context.message_node(gain, msg), Ok(()));
+        assert_eq!(rx.recv(), Ok(vec![1., 2.]));
+    }
+
+    struct Silence;
+
+    impl AudioWorkletProcessor for Silence {
+        fn process(&mut self, _: &[Block], _: &mut [Block], _: &[Vec<f32>], _: &BlockInfo) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_worklet_options() {
+        let context = DummyBackend.create_audio_context(
+            &ClientContextId::build(1, 3),
+            AudioContextOptions::RealTimeAudioContext(Default::default()),
+        );
+        let context = context.lock().unwrap();
+        let mut options = AudioWorkletNodeOptions::new(Box::new(Silence));
+        options.number_of_inputs = 0;
+        options.number_of_outputs = 0;
+        let node =
+            context.create_node(AudioNodeInit::AudioWorkletNode(options), Default::default());
+        match node {
+            Err(AudioContextError::NotSupported(_)) => {}
+            _ => panic!("a worklet without inputs and outputs was created"),
+        }
+        let mut options = AudioWorkletNodeOptions::new(Box::new(Silence));
+        options.number_of_outputs = 2;
+        options.output_channel_count = Some(vec![2]);
+        let node =
+            context.create_node(AudioNodeInit::AudioWorkletNode(options), Default::default());
+        match node {
+            Err(AudioContextError::IndexSize(_)) => {}
+            _ => panic!("a worklet with too few output channel counts was created"),
+        }
+    }
+
+    #[test]
+    fn offline_rendering_suspends_at_quantum_boundaries() {
+        let options = OfflineAudioContextOptions {
+            length: 44100,
+            sample_rate: 44100.,
+            ..Default::default()
+        };
+        let context = DummyBackend.create_audio_context(
+            &ClientContextId::build(1, 4),
+            AudioContextOptions::OfflineAudioContext(options),
+        );
+        let context = context.lock().unwrap();
+        let (tx, rx) = mpsc::channel();
+        // 441 frames, rounded up to 4 render quanta
+        assert_eq!(
+            context.suspend_at(0.01, move || tx.send(()).unwrap()),
+            Ok(())
+        );
+        assert_eq!(context.resume(), Ok(()));
+        assert_eq!(rx.recv(), Ok(()));
+        assert_eq!(context.state(), ProcessingState::Suspended);
+        assert_eq!(context.current_time(), 512. / 44100.);
+        assert_eq!(context.resume(), Ok(()));
+        assert_eq!(context.state(), ProcessingState::Running);
+    }
+
+    #[test]
+    fn rejects_render_writer_after_rendering_started() {
+        let options = OfflineAudioContextOptions {
+            length: 44100,
+            sample_rate: 44100.,
+            ..Default::default()
+        };
+        let context = DummyBackend.create_audio_context(
+            &ClientContextId::build(1, 5),
+            AudioContextOptions::OfflineAudioContext(options),
+        );
+        let context = context.lock().unwrap();
+        let (tx, rx) = mpsc::channel();
+        assert_eq!(
+            context.suspend_at(0.01, move || tx.send(()).unwrap()),
+            Ok(())
+        );
+        assert_eq!(context.resume(), Ok(()));
+        assert_eq!(rx.recv(), Ok(()));
+
+        let writer = WavWriter::new(Cursor::new(Vec::new()), 2, 44100., Default::default());
+        let (tx, rx) = mpsc::channel();
+        context.set_render_writer(Box::new(writer.unwrap()), move |result| {
+            tx.send(result.map_err(|e| e.kind())).unwrap()
+        });
+        assert_eq!(rx.recv(), Ok(Err(io::ErrorKind::InvalidInput)));
+    }
+}
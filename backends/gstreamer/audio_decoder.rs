@@ -5,6 +5,7 @@ use gst_app;
 use gst_audio;
 use servo_media_audio::decoder::{AudioDecoder, AudioDecoderCallbacks};
 use servo_media_audio::decoder::{AudioDecoderError, AudioDecoderOptions};
+use servo_media_audio::resampler::{self, Resampler};
 use std::io::Cursor;
 use std::io::Read;
 use std::sync::{mpsc, Arc, Mutex};
@@ -17,6 +18,13 @@ impl AsRef<[f32]> for GStreamerAudioDecoderProgress {
     }
 }
 
+/// Resamples one of the deinterleaved channels to the context's rate,
+/// the channel it is for is only known once its first sample arrives
+struct ChannelResampler {
+    resampler: Resampler,
+    channel: Option<u32>,
+}
+
 pub struct GStreamerAudioDecoder {}
 
 impl GStreamerAudioDecoder {
@@ -86,7 +94,10 @@ impl AudioDecoder for GStreamerAudioDecoder {
             //
             // Sub pipeline looks like
             //
-            // ... decodebin2 ! audioconvert ! audioresample ! capsfilter ! deinterleave ...
+            // ... decodebin2 ! audioconvert ! capsfilter ! deinterleave ...
+            //
+            // The decoded audio keeps its sample rate, each channel is
+            // resampled with Speex as it is pulled from the appsink.
             //
             // deinterleave also uses a sometime-pad, so we need to wait until
             // a pad for a planar channel is added to plug in the last part of
@@ -141,6 +152,7 @@ impl AudioDecoder for GStreamerAudioDecoder {
                 }
             };
             let channels = sample_audio_info.channels();
+            let rate = sample_audio_info.rate();
             callbacks.ready(channels);
 
             let insert_deinterleave = || -> Result<(), AudioDecoderError> {
@@ -150,9 +162,6 @@ impl AudioDecoder for GStreamerAudioDecoder {
                 convert
                     .set_property("mix-matrix", &gst::Array::new(&[]).to_value())
                     .expect("mix-matrix property didn't work");
-                let resample = gst::ElementFactory::make("audioresample", None).map_err(|_| {
-                    AudioDecoderError::Backend("audioresample creation failed".to_owned())
-                })?;
                 let filter = gst::ElementFactory::make("capsfilter", None).map_err(|_| {
                     AudioDecoderError::Backend("capsfilter creation failed".to_owned())
                 })?;
@@ -166,6 +175,8 @@ impl AudioDecoder for GStreamerAudioDecoder {
                     .expect("deinterleave doesn't have expected 'keep-positions' property");
                 let pipeline_ = pipeline.downgrade();
                 let callbacks_ = callbacks.clone();
+                let (from, to) = (rate as f32, options.sample_rate);
+                let quality = options.resample_quality;
                 deinterleave.connect_pad_added(move |_, src_pad| {
                     // A new pad for a planar channel was added in deinterleave.
                     // Plug in an appsink so we can pull the data from each channel.
@@ -193,7 +204,19 @@ impl AudioDecoder for GStreamerAudioDecoder {
                         sink.set_property("sync", &false.to_value())
                             .expect("appsink doesn't handle expected 'sync' property");
 
+                        let resampler = if resampler::needs_resampling(from, to) {
+                            Some(Arc::new(Mutex::new(ChannelResampler {
+                                resampler: Resampler::new(1, from, to, quality)
+                                    .map_err(|_| AudioDecoderError::InvalidMediaFormat)?,
+                                channel: None,
+                            })))
+                        } else {
+                            None
+                        };
+
                         let callbacks_ = callbacks.clone();
+                        let resampler_ = resampler.clone();
+                        let callbacks_eos = callbacks.clone();
                         appsink.set_callbacks(
                             gst_app::AppSinkCallbacks::new()
                                 .new_sample(move |appsink| {
@@ -230,13 +253,40 @@ impl AudioDecoder for GStreamerAudioDecoder {
                                             callbacks_.error(AudioDecoderError::BufferReadFailed);
                                             return Err(gst::FlowError::Error);
                                         };
-                                        let progress = Box::new(GStreamerAudioDecoderProgress(map));
                                         let channel = position.to_mask() as u32;
-                                        callbacks_.progress(progress, channel);
+                                        let resampler = match resampler_ {
+                                            Some(ref resampler) => resampler,
+                                            None => {
+                                                let progress =
+                                                    Box::new(GStreamerAudioDecoderProgress(map));
+                                                callbacks_.progress(progress, channel);
+                                                continue;
+                                            }
+                                        };
+                                        let data =
+                                            map.as_ref().as_slice_of::<f32>().map_err(|_| {
+                                                callbacks_.error(AudioDecoderError::InvalidSample);
+                                                gst::FlowError::Error
+                                            })?;
+                                        let mut resampler = resampler.lock().unwrap();
+                                        resampler.channel = Some(channel);
+                                        let resampled = resampler.resampler.process(0, data);
+                                        callbacks_.progress(Box::new(resampled), channel);
                                     }
 
                                     Ok(gst::FlowSuccess::Ok)
                                 })
+                                .eos(move |_| {
+                                    // Push out what the resampler still holds
+                                    // before the pipeline reports EOS
+                                    if let Some(ref resampler) = resampler {
+                                        let mut resampler = resampler.lock().unwrap();
+                                        if let Some(channel) = resampler.channel {
+                                            let rest = resampler.resampler.flush(0);
+                                            callbacks_eos.progress(Box::new(rest), channel);
+                                        }
+                                    }
+                                })
                                 .build(),
                         );
 
@@ -268,11 +318,8 @@ impl AudioDecoder for GStreamerAudioDecoder {
                     }
                 });
 
-                let mut audio_info_builder = gst_audio::AudioInfo::new(
-                    gst_audio::AUDIO_FORMAT_F32,
-                    options.sample_rate as u32,
-                    channels,
-                );
+                let mut audio_info_builder =
+                    gst_audio::AudioInfo::new(gst_audio::AUDIO_FORMAT_F32, rate, channels);
                 if let Some(positions) = sample_audio_info.positions() {
                     audio_info_builder = audio_info_builder.positions(positions);
                 }
@@ -286,7 +333,7 @@ impl AudioDecoder for GStreamerAudioDecoder {
                     .set_property("caps", &caps)
                     .expect("capsfilter doesn't have expected 'caps' property");
 
-                let elements = &[&convert, &resample, &filter, &deinterleave];
+                let elements = &[&convert, &filter, &deinterleave];
                 pipeline
                     .add_many(elements)
                     .map_err(|e| AudioDecoderError::Backend(e.to_string()))?;
@@ -0,0 +1,95 @@
+use device_monitor::find_audio_output;
+use glib;
+use gst;
+use gst::prelude::*;
+use servo_media_audio::sink::AudioSinkError;
+use servo_media_player::PlayerError;
+use std::sync::Mutex;
+
+pub enum AudioOutputError {
+    /// There is no audio output device with the requested id
+    DeviceNotFound,
+    Backend(String),
+}
+
+impl From<AudioOutputError> for AudioSinkError {
+    fn from(error: AudioOutputError) -> Self {
+        match error {
+            AudioOutputError::DeviceNotFound => AudioSinkError::SetSinkIdFailed,
+            AudioOutputError::Backend(error) => AudioSinkError::Backend(error),
+        }
+    }
+}
+
+impl From<AudioOutputError> for PlayerError {
+    fn from(error: AudioOutputError) -> Self {
+        match error {
+            AudioOutputError::DeviceNotFound => PlayerError::SetSinkIdFailed,
+            AudioOutputError::Backend(error) => PlayerError::Backend(error),
+        }
+    }
+}
+
+/// Create a sink playing to the audio output device `sink_id`,
+/// or a `default_sink` element for the default device
+pub fn make_audio_output(
+    default_sink: &str,
+    sink_id: Option<&str>,
+) -> Result<gst::Element, AudioOutputError> {
+    let sink = match sink_id {
+        Some(sink_id) => find_audio_output(sink_id)
+            .ok_or(AudioOutputError::DeviceNotFound)?
+            .create_element(None)
+            .ok_or_else(|| {
+                AudioOutputError::Backend(format!("Sink creation failed for {}", sink_id))
+            })?,
+        None => gst::ElementFactory::make(default_sink, None)
+            .map_err(|_| AudioOutputError::Backend(format!("{} creation failed", default_sink)))?,
+    };
+    Ok(sink)
+}
+
+/// Replace `old`, the sink linked downstream of `upstream` in `bin`,
+/// with `new`
+///
+/// While data is flowing, the switch happens from a blocking probe on
+/// the source pad of `upstream`, between two buffers, so that nothing
+/// is dropped or pushed to a half linked sink.
+pub fn replace_audio_output(
+    bin: &gst::Bin,
+    upstream: &gst::Element,
+    old: &gst::Element,
+    new: gst::Element,
+) -> Result<(), AudioOutputError> {
+    let swap = {
+        let bin = bin.clone();
+        let upstream = upstream.clone();
+        let old = old.clone();
+        move |new: &gst::Element| -> Result<(), glib::BoolError> {
+            upstream.unlink(&old);
+            let _ = old.set_state(gst::State::Null);
+            bin.remove(&old)?;
+            bin.add(new)?;
+            upstream.link(new)?;
+            new.sync_state_with_parent()
+        }
+    };
+
+    if bin.get_current_state() != gst::State::Playing {
+        return swap(&new).map_err(|e| AudioOutputError::Backend(e.to_string()));
+    }
+
+    let pad = upstream
+        .get_static_pad("src")
+        .ok_or_else(|| AudioOutputError::Backend("Missing src pad".to_owned()))?;
+    let new = Mutex::new(Some(new));
+    pad.add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, move |_, _| {
+        if let Some(new) = new.lock().unwrap().take() {
+            if let Err(e) = swap(&new) {
+                warn!("Could not switch audio output: {}", e);
+            }
+        }
+        gst::PadProbeReturn::Remove
+    });
+    Ok(())
+}
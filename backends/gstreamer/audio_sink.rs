@@ -1,15 +1,15 @@
 use crate::media_stream::GstreamerMediaSocket;
+use audio_output::{make_audio_output, replace_audio_output};
 use byte_slice_cast::*;
 use gst;
 use gst::prelude::*;
 use gst_app::{AppSrc, AppSrcCallbacks};
 use gst_audio;
-use servo_media_audio::block::{Chunk, FRAMES_PER_BLOCK};
-use servo_media_audio::render_thread::AudioRenderThreadMsg;
-use servo_media_audio::sink::{AudioSink, AudioSinkError};
+use servo_media_audio::block::{frames_per_block, Chunk};
+use servo_media_audio::render_thread::RenderThreadWaker;
+use servo_media_audio::sink::{AudioSink, AudioSinkError, AudioSinkOutput};
 use servo_media_streams::MediaSocket;
 use sink_type::SinkType;
-use std::sync::mpsc::Sender;
 use std::sync::Arc;
 use std::thread::Builder;
 use std::{
@@ -25,6 +25,16 @@ pub struct GStreamerAudioSink<T: SinkType> {
     sample_rate: Cell<f32>,
     audio_info: RefCell<Option<gst_audio::AudioInfo>>,
     sample_offset: Cell<u64>,
+    /// The element upstream of the sink, once initialized
+    convert: RefCell<Option<gst::Element>>,
+    /// The sink, or the one to use in `init` when the sink id
+    /// is set before it
+    sink: RefCell<Option<gst::Element>>,
+    sink_id: RefCell<Option<String>>,
+    /// The number of times buffers arrived after the time they should
+    /// have been played at, counting consecutive late buffers once
+    underruns: Cell<u64>,
+    late: Cell<bool>,
     phantom: PhantomData<T>,
 }
 
@@ -44,6 +54,11 @@ impl<T: SinkType> GStreamerAudioSink<T> {
             sample_rate: Cell::new(DEFAULT_SAMPLE_RATE),
             audio_info: RefCell::new(None),
             sample_offset: Cell::new(0),
+            convert: RefCell::new(None),
+            sink: RefCell::new(None),
+            sink_id: RefCell::new(None),
+            underruns: Cell::new(0),
+            late: Cell::new(false),
             phantom: PhantomData,
         })
     }
@@ -63,6 +78,42 @@ impl<T: SinkType> GStreamerAudioSink<T> {
         Ok(())
     }
 
+    fn make_sink(sink_id: Option<&str>) -> Result<gst::Element, AudioSinkError> {
+        let sink = make_audio_output(&T::get_sink_name(), sink_id)?;
+        sink.set_property("sync", &true.to_value())
+            .expect("sink doesn't handle expected 'sync' property");
+        Ok(sink)
+    }
+
+    /// Find the device `sink_id` refers to and create the sink element
+    /// for it, which can block, so this runs on the control thread
+    pub fn make_output(sink_id: Option<&str>) -> Result<AudioSinkOutput, AudioSinkError> {
+        let id = sink_id.map(|id| id.to_owned());
+        if !T::has_output_device() {
+            // there is no device to switch, just remember the id
+            return Ok(AudioSinkOutput::new(id, None));
+        }
+        gst::init().map_err(|_| AudioSinkError::Backend("GStreamer init failed".to_owned()))?;
+        let sink = Self::make_sink(sink_id)?;
+        Ok(AudioSinkOutput::new(id, Some(Box::new(sink))))
+    }
+
+    /// Count an underrun when the playback position already went past
+    /// the buffer about to be pushed
+    fn check_underrun(&self, pts: gst::ClockTime) {
+        if self.pipeline.get_current_state() != gst::State::Playing {
+            return;
+        }
+        let late = match self.pipeline.query_position::<gst::ClockTime>() {
+            Some(position) => position > pts,
+            None => false,
+        };
+        if late && !self.late.get() {
+            self.underruns.set(self.underruns.get() + 1);
+        }
+        self.late.set(late);
+    }
+
     fn set_channels_if_changed(&self, channels: u8) -> Result<(), AudioSinkError> {
         let curr_channels = if let Some(ch) = self.audio_info.borrow().as_ref() {
             ch.channels()
@@ -77,11 +128,7 @@ impl<T: SinkType> GStreamerAudioSink<T> {
 }
 
 impl<T: SinkType> AudioSink for GStreamerAudioSink<T> {
-    fn init(
-        &self,
-        sample_rate: f32,
-        graph_thread_channel: Sender<AudioRenderThreadMsg>,
-    ) -> Result<(), AudioSinkError> {
+    fn init(&self, sample_rate: f32, waker: RenderThreadWaker) -> Result<(), AudioSinkError> {
         self.sample_rate.set(sample_rate);
         self.set_audio_info(sample_rate, 2)?;
         self.appsrc.set_property_format(gst::Format::Time);
@@ -93,11 +140,7 @@ impl<T: SinkType> AudioSink for GStreamerAudioSink<T> {
         Builder::new()
             .name("GstAppSrcCallbacks".to_owned())
             .spawn(move || {
-                let need_data = move |_: &AppSrc, _: u32| {
-                    graph_thread_channel
-                        .send(AudioRenderThreadMsg::SinkNeedData)
-                        .unwrap();
-                };
+                let need_data = move |_: &AppSrc, _: u32| waker.wake();
                 appsrc.set_callbacks(AppSrcCallbacks::new().need_data(need_data).build());
             })
             .unwrap();
@@ -107,16 +150,18 @@ impl<T: SinkType> AudioSink for GStreamerAudioSink<T> {
             .map_err(|_| AudioSinkError::Backend("audioresample creation failed".to_owned()))?;
         let convert = gst::ElementFactory::make("audioconvert", None)
             .map_err(|_| AudioSinkError::Backend("audioconvert creation failed".to_owned()))?;
-        let sink = gst::ElementFactory::make(&T::get_sink_name(), None)
-            .map_err(|_| AudioSinkError::Backend("sink creation failed".to_owned()))?;
-        sink.set_property("sync", &true.to_value())
-            .expect("sink doesn't handle expected 'sync' property");
+        let sink = match self.sink.borrow_mut().take() {
+            Some(sink) => sink,
+            None => Self::make_sink(None)?,
+        };
 
         self.pipeline
             .add_many(&[&appsrc, &resample, &convert, &sink])
             .map_err(|e| AudioSinkError::Backend(e.to_string()))?;
         gst::Element::link_many(&[&appsrc, &resample, &convert, &sink])
             .map_err(|e| AudioSinkError::Backend(e.to_string()))?;
+        *self.convert.borrow_mut() = Some(convert);
+        *self.sink.borrow_mut() = Some(sink);
 
         Ok(())
     }
@@ -181,7 +226,7 @@ impl<T: SinkType> AudioSink for GStreamerAudioSink<T> {
         let channels = audio_info.channels();
         let bpf = audio_info.bpf() as usize;
         assert!(bpf == 4 * channels as usize);
-        let n_samples = FRAMES_PER_BLOCK.0 as u64;
+        let n_samples = frames_per_block().0 as u64;
         let buf_size = (n_samples as usize) * (bpf);
         let mut buffer = gst::Buffer::with_size(buf_size).unwrap();
         {
@@ -200,6 +245,7 @@ impl<T: SinkType> AudioSink for GStreamerAudioSink<T> {
                 .into();
             buffer.set_pts(pts);
             buffer.set_duration(next_pts - pts);
+            self.check_underrun(pts);
 
             // sometimes nothing reaches the output
             if chunk.len() == 0 {
@@ -226,6 +272,41 @@ impl<T: SinkType> AudioSink for GStreamerAudioSink<T> {
     }
 
     fn set_eos_callback(&self, _: Box<dyn Fn(Box<dyn AsRef<[f32]>>) + Send + Sync + 'static>) {}
+
+    fn set_output(&self, mut output: AudioSinkOutput) -> Result<(), AudioSinkError> {
+        let sink = match output.take_output::<gst::Element>() {
+            Some(sink) => sink,
+            None => {
+                // there is no device to switch, just remember the id
+                *self.sink_id.borrow_mut() = output.sink_id().map(|id| id.to_owned());
+                return Ok(());
+            }
+        };
+        let old = self.sink.borrow_mut().take();
+        match (old, self.convert.borrow().as_ref()) {
+            (Some(old), Some(convert)) => {
+                if let Err(e) =
+                    replace_audio_output(self.pipeline.upcast_ref(), convert, &old, sink.clone())
+                {
+                    *self.sink.borrow_mut() = Some(old);
+                    return Err(e.into());
+                }
+            }
+            // not initialized yet, `init` will pick it up
+            _ => {}
+        }
+        *self.sink.borrow_mut() = Some(sink);
+        *self.sink_id.borrow_mut() = output.sink_id().map(|id| id.to_owned());
+        Ok(())
+    }
+
+    fn sink_id(&self) -> Option<String> {
+        self.sink_id.borrow().clone()
+    }
+
+    fn underrun_count(&self) -> u64 {
+        self.underruns.get()
+    }
 }
 
 impl<T: SinkType> Drop for GStreamerAudioSink<T> {
@@ -1,5 +1,5 @@
 use media_stream::GStreamerMediaStream;
-use servo_media_audio::block::{Block, FRAMES_PER_BLOCK_USIZE};
+use servo_media_audio::block::{frames_per_block_usize, Block};
 use servo_media_audio::AudioStreamReader;
 use servo_media_streams::registry::{get_stream, MediaStreamId};
 use std::sync::mpsc::{channel, Receiver};
@@ -26,7 +26,10 @@ impl GStreamerAudioStreamReader {
         let element = g_stream.src_element();
         let pipeline = g_stream.pipeline_or_new();
         drop(stream);
-        let time_per_block = Fraction::new(FRAMES_PER_BLOCK_USIZE as i32, sample_rate as i32);
+        // The appsink callback runs on a streaming thread, which doesn't
+        // know the render quantum size of the context
+        let frames = frames_per_block_usize();
+        let time_per_block = Fraction::new(frames as i32, sample_rate as i32);
 
         // XXXManishearth this is only necessary because of an upstream
         // gstreamer bug
@@ -82,7 +85,7 @@ impl GStreamerAudioStreamReader {
                         .as_slice_of::<f32>()
                         .map_err(|_| gst::FlowError::Error)?;
 
-                    let block = Block::for_vec(floatref.into());
+                    let block = Block::for_vec_with_frames(floatref.into(), frames);
                     tx.send(block).map_err(|_| gst::FlowError::Error)?;
                     Ok(gst::FlowSuccess::Ok)
                 })
@@ -7,6 +7,22 @@ use std::cell::RefCell;
 
 use servo_media_streams::device_monitor::{MediaDeviceInfo, MediaDeviceKind, MediaDeviceMonitor};
 
+const AUDIO_SOURCE: &str = "Audio/Source";
+const AUDIO_SINK: &str = "Audio/Sink";
+const VIDEO_SOURCE: &str = "Video/Source";
+
+/// The audio output device with the given id, as enumerated
+/// by `GStreamerDeviceMonitor`
+pub fn find_audio_output(device_id: &str) -> Option<gst::Device> {
+    let device_monitor = GstDeviceMonitor::new();
+    let audio_caps = Caps::new_simple("audio/x-raw", &[]);
+    device_monitor.add_filter(Some(AUDIO_SINK), Some(&audio_caps));
+    device_monitor
+        .get_devices()
+        .into_iter()
+        .find(|device| device.get_display_name().as_str() == device_id)
+}
+
 pub struct GStreamerDeviceMonitor {
     devices: RefCell<Option<Vec<MediaDeviceInfo>>>,
 }
@@ -19,9 +35,6 @@ impl GStreamerDeviceMonitor {
     }
 
     fn get_devices(&self) -> Result<Vec<MediaDeviceInfo>, ()> {
-        const AUDIO_SOURCE: &str = "Audio/Source";
-        const AUDIO_SINK: &str = "Audio/Sink";
-        const VIDEO_SOURCE: &str = "Video/Source";
         let device_monitor = GstDeviceMonitor::new();
         let audio_caps = Caps::new_simple("audio/x-raw", &[]);
         device_monitor.add_filter(Some(AUDIO_SOURCE), Some(&audio_caps));
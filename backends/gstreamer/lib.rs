@@ -32,6 +32,7 @@ extern crate servo_media_webrtc;
 extern crate url;
 
 pub mod audio_decoder;
+mod audio_output;
 pub mod audio_sink;
 pub mod audio_stream_reader;
 mod datachannel;
@@ -55,7 +56,7 @@ use registry_scanner::GSTREAMER_REGISTRY_SCANNER;
 use servo_media::{Backend, BackendInit, SupportsMediaType};
 use servo_media_audio::context::{AudioContext, AudioContextOptions};
 use servo_media_audio::decoder::AudioDecoder;
-use servo_media_audio::sink::AudioSinkError;
+use servo_media_audio::sink::{AudioSinkError, AudioSinkOutput};
 use servo_media_audio::{AudioBackend, AudioStreamReader};
 use servo_media_player::audio::AudioRenderer;
 use servo_media_player::context::PlayerGLContext;
@@ -180,6 +181,7 @@ impl<T: SinkType> Backend for GStreamerBackend<T> {
         renderer: Option<Arc<Mutex<dyn VideoFrameRenderer>>>,
         audio_renderer: Option<Arc<Mutex<dyn AudioRenderer>>>,
         gl_context: Box<dyn PlayerGLContext>,
+        sink_id: Option<String>,
     ) -> Arc<Mutex<dyn Player>> {
         let id = self.next_instance_id.fetch_add(1, Ordering::Relaxed);
         let player = Arc::new(Mutex::new(player::GStreamerPlayer::new(
@@ -191,6 +193,7 @@ impl<T: SinkType> Backend for GStreamerBackend<T> {
             renderer,
             audio_renderer,
             gl_context,
+            sink_id,
         )));
         let mut instances = self.instances.lock().unwrap();
         let entry = instances.entry(*context_id).or_insert(Vec::new());
@@ -323,6 +326,10 @@ impl<T: SinkType> AudioBackend for GStreamerBackend<T> {
         audio_sink::GStreamerAudioSink::new()
     }
 
+    fn make_sink_output(sink_id: Option<&str>) -> Result<AudioSinkOutput, AudioSinkError> {
+        audio_sink::GStreamerAudioSink::<T>::make_output(sink_id)
+    }
+
     fn make_streamreader(id: MediaStreamId, sample_rate: f32) -> Box<dyn AudioStreamReader + Send> {
         Box::new(audio_stream_reader::GStreamerAudioStreamReader::new(id, sample_rate).unwrap())
     }
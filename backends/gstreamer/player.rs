@@ -1,5 +1,7 @@
 use super::BACKEND_BASE_TIME;
+use audio_output::{make_audio_output, replace_audio_output, AudioOutputError};
 use byte_slice_cast::AsSliceOf;
+use device_monitor::find_audio_output;
 use glib;
 use glib::prelude::*;
 use gst;
@@ -350,6 +352,61 @@ impl SeekChannel {
     }
 }
 
+/// The bin used as playbin's audio sink when there is no audio renderer,
+/// so that the device sink can be switched while playing
+struct AudioOutput {
+    bin: gst::Bin,
+    identity: gst::Element,
+    sink: gst::Element,
+}
+
+impl AudioOutput {
+    fn new(sink_id: Option<&str>) -> Result<Self, PlayerError> {
+        let bin = gst::Bin::new(Some("servoaudiooutput"));
+        let identity = gst::ElementFactory::make("identity", None)
+            .map_err(|_| PlayerError::Backend("identity creation failed".to_owned()))?;
+        let sink = match make_audio_output("autoaudiosink", sink_id) {
+            Err(AudioOutputError::DeviceNotFound) => {
+                warn!(
+                    "Could not use audio output {:?}, using the default",
+                    sink_id
+                );
+                make_audio_output("autoaudiosink", None)?
+            }
+            sink => sink?,
+        };
+        bin.add_many(&[&identity, &sink])
+            .map_err(|e| PlayerError::Backend(e.to_string()))?;
+        identity
+            .link(&sink)
+            .map_err(|e| PlayerError::Backend(e.to_string()))?;
+
+        let pad = identity
+            .get_static_pad("sink")
+            .ok_or_else(|| PlayerError::Backend("Missing identity sink pad".to_owned()))?;
+        let ghost_pad = gst::GhostPad::new(Some("sink"), &pad)
+            .map_err(|e| PlayerError::Backend(e.to_string()))?;
+        ghost_pad
+            .set_active(true)
+            .map_err(|e| PlayerError::Backend(e.to_string()))?;
+        bin.add_pad(&ghost_pad)
+            .map_err(|e| PlayerError::Backend(e.to_string()))?;
+
+        Ok(AudioOutput {
+            bin,
+            identity,
+            sink,
+        })
+    }
+
+    fn set_sink_id(&mut self, sink_id: Option<&str>) -> Result<(), PlayerError> {
+        let sink = make_audio_output("autoaudiosink", sink_id)?;
+        replace_audio_output(&self.bin, &self.identity, &self.sink, sink.clone())?;
+        self.sink = sink;
+        Ok(())
+    }
+}
+
 pub struct GStreamerPlayer {
     /// The player unique ID.
     id: usize,
@@ -368,6 +425,10 @@ pub struct GStreamerPlayer {
     stream_type: StreamType,
     /// Decorator used to setup the video sink and process the produced frames.
     render: Arc<Mutex<GStreamerRender>>,
+    /// The id of the audio output device, `None` for the default one.
+    sink_id: RefCell<Option<String>>,
+    /// The audio sink, once set up, unless there is an audio renderer.
+    audio_output: RefCell<Option<AudioOutput>>,
 }
 
 impl GStreamerPlayer {
@@ -380,6 +441,7 @@ impl GStreamerPlayer {
         video_renderer: Option<Arc<Mutex<dyn VideoFrameRenderer>>>,
         audio_renderer: Option<Arc<Mutex<dyn AudioRenderer>>>,
         gl_context: Box<dyn PlayerGLContext>,
+        sink_id: Option<String>,
     ) -> GStreamerPlayer {
         let _ = gst::DebugCategory::new(
             "servoplayer",
@@ -398,6 +460,8 @@ impl GStreamerPlayer {
             is_ready: Arc::new(Once::new()),
             stream_type,
             render: Arc::new(Mutex::new(GStreamerRender::new(gl_context))),
+            sink_id: RefCell::new(sink_id),
+            audio_output: RefCell::new(None),
         }
     }
 
@@ -508,6 +572,12 @@ impl GStreamerPlayer {
                     })
                     .build(),
             );
+        } else {
+            let audio_output = AudioOutput::new(self.sink_id.borrow().as_ref().map(|id| &**id))?;
+            pipeline
+                .set_property("audio-sink", &audio_output.bin)
+                .expect("playbin doesn't have expected 'audio-sink' property");
+            *self.audio_output.borrow_mut() = Some(audio_output);
         }
 
         let video_sink = self.render.lock().unwrap().setup_video_sink(&pipeline)?;
@@ -863,6 +933,27 @@ impl Player for GStreamerPlayer {
         let mut inner = inner.as_ref().unwrap().lock().unwrap();
         inner.set_video_track(stream_index, enabled)
     }
+
+    fn set_sink_id(&self, sink_id: Option<&str>) -> Result<(), PlayerError> {
+        // The audio renderer decides where the audio goes
+        if self.audio_renderer.is_some() {
+            return Err(PlayerError::SetSinkIdFailed);
+        }
+        if let Some(ref mut audio_output) = *self.audio_output.borrow_mut() {
+            audio_output.set_sink_id(sink_id)?;
+        } else if let Some(sink_id) = sink_id {
+            // Not set up yet, only check that the device exists
+            if find_audio_output(sink_id).is_none() {
+                return Err(PlayerError::SetSinkIdFailed);
+            }
+        }
+        *self.sink_id.borrow_mut() = sink_id.map(|id| id.to_owned());
+        Ok(())
+    }
+
+    fn sink_id(&self) -> Option<String> {
+        self.sink_id.borrow().clone()
+    }
 }
 
 impl MediaInstance for GStreamerPlayer {
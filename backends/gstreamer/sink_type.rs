@@ -1,5 +1,10 @@
 pub trait SinkType: Send + Sync + 'static {
     fn get_sink_name() -> String;
+    /// Whether the sink plays to an audio output device, which can
+    /// be chosen with a sink id
+    fn has_output_device() -> bool {
+        true
+    }
 }
 
 pub struct AutoSinkType;
@@ -14,4 +19,7 @@ impl SinkType for DummySinkType {
     fn get_sink_name() -> String {
         "fakesink".to_owned()
     }
+    fn has_output_device() -> bool {
+        false
+    }
 }
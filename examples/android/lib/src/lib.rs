@@ -31,22 +31,30 @@ impl AudioStream {
             .create_audio_context(&ClientContextId::build(1, 1), Default::default());
         {
             let context = context.lock().unwrap();
-            let osc = context.create_node(
-                AudioNodeInit::OscillatorNode(Default::default()),
-                Default::default(),
-            );
+            let osc = context
+                .create_node(
+                    AudioNodeInit::OscillatorNode(Default::default()),
+                    Default::default(),
+                )
+                .unwrap();
             let mut options = GainNodeOptions::default();
             options.gain = 0.5;
-            let gain = context.create_node(AudioNodeInit::GainNode(options), Default::default());
+            let gain = context
+                .create_node(AudioNodeInit::GainNode(options), Default::default())
+                .unwrap();
             let dest = context.dest_node();
-            context.connect_ports(osc.output(0), gain.input(0));
-            context.connect_ports(gain.output(0), dest.input(0));
-            context.message_node(
-                osc,
-                AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(
-                    0.,
-                )),
-            );
+            context.connect_ports(osc.output(0), gain.input(0)).unwrap();
+            context
+                .connect_ports(gain.output(0), dest.input(0))
+                .unwrap();
+            context
+                .message_node(
+                    osc,
+                    AudioNodeMessage::AudioScheduledSourceNode(
+                        AudioScheduledSourceNodeMessage::Start(0.),
+                    ),
+                )
+                .unwrap();
         }
         Self { context }
     }
@@ -61,22 +61,30 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     println!("Decoding audio");
     receiver.recv().unwrap();
     println!("Audio decoded");
-    let buffer_source = context.create_node(
-        AudioNodeInit::AudioBufferSourceNode(Default::default()),
-        Default::default(),
-    );
+    let buffer_source = context
+        .create_node(
+            AudioNodeInit::AudioBufferSourceNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
     let dest = context.dest_node();
-    context.connect_ports(buffer_source.output(0), dest.input(0));
-    context.message_node(
-        buffer_source,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
-    context.message_node(
-        buffer_source,
-        AudioNodeMessage::AudioBufferSourceNode(AudioBufferSourceNodeMessage::SetBuffer(Some(
-            AudioBuffer::from_buffers(decoded_audio.lock().unwrap().to_vec(), sample_rate),
-        ))),
-    );
+    context
+        .connect_ports(buffer_source.output(0), dest.input(0))
+        .unwrap();
+    context
+        .message_node(
+            buffer_source,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+    context
+        .message_node(
+            buffer_source,
+            AudioNodeMessage::AudioBufferSourceNode(AudioBufferSourceNodeMessage::SetBuffer(Some(
+                AudioBuffer::from_buffers(decoded_audio.lock().unwrap().to_vec(), sample_rate),
+            ))),
+        )
+        .unwrap();
     let _ = context.resume();
     thread::sleep(time::Duration::from_millis(5000));
     let _ = context.close();
@@ -0,0 +1,135 @@
+extern crate servo_media;
+extern crate servo_media_auto;
+
+use servo_media::audio::block::Block;
+use servo_media::audio::node::BlockInfo;
+use servo_media::audio::node::{AudioNodeInit, AudioNodeMessage, AudioScheduledSourceNodeMessage};
+use servo_media::audio::param::{ParamRate, RampKind, UserAutomationEvent};
+use servo_media::audio::worklet_node::{AudioParamDescriptor, AudioWorkletNodeOptions};
+use servo_media::audio::worklet_node::{AudioWorkletProcessor, MessagePort, PortMessage};
+use servo_media::{ClientContextId, ServoMedia};
+use std::sync::Arc;
+use std::{thread, time};
+
+/// Reduces the bit depth and the sample rate of its input
+struct BitCrusher {
+    /// Only every `hold`th frame is sampled
+    hold: usize,
+    counter: usize,
+    held: Vec<f32>,
+}
+
+impl AudioWorkletProcessor for BitCrusher {
+    fn process(
+        &mut self,
+        inputs: &[Block],
+        outputs: &mut [Block],
+        params: &[Vec<f32>],
+        _: &BlockInfo,
+    ) -> bool {
+        if inputs[0].is_silence() {
+            return true;
+        }
+        // k-rate, so there is a single value
+        let step = 2f32.powf(-params[0][0]);
+        let chans = outputs[0].chan_count();
+        self.held.resize(chans as usize, 0.);
+        for frame in 0..inputs[0].data_chan(0).len() {
+            if self.counter == 0 {
+                for chan in 0..chans {
+                    let input = inputs[0].data_chan(chan)[frame];
+                    self.held[chan as usize] = (input / step).round() * step;
+                }
+            }
+            self.counter = (self.counter + 1) % self.hold;
+            for chan in 0..chans {
+                outputs[0].data_chan_mut(chan)[frame] = self.held[chan as usize];
+            }
+        }
+        true
+    }
+
+    fn handle_message(&mut self, message: PortMessage, port: &MessagePort) {
+        if let Ok(hold) = message.downcast::<usize>() {
+            self.hold = (*hold).max(1);
+            self.counter = 0;
+            port.post_message(Box::new(format!("now holding for {} frames", self.hold)));
+        }
+    }
+}
+
+fn run_example(servo_media: Arc<ServoMedia>) {
+    let context =
+        servo_media.create_audio_context(&ClientContextId::build(1, 1), Default::default());
+    let context = context.lock().unwrap();
+    let dest = context.dest_node();
+
+    let (port, processor_port) = MessagePort::channel();
+    let mut options = AudioWorkletNodeOptions::new(Box::new(BitCrusher {
+        hold: 1,
+        counter: 0,
+        held: Vec::new(),
+    }));
+    options.parameter_descriptors.push(AudioParamDescriptor {
+        default_value: 16.,
+        min_value: 1.,
+        max_value: 16.,
+        automation_rate: ParamRate::KRate,
+        ..AudioParamDescriptor::new("bits")
+    });
+    options.port = Some(processor_port);
+    let bits = options.param_type("bits").unwrap();
+
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
+    let crusher = context
+        .create_node(AudioNodeInit::AudioWorkletNode(options), Default::default())
+        .unwrap();
+    context
+        .connect_ports(osc.output(0), crusher.input(0))
+        .unwrap();
+    context
+        .connect_ports(crusher.output(0), dest.input(0))
+        .unwrap();
+
+    // Slowly reduce the bit depth
+    context
+        .message_node(
+            crusher,
+            AudioNodeMessage::SetParam(
+                bits,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Exponential, 2., 3.),
+            ),
+        )
+        .unwrap();
+
+    let _ = context.resume();
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+
+    thread::sleep(time::Duration::from_millis(3000));
+    port.post_message(Box::new(8usize));
+    if let Some(reply) = port.recv() {
+        println!("{}", reply.downcast::<String>().unwrap());
+    }
+
+    thread::sleep(time::Duration::from_millis(2000));
+    let _ = context.close();
+}
+
+fn main() {
+    ServoMedia::init::<servo_media_auto::Backend>();
+    if let Ok(servo_media) = ServoMedia::get() {
+        run_example(servo_media);
+    } else {
+        unreachable!();
+    }
+}
@@ -18,46 +18,66 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     let dest = context.dest_node();
     let mut options = OscillatorNodeOptions::default();
     options.freq = 100.;
-    let osc1 = context.create_node(
-        AudioNodeInit::OscillatorNode(options.clone()),
-        Default::default(),
-    );
+    let osc1 = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(options.clone()),
+            Default::default(),
+        )
+        .unwrap();
     options.freq = 800.;
-    let osc2 = context.create_node(
-        AudioNodeInit::OscillatorNode(options.clone()),
-        Default::default(),
-    );
+    let osc2 = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(options.clone()),
+            Default::default(),
+        )
+        .unwrap();
     let mut options = BiquadFilterNodeOptions::default();
     options.frequency = 50.;
     options.filter = FilterType::LowPass;
-    let biquad = context.create_node(AudioNodeInit::BiquadFilterNode(options), Default::default());
-    context.connect_ports(osc1.output(0), biquad.input(0));
-    context.connect_ports(osc2.output(0), biquad.input(0));
-    context.connect_ports(biquad.output(0), dest.input(0));
+    let biquad = context
+        .create_node(AudioNodeInit::BiquadFilterNode(options), Default::default())
+        .unwrap();
+    context
+        .connect_ports(osc1.output(0), biquad.input(0))
+        .unwrap();
+    context
+        .connect_ports(osc2.output(0), biquad.input(0))
+        .unwrap();
+    context
+        .connect_ports(biquad.output(0), dest.input(0))
+        .unwrap();
     let _ = context.resume();
-    context.message_node(
-        osc1,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
-    context.message_node(
-        osc2,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
-    context.message_node(
-        biquad,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1000., 2.),
-        ),
-    );
+    context
+        .message_node(
+            osc1,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+    context
+        .message_node(
+            osc2,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+    context
+        .message_node(
+            biquad,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1000., 2.),
+            ),
+        )
+        .unwrap();
 
     thread::sleep(time::Duration::from_millis(2200));
-    context.message_node(
-        biquad,
-        AudioNodeMessage::BiquadFilterNode(BiquadFilterNodeMessage::SetFilterType(
-            FilterType::BandPass,
-        )),
-    );
+    context
+        .message_node(
+            biquad,
+            AudioNodeMessage::BiquadFilterNode(BiquadFilterNodeMessage::SetFilterType(
+                FilterType::BandPass,
+            )),
+        )
+        .unwrap();
 
     thread::sleep(time::Duration::from_millis(1000));
 }
@@ -14,49 +14,77 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         servo_media.create_audio_context(&ClientContextId::build(1, 1), Default::default());
     let context = context.lock().unwrap();
     let mut options = OscillatorNodeOptions::default();
-    let osc = context.create_node(
-        AudioNodeInit::OscillatorNode(options.clone()),
-        Default::default(),
-    );
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(options.clone()),
+            Default::default(),
+        )
+        .unwrap();
     options.freq = 213.;
-    let osc2 = context.create_node(
-        AudioNodeInit::OscillatorNode(options.clone()),
-        Default::default(),
-    );
+    let osc2 = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(options.clone()),
+            Default::default(),
+        )
+        .unwrap();
     options.freq = 100.;
-    let osc3 = context.create_node(AudioNodeInit::OscillatorNode(options), Default::default());
+    let osc3 = context
+        .create_node(AudioNodeInit::OscillatorNode(options), Default::default())
+        .unwrap();
     let mut options = GainNodeOptions::default();
     options.gain = 0.7;
-    let gain = context.create_node(AudioNodeInit::GainNode(options.clone()), Default::default());
+    let gain = context
+        .create_node(AudioNodeInit::GainNode(options.clone()), Default::default())
+        .unwrap();
 
     let options = ChannelNodeOptions { channels: 2 };
-    let merger = context.create_node(
-        AudioNodeInit::ChannelMergerNode(options),
-        Default::default(),
-    );
+    let merger = context
+        .create_node(
+            AudioNodeInit::ChannelMergerNode(options),
+            Default::default(),
+        )
+        .unwrap();
 
     let dest = context.dest_node();
-    context.connect_ports(osc.output(0), merger.input(0));
-    context.connect_ports(osc2.output(0), merger.input(1));
-    context.connect_ports(merger.output(0), gain.input(0));
-    context.connect_ports(osc3.output(0), gain.input(0));
-    context.connect_ports(gain.output(0), dest.input(0));
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
-    context.message_node(
-        osc2,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
-    context.message_node(
-        osc3,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .connect_ports(osc.output(0), merger.input(0))
+        .unwrap();
+    context
+        .connect_ports(osc2.output(0), merger.input(1))
+        .unwrap();
+    context
+        .connect_ports(merger.output(0), gain.input(0))
+        .unwrap();
+    context
+        .connect_ports(osc3.output(0), gain.input(0))
+        .unwrap();
+    context
+        .connect_ports(gain.output(0), dest.input(0))
+        .unwrap();
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+    context
+        .message_node(
+            osc2,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+    context
+        .message_node(
+            osc3,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
     let _ = context.resume();
 
     thread::sleep(time::Duration::from_millis(2000));
-    context.message_node(dest, AudioNodeMessage::SetChannelCount(1));
+    context
+        .message_node(dest, AudioNodeMessage::SetChannelCount(1))
+        .unwrap();
     thread::sleep(time::Duration::from_millis(2000));
     let _ = context.close();
 }
@@ -15,74 +15,98 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     let context = context.lock().unwrap();
     let dest = context.dest_node();
 
-    let cs = context.create_node(
-        AudioNodeInit::ConstantSourceNode(ConstantSourceNodeOptions::default()),
-        Default::default(),
-    );
+    let cs = context
+        .create_node(
+            AudioNodeInit::ConstantSourceNode(ConstantSourceNodeOptions::default()),
+            Default::default(),
+        )
+        .unwrap();
 
     let mut gain_options = GainNodeOptions::default();
     gain_options.gain = 0.1;
-    let gain = context.create_node(
-        AudioNodeInit::GainNode(gain_options.clone()),
-        Default::default(),
-    );
+    let gain = context
+        .create_node(
+            AudioNodeInit::GainNode(gain_options.clone()),
+            Default::default(),
+        )
+        .unwrap();
 
-    let osc = context.create_node(
-        AudioNodeInit::OscillatorNode(Default::default()),
-        Default::default(),
-    );
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
 
-    context.connect_ports(osc.output(0), gain.input(0));
-    context.connect_ports(cs.output(0), gain.param(ParamType::Gain));
-    context.connect_ports(gain.output(0), dest.input(0));
+    context.connect_ports(osc.output(0), gain.input(0)).unwrap();
+    context
+        .connect_ports(cs.output(0), gain.param(ParamType::Gain))
+        .unwrap();
+    context
+        .connect_ports(gain.output(0), dest.input(0))
+        .unwrap();
 
     let _ = context.resume();
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
 
-    context.message_node(
-        gain,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            gain,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
 
-    context.message_node(
-        cs,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            cs,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
 
-    context.message_node(
-        cs,
-        AudioNodeMessage::SetParam(
-            ParamType::Offset,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1., 1.5),
-        ),
-    );
+    context
+        .message_node(
+            cs,
+            AudioNodeMessage::SetParam(
+                ParamType::Offset,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1., 1.5),
+            ),
+        )
+        .unwrap();
 
-    context.message_node(
-        cs,
-        AudioNodeMessage::SetParam(
-            ParamType::Offset,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 0.1, 3.0),
-        ),
-    );
+    context
+        .message_node(
+            cs,
+            AudioNodeMessage::SetParam(
+                ParamType::Offset,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 0.1, 3.0),
+            ),
+        )
+        .unwrap();
 
-    context.message_node(
-        cs,
-        AudioNodeMessage::SetParam(
-            ParamType::Offset,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1., 4.5),
-        ),
-    );
+    context
+        .message_node(
+            cs,
+            AudioNodeMessage::SetParam(
+                ParamType::Offset,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1., 4.5),
+            ),
+        )
+        .unwrap();
 
-    context.message_node(
-        cs,
-        AudioNodeMessage::SetParam(
-            ParamType::Offset,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 0.1, 6.0),
-        ),
-    );
+    context
+        .message_node(
+            cs,
+            AudioNodeMessage::SetParam(
+                ParamType::Offset,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 0.1, 6.0),
+            ),
+        )
+        .unwrap();
 
     thread::sleep(time::Duration::from_millis(9000));
     let _ = context.close();
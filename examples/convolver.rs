@@ -0,0 +1,126 @@
+extern crate servo_media;
+extern crate servo_media_auto;
+
+use servo_media::audio::buffer_source_node::AudioBuffer;
+use servo_media::audio::convolver_node::{ConvolverNodeMessage, ConvolverNodeOptions};
+use servo_media::audio::gain_node::GainNodeOptions;
+use servo_media::audio::node::{AudioNodeInit, AudioNodeMessage, AudioScheduledSourceNodeMessage};
+use servo_media::audio::param::{ParamType, UserAutomationEvent};
+use servo_media::{ClientContextId, ServoMedia};
+use std::sync::Arc;
+use std::{thread, time};
+
+/// A synthetic stereo reverb: exponentially decaying noise,
+/// decorrelated between the two channels
+fn make_impulse_response(sample_rate: f32, duration: f32) -> AudioBuffer {
+    let len = (sample_rate * duration) as usize;
+    let mut seed: u32 = 0x1234_5678;
+    let mut noise = || {
+        // xorshift
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as f32 / u32::max_value() as f32 * 2. - 1.
+    };
+    let buffers = (0..2)
+        .map(|_| {
+            (0..len)
+                .map(|i| noise() * (-4. * i as f32 / len as f32).exp())
+                .collect()
+        })
+        .collect();
+    AudioBuffer::from_buffers(buffers, sample_rate)
+}
+
+fn run_example(servo_media: Arc<ServoMedia>) {
+    let context =
+        servo_media.create_audio_context(&ClientContextId::build(1, 1), Default::default());
+    let context = context.lock().unwrap();
+    let dest = context.dest_node();
+
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
+    let envelope = context
+        .create_node(
+            AudioNodeInit::GainNode(GainNodeOptions { gain: 0. }),
+            Default::default(),
+        )
+        .unwrap();
+    let convolver = context
+        .create_node(
+            AudioNodeInit::ConvolverNode(ConvolverNodeOptions {
+                buffer: Some(make_impulse_response(44100., 2.)),
+                normalize: true,
+            }),
+            Default::default(),
+        )
+        .unwrap();
+
+    // osc -> envelope -> convolver -> dest
+    context
+        .connect_ports(osc.output(0), envelope.input(0))
+        .unwrap();
+    context
+        .connect_ports(envelope.output(0), convolver.input(0))
+        .unwrap();
+    context
+        .connect_ports(convolver.output(0), dest.input(0))
+        .unwrap();
+
+    for i in 0..3 {
+        let start = 2. * i as f64;
+        context
+            .message_node(
+                envelope,
+                AudioNodeMessage::SetParam(
+                    ParamType::Gain,
+                    UserAutomationEvent::SetValueAtTime(1., start),
+                ),
+            )
+            .unwrap();
+        context
+            .message_node(
+                envelope,
+                AudioNodeMessage::SetParam(
+                    ParamType::Gain,
+                    UserAutomationEvent::SetValueAtTime(0., start + 0.1),
+                ),
+            )
+            .unwrap();
+    }
+
+    let _ = context.resume();
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+
+    thread::sleep(time::Duration::from_millis(4000));
+    println!("shorter reverb");
+    context
+        .message_node(
+            convolver,
+            AudioNodeMessage::ConvolverNode(ConvolverNodeMessage::SetBuffer(Some(
+                make_impulse_response(44100., 0.5),
+            ))),
+        )
+        .unwrap();
+
+    thread::sleep(time::Duration::from_millis(3000));
+    let _ = context.close();
+}
+
+fn main() {
+    ServoMedia::init::<servo_media_auto::Backend>();
+    if let Ok(servo_media) = ServoMedia::get() {
+        run_example(servo_media);
+    } else {
+        unreachable!();
+    }
+}
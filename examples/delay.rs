@@ -0,0 +1,119 @@
+extern crate servo_media;
+extern crate servo_media_auto;
+
+use servo_media::audio::delay_node::DelayNodeOptions;
+use servo_media::audio::gain_node::GainNodeOptions;
+use servo_media::audio::node::{AudioNodeInit, AudioNodeMessage, AudioScheduledSourceNodeMessage};
+use servo_media::audio::param::{ParamType, UserAutomationEvent};
+use servo_media::{ClientContextId, ServoMedia};
+use std::sync::Arc;
+use std::{thread, time};
+
+fn run_example(servo_media: Arc<ServoMedia>) {
+    let context =
+        servo_media.create_audio_context(&ClientContextId::build(1, 1), Default::default());
+    let context = context.lock().unwrap();
+    let dest = context.dest_node();
+
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
+    // Short blips, which make the echoes easy to hear
+    let envelope = context
+        .create_node(
+            AudioNodeInit::GainNode(GainNodeOptions { gain: 0. }),
+            Default::default(),
+        )
+        .unwrap();
+    let delay = context
+        .create_node(
+            AudioNodeInit::DelayNode(DelayNodeOptions {
+                max_delay_time: 1.,
+                delay_time: 0.3,
+            }),
+            Default::default(),
+        )
+        .unwrap();
+    let feedback = context
+        .create_node(
+            AudioNodeInit::GainNode(GainNodeOptions { gain: 0.6 }),
+            Default::default(),
+        )
+        .unwrap();
+
+    // osc -> envelope -> dest
+    //                 -> delay -> dest
+    //                    delay -> feedback -> delay
+    context
+        .connect_ports(osc.output(0), envelope.input(0))
+        .unwrap();
+    context
+        .connect_ports(envelope.output(0), dest.input(0))
+        .unwrap();
+    context
+        .connect_ports(envelope.output(0), delay.input(0))
+        .unwrap();
+    context
+        .connect_ports(delay.output(0), dest.input(0))
+        .unwrap();
+    context
+        .connect_ports(delay.output(0), feedback.input(0))
+        .unwrap();
+    context
+        .connect_ports(feedback.output(0), delay.input(0))
+        .unwrap();
+
+    for i in 0..3 {
+        let start = 2. * i as f64;
+        context
+            .message_node(
+                envelope,
+                AudioNodeMessage::SetParam(
+                    ParamType::Gain,
+                    UserAutomationEvent::SetValueAtTime(1., start),
+                ),
+            )
+            .unwrap();
+        context
+            .message_node(
+                envelope,
+                AudioNodeMessage::SetParam(
+                    ParamType::Gain,
+                    UserAutomationEvent::SetValueAtTime(0., start + 0.1),
+                ),
+            )
+            .unwrap();
+    }
+
+    let _ = context.resume();
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+
+    thread::sleep(time::Duration::from_millis(3000));
+    println!("shorter delay");
+    context
+        .message_node(
+            delay,
+            AudioNodeMessage::SetParam(ParamType::DelayTime, UserAutomationEvent::SetValue(0.1)),
+        )
+        .unwrap();
+
+    thread::sleep(time::Duration::from_millis(4000));
+    let _ = context.close();
+}
+
+fn main() {
+    ServoMedia::init::<servo_media_auto::Backend>();
+    if let Ok(servo_media) = ServoMedia::get() {
+        run_example(servo_media);
+    } else {
+        unreachable!();
+    }
+}
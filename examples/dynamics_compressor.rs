@@ -0,0 +1,95 @@
+extern crate servo_media;
+extern crate servo_media_auto;
+
+use servo_media::audio::dynamics_compressor_node::{
+    DynamicsCompressorNodeMessage, DynamicsCompressorNodeOptions,
+};
+use servo_media::audio::gain_node::GainNodeOptions;
+use servo_media::audio::node::{AudioNodeInit, AudioNodeMessage, AudioScheduledSourceNodeMessage};
+use servo_media::audio::param::{ParamType, RampKind, UserAutomationEvent};
+use servo_media::{ClientContextId, ServoMedia};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::{thread, time};
+
+fn run_example(servo_media: Arc<ServoMedia>) {
+    let context =
+        servo_media.create_audio_context(&ClientContextId::build(1, 1), Default::default());
+    let context = context.lock().unwrap();
+    let dest = context.dest_node();
+
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
+    let gain = context
+        .create_node(
+            AudioNodeInit::GainNode(GainNodeOptions { gain: 0.05 }),
+            Default::default(),
+        )
+        .unwrap();
+    let compressor = context
+        .create_node(
+            AudioNodeInit::DynamicsCompressionNode(DynamicsCompressorNodeOptions {
+                threshold: -30.,
+                ratio: 8.,
+                ..Default::default()
+            }),
+            Default::default(),
+        )
+        .unwrap();
+
+    // osc -> gain -> compressor -> dest
+    context.connect_ports(osc.output(0), gain.input(0)).unwrap();
+    context
+        .connect_ports(gain.output(0), compressor.input(0))
+        .unwrap();
+    context
+        .connect_ports(compressor.output(0), dest.input(0))
+        .unwrap();
+
+    // Swell the input so that the compressor kicks in
+    context
+        .message_node(
+            gain,
+            AudioNodeMessage::SetParam(
+                ParamType::Gain,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1., 3.),
+            ),
+        )
+        .unwrap();
+
+    let _ = context.resume();
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+
+    for _ in 0..20 {
+        thread::sleep(time::Duration::from_millis(200));
+        let (tx, rx) = mpsc::channel();
+        context
+            .message_node(
+                compressor,
+                AudioNodeMessage::DynamicsCompressionNode(
+                    DynamicsCompressorNodeMessage::GetReduction(tx),
+                ),
+            )
+            .unwrap();
+        println!("reduction: {:.2} dB", rx.recv().unwrap());
+    }
+    let _ = context.close();
+}
+
+fn main() {
+    ServoMedia::init::<servo_media_auto::Backend>();
+    if let Ok(servo_media) = ServoMedia::get() {
+        run_example(servo_media);
+    } else {
+        unreachable!();
+    }
+}
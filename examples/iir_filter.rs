@@ -17,42 +17,50 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         let context = context.lock().unwrap();
 
         let dest = context.dest_node();
-        let osc = context.create_node(
-            AudioNodeInit::OscillatorNode(OscillatorNodeOptions::default()),
-            Default::default(),
-        );
+        let osc = context
+            .create_node(
+                AudioNodeInit::OscillatorNode(OscillatorNodeOptions::default()),
+                Default::default(),
+            )
+            .unwrap();
 
         let feedback = Arc::new(vec![7.0, 1.0, 1.0]);
         let feedforward = Arc::new(vec![1.0, 1.0, 1.0]);
 
-        let iir = context.create_node(
-            AudioNodeInit::IIRFilterNode(IIRFilterNodeOptions {
-                feedback: feedback.clone(),
-                feedforward: feedforward.clone(),
-            }),
-            Default::default(),
-        );
+        let iir = context
+            .create_node(
+                AudioNodeInit::IIRFilterNode(IIRFilterNodeOptions {
+                    feedback: feedback.clone(),
+                    feedforward: feedforward.clone(),
+                }),
+                Default::default(),
+            )
+            .unwrap();
 
-        context.connect_ports(osc.output(0), dest.input(0));
+        context.connect_ports(osc.output(0), dest.input(0)).unwrap();
         let _ = context.resume();
-        context.message_node(
-            osc,
-            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-        );
+        context
+            .message_node(
+                osc,
+                AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(
+                    0.,
+                )),
+            )
+            .unwrap();
 
         println!("raw oscillator");
         thread::sleep(time::Duration::from_millis(2000));
 
         println!("oscillator through iir filter");
         context.disconnect_output(osc.output(0));
-        context.connect_ports(osc.output(0), iir.input(0));
-        context.connect_ports(iir.output(0), dest.input(0));
+        context.connect_ports(osc.output(0), iir.input(0)).unwrap();
+        context.connect_ports(iir.output(0), dest.input(0)).unwrap();
         thread::sleep(time::Duration::from_millis(2000));
 
         println!("raw oscillator");
         context.disconnect_output(osc.output(0));
         context.disconnect_output(iir.output(0));
-        context.connect_ports(osc.output(0), dest.input(0));
+        context.connect_ports(osc.output(0), dest.input(0)).unwrap();
         thread::sleep(time::Duration::from_millis(2000));
 
         let freqs = vec![0.0, 0.3, 0.5];
@@ -39,16 +39,19 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     let context = context.lock().unwrap();
     let listener = context.listener();
 
-    let source_node =
-        context.create_node(AudioNodeInit::MediaElementSourceNode, Default::default());
+    let source_node = context
+        .create_node(AudioNodeInit::MediaElementSourceNode, Default::default())
+        .unwrap();
 
     let (sender, receiver) = mpsc::channel();
-    context.message_node(
-        source_node,
-        AudioNodeMessage::MediaElementSourceNode(MediaElementSourceNodeMessage::GetAudioRenderer(
-            sender,
-        )),
-    );
+    context
+        .message_node(
+            source_node,
+            AudioNodeMessage::MediaElementSourceNode(
+                MediaElementSourceNodeMessage::GetAudioRenderer(sender),
+            ),
+        )
+        .unwrap();
     let audio_renderer = receiver.recv().unwrap();
 
     let mut options = PannerNodeOptions::default();
@@ -58,12 +61,18 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     options.position_z = 100.;
     options.ref_distance = 100.;
     options.rolloff_factor = 0.01;
-    let panner = context.create_node(AudioNodeInit::PannerNode(options), Default::default());
+    let panner = context
+        .create_node(AudioNodeInit::PannerNode(options), Default::default())
+        .unwrap();
 
     let dest = context.dest_node();
 
-    context.connect_ports(source_node.output(0), panner.input(0));
-    context.connect_ports(panner.output(0), dest.input(0));
+    context
+        .connect_ports(source_node.output(0), panner.input(0))
+        .unwrap();
+    context
+        .connect_ports(panner.output(0), dest.input(0))
+        .unwrap();
 
     let (sender, receiver) = ipc::channel().unwrap();
     let player = servo_media.create_player(
@@ -73,6 +82,7 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         None,
         Some(audio_renderer),
         Box::new(PlayerContextDummy()),
+        None,
     );
 
     let filename = "./examples/resources/viper_cut.ogg";
@@ -141,142 +151,180 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     let _ = context.resume();
 
     // trace a square around your head twice
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.2),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.2),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.4),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.4),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.6),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.6),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.8),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.8),
-        ),
-    );
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.2),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.2),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.4),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.4),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.6),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.6),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.8),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.8),
+            ),
+        )
+        .unwrap();
 
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.0),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.0),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.2),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.2),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.4),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.4),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.6),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.6),
-        ),
-    );
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.0),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.0),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.2),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.2),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.4),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.4),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.6),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.6),
+            ),
+        )
+        .unwrap();
     // now it runs away
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 10000., 3.),
-        ),
-    );
-    context.message_node(
-        listener,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::SetValueAtTime(0., 3.),
-        ),
-    );
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 10000., 3.),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            listener,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::SetValueAtTime(0., 3.),
+            ),
+        )
+        .unwrap();
     // chase it
-    context.message_node(
-        listener,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 10000., 4.),
-        ),
-    );
+    context
+        .message_node(
+            listener,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 10000., 4.),
+            ),
+        )
+        .unwrap();
 
     while let Ok(event) = receiver.recv() {
         match event {
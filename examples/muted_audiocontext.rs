@@ -16,16 +16,24 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         let context = context1.lock().unwrap();
         let dest = context.dest_node();
         let options = OscillatorNodeOptions::default();
-        let osc1 = context.create_node(
-            AudioNodeInit::OscillatorNode(options.clone()),
-            Default::default(),
-        );
-        context.connect_ports(osc1.output(0), dest.input(0));
+        let osc1 = context
+            .create_node(
+                AudioNodeInit::OscillatorNode(options.clone()),
+                Default::default(),
+            )
+            .unwrap();
+        context
+            .connect_ports(osc1.output(0), dest.input(0))
+            .unwrap();
         let _ = context.resume();
-        context.message_node(
-            osc1,
-            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-        );
+        context
+            .message_node(
+                osc1,
+                AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(
+                    0.,
+                )),
+            )
+            .unwrap();
     }
 
     let context_id2 = &ClientContextId::build(1, 3);
@@ -35,17 +43,25 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         options.oscillator_type = Sawtooth;
         let context = context2.lock().unwrap();
         let dest = context.dest_node();
-        let osc3 = context.create_node(
-            AudioNodeInit::OscillatorNode(options.clone()),
-            Default::default(),
-        );
-        context.connect_ports(osc3.output(0), dest.input(0));
+        let osc3 = context
+            .create_node(
+                AudioNodeInit::OscillatorNode(options.clone()),
+                Default::default(),
+            )
+            .unwrap();
+        context
+            .connect_ports(osc3.output(0), dest.input(0))
+            .unwrap();
 
         let _ = context.resume();
-        context.message_node(
-            osc3,
-            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-        );
+        context
+            .message_node(
+                osc3,
+                AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(
+                    0.,
+                )),
+            )
+            .unwrap();
     }
 
     println!("servo_media raw s1");
@@ -1,10 +1,11 @@
 extern crate servo_media;
 extern crate servo_media_auto;
 
-use servo_media::audio::block::FRAMES_PER_BLOCK_USIZE;
+use servo_media::audio::block::DEFAULT_FRAMES_PER_BLOCK_USIZE;
 use servo_media::audio::buffer_source_node::{AudioBuffer, AudioBufferSourceNodeMessage};
 use servo_media::audio::context::{AudioContextOptions, OfflineAudioContextOptions};
 use servo_media::audio::node::{AudioNodeInit, AudioNodeMessage, AudioScheduledSourceNodeMessage};
+use servo_media::audio::param::{ParamType, UserAutomationEvent};
 use servo_media::{ClientContextId, ServoMedia};
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
@@ -12,10 +13,10 @@ use std::{thread, time};
 
 fn run_example(servo_media: Arc<ServoMedia>) {
     // Create offline context to process 1024 blocks of a oscillator node produced
-    // sine wave.
+    // sine wave, an octave higher in the second half.
     let mut options = <OfflineAudioContextOptions>::default();
     options.channels = 2;
-    options.length = 1024 * FRAMES_PER_BLOCK_USIZE;
+    options.length = 1024 * DEFAULT_FRAMES_PER_BLOCK_USIZE;
     let sample_rate = options.sample_rate;
     let options = AudioContextOptions::OfflineAudioContext(options);
     let context = servo_media.create_audio_context(&ClientContextId::build(1, 1), options);
@@ -31,16 +32,38 @@ fn run_example(servo_media: Arc<ServoMedia>) {
             .extend_from_slice((*buffer).as_ref());
         sender.lock().unwrap().send(()).unwrap();
     }));
-    let osc = context.create_node(
-        AudioNodeInit::OscillatorNode(Default::default()),
-        Default::default(),
-    );
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
     let dest = context.dest_node();
-    context.connect_ports(osc.output(0), dest.input(0));
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+    context.connect_ports(osc.output(0), dest.input(0)).unwrap();
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+    let length = 1024 * DEFAULT_FRAMES_PER_BLOCK_USIZE;
+    context.set_progress_callback(
+        length / 10,
+        Box::new(move |frames| println!("Rendered {}%", frames * 100 / length)),
     );
+    // Halfway through, stop rendering to change the frequency.
+    let (suspended_sender, suspended) = mpsc::channel();
+    let _ = context.suspend_at(length as f64 / 2. / sample_rate as f64, move || {
+        suspended_sender.send(()).unwrap();
+    });
+    let _ = context.resume();
+    suspended.recv().unwrap();
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(ParamType::Frequency, UserAutomationEvent::SetValue(880.)),
+        )
+        .unwrap();
     let _ = context.resume();
     // Block until we processed the data.
     receiver.recv().unwrap();
@@ -50,22 +73,30 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     let context =
         servo_media.create_audio_context(&ClientContextId::build(1, 2), Default::default());
     let context = context.lock().unwrap();
-    let buffer_source = context.create_node(
-        AudioNodeInit::AudioBufferSourceNode(Default::default()),
-        Default::default(),
-    );
+    let buffer_source = context
+        .create_node(
+            AudioNodeInit::AudioBufferSourceNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
     let dest = context.dest_node();
-    context.connect_ports(buffer_source.output(0), dest.input(0));
-    context.message_node(
-        buffer_source,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
-    context.message_node(
-        buffer_source,
-        AudioNodeMessage::AudioBufferSourceNode(AudioBufferSourceNodeMessage::SetBuffer(Some(
-            AudioBuffer::from_buffer(processed_audio_.lock().unwrap().to_vec(), sample_rate),
-        ))),
-    );
+    context
+        .connect_ports(buffer_source.output(0), dest.input(0))
+        .unwrap();
+    context
+        .message_node(
+            buffer_source,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+    context
+        .message_node(
+            buffer_source,
+            AudioNodeMessage::AudioBufferSourceNode(AudioBufferSourceNodeMessage::SetBuffer(Some(
+                AudioBuffer::from_buffer(processed_audio_.lock().unwrap().to_vec(), sample_rate),
+            ))),
+        )
+        .unwrap();
     let _ = context.resume();
     thread::sleep(time::Duration::from_millis(5000));
     let _ = context.close();
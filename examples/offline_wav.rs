@@ -0,0 +1,72 @@
+extern crate servo_media;
+extern crate servo_media_auto;
+
+use servo_media::audio::context::{AudioContextOptions, OfflineAudioContextOptions};
+use servo_media::audio::node::{AudioNodeInit, AudioNodeMessage, AudioScheduledSourceNodeMessage};
+use servo_media::audio::wav_writer::{WavSampleFormat, WavWriter, WavWriterOptions};
+use servo_media::{ClientContextId, ServoMedia};
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::mpsc;
+use std::sync::Arc;
+
+fn run_example(servo_media: Arc<ServoMedia>) {
+    let args: Vec<_> = env::args().collect();
+    let filename: &str = if args.len() == 2 {
+        args[1].as_ref()
+    } else {
+        "offline.wav"
+    };
+
+    // Bounce ten seconds of a sine wave to a 24-bit WAV file, without
+    // keeping the rendered audio in memory.
+    let mut options = <OfflineAudioContextOptions>::default();
+    options.channels = 2;
+    options.length = 10 * options.sample_rate as usize;
+    let channels = options.channels;
+    let sample_rate = options.sample_rate;
+    let options = AudioContextOptions::OfflineAudioContext(options);
+    let context = servo_media.create_audio_context(&ClientContextId::build(1, 1), options);
+    let context = context.lock().unwrap();
+
+    let file = File::create(filename).expect("Could not create the output file");
+    let mut writer_options = WavWriterOptions::default();
+    writer_options.format = WavSampleFormat::Int24;
+    let writer = WavWriter::new(BufWriter::new(file), channels, sample_rate, writer_options)
+        .expect("Could not write the WAV header");
+    let (sender, receiver) = mpsc::channel();
+    context.set_render_writer(Box::new(writer), move |result| {
+        sender.send(result).unwrap();
+    });
+
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
+    let dest = context.dest_node();
+    context.connect_ports(osc.output(0), dest.input(0)).unwrap();
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+    let _ = context.resume();
+    match receiver.recv().unwrap() {
+        Ok(()) => println!("Rendered to {}", filename),
+        Err(e) => println!("Could not render to {}: {}", filename, e),
+    }
+    let _ = context.close();
+}
+
+fn main() {
+    ServoMedia::init::<servo_media_auto::Backend>();
+    if let Ok(servo_media) = ServoMedia::get() {
+        run_example(servo_media);
+    } else {
+        unreachable!()
+    }
+}
@@ -3,10 +3,10 @@ extern crate servo_media_auto;
 
 use servo_media::audio::node::{AudioNodeInit, AudioNodeMessage, AudioScheduledSourceNodeMessage};
 use servo_media::audio::oscillator_node::OscillatorNodeOptions;
-use servo_media::audio::oscillator_node::OscillatorType::Custom;
 use servo_media::audio::oscillator_node::OscillatorType::Sawtooth;
 use servo_media::audio::oscillator_node::OscillatorType::Square;
 use servo_media::audio::oscillator_node::OscillatorType::Triangle;
+use servo_media::audio::oscillator_node::{PeriodicWave, PeriodicWaveOptions};
 use servo_media::{ClientContextId, ServoMedia};
 use std::sync::Arc;
 use std::{thread, time};
@@ -17,16 +17,22 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     let context = context.lock().unwrap();
     let dest = context.dest_node();
     let mut options = OscillatorNodeOptions::default();
-    let osc1 = context.create_node(
-        AudioNodeInit::OscillatorNode(options.clone()),
-        Default::default(),
-    );
-    context.connect_ports(osc1.output(0), dest.input(0));
+    let osc1 = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(options.clone()),
+            Default::default(),
+        )
+        .unwrap();
+    context
+        .connect_ports(osc1.output(0), dest.input(0))
+        .unwrap();
     let _ = context.resume();
-    context.message_node(
-        osc1,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc1,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
 
     thread::sleep(time::Duration::from_millis(3000));
     let _ = context.close();
@@ -37,16 +43,22 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         servo_media.create_audio_context(&ClientContextId::build(1, 2), Default::default());
     let context = context.lock().unwrap();
     let dest = context.dest_node();
-    let osc2 = context.create_node(
-        AudioNodeInit::OscillatorNode(options.clone()),
-        Default::default(),
-    );
-    context.connect_ports(osc2.output(0), dest.input(0));
+    let osc2 = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(options.clone()),
+            Default::default(),
+        )
+        .unwrap();
+    context
+        .connect_ports(osc2.output(0), dest.input(0))
+        .unwrap();
     let _ = context.resume();
-    context.message_node(
-        osc2,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc2,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
 
     thread::sleep(time::Duration::from_millis(3000));
     let _ = context.close();
@@ -57,18 +69,24 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         servo_media.create_audio_context(&ClientContextId::build(1, 3), Default::default());
     let context = context.lock().unwrap();
     let dest = context.dest_node();
-    let osc3 = context.create_node(
-        AudioNodeInit::OscillatorNode(options.clone()),
-        Default::default(),
-    );
-    context.connect_ports(osc3.output(0), dest.input(0));
+    let osc3 = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(options.clone()),
+            Default::default(),
+        )
+        .unwrap();
+    context
+        .connect_ports(osc3.output(0), dest.input(0))
+        .unwrap();
     thread::sleep(time::Duration::from_millis(3000));
 
     let _ = context.resume();
-    context.message_node(
-        osc3,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc3,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
     thread::sleep(time::Duration::from_millis(3000));
     let _ = context.close();
     thread::sleep(time::Duration::from_millis(1000));
@@ -78,41 +96,61 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         servo_media.create_audio_context(&ClientContextId::build(1, 4), Default::default());
     let context = context.lock().unwrap();
     let dest = context.dest_node();
-    let osc4 = context.create_node(
-        AudioNodeInit::OscillatorNode(options.clone()),
-        Default::default(),
-    );
-    context.connect_ports(osc4.output(0), dest.input(0));
+    let osc4 = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(options.clone()),
+            Default::default(),
+        )
+        .unwrap();
+    context
+        .connect_ports(osc4.output(0), dest.input(0))
+        .unwrap();
     thread::sleep(time::Duration::from_millis(3000));
 
     let _ = context.resume();
-    context.message_node(
-        osc4,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc4,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
     thread::sleep(time::Duration::from_millis(3000));
     let _ = context.close();
 
     thread::sleep(time::Duration::from_millis(3000));
 
-    options.oscillator_type = Custom;
+    // A few odd harmonics, a bit like an organ
+    options.periodic_wave = Some(
+        PeriodicWave::new(PeriodicWaveOptions {
+            real: None,
+            imag: Some(vec![0., 1., 0., 0.5, 0., 0.25]),
+            disable_normalization: false,
+        })
+        .unwrap(),
+    );
 
     let context =
         servo_media.create_audio_context(&ClientContextId::build(1, 5), Default::default());
     let context = context.lock().unwrap();
     let dest = context.dest_node();
-    let osc5 = context.create_node(
-        AudioNodeInit::OscillatorNode(options.clone()),
-        Default::default(),
-    );
-    context.connect_ports(osc5.output(0), dest.input(0));
+    let osc5 = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(options.clone()),
+            Default::default(),
+        )
+        .unwrap();
+    context
+        .connect_ports(osc5.output(0), dest.input(0))
+        .unwrap();
     thread::sleep(time::Duration::from_millis(3000));
 
     let _ = context.resume();
-    context.message_node(
-        osc4,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc5,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
 
     thread::sleep(time::Duration::from_millis(3000));
     let _ = context.close();
@@ -2,9 +2,10 @@ extern crate servo_media;
 extern crate servo_media_auto;
 
 use servo_media::audio::node::{AudioNodeInit, AudioNodeMessage, AudioScheduledSourceNodeMessage};
-use servo_media::audio::panner_node::PannerNodeOptions;
+use servo_media::audio::panner_node::{PannerNodeOptions, PanningModel};
 use servo_media::audio::param::{ParamDir, ParamType, RampKind, UserAutomationEvent};
 use servo_media::{ClientContextId, ServoMedia};
+use std::env;
 use std::sync::Arc;
 use std::{thread, time};
 
@@ -14,10 +15,12 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     let context = context.lock().unwrap();
     let dest = context.dest_node();
     let listener = context.listener();
-    let osc = context.create_node(
-        AudioNodeInit::OscillatorNode(Default::default()),
-        Default::default(),
-    );
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
     let mut options = PannerNodeOptions::default();
     options.cone_outer_angle = 0.;
     options.position_x = 100.;
@@ -25,151 +28,201 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     options.position_z = 100.;
     options.ref_distance = 100.;
     options.rolloff_factor = 0.01;
-    let panner = context.create_node(AudioNodeInit::PannerNode(options), Default::default());
-    context.connect_ports(osc.output(0), panner.input(0));
-    context.connect_ports(panner.output(0), dest.input(0));
+    // pass --hrtf to hear the binaural version, best with headphones
+    if env::args().any(|arg| arg == "--hrtf") {
+        options.panning_model = PanningModel::HRTF;
+    }
+    let panner = context
+        .create_node(AudioNodeInit::PannerNode(options), Default::default())
+        .unwrap();
+    context
+        .connect_ports(osc.output(0), panner.input(0))
+        .unwrap();
+    context
+        .connect_ports(panner.output(0), dest.input(0))
+        .unwrap();
     let _ = context.resume();
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
     // trace a square around your head twice
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.2),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.2),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.4),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.4),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.6),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.6),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.8),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.8),
-        ),
-    );
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.2),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.2),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.4),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.4),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.6),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 0.6),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.8),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 0.8),
+            ),
+        )
+        .unwrap();
 
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.0),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.0),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.2),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.2),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.4),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.4),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::X),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.6),
-        ),
-    );
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.6),
-        ),
-    );
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.0),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.0),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.2),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.2),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.4),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, -100., 1.4),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::X),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.6),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 100., 1.6),
+            ),
+        )
+        .unwrap();
     // now it runs away
-    context.message_node(
-        panner,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 10000., 3.),
-        ),
-    );
-    context.message_node(
-        listener,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::SetValueAtTime(0., 3.),
-        ),
-    );
+    context
+        .message_node(
+            panner,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 10000., 3.),
+            ),
+        )
+        .unwrap();
+    context
+        .message_node(
+            listener,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::SetValueAtTime(0., 3.),
+            ),
+        )
+        .unwrap();
     // chase it
-    context.message_node(
-        listener,
-        AudioNodeMessage::SetParam(
-            ParamType::Position(ParamDir::Z),
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 10000., 4.),
-        ),
-    );
+    context
+        .message_node(
+            listener,
+            AudioNodeMessage::SetParam(
+                ParamType::Position(ParamDir::Z),
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 10000., 4.),
+            ),
+        )
+        .unwrap();
     thread::sleep(time::Duration::from_millis(4000));
 }
 
@@ -13,85 +13,109 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         servo_media.create_audio_context(&ClientContextId::build(1, 1), Default::default());
     let context = context.lock().unwrap();
     let dest = context.dest_node();
-    let osc = context.create_node(
-        AudioNodeInit::OscillatorNode(Default::default()),
-        Default::default(),
-    );
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
     let mut options = GainNodeOptions::default();
     options.gain = 0.5;
-    let gain = context.create_node(AudioNodeInit::GainNode(options), Default::default());
-    context.connect_ports(osc.output(0), gain.input(0));
-    context.connect_ports(gain.output(0), dest.input(0));
+    let gain = context
+        .create_node(AudioNodeInit::GainNode(options), Default::default())
+        .unwrap();
+    context.connect_ports(osc.output(0), gain.input(0)).unwrap();
+    context
+        .connect_ports(gain.output(0), dest.input(0))
+        .unwrap();
     let _ = context.resume();
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
     // 0.5s: Set frequency to 110Hz
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::SetValueAtTime(110., 0.5),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::SetValueAtTime(110., 0.5),
+            ),
+        )
+        .unwrap();
     // 1s: Set frequency to 220Hz
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::SetValueAtTime(220., 1.),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::SetValueAtTime(220., 1.),
+            ),
+        )
+        .unwrap();
     // 0.75s: Set gain to 0.25
-    context.message_node(
-        gain,
-        AudioNodeMessage::SetParam(
-            ParamType::Gain,
-            UserAutomationEvent::SetValueAtTime(0.25, 0.75),
-        ),
-    );
+    context
+        .message_node(
+            gain,
+            AudioNodeMessage::SetParam(
+                ParamType::Gain,
+                UserAutomationEvent::SetValueAtTime(0.25, 0.75),
+            ),
+        )
+        .unwrap();
     // 0.75s - 1.5s: Exponentially ramp gain to 1
-    context.message_node(
-        gain,
-        AudioNodeMessage::SetParam(
-            ParamType::Gain,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Exponential, 1., 1.5),
-        ),
-    );
+    context
+        .message_node(
+            gain,
+            AudioNodeMessage::SetParam(
+                ParamType::Gain,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Exponential, 1., 1.5),
+            ),
+        )
+        .unwrap();
     // 0.75s - 1.75s: Linearly ramp frequency to 880Hz
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 880., 1.75),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 880., 1.75),
+            ),
+        )
+        .unwrap();
     // 1.75s - 2.5s: Exponentially ramp frequency to 110Hz
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Exponential, 110., 2.5),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Exponential, 110., 2.5),
+            ),
+        )
+        .unwrap();
 
     // 2.75s: Exponentially approach 110Hz
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::SetTargetAtTime(1100., 2.75, 1.1),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::SetTargetAtTime(1100., 2.75, 1.1),
+            ),
+        )
+        .unwrap();
     // 3.3s: But actually stop at 3.3Hz and hold
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::CancelAndHoldAtTime(3.3),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::CancelAndHoldAtTime(3.3),
+            ),
+        )
+        .unwrap();
     thread::sleep(time::Duration::from_millis(5000));
 }
 
@@ -12,50 +12,62 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         servo_media.create_audio_context(&ClientContextId::build(1, 1), Default::default());
     let context = context.lock().unwrap();
     let dest = context.dest_node();
-    let osc = context.create_node(
-        AudioNodeInit::OscillatorNode(Default::default()),
-        Default::default(),
-    );
-    context.connect_ports(osc.output(0), dest.input(0));
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
+    context.connect_ports(osc.output(0), dest.input(0)).unwrap();
     let _ = context.resume();
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
     // 0.1s: Set frequency to 110Hz
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::SetValueAtTime(110., 0.1),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::SetValueAtTime(110., 0.1),
+            ),
+        )
+        .unwrap();
     // 0.3s: Start increasing frequency to 440Hz exponentially with a time constant of 1
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::SetTargetAtTime(440., 0.3, 1.),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::SetTargetAtTime(440., 0.3, 1.),
+            ),
+        )
+        .unwrap();
     // 1.5s: Start increasing frequency to 1760Hz exponentially
     // this event effectively doesn't happen, but instead sets a starting point
     // for the next ramp event
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::SetTargetAtTime(1760., 1.5, 0.1),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::SetTargetAtTime(1760., 1.5, 0.1),
+            ),
+        )
+        .unwrap();
     // 1.5s - 3s Linearly ramp down from the previous event (1.5s) to 110Hz
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 110., 3.0),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 110., 3.0),
+            ),
+        )
+        .unwrap();
     thread::sleep(time::Duration::from_millis(5000));
 }
 
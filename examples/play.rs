@@ -14,59 +14,77 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     let context =
         servo_media.create_audio_context(&ClientContextId::build(1, 1), Default::default());
     let context = context.lock().unwrap();
-    let osc = context.create_node(
-        AudioNodeInit::OscillatorNode(Default::default()),
-        Default::default(),
-    );
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
     let mut options = GainNodeOptions::default();
     options.gain = 0.5;
-    let gain = context.create_node(AudioNodeInit::GainNode(options), Default::default());
+    let gain = context
+        .create_node(AudioNodeInit::GainNode(options), Default::default())
+        .unwrap();
     let dest = context.dest_node();
-    context.connect_ports(osc.output(0), gain.input(0));
-    context.connect_ports(gain.output(0), dest.input(0));
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Stop(3.)),
-    );
+    context.connect_ports(osc.output(0), gain.input(0)).unwrap();
+    context
+        .connect_ports(gain.output(0), dest.input(0))
+        .unwrap();
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Stop(3.)),
+        )
+        .unwrap();
     let callback = OnEndedCallback::new(|| {
         println!("Playback ended");
     });
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(
-            AudioScheduledSourceNodeMessage::RegisterOnEndedCallback(callback),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(
+                AudioScheduledSourceNodeMessage::RegisterOnEndedCallback(callback),
+            ),
+        )
+        .unwrap();
     assert_eq!(context.current_time(), 0.);
     let _ = context.resume();
     // 0.5s: Set frequency to 110Hz
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::SetValueAtTime(110., 0.5),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::SetValueAtTime(110., 0.5),
+            ),
+        )
+        .unwrap();
     // 1s: Set frequency to 220Hz
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::SetValueAtTime(220., 1.),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::SetValueAtTime(220., 1.),
+            ),
+        )
+        .unwrap();
     // 0.75s: Set gain to 0.25
-    context.message_node(
-        gain,
-        AudioNodeMessage::SetParam(
-            ParamType::Gain,
-            UserAutomationEvent::SetValueAtTime(0.25, 0.75),
-        ),
-    );
+    context
+        .message_node(
+            gain,
+            AudioNodeMessage::SetParam(
+                ParamType::Gain,
+                UserAutomationEvent::SetValueAtTime(0.25, 0.75),
+            ),
+        )
+        .unwrap();
     thread::sleep(time::Duration::from_millis(1200));
     // 1.2s: Suspend processing
     let _ = context.suspend();
@@ -24,58 +24,78 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     let n = values.len() as f32;
     let value_next = values[(n - 1.) as usize];
 
-    let cs = context.create_node(
-        AudioNodeInit::ConstantSourceNode(ConstantSourceNodeOptions::default()),
-        Default::default(),
-    );
+    let cs = context
+        .create_node(
+            AudioNodeInit::ConstantSourceNode(ConstantSourceNodeOptions::default()),
+            Default::default(),
+        )
+        .unwrap();
 
     let mut gain_options = GainNodeOptions::default();
     gain_options.gain = 0.0;
-    let gain = context.create_node(
-        AudioNodeInit::GainNode(gain_options.clone()),
-        Default::default(),
-    );
+    let gain = context
+        .create_node(
+            AudioNodeInit::GainNode(gain_options.clone()),
+            Default::default(),
+        )
+        .unwrap();
 
-    let osc = context.create_node(
-        AudioNodeInit::OscillatorNode(Default::default()),
-        Default::default(),
-    );
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
 
-    context.connect_ports(osc.output(0), gain.input(0));
-    context.connect_ports(cs.output(0), gain.param(ParamType::Gain));
-    context.connect_ports(gain.output(0), dest.input(0));
+    context.connect_ports(osc.output(0), gain.input(0)).unwrap();
+    context
+        .connect_ports(cs.output(0), gain.param(ParamType::Gain))
+        .unwrap();
+    context
+        .connect_ports(gain.output(0), dest.input(0))
+        .unwrap();
 
     let _ = context.resume();
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
 
-    context.message_node(
-        gain,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            gain,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
 
-    context.message_node(
-        cs,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            cs,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
 
-    context.message_node(
-        cs,
-        AudioNodeMessage::SetParam(
-            ParamType::Offset,
-            UserAutomationEvent::SetValueCurveAtTime(values, start_time, end_time),
-        ),
-    );
+    context
+        .message_node(
+            cs,
+            AudioNodeMessage::SetParam(
+                ParamType::Offset,
+                UserAutomationEvent::SetValueCurveAtTime(values, start_time, end_time),
+            ),
+        )
+        .unwrap();
 
-    context.message_node(
-        osc,
-        AudioNodeMessage::SetParam(
-            ParamType::Frequency,
-            UserAutomationEvent::SetValueAtTime(value_next, end_time),
-        ),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::SetParam(
+                ParamType::Frequency,
+                UserAutomationEvent::SetValueAtTime(value_next, end_time),
+            ),
+        )
+        .unwrap();
 
     thread::sleep(time::Duration::from_millis(7000));
     let _ = context.close();
@@ -13,41 +13,56 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         servo_media.create_audio_context(&ClientContextId::build(1, 1), Default::default());
     let context = context.lock().unwrap();
     let dest = context.dest_node();
-    let osc = context.create_node(
-        AudioNodeInit::OscillatorNode(Default::default()),
-        Default::default(),
-    );
+    let osc = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(Default::default()),
+            Default::default(),
+        )
+        .unwrap();
     let mut options = StereoPannerOptions::default();
     options.pan = 0.;
-    let pan = context.create_node(AudioNodeInit::StereoPannerNode(options), Default::default());
-    context.connect_ports(osc.output(0), pan.input(0));
-    context.connect_ports(pan.output(0), dest.input(0));
+    let pan = context
+        .create_node(AudioNodeInit::StereoPannerNode(options), Default::default())
+        .unwrap();
+    context.connect_ports(osc.output(0), pan.input(0)).unwrap();
+    context.connect_ports(pan.output(0), dest.input(0)).unwrap();
     let _ = context.resume();
-    context.message_node(
-        osc,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
     // 2s: Set pan to -1
-    context.message_node(
-        pan,
-        AudioNodeMessage::SetParam(ParamType::Pan, UserAutomationEvent::SetValueAtTime(-1., 2.)),
-    );
+    context
+        .message_node(
+            pan,
+            AudioNodeMessage::SetParam(
+                ParamType::Pan,
+                UserAutomationEvent::SetValueAtTime(-1., 2.),
+            ),
+        )
+        .unwrap();
     // 4s: Linearly ramp pan to 0
-    context.message_node(
-        pan,
-        AudioNodeMessage::SetParam(
-            ParamType::Pan,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 0., 4.),
-        ),
-    );
+    context
+        .message_node(
+            pan,
+            AudioNodeMessage::SetParam(
+                ParamType::Pan,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 0., 4.),
+            ),
+        )
+        .unwrap();
     // 6s: Linearly ramp pan to 1
-    context.message_node(
-        pan,
-        AudioNodeMessage::SetParam(
-            ParamType::Pan,
-            UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1., 6.),
-        ),
-    );
+    context
+        .message_node(
+            pan,
+            AudioNodeMessage::SetParam(
+                ParamType::Pan,
+                UserAutomationEvent::RampToValueAtTime(RampKind::Linear, 1., 6.),
+            ),
+        )
+        .unwrap();
     thread::sleep(time::Duration::from_millis(5000));
 }
 
@@ -13,25 +13,33 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         servo_media.create_audio_context(&ClientContextId::build(1, 1), Default::default());
     let context = context.lock().unwrap();
     let options = OscillatorNodeOptions::default();
-    let osc1 = context.create_node(
-        AudioNodeInit::OscillatorNode(options.clone()),
-        Default::default(),
-    );
+    let osc1 = context
+        .create_node(
+            AudioNodeInit::OscillatorNode(options.clone()),
+            Default::default(),
+        )
+        .unwrap();
 
     let (socket, id) = servo_media.create_stream_and_socket(MediaStreamType::Audio);
-    let dest = context.create_node(
-        AudioNodeInit::MediaStreamDestinationNode(socket),
-        Default::default(),
-    );
-    context.connect_ports(osc1.output(0), dest.input(0));
+    let dest = context
+        .create_node(
+            AudioNodeInit::MediaStreamDestinationNode(socket),
+            Default::default(),
+        )
+        .unwrap();
+    context
+        .connect_ports(osc1.output(0), dest.input(0))
+        .unwrap();
 
     let mut output = servo_media.create_stream_output();
     output.add_stream(&id);
     let _ = context.resume();
-    context.message_node(
-        osc1,
-        AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-    );
+    context
+        .message_node(
+            osc1,
+            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
+        )
+        .unwrap();
 
     thread::sleep(time::Duration::from_millis(3000));
     let _ = context.close();
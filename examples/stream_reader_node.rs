@@ -12,11 +12,15 @@ fn run_example(servo_media: Arc<ServoMedia>) {
     let input = servo_media.create_audiostream();
     let context = context.lock().unwrap();
     let dest = context.dest_node();
-    let osc1 = context.create_node(
-        AudioNodeInit::MediaStreamSourceNode(input),
-        Default::default(),
-    );
-    context.connect_ports(osc1.output(0), dest.input(0));
+    let osc1 = context
+        .create_node(
+            AudioNodeInit::MediaStreamSourceNode(input),
+            Default::default(),
+        )
+        .unwrap();
+    context
+        .connect_ports(osc1.output(0), dest.input(0))
+        .unwrap();
     let _ = context.resume();
 
     thread::sleep(time::Duration::from_millis(6000));
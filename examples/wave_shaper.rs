@@ -19,74 +19,96 @@ fn run_example(servo_media: Arc<ServoMedia>) {
         let curve = vec![1., 0., 0., 0.75, 0.5];
 
         let dest = context.dest_node();
-        let osc = context.create_node(
-            AudioNodeInit::OscillatorNode(OscillatorNodeOptions::default()),
-            Default::default(),
-        );
-        let wsh = context.create_node(
-            AudioNodeInit::WaveShaperNode(WaveShaperNodeOptions {
-                curve: Some(curve.clone()),
-                oversample: OverSampleType::None,
-            }),
-            Default::default(),
-        );
-        let wshx2 = context.create_node(
-            AudioNodeInit::WaveShaperNode(WaveShaperNodeOptions {
-                curve: Some(curve.clone()),
-                oversample: OverSampleType::Double,
-            }),
-            Default::default(),
-        );
-        let wshx4 = context.create_node(
-            AudioNodeInit::WaveShaperNode(WaveShaperNodeOptions {
-                curve: Some(curve.clone()),
-                oversample: OverSampleType::Quadruple,
-            }),
-            Default::default(),
-        );
+        let osc = context
+            .create_node(
+                AudioNodeInit::OscillatorNode(OscillatorNodeOptions::default()),
+                Default::default(),
+            )
+            .unwrap();
+        let wsh = context
+            .create_node(
+                AudioNodeInit::WaveShaperNode(WaveShaperNodeOptions {
+                    curve: Some(curve.clone()),
+                    oversample: OverSampleType::None,
+                }),
+                Default::default(),
+            )
+            .unwrap();
+        let wshx2 = context
+            .create_node(
+                AudioNodeInit::WaveShaperNode(WaveShaperNodeOptions {
+                    curve: Some(curve.clone()),
+                    oversample: OverSampleType::Double,
+                }),
+                Default::default(),
+            )
+            .unwrap();
+        let wshx4 = context
+            .create_node(
+                AudioNodeInit::WaveShaperNode(WaveShaperNodeOptions {
+                    curve: Some(curve.clone()),
+                    oversample: OverSampleType::Quadruple,
+                }),
+                Default::default(),
+            )
+            .unwrap();
 
-        context.connect_ports(osc.output(0), dest.input(0));
+        context.connect_ports(osc.output(0), dest.input(0)).unwrap();
         let _ = context.resume();
-        context.message_node(
-            osc,
-            AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(0.)),
-        );
+        context
+            .message_node(
+                osc,
+                AudioNodeMessage::AudioScheduledSourceNode(AudioScheduledSourceNodeMessage::Start(
+                    0.,
+                )),
+            )
+            .unwrap();
 
         println!("raw oscillator");
         thread::sleep(time::Duration::from_millis(2000));
 
         println!("oscillator through waveshaper with no oversampling");
         context.disconnect_output(osc.output(0));
-        context.connect_ports(osc.output(0), wsh.input(0));
-        context.connect_ports(wsh.output(0), dest.input(0));
+        context.connect_ports(osc.output(0), wsh.input(0)).unwrap();
+        context.connect_ports(wsh.output(0), dest.input(0)).unwrap();
         thread::sleep(time::Duration::from_millis(2000));
 
         println!("oscillator through waveshaper with 2x oversampling");
         context.disconnect_output(osc.output(0));
         context.disconnect_output(wsh.output(0));
-        context.connect_ports(osc.output(0), wshx2.input(0));
-        context.connect_ports(wshx2.output(0), dest.input(0));
+        context
+            .connect_ports(osc.output(0), wshx2.input(0))
+            .unwrap();
+        context
+            .connect_ports(wshx2.output(0), dest.input(0))
+            .unwrap();
         thread::sleep(time::Duration::from_millis(2000));
 
         println!("oscillator through waveshaper with 4x oversampling");
         context.disconnect_output(osc.output(0));
         context.disconnect_output(wshx2.output(0));
-        context.connect_ports(osc.output(0), wshx4.input(0));
-        context.connect_ports(wshx4.output(0), dest.input(0));
+        context
+            .connect_ports(osc.output(0), wshx4.input(0))
+            .unwrap();
+        context
+            .connect_ports(wshx4.output(0), dest.input(0))
+            .unwrap();
         thread::sleep(time::Duration::from_millis(2000));
 
         println!("oscillator through waveshaper with no oversampling");
         context.disconnect_output(osc.output(0));
         context.disconnect_output(wshx4.output(0));
-        context.connect_ports(osc.output(0), wsh.input(0));
-        context.connect_ports(wsh.output(0), dest.input(0));
+        context.connect_ports(osc.output(0), wsh.input(0)).unwrap();
+        context.connect_ports(wsh.output(0), dest.input(0)).unwrap();
         thread::sleep(time::Duration::from_millis(2000));
 
         println!("oscillator through waveshaper with no curve (should be same as raw oscillator)");
-        context.message_node(
-            wsh,
-            AudioNodeMessage::WaveShaperNode(WaveShaperNodeMessage::SetCurve(None)),
-        );
+        context
+            .message_node(
+                wsh,
+                AudioNodeMessage::WaveShaperNode(WaveShaperNodeMessage::SetCurve(None)),
+            )
+            .unwrap();
         thread::sleep(time::Duration::from_millis(2000));
     }
 }
@@ -41,6 +41,9 @@ pub enum PlayerError {
     SetStreamFailed,
     // Setting an audio or video track failed.
     SetTrackFailed,
+    /// There is no audio output device with the requested sink id,
+    /// or the player's audio doesn't go to a device.
+    SetSinkIdFailed,
 }
 
 pub type SeekLockMsg = (bool, IpcSender<()>);
@@ -116,4 +119,10 @@ pub trait Player: Send + MediaInstance {
     fn render_use_gl(&self) -> bool;
     fn set_audio_track(&self, stream_index: i32, enabled: bool) -> Result<(), PlayerError>;
     fn set_video_track(&self, stream_index: i32, enabled: bool) -> Result<(), PlayerError>;
+    /// Route the audio to the output device with the given id, as
+    /// enumerated by the backend's `MediaDeviceMonitor`, or to the default
+    /// device for `None`. This can be changed while playing.
+    fn set_sink_id(&self, sink_id: Option<&str>) -> Result<(), PlayerError>;
+    /// The id of the audio output device, `None` being the default device
+    fn sink_id(&self) -> Option<String>;
 }
@@ -26,7 +26,7 @@ fn impl_audio_scheduled_source_node(ast: &syn::DeriveInput) -> proc_macro2::Toke
                     return ShouldPlay::No;
                 };
 
-                let frame_end = tick + Tick::FRAMES_PER_BLOCK;
+                let frame_end = tick + Tick::frames_per_block();
                 if tick < start {
                     if frame_end < start {
                         ShouldPlay::No
@@ -38,23 +38,23 @@ fn impl_audio_scheduled_source_node(ast: &syn::DeriveInput) -> proc_macro2::Toke
                                 return ShouldPlay::No;
                             }
                             if stop > frame_end {
-                                ShouldPlay::Between(delta_start, Tick::FRAMES_PER_BLOCK)
+                                ShouldPlay::Between(delta_start, Tick::frames_per_block())
                             } else {
                                 self.maybe_trigger_onended_callback();
                                 ShouldPlay::Between(delta_start, stop - tick)
                             }
                         } else {
-                            ShouldPlay::Between(delta_start, Tick::FRAMES_PER_BLOCK)
+                            ShouldPlay::Between(delta_start, Tick::frames_per_block())
                         }
                     }
                 } else {
                     let stop = if let Some(stop) = self.stop_at {
                         stop
                     } else {
-                        return ShouldPlay::Between(Tick(0), Tick::FRAMES_PER_BLOCK);
+                        return ShouldPlay::Between(Tick(0), Tick::frames_per_block());
                     };
                     if stop > frame_end {
-                        ShouldPlay::Between(Tick(0), Tick::FRAMES_PER_BLOCK)
+                        ShouldPlay::Between(Tick(0), Tick::frames_per_block())
                     } else if stop < tick {
                         self.maybe_trigger_onended_callback();
                         ShouldPlay::No
@@ -91,6 +91,7 @@ fn impl_audio_scheduled_source_node(ast: &syn::DeriveInput) -> proc_macro2::Toke
                 if self.start_at.is_none() {
                     return;
                 }
+                self.ended = true;
                 if let Some(cb) = self.onended_callback.take() {
                     cb.0.call()
                 }